@@ -0,0 +1,241 @@
+/*
+实例化绘制
+同一个网格画很多次时，为每个物体都调用一次 draw_indexed 既慢又浪费。实例化（Instancing）让我们用一次绘制命令画出同一个网格的多个副本，
+每个副本（实例）有自己的变换和颜色。
+
+实例数据放在第二个顶点缓冲区中，它的 step_mode 是 VertexStepMode::Instance：着色器每开始绘制一个新实例才读取下一个元素，而不是每个顶点读一次。
+draw_indexed 的第三个参数 instances 就是要绘制的实例范围，@builtin(instance_index) 是当前实例的索引。
+*/
+use std::ops::Range;
+
+use cgmath::{Matrix, Matrix3, Matrix4, Quaternion, SquareMatrix, Vector3};
+use wgpu::{Buffer, BufferAddress, BufferUsages, Device, Queue};
+
+use super::VertexLayout;
+
+//一个实例：位置、旋转、缩放和可选的颜色（与纹理颜色相乘，None 表示白色，即不改变颜色）
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Instance {
+    pub position: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+    pub tint: Option<[f32; 4]>,
+}
+
+impl Instance {
+    //位于 position、没有旋转和缩放的实例
+    pub fn new(position: Vector3<f32>) -> Self {
+        Self {
+            position,
+            rotation: Quaternion::new(1.0, 0.0, 0.0, 0.0),
+            scale: Vector3::new(1.0, 1.0, 1.0),
+            tint: None,
+        }
+    }
+
+    //着色器不能直接使用四元数，所以把变换合成为模型矩阵：先缩放，再旋转，最后平移
+    pub fn model_matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.position)
+            * Matrix4::from(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+
+    pub fn to_raw(&self) -> InstanceRaw {
+        self.to_raw_with(Matrix4::identity())
+    }
+
+    //local 是实例内部的变换（例如模型节点的变换），先于实例自己的变换应用
+    pub fn to_raw_with(&self, local: Matrix4<f32>) -> InstanceRaw {
+        let model = self.model_matrix() * local;
+        InstanceRaw {
+            model: model.into(),
+            normal: normal_matrix(&model).into(),
+            tint: self.tint.unwrap_or([1.0; 4]),
+        }
+    }
+}
+
+/*
+法线矩阵：模型矩阵左上角 3x3 部分的逆矩阵的转置。
+只有旋转和等比缩放时直接用模型矩阵变换法线也可以，但不等比缩放会让法线不再垂直于表面，必须使用法线矩阵。
+*/
+fn normal_matrix(model: &Matrix4<f32>) -> Matrix3<f32> {
+    let linear = Matrix3::from_cols(model.x.truncate(), model.y.truncate(), model.z.truncate());
+    linear.invert().map(|m| m.transpose()).unwrap_or_else(Matrix3::identity)
+}
+
+/*
+写入实例缓冲区的数据，每个实例读取一次，而不是每个顶点读取一次。
+顶点属性最大只能是 Float32x4，所以 4x4 的矩阵要占用 4 个连续的 location，着色器中再把它们组合成 mat4x4f。
+location 从 5 开始，给 Vertex 以后增加的属性（法线、切线等）留出位置。
+5..=8 是模型矩阵，9..=11 是法线矩阵，12 是颜色。
+*/
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, VertexLayout)]
+#[vertex(instance, location = 5)]
+pub struct InstanceRaw {
+    pub model: [[f32; 4]; 4],
+    pub normal: [[f32; 3]; 3],
+    pub tint: [f32; 4],
+}
+
+/*
+实例列表和它的顶点缓冲区。
+修改实例时只记录哪些元素变了，upload 时把相邻的修改合并成连续的区间，每个区间调用一次 write_buffer，而不是每帧上传整个缓冲区。
+实例数量超过缓冲区容量时，按两倍扩容并重新上传全部数据。
+*/
+pub struct InstanceBuffer {
+    instances: Vec<Instance>,
+    buffer: Buffer,
+    //缓冲区能容纳的实例数量
+    capacity: usize,
+    //自上次 upload 以来被修改过的实例的索引区间
+    dirty: Vec<Range<usize>>,
+    //缓冲区需要重新创建
+    resized: bool,
+}
+
+impl InstanceBuffer {
+    pub fn new(device: &Device, instances: Vec<Instance>) -> Self {
+        let capacity = instances.len().max(1);
+        let buffer = Self::create_buffer(device, capacity);
+        let dirty = if instances.is_empty() { Vec::new() } else { std::iter::once(0..instances.len()).collect() };
+        Self {
+            instances,
+            buffer,
+            capacity,
+            dirty,
+            resized: false,
+        }
+    }
+
+    fn create_buffer(device: &Device, capacity: usize) -> Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Instance Buffer"),
+            size: (capacity * std::mem::size_of::<InstanceRaw>()) as BufferAddress,
+            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.instances.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instances.is_empty()
+    }
+
+    pub fn instances(&self) -> &[Instance] {
+        &self.instances
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Instance> {
+        self.instances.get(index)
+    }
+
+    //添加一个实例，返回它的索引
+    pub fn push(&mut self, instance: Instance) -> usize {
+        let index = self.instances.len();
+        self.instances.push(instance);
+        if self.instances.len() > self.capacity {
+            self.resized = true;
+        }
+        self.dirty.push(index..index + 1);
+        index
+    }
+
+    /*
+    移除一个实例。为了不移动后面所有的实例，最后一个实例会被移到 index 处（与 Vec::swap_remove 相同），
+    所以之前取得的最后一个实例的索引会变成 index。
+    */
+    pub fn remove(&mut self, index: usize) -> Instance {
+        let removed = self.instances.swap_remove(index);
+        if index < self.instances.len() {
+            self.dirty.push(index..index + 1);
+        }
+        removed
+    }
+
+    //替换一个实例
+    pub fn set(&mut self, index: usize, instance: Instance) {
+        self.instances[index] = instance;
+        self.dirty.push(index..index + 1);
+    }
+
+    //就地修改一个实例
+    pub fn update<F: FnOnce(&mut Instance)>(&mut self, index: usize, f: F) {
+        f(&mut self.instances[index]);
+        self.dirty.push(index..index + 1);
+    }
+
+    //把修改过的实例写入缓冲区，每帧绘制前调用。返回这次写入的实例区间（缓冲区重新创建时是全部实例），没有修改时为空
+    pub fn upload(&mut self, device: &Device, queue: &Queue) -> Vec<Range<usize>> {
+        if self.resized {
+            self.capacity = self.instances.len().max(self.capacity * 2);
+            self.buffer = Self::create_buffer(device, self.capacity);
+            self.dirty = std::iter::once(0..self.instances.len()).collect();
+            self.resized = false;
+        }
+        let len = self.instances.len();
+        let mut uploaded = Vec::new();
+        for range in merge_ranges(std::mem::take(&mut self.dirty)) {
+            //被移除的实例不需要上传
+            let range = range.start.min(len)..range.end.min(len);
+            if range.is_empty() {
+                continue;
+            }
+            let data: Vec<InstanceRaw> = self.instances[range.clone()].iter().map(Instance::to_raw).collect();
+            let offset = (range.start * std::mem::size_of::<InstanceRaw>()) as BufferAddress;
+            queue.write_buffer(&self.buffer, offset, bytemuck::cast_slice(&data));
+            uploaded.push(range);
+        }
+        uploaded
+    }
+
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+
+    //绘制时使用的实例范围
+    pub fn range(&self) -> Range<u32> {
+        0..self.instances.len() as u32
+    }
+}
+
+//把区间排序，并合并重叠或相邻的区间
+fn merge_ranges(mut ranges: Vec<Range<usize>>) -> Vec<Range<usize>> {
+    ranges.sort_by_key(|range| range.start);
+    let mut merged: Vec<Range<usize>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_overlapping_and_adjacent_ranges() {
+        assert_eq!(merge_ranges(vec![5..6, 0..1, 1..2, 4..5, 8..10, 9..12]), vec![0..2, 4..6, 8..12]);
+        assert_eq!(merge_ranges(vec![3..4, 3..4]), vec![3..4]);
+        assert!(merge_ranges(Vec::new()).is_empty());
+    }
+
+    //派生的属性与之前手写的 vertex_attr_array! 完全一致
+    #[test]
+    fn instance_layout_matches_vertex_attr_array() {
+        assert_eq!(InstanceRaw::ATTRIBUTES, wgpu::vertex_attr_array![
+            5 => Float32x4, 6 => Float32x4, 7 => Float32x4, 8 => Float32x4,
+            9 => Float32x3, 10 => Float32x3, 11 => Float32x3,
+            12 => Float32x4
+        ]);
+        assert_eq!(InstanceRaw::STEP_MODE, wgpu::VertexStepMode::Instance);
+        assert_eq!(InstanceRaw::desc().array_stride, std::mem::size_of::<InstanceRaw>() as BufferAddress);
+    }
+}
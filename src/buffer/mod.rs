@@ -22,16 +22,28 @@
 现在我们要用 tex_coords 代替 color，这些坐标会被传递给采样器以获取纹素（Texel）的颜色。
 
 由于 tex_coords 是二维的，需要修改这个字段的类型为两个浮点数的数组。
+
+光照需要知道表面朝向哪里，所以再加上法线（normal），它是垂直于表面、长度为 1 的向量。
+
+法线贴图中的法线是相对于表面的（切线空间），还需要切线（tangent）和副切线（bitangent）把它转换到模型空间。
+切线和副切线分别是纹理坐标 u 和 v 增大的方向，与法线一起组成 TBN 矩阵。
 */
-use bytemuck::Zeroable;
-use wgpu::{BufferAddress, vertex_attr_array, VertexAttribute, VertexBufferLayout, VertexStepMode};
+pub mod instance;
+
+use wgpu::{BufferAddress, VertexAttribute, VertexBufferLayout, VertexStepMode};
+
+//派生宏与 trait 同名，use crate::buffer::VertexLayout 同时引入两者
+pub use wgpu_01_derive::VertexLayout;
 
 #[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, VertexLayout)]
 pub struct Vertex {
     pub position: [f32; 3],
     // pub color: [f32; 3]
-    pub tex_coords: [f32; 2]
+    pub tex_coords: [f32; 2],
+    pub normal: [f32; 3],
+    pub tangent: [f32; 3],
+    pub bitangent: [f32; 3],
 }
 
 /*
@@ -39,11 +51,20 @@ pub struct Vertex {
 */
 // unsafe impl bytemuck::Pod for Vertex {}
 // unsafe impl bytemuck::Zeroable for Vertex {}
-impl Vertex {
-    const ATTRIBS: [VertexAttribute; 2] = vertex_attr_array![0 => Float32x3, 1 => Float32x2];
+
+/*
+顶点缓冲区中一个元素的布局。
+最初 Vertex 手写了 ATTRIBS 数组：vertex_attr_array![0 => Float32x3, 1 => Float32x2, ...]，每次修改字段都要同时修改 location 和格式，很容易忘记。
+现在用 #[derive(VertexLayout)] 根据字段的类型生成属性，偏移量由编译器计算，支持的类型和属性见 wgpu_01_derive。
+*/
+pub trait VertexLayout {
+    //每个字段（矩阵是每一行）对应的顶点属性
+    const ATTRIBUTES: &'static [VertexAttribute];
+    //每个顶点还是每个实例读取一次
+    const STEP_MODE: VertexStepMode;
 
     //创建顶点缓冲区布局
-    pub fn desc<'a>() -> VertexBufferLayout<'a> {
+    fn desc<'a>() -> VertexBufferLayout<'a> where Self: Sized {
         /*
         我们需要告诉 render_pipeline 在绘制时使用这个缓冲区，但首先需要告诉它如何读取此缓冲区。
         顶点缓冲区布局（VertexBufferLayout）对象和 vertex_buffers 字段可以用来完成这件事，我保证在创建 render_pipeline 时会详细讨论这个问题。
@@ -92,13 +113,25 @@ impl Vertex {
         我们可以将wgpu::VertexBufferLayout 的生命周期改为 'static，或者使其成为 const
         */
         VertexBufferLayout {
-            array_stride: std::mem::size_of::<Vertex>() as BufferAddress,
-            step_mode: VertexStepMode::Vertex,
-            attributes: &Self::ATTRIBS
+            array_stride: std::mem::size_of::<Self>() as BufferAddress,
+            step_mode: Self::STEP_MODE,
+            attributes: Self::ATTRIBUTES
         }
     }
 }
 
+/*
+带颜色的顶点
+Vertex 用纹理坐标代替了最初的 color 字段。不需要纹理的 2D 图形（见 painter 模块）仍然只要位置和颜色，
+位置是屏幕像素，颜色带 alpha 通道，以便画半透明的图形。
+*/
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable, VertexLayout)]
+pub struct ColorVertex {
+    pub position: [f32; 2],
+    pub color: [f32; 4],
+}
+
 /*
 它总共有 5 个顶点和 3 个三角形。现在，如果我们想只用顶点来显示这样的东西，我们就需要以下顶点数据：
 
@@ -123,4 +156,51 @@ const VERTICES: &[Vertex] = &[
 这，就是索引缓冲区发挥作用的地方。
 
 大体上来说，我们在 VERTICES 中存储所有唯一的顶点，我们创建另一个缓冲区，将索引存储在 VERTICES 中的元素以创建三角形。下面还是以五边形为例：
-*/
\ No newline at end of file
+*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wgpu::vertex_attr_array;
+
+    //派生的属性与之前手写的 vertex_attr_array! 完全一致
+    #[test]
+    fn derived_layouts_match_vertex_attr_array() {
+        assert_eq!(Vertex::ATTRIBUTES, vertex_attr_array![0 => Float32x3, 1 => Float32x2, 2 => Float32x3, 3 => Float32x3, 4 => Float32x3]);
+        assert_eq!(Vertex::STEP_MODE, VertexStepMode::Vertex);
+        assert_eq!(Vertex::desc().array_stride, std::mem::size_of::<Vertex>() as BufferAddress);
+
+        assert_eq!(ColorVertex::ATTRIBUTES, vertex_attr_array![0 => Float32x2, 1 => Float32x4]);
+        assert_eq!(ColorVertex::STEP_MODE, VertexStepMode::Vertex);
+    }
+
+    #[repr(C)]
+    #[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, VertexLayout)]
+    #[vertex(location = 2)]
+    struct Options {
+        color: [u8; 4],
+        #[vertex(normalized = false)]
+        ids: [u16; 2],
+        #[vertex(skip)]
+        _padding: u32,
+        #[vertex(location = 7)]
+        weight: f32,
+        transform: [[f32; 2]; 2],
+        index: [i32; 3],
+    }
+
+    #[test]
+    fn derive_options() {
+        assert_eq!(Options::ATTRIBUTES, vertex_attr_array![
+            2 => Unorm8x4,
+            3 => Uint16x2,
+            7 => Float32,
+            8 => Float32x2,
+            9 => Float32x2,
+            10 => Sint32x3,
+        ].map(|attribute| VertexAttribute {
+            //跳过的字段仍然占用空间，之后的偏移量比紧密排列的多 4 字节
+            offset: if attribute.shader_location >= 7 { attribute.offset + 4 } else { attribute.offset },
+            ..attribute
+        }));
+    }
+}
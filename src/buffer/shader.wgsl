@@ -1,26 +1,6 @@
-
-
-struct VertexInput {
-    @location(0) position: vec3f,
-    @location(1) color: vec3f
-}
-
-//顶点着色器
-struct VertexOutput {
-    @builtin(position) clip_position: vec4f,
-    @location(0) color: vec3f
-};
-
-@vertex
-fn vs_main (
-    model: VertexInput
-) -> VertexOutput {
-    var out: VertexOutput;
-    out.color = model.color;
-    out.clip_position = vec4f(model.position, 1.0);
-    return out;
-}
-
+//顶点着色器在 pipeline/triangle.wgsl 中，VERTEX_COLOR 选择从顶点缓冲区（buffer 模块的 Vertex）读取位置和纹理坐标的版本
+#define VERTEX_COLOR
+#include "pipeline/triangle.wgsl"
 
 //接下来是片元着色器。还是在 shader.wgsl 中添加以下代码：
 @fragment
@@ -0,0 +1,443 @@
+/*
+相机控制器
+控制器接收窗口事件（键盘、鼠标移动、滚轮），只记录输入的状态；真正移动相机是在 update_camera 中根据帧间隔时间完成的，
+这样相机的移动速度就与帧率无关。
+
+OrbitController：围绕目标点旋转（拖动左键）和缩放（滚轮），适合查看模型。
+FlyController：WASD 移动、空格/Shift 上升下降，拖动右键转动视角，适合在场景中漫游。
+PanZoomController：拖动左键平移、滚轮缩放，不旋转，适合查看图片和精灵。
+*/
+use std::f32::consts::FRAC_PI_2;
+use std::time::Duration;
+
+use cgmath::{InnerSpace, Rad, Vector3};
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+use winit::event::{ElementState, KeyboardInput, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent};
+
+use super::{Camera, Projection};
+
+//俯仰角不能到达正负 90 度，否则视线会与 up 向量平行，look_at 矩阵就无法计算了
+const SAFE_PITCH: f32 = FRAC_PI_2 - 0.01;
+
+pub trait CameraController {
+    //处理一个窗口事件，返回 true 表示事件已被消费
+    fn process_event(&mut self, event: &WindowEvent) -> bool;
+
+    //根据记录的输入和帧间隔时间更新相机。viewport 是渲染目标的大小（像素）
+    fn update_camera(&mut self, camera: &mut Camera, viewport: PhysicalSize<u32>, dt: Duration);
+}
+
+//记录鼠标按键和两次更新之间的移动量，三种控制器都需要
+#[derive(Default)]
+struct MouseState {
+    last_position: Option<PhysicalPosition<f64>>,
+    //按下拖动键时累计的移动量（像素）
+    drag: (f32, f32),
+    dragging: bool,
+    //累计的滚轮移动量（行）
+    scroll: f32,
+}
+
+impl MouseState {
+    fn process_event(&mut self, event: &WindowEvent, drag_button: MouseButton) -> bool {
+        match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                if let (true, Some(last)) = (self.dragging, self.last_position) {
+                    self.drag.0 += (position.x - last.x) as f32;
+                    self.drag.1 += (position.y - last.y) as f32;
+                }
+                self.last_position = Some(*position);
+                self.dragging
+            }
+            WindowEvent::MouseInput { state, button, .. } if *button == drag_button => {
+                self.dragging = *state == ElementState::Pressed;
+                true
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                self.scroll += match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    //触控板以像素为单位，大约 50 像素算作一行
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 50.0,
+                };
+                true
+            }
+            _ => false,
+        }
+    }
+
+    //取出累计的移动量并清零
+    fn take_drag(&mut self) -> (f32, f32) {
+        std::mem::take(&mut self.drag)
+    }
+
+    fn take_scroll(&mut self) -> f32 {
+        std::mem::take(&mut self.scroll)
+    }
+}
+
+//从键盘事件中取出按下/松开的键
+fn key_event(event: &WindowEvent) -> Option<(VirtualKeyCode, bool)> {
+    match event {
+        WindowEvent::KeyboardInput {
+            input: KeyboardInput {
+                state,
+                virtual_keycode: Some(keycode),
+                ..
+            },
+            ..
+        } => Some((*keycode, *state == ElementState::Pressed)),
+        _ => None,
+    }
+}
+
+//轨道（arcball）控制器：相机始终看向 target，在以 target 为中心的球面上移动
+pub struct OrbitController {
+    //每像素旋转的弧度
+    pub rotate_speed: f32,
+    //每行滚轮缩放的比例
+    pub zoom_speed: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+    mouse: MouseState,
+}
+
+impl OrbitController {
+    pub fn new() -> Self {
+        Self {
+            rotate_speed: 0.005,
+            zoom_speed: 0.1,
+            min_distance: 0.2,
+            max_distance: 100.0,
+            mouse: MouseState::default(),
+        }
+    }
+}
+
+impl Default for OrbitController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CameraController for OrbitController {
+    fn process_event(&mut self, event: &WindowEvent) -> bool {
+        self.mouse.process_event(event, MouseButton::Left)
+    }
+
+    fn update_camera(&mut self, camera: &mut Camera, _viewport: PhysicalSize<u32>, _dt: Duration) {
+        let (dx, dy) = self.mouse.take_drag();
+        let scroll = self.mouse.take_scroll();
+        if dx == 0.0 && dy == 0.0 && scroll == 0.0 {
+            return;
+        }
+
+        //把相机相对目标点的位置转换为球坐标（距离、偏航角、俯仰角）。
+        //相机与目标点重合时没有方向，从 +Z 方向看，距离之后会被限制到 min_distance
+        let offset = camera.eye - camera.target;
+        let distance = offset.magnitude();
+        let (mut yaw, mut pitch) = if distance > f32::EPSILON {
+            (offset.x.atan2(offset.z), (offset.y / distance).clamp(-1.0, 1.0).asin())
+        } else {
+            (0.0, 0.0)
+        };
+
+        //向右拖动时相机向左绕，看起来就像物体向右转
+        yaw -= dx * self.rotate_speed;
+        pitch = (pitch + dy * self.rotate_speed).clamp(-SAFE_PITCH, SAFE_PITCH);
+        let distance = (distance * (1.0 - scroll * self.zoom_speed)).clamp(self.min_distance, self.max_distance);
+
+        camera.eye = camera.target + Vector3::new(
+            distance * pitch.cos() * yaw.sin(),
+            distance * pitch.sin(),
+            distance * pitch.cos() * yaw.cos(),
+        );
+    }
+}
+
+//飞行控制器：像第一人称游戏一样移动和转动视角
+pub struct FlyController {
+    //每秒移动的距离
+    pub speed: f32,
+    //每像素转动的弧度
+    pub sensitivity: f32,
+    forward: f32,
+    backward: f32,
+    left: f32,
+    right: f32,
+    up: f32,
+    down: f32,
+    //上一次的视线方向，相机与目标点重合时沿用它
+    direction: Vector3<f32>,
+    mouse: MouseState,
+}
+
+impl FlyController {
+    pub fn new(speed: f32) -> Self {
+        Self {
+            speed,
+            sensitivity: 0.003,
+            forward: 0.0,
+            backward: 0.0,
+            left: 0.0,
+            right: 0.0,
+            up: 0.0,
+            down: 0.0,
+            direction: -Vector3::unit_z(),
+            mouse: MouseState::default(),
+        }
+    }
+}
+
+impl Default for FlyController {
+    fn default() -> Self {
+        Self::new(2.0)
+    }
+}
+
+impl CameraController for FlyController {
+    fn process_event(&mut self, event: &WindowEvent) -> bool {
+        if let Some((keycode, pressed)) = key_event(event) {
+            let amount = if pressed { 1.0 } else { 0.0 };
+            match keycode {
+                VirtualKeyCode::W | VirtualKeyCode::Up => self.forward = amount,
+                VirtualKeyCode::S | VirtualKeyCode::Down => self.backward = amount,
+                VirtualKeyCode::A | VirtualKeyCode::Left => self.left = amount,
+                VirtualKeyCode::D | VirtualKeyCode::Right => self.right = amount,
+                VirtualKeyCode::Space => self.up = amount,
+                VirtualKeyCode::LShift => self.down = amount,
+                _ => return false,
+            }
+            return true;
+        }
+        self.mouse.process_event(event, MouseButton::Right)
+    }
+
+    fn update_camera(&mut self, camera: &mut Camera, _viewport: PhysicalSize<u32>, dt: Duration) {
+        let dt = dt.as_secs_f32();
+
+        //由视线方向求出偏航角和俯仰角，加上鼠标的转动量后再转换回方向向量
+        let (dx, dy) = self.mouse.take_drag();
+        let offset = camera.target - camera.eye;
+        let direction = if offset.magnitude2() > f32::EPSILON { offset.normalize() } else { self.direction };
+        let yaw = Rad(direction.z.atan2(direction.x) + dx * self.sensitivity);
+        let pitch = Rad((direction.y.asin() - dy * self.sensitivity).clamp(-SAFE_PITCH, SAFE_PITCH));
+        let forward = Vector3::new(pitch.0.cos() * yaw.0.cos(), pitch.0.sin(), pitch.0.cos() * yaw.0.sin());
+        self.direction = forward;
+        let right = forward.cross(camera.up).normalize();
+
+        let mut movement = forward * (self.forward - self.backward)
+            + right * (self.right - self.left)
+            + camera.up * (self.up - self.down);
+        if movement.magnitude2() > 0.0 {
+            movement = movement.normalize() * self.speed * dt;
+        }
+
+        //滚轮沿视线方向前进或后退
+        movement += forward * self.mouse.take_scroll() * self.speed * 0.1;
+
+        camera.eye += movement;
+        camera.target = camera.eye + forward;
+    }
+}
+
+//二维平移缩放控制器：相机不旋转，拖动时画面跟随鼠标移动
+pub struct PanZoomController {
+    //每行滚轮缩放的比例
+    pub zoom_speed: f32,
+    mouse: MouseState,
+}
+
+impl PanZoomController {
+    pub fn new() -> Self {
+        Self {
+            zoom_speed: 0.1,
+            mouse: MouseState::default(),
+        }
+    }
+}
+
+impl Default for PanZoomController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CameraController for PanZoomController {
+    fn process_event(&mut self, event: &WindowEvent) -> bool {
+        self.mouse.process_event(event, MouseButton::Left)
+    }
+
+    fn update_camera(&mut self, camera: &mut Camera, viewport: PhysicalSize<u32>, _dt: Duration) {
+        let (dx, dy) = self.mouse.take_drag();
+        let scroll = self.mouse.take_scroll();
+        let zoom = (1.0 - scroll * self.zoom_speed).max(0.1);
+
+        //一个像素对应的世界空间距离：正交投影由可见范围决定，透视投影由目标点处的可见高度决定
+        let distance = (camera.target - camera.eye).magnitude();
+        let visible_height = match camera.projection {
+            Projection::Orthographic { bottom, top, .. } => top - bottom,
+            Projection::Perspective { fovy, .. } => 2.0 * distance * (fovy.0 / 2.0).tan(),
+        };
+        let world_per_pixel = visible_height / viewport.height.max(1) as f32;
+
+        //鼠标向右拖动时画面向右移动，也就是相机向左移动；屏幕的 y 轴向下，世界的 y 轴向上
+        let direction = (camera.target - camera.eye).normalize();
+        let right = direction.cross(camera.up).normalize();
+        let up = right.cross(direction);
+        let pan = right * (-dx * world_per_pixel) + up * (dy * world_per_pixel);
+        camera.eye += pan;
+        camera.target += pan;
+
+        if zoom != 1.0 {
+            match &mut camera.projection {
+                //正交投影缩放可见范围
+                Projection::Orthographic { left, right, bottom, top, .. } => {
+                    let center_x = (*left + *right) / 2.0;
+                    let center_y = (*bottom + *top) / 2.0;
+                    *left = center_x + (*left - center_x) * zoom;
+                    *right = center_x + (*right - center_x) * zoom;
+                    *bottom = center_y + (*bottom - center_y) * zoom;
+                    *top = center_y + (*top - center_y) * zoom;
+                }
+                //透视投影沿视线方向靠近或远离目标点
+                Projection::Perspective { znear, .. } => {
+                    let distance = (distance * zoom).max(*znear * 2.0);
+                    camera.eye = camera.target - direction * distance;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::Point3;
+    use winit::event::{DeviceId, ModifiersState, TouchPhase};
+
+    const VIEWPORT: PhysicalSize<u32> = PhysicalSize::new(800, 600);
+
+    fn device_id() -> DeviceId {
+        //只用于构造事件，控制器不使用它
+        unsafe { DeviceId::dummy() }
+    }
+
+    #[allow(deprecated)]
+    fn cursor(x: f64, y: f64) -> WindowEvent<'static> {
+        WindowEvent::CursorMoved {
+            device_id: device_id(),
+            position: PhysicalPosition::new(x, y),
+            modifiers: ModifiersState::empty(),
+        }
+    }
+
+    #[allow(deprecated)]
+    fn button(button: MouseButton, pressed: bool) -> WindowEvent<'static> {
+        WindowEvent::MouseInput {
+            device_id: device_id(),
+            state: if pressed { ElementState::Pressed } else { ElementState::Released },
+            button,
+            modifiers: ModifiersState::empty(),
+        }
+    }
+
+    #[allow(deprecated)]
+    fn scroll(lines: f32) -> WindowEvent<'static> {
+        WindowEvent::MouseWheel {
+            device_id: device_id(),
+            delta: MouseScrollDelta::LineDelta(0.0, lines),
+            phase: TouchPhase::Moved,
+            modifiers: ModifiersState::empty(),
+        }
+    }
+
+    #[allow(deprecated)]
+    fn key(keycode: VirtualKeyCode, pressed: bool) -> WindowEvent<'static> {
+        WindowEvent::KeyboardInput {
+            device_id: device_id(),
+            input: KeyboardInput {
+                scancode: 0,
+                state: if pressed { ElementState::Pressed } else { ElementState::Released },
+                virtual_keycode: Some(keycode),
+                modifiers: ModifiersState::empty(),
+            },
+            is_synthetic: false,
+        }
+    }
+
+    //按住 drag_button 从 (0, 0) 拖动到 (dx, dy)
+    fn drag(controller: &mut dyn CameraController, drag_button: MouseButton, dx: f64, dy: f64) {
+        for event in [cursor(0.0, 0.0), button(drag_button, true), cursor(dx, dy), button(drag_button, false)] {
+            controller.process_event(&event);
+        }
+    }
+
+    fn assert_close(actual: Point3<f32>, expected: Point3<f32>) {
+        assert!((actual - expected).magnitude() < 1e-4, "{:?} != {:?}", actual, expected);
+    }
+
+    fn assert_finite(camera: &Camera) {
+        let [eye, target]: [[f32; 3]; 2] = [camera.eye.into(), camera.target.into()];
+        assert!(eye.iter().chain(&target).all(|c| c.is_finite()), "{:?} {:?}", camera.eye, camera.target);
+    }
+
+    #[test]
+    fn orbit_rotates_and_zooms_around_the_target() {
+        let mut camera = Camera::perspective(Point3::new(0.0, 0.0, 2.0), 1.0);
+        let mut controller = OrbitController::new();
+
+        //向右拖动 100 像素，偏航角减少 0.5 弧度
+        drag(&mut controller, MouseButton::Left, 100.0, 0.0);
+        controller.update_camera(&mut camera, VIEWPORT, Duration::from_millis(16));
+        assert_close(camera.eye, Point3::new(2.0 * (-0.5f32).sin(), 0.0, 2.0 * 0.5f32.cos()));
+        assert_close(camera.target, Point3::new(0.0, 0.0, 0.0));
+
+        //向前滚动一行，距离缩短 10%
+        controller.process_event(&scroll(1.0));
+        controller.update_camera(&mut camera, VIEWPORT, Duration::from_millis(16));
+        assert!(((camera.eye - camera.target).magnitude() - 1.8).abs() < 1e-4);
+    }
+
+    #[test]
+    fn orbit_with_the_eye_at_the_target() {
+        let mut camera = Camera::perspective(Point3::new(0.0, 0.0, 0.0), 1.0);
+        let mut controller = OrbitController::new();
+        drag(&mut controller, MouseButton::Left, 0.0, 10.0);
+        controller.update_camera(&mut camera, VIEWPORT, Duration::from_millis(16));
+        assert_finite(&camera);
+        assert!(((camera.eye - camera.target).magnitude() - controller.min_distance).abs() < 1e-4);
+    }
+
+    #[test]
+    fn fly_moves_along_the_view_direction() {
+        let mut camera = Camera::perspective(Point3::new(0.0, 0.0, 2.0), 1.0);
+        let mut controller = FlyController::new(2.0);
+
+        //按住 W 半秒，以每秒 2 的速度前进 1
+        controller.process_event(&key(VirtualKeyCode::W, true));
+        controller.update_camera(&mut camera, VIEWPORT, Duration::from_millis(500));
+        assert_close(camera.eye, Point3::new(0.0, 0.0, 1.0));
+        assert_close(camera.target, Point3::new(0.0, 0.0, 0.0));
+
+        //松开后按 D 向右平移
+        controller.process_event(&key(VirtualKeyCode::W, false));
+        controller.process_event(&key(VirtualKeyCode::D, true));
+        controller.update_camera(&mut camera, VIEWPORT, Duration::from_millis(500));
+        assert_close(camera.eye, Point3::new(1.0, 0.0, 1.0));
+        assert_close(camera.target, Point3::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn fly_with_the_eye_at_the_target_keeps_the_previous_direction() {
+        let mut camera = Camera::perspective(Point3::new(0.0, 0.0, 2.0), 1.0);
+        let mut controller = FlyController::new(2.0);
+        controller.update_camera(&mut camera, VIEWPORT, Duration::from_millis(16));
+
+        camera.target = camera.eye;
+        controller.process_event(&key(VirtualKeyCode::W, true));
+        controller.update_camera(&mut camera, VIEWPORT, Duration::from_millis(500));
+        assert_finite(&camera);
+        assert_close(camera.eye, Point3::new(0.0, 0.0, 1.0));
+        assert_close(camera.target, Point3::new(0.0, 0.0, 0.0));
+    }
+}
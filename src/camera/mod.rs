@@ -0,0 +1,270 @@
+/*
+相机
+到目前为止，顶点着色器直接把顶点位置当作裁剪空间坐标来使用，所以没法从别的角度观察 3D 场景。
+相机由两部分组成：
+视图矩阵（view）把世界坐标变换到相机坐标，由相机的位置（eye）、观察的目标点（target）和向上的方向（up）决定。
+投影矩阵（projection）把相机坐标变换到裁剪空间，透视投影有近大远小的效果，正交投影没有。
+
+两个矩阵相乘得到视图投影矩阵（view_proj），放在统一缓冲区（Uniform Buffer）中交给着色器使用。
+*/
+pub mod controller;
+
+use cgmath::{Matrix4, Point3, Rad, SquareMatrix, Vector3};
+use wgpu::util::DeviceExt;
+use wgpu::{BindGroup, BindGroupLayout, Buffer, BufferUsages, Device, Queue, ShaderStages};
+
+/*
+cgmath 是为 OpenGL 的坐标系设计的，OpenGL 裁剪空间的 z 在 -1.0 到 1.0 之间，而 wgpu 的在 0.0 到 1.0 之间。
+这个矩阵把 OpenGL 的裁剪空间转换到 wgpu 的裁剪空间。
+*/
+#[rustfmt::skip]
+pub const OPENGL_TO_WGPU_MATRIX: Matrix4<f32> = Matrix4::new(
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, 0.5, 0.0,
+    0.0, 0.0, 0.5, 1.0,
+);
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Projection {
+    //透视投影：fovy 是垂直方向的视野角度
+    Perspective {
+        fovy: Rad<f32>,
+        znear: f32,
+        zfar: f32,
+    },
+    //正交投影：相机坐标系中可见的范围
+    Orthographic {
+        left: f32,
+        right: f32,
+        bottom: f32,
+        top: f32,
+        znear: f32,
+        zfar: f32,
+    },
+}
+
+pub struct Camera {
+    pub eye: Point3<f32>,
+    pub target: Point3<f32>,
+    pub up: Vector3<f32>,
+    //宽高比，随渲染目标的大小变化
+    pub aspect: f32,
+    pub projection: Projection,
+}
+
+impl Camera {
+    pub fn new(eye: Point3<f32>, target: Point3<f32>, aspect: f32, projection: Projection) -> Self {
+        Self {
+            eye,
+            target,
+            up: Vector3::unit_y(),
+            aspect,
+            projection,
+        }
+    }
+
+    //看向原点的透视相机
+    pub fn perspective(eye: Point3<f32>, aspect: f32) -> Self {
+        Self::new(eye, Point3::new(0.0, 0.0, 0.0), aspect, Projection::Perspective {
+            fovy: cgmath::Deg(45.0).into(),
+            znear: 0.1,
+            zfar: 100.0,
+        })
+    }
+
+    /*
+    渲染目标大小改变时更新宽高比。
+    透视投影直接使用宽高比；正交投影保持垂直方向的可见范围不变，以中心为基准重新计算水平方向的范围，这样画面不会被拉伸。
+    */
+    pub fn set_aspect(&mut self, aspect: f32) {
+        self.aspect = aspect;
+        if let Projection::Orthographic { left, right, bottom, top, .. } = &mut self.projection {
+            let center = (*left + *right) / 2.0;
+            let half_width = (*top - *bottom) / 2.0 * aspect;
+            *left = center - half_width;
+            *right = center + half_width;
+        }
+    }
+
+    pub fn build_view_matrix(&self) -> Matrix4<f32> {
+        Matrix4::look_at_rh(self.eye, self.target, self.up)
+    }
+
+    pub fn build_projection_matrix(&self) -> Matrix4<f32> {
+        let projection = match self.projection {
+            Projection::Perspective { fovy, znear, zfar } => cgmath::perspective(fovy, self.aspect, znear, zfar),
+            Projection::Orthographic { left, right, bottom, top, znear, zfar } => cgmath::ortho(left, right, bottom, top, znear, zfar),
+        };
+        OPENGL_TO_WGPU_MATRIX * projection
+    }
+
+    pub fn build_view_projection_matrix(&self) -> Matrix4<f32> {
+        self.build_projection_matrix() * self.build_view_matrix()
+    }
+}
+
+/*
+着色器中的相机数据。
+我们不能直接把 cgmath 的矩阵交给 bytemuck，所以把它转换成 4x4 的 f32 数组。
+view_position 在光照计算中会用到；使用 vec4 是因为统一缓冲区要求 16 字节对齐。
+inv_proj 和 inv_view 是投影矩阵和视图矩阵的逆矩阵，用来把屏幕上的点还原成世界空间中的方向（天空盒）。
+*/
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct CameraUniform {
+    pub view_position: [f32; 4],
+    pub view_proj: [[f32; 4]; 4],
+    pub inv_proj: [[f32; 4]; 4],
+    pub inv_view: [[f32; 4]; 4],
+}
+
+impl Default for CameraUniform {
+    fn default() -> Self {
+        Self {
+            view_position: [0.0; 4],
+            view_proj: Matrix4::identity().into(),
+            inv_proj: Matrix4::identity().into(),
+            inv_view: Matrix4::identity().into(),
+        }
+    }
+}
+
+impl CameraUniform {
+    pub fn update_view_proj(&mut self, camera: &Camera) {
+        self.view_position = camera.eye.to_homogeneous().into();
+        self.view_proj = camera.build_view_projection_matrix().into();
+        //相机参数无效（例如宽高比为 0）时矩阵不可逆，这时保持单位矩阵
+        self.inv_proj = camera.build_projection_matrix().invert().unwrap_or_else(Matrix4::identity).into();
+        self.inv_view = camera.build_view_matrix().invert().unwrap_or_else(Matrix4::identity).into();
+    }
+}
+
+//相机的统一缓冲区与它的绑定组。着色器中对应 @group(1) @binding(0) var<uniform> camera: CameraUniform;
+pub struct CameraBinding {
+    pub uniform: CameraUniform,
+    pub buffer: Buffer,
+    pub bind_group_layout: BindGroupLayout,
+    pub bind_group: BindGroup,
+}
+
+impl CameraBinding {
+    //绑定组布局的条目，其他管线用 PipelineLayoutBuilder::bind_group_layout 共享这个布局时用来和着色器比较。
+    //顶点着色器需要视图投影矩阵，片元着色器的光照计算需要相机的位置
+    pub const LAYOUT_ENTRIES: &'static [wgpu::BindGroupLayoutEntry] = &[
+        wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: ShaderStages::VERTEX.union(ShaderStages::FRAGMENT),
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        },
+    ];
+
+    pub fn new(device: &Device, camera: &Camera) -> Self {
+        let mut uniform = CameraUniform::default();
+        uniform.update_view_proj(camera);
+
+        //COPY_DST 让我们可以在每一帧用 queue.write_buffer 更新缓冲区
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Camera Buffer"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: Self::LAYOUT_ENTRIES,
+            label: Some("camera_bind_group_layout"),
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("camera_bind_group"),
+        });
+
+        Self {
+            uniform,
+            buffer,
+            bind_group_layout,
+            bind_group,
+        }
+    }
+
+    //把相机的最新状态写入统一缓冲区
+    pub fn update(&mut self, queue: &Queue, camera: &Camera) {
+        self.uniform.update_view_proj(camera);
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.uniform]));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{InnerSpace, Vector4};
+
+    use super::*;
+
+    fn ortho(left: f32, right: f32) -> Projection {
+        Projection::Orthographic { left, right, bottom: -1.0, top: 1.0, znear: 1.0, zfar: 9.0 }
+    }
+
+    //变换到 NDC
+    fn project(camera: &Camera, point: Point3<f32>) -> Vector3<f32> {
+        let clip = camera.build_view_projection_matrix() * Vector4::new(point.x, point.y, point.z, 1.0);
+        clip.truncate() / clip.w
+    }
+
+    fn assert_near(actual: Vector3<f32>, expected: Vector3<f32>) {
+        assert!((actual - expected).magnitude2() < 1e-10, "{:?} != {:?}", actual, expected);
+    }
+
+    #[test]
+    fn orthographic_extent_maps_to_ndc_edges() {
+        let camera = Camera::new(Point3::new(0.0, 0.0, 5.0), Point3::new(0.0, 0.0, 0.0), 2.0, ortho(-2.0, 2.0));
+        //wgpu 的 z 从近平面的 0 到远平面的 1
+        assert_near(project(&camera, Point3::new(2.0, 1.0, 4.0)), Vector3::new(1.0, 1.0, 0.0));
+        assert_near(project(&camera, Point3::new(-2.0, -1.0, -4.0)), Vector3::new(-1.0, -1.0, 1.0));
+        assert_near(project(&camera, Point3::new(0.0, 0.0, 0.0)), Vector3::new(0.0, 0.0, 0.5));
+        //正交投影没有近大远小
+        assert_near(project(&camera, Point3::new(1.0, 0.5, 3.0)), Vector3::new(0.5, 0.5, 0.125));
+        assert_near(project(&camera, Point3::new(1.0, 0.5, -3.0)), Vector3::new(0.5, 0.5, 0.875));
+    }
+
+    #[test]
+    fn perspective_depth_range() {
+        let camera = Camera::perspective(Point3::new(0.0, 0.0, 5.0), 1.5);
+        assert!(project(&camera, Point3::new(0.0, 0.0, 4.9)).z.abs() < 1e-4);
+        assert!((project(&camera, Point3::new(0.0, 0.0, -95.0)).z - 1.0).abs() < 1e-4);
+        //越远越靠近画面中心
+        let near = project(&camera, Point3::new(1.0, 0.0, 0.0));
+        let far = project(&camera, Point3::new(1.0, 0.0, -10.0));
+        assert!(near.x > far.x && far.x > 0.0);
+    }
+
+    #[test]
+    fn set_aspect_keeps_the_vertical_extent() {
+        let mut camera = Camera::new(Point3::new(0.0, 0.0, 5.0), Point3::new(0.0, 0.0, 0.0), 2.0, ortho(0.0, 4.0));
+        camera.set_aspect(1.0);
+        assert_eq!(camera.aspect, 1.0);
+        assert_eq!(camera.projection, ortho(1.0, 3.0));
+        camera.set_aspect(4.0);
+        assert_eq!(camera.projection, ortho(-2.0, 6.0));
+        assert_near(project(&camera, Point3::new(6.0, 1.0, 0.0)), Vector3::new(1.0, 1.0, 0.5));
+
+        //透视投影的水平缩放是垂直缩放除以宽高比
+        let mut camera = Camera::perspective(Point3::new(0.0, 0.0, 5.0), 1.0);
+        let square = camera.build_projection_matrix();
+        camera.set_aspect(2.0);
+        let wide = camera.build_projection_matrix();
+        assert!((wide.x.x - square.x.x / 2.0).abs() < 1e-6);
+        assert_eq!(wide.y.y, square.y.y);
+    }
+}
@@ -0,0 +1,188 @@
+//计算着色器使用的缓冲区，以及把结果异步读回 CPU 的 Readback
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use bytemuck::Pod;
+use wgpu::{Buffer, BufferAsyncError, BufferUsages, CommandEncoderDescriptor, Device, MapMode, Queue};
+use wgpu::util::DeviceExt;
+
+use super::ComputeError;
+
+//存储缓冲区：着色器中的 var<storage> 数组，元素类型为 T
+pub struct StorageBuffer<T> {
+    buffer: Buffer,
+    len: usize,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Pod> StorageBuffer<T> {
+    //用初始数据创建。计算的结果可以直接作为顶点缓冲区绘制，也可以复制出来读回
+    pub fn new(device: &Device, label: &str, data: &[T]) -> Self {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(label),
+            contents: bytemuck::cast_slice(data),
+            usage: Self::usage(),
+        });
+        Self {
+            buffer,
+            len: data.len(),
+            _marker: PhantomData,
+        }
+    }
+
+    //创建 len 个元素、内容全为 0 的缓冲区，通常用来保存输出
+    pub fn zeroed(device: &Device, label: &str, len: usize) -> Self {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size: (len * std::mem::size_of::<T>()) as u64,
+            usage: Self::usage(),
+            mapped_at_creation: false,
+        });
+        Self {
+            buffer,
+            len,
+            _marker: PhantomData,
+        }
+    }
+
+    fn usage() -> BufferUsages {
+        BufferUsages::STORAGE | BufferUsages::VERTEX | BufferUsages::COPY_DST | BufferUsages::COPY_SRC
+    }
+
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    //从第 offset 个元素开始覆盖数据，超出缓冲区的部分会被忽略
+    pub fn write(&self, queue: &Queue, offset: usize, data: &[T]) {
+        let count = data.len().min(self.len.saturating_sub(offset));
+        if count > 0 {
+            queue.write_buffer(&self.buffer, (offset * std::mem::size_of::<T>()) as u64, bytemuck::cast_slice(&data[..count]));
+        }
+    }
+
+    //把缓冲区复制到一个 CPU 可以映射的缓冲区并提交，返回的 Readback 完成后得到所有元素
+    pub fn read<'a>(&self, device: &'a Device, queue: &Queue) -> Readback<'a, T> {
+        let size = (self.len * std::mem::size_of::<T>()) as u64;
+        let staging = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Readback Buffer"),
+            size,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("Readback Encoder")
+        });
+        encoder.copy_buffer_to_buffer(&self.buffer, 0, &staging, 0, size);
+        queue.submit(std::iter::once(encoder.finish()));
+        Readback::new(device, staging)
+    }
+}
+
+//统一缓冲区：着色器中的 var<uniform>，保存一个 T，例如问题的规模、时间步长等参数
+pub struct UniformBuffer<T> {
+    buffer: Buffer,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Pod> UniformBuffer<T> {
+    pub fn new(device: &Device, label: &str, value: &T) -> Self {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(label),
+            contents: bytemuck::bytes_of(value),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        Self {
+            buffer,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+
+    pub fn write(&self, queue: &Queue, value: &T) {
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(value));
+    }
+}
+
+//映射的结果和等待它的任务，由 map_async 的回调填入
+#[derive(Default)]
+struct MapState {
+    result: Option<Result<(), BufferAsyncError>>,
+    waker: Option<Waker>,
+}
+
+/*
+异步读回
+映射缓冲区是异步的：map_async 的回调要等 GPU 执行完之前提交的命令才会被调用。
+Readback 实现了 Future，可以在 async 函数中 await，或者用 pollster::block_on 等待。
+在浏览器中回调由事件循环触发；原生平台需要调用 device.poll 才会触发回调，所以在结果还没有准备好时 Readback 会等待设备完成。
+*/
+pub struct Readback<'a, T> {
+    device: &'a Device,
+    buffer: Buffer,
+    state: Arc<Mutex<MapState>>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<'a, T: Pod> Readback<'a, T> {
+    fn new(device: &'a Device, buffer: Buffer) -> Self {
+        let state = Arc::new(Mutex::new(MapState::default()));
+        let callback_state = state.clone();
+        buffer.slice(..).map_async(MapMode::Read, move |result| {
+            let mut state = callback_state.lock().unwrap();
+            state.result = Some(result);
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        });
+        Self {
+            device,
+            buffer,
+            state,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: Pod> Future for Readback<'_, T> {
+    type Output = Result<Vec<T>, ComputeError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.state.lock().unwrap().result.is_none() {
+            self.device.poll(wgpu::Maintain::Wait);
+        }
+
+        let mut state = self.state.lock().unwrap();
+        match state.result.take() {
+            Some(Ok(())) => {
+                drop(state);
+                let data = {
+                    let view = self.buffer.slice(..).get_mapped_range();
+                    bytemuck::cast_slice(&view).to_vec()
+                };
+                self.buffer.unmap();
+                Poll::Ready(Ok(data))
+            }
+            Some(Err(e)) => Poll::Ready(Err(e.into())),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
@@ -0,0 +1,13 @@
+//把存储缓冲区中的每个数乘以 2，模块文档中的例子和测试使用
+
+@group(0) @binding(0)
+var<storage, read_write> data: array<f32>;
+
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3u) {
+    //最后一个工作组可能有多出来的调用
+    if id.x >= arrayLength(&data) {
+        return;
+    }
+    data[id.x] = data[id.x] * 2.0;
+}
@@ -0,0 +1,228 @@
+/*
+计算着色器
+计算着色器不画任何东西，只是在 GPU 上并行地执行一段程序，适合粒子模拟、图像处理这类每个元素互相独立的大量计算。
+
+计算任务按工作组（workgroup）调度：着色器用 @workgroup_size(x, y, z) 声明一个工作组有多少个调用（invocation），
+dispatch_workgroups 指定调度多少个工作组。Kernel 从 WGSL 中读出工作组大小，根据问题的规模（要处理多少个元素）算出工作组的数量，
+最后一个工作组可能有多出来的调用，着色器需要用 global_invocation_id 判断是否越界。
+
+数据通过绑定组传给着色器：var<storage, read> / var<storage, read_write> 是存储缓冲区，var<uniform> 是统一缓冲区，见 buffer 子模块。
+结果留在存储缓冲区中，可以直接作为顶点缓冲区绘制，也可以用 StorageBuffer::read 读回 CPU。
+
+计算不需要展示平面，用 headless::request_device 创建的设备（包括 fallback 适配器）就可以运行：
+
+    let (device, queue) = pollster::block_on(headless::request_device());
+    let kernel = Kernel::new(&device, include_str!("double.wgsl"), "cs_main")?;
+    let data = StorageBuffer::new(&device, "data", &[1.0f32, 2.0, 3.0]);
+    let bind_group = kernel.bind_group(&device, 0, &[data.buffer()]);
+    kernel.run(&device, &queue, &[&bind_group], [data.len() as u32, 1, 1])?;
+    let result = pollster::block_on(data.read(&device, &queue))?;
+*/
+pub mod buffer;
+
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
+
+use wgpu::{BindGroup, BindGroupEntry, BufferAsyncError, CommandEncoder, CommandEncoderDescriptor, ComputePassDescriptor, ComputePipeline, Device, Queue, ShaderModuleDescriptor, ShaderSource};
+
+use crate::pipeline::ComputePipelineBuilder;
+
+pub use buffer::{Readback, StorageBuffer, UniformBuffer};
+
+#[derive(Debug)]
+pub enum ComputeError {
+    //WGSL 解析失败，内容是带有源码位置的错误信息
+    Parse(String),
+    //着色器中没有这个名字的计算入口点
+    EntryPoint(String),
+    //某一维的工作组数量超过了设备的限制
+    TooManyWorkgroups { count: [u32; 3], max: u32 },
+    //读回时映射缓冲区失败
+    Map(BufferAsyncError),
+}
+
+impl fmt::Display for ComputeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ComputeError::Parse(message) => write!(f, "计算着色器解析失败:\n{}", message),
+            ComputeError::EntryPoint(name) => write!(f, "计算着色器中没有名为 {} 的计算入口点", name),
+            ComputeError::TooManyWorkgroups { count, max } => write!(f, "工作组数量 {:?} 超过了设备的限制（每一维最多 {}）", count, max),
+            ComputeError::Map(e) => write!(f, "无法映射读回缓冲区: {}", e),
+        }
+    }
+}
+
+impl Error for ComputeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ComputeError::Map(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<BufferAsyncError> for ComputeError {
+    fn from(e: BufferAsyncError) -> Self {
+        ComputeError::Map(e)
+    }
+}
+
+//覆盖 problem_size 个元素需要的工作组数量（向上取整）
+pub fn workgroup_count(problem_size: [u32; 3], workgroup_size: [u32; 3]) -> [u32; 3] {
+    [
+        problem_size[0].div_ceil(workgroup_size[0].max(1)),
+        problem_size[1].div_ceil(workgroup_size[1].max(1)),
+        problem_size[2].div_ceil(workgroup_size[2].max(1)),
+    ]
+}
+
+//一个计算入口点和它的管线。绑定组布局由 wgpu 根据着色器推导
+pub struct Kernel {
+    pipeline: ComputePipeline,
+    workgroup_size: [u32; 3],
+    max_workgroups: u32,
+}
+
+impl Kernel {
+    //从 WGSL 源码创建。先用 naga 解析一遍，这样语法错误会作为 Err 返回，而不是让 wgpu 直接 panic
+    pub fn new(device: &Device, source: &str, entry: &str) -> Result<Self, ComputeError> {
+        let module = naga::front::wgsl::parse_str(source)
+            .map_err(|e| ComputeError::Parse(e.emit_to_string(source)))?;
+        let workgroup_size = module.entry_points.iter()
+            .find(|ep| ep.name == entry && ep.stage == naga::ShaderStage::Compute)
+            .map(|ep| ep.workgroup_size)
+            .ok_or_else(|| ComputeError::EntryPoint(entry.to_string()))?;
+
+        let shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some(entry),
+            source: ShaderSource::Wgsl(Cow::Borrowed(source)),
+        });
+        let pipeline = ComputePipelineBuilder::new(&shader)
+            .label(entry)
+            .entry_point(entry)
+            .build(device);
+
+        Ok(Self {
+            pipeline,
+            workgroup_size,
+            max_workgroups: device.limits().max_compute_workgroups_per_dimension,
+        })
+    }
+
+    pub fn pipeline(&self) -> &ComputePipeline {
+        &self.pipeline
+    }
+
+    //着色器中 @workgroup_size 声明的大小
+    pub fn workgroup_size(&self) -> [u32; 3] {
+        self.workgroup_size
+    }
+
+    pub fn workgroup_count(&self, problem_size: [u32; 3]) -> [u32; 3] {
+        workgroup_count(problem_size, self.workgroup_size)
+    }
+
+    //为第 group 个绑定组创建绑定组，buffers 依次对应 @binding(0)、@binding(1)...
+    pub fn bind_group(&self, device: &Device, group: u32, buffers: &[&wgpu::Buffer]) -> BindGroup {
+        let entries: Vec<BindGroupEntry> = buffers.iter()
+            .enumerate()
+            .map(|(binding, buffer)| BindGroupEntry {
+                binding: binding as u32,
+                resource: buffer.as_entire_binding(),
+            })
+            .collect();
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Compute Bind Group"),
+            layout: &self.pipeline.get_bind_group_layout(group),
+            entries: &entries,
+        })
+    }
+
+    //在 encoder 中记录一次计算，bind_groups 依次对应 @group(0)、@group(1)...
+    pub fn dispatch(&self, encoder: &mut CommandEncoder, bind_groups: &[&BindGroup], problem_size: [u32; 3]) -> Result<(), ComputeError> {
+        let count = self.workgroup_count(problem_size);
+        if count.iter().any(|&n| n > self.max_workgroups) {
+            return Err(ComputeError::TooManyWorkgroups { count, max: self.max_workgroups });
+        }
+        if count.contains(&0) {
+            return Ok(());
+        }
+
+        let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("Compute Pass"),
+        });
+        compute_pass.set_pipeline(&self.pipeline);
+        for (index, bind_group) in bind_groups.iter().enumerate() {
+            compute_pass.set_bind_group(index as u32, bind_group, &[]);
+        }
+        compute_pass.dispatch_workgroups(count[0], count[1], count[2]);
+        Ok(())
+    }
+
+    //记录一次计算并立即提交
+    pub fn run(&self, device: &Device, queue: &Queue, bind_groups: &[&BindGroup], problem_size: [u32; 3]) -> Result<(), ComputeError> {
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("Compute Encoder")
+        });
+        self.dispatch(&mut encoder, bind_groups, problem_size)?;
+        queue.submit(std::iter::once(encoder.finish()));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headless;
+
+    #[test]
+    fn workgroup_count_rounds_up() {
+        assert_eq!(workgroup_count([100, 1, 1], [64, 1, 1]), [2, 1, 1]);
+        assert_eq!(workgroup_count([128, 9, 1], [64, 8, 1]), [2, 2, 1]);
+        //某一维为 0 时没有要调度的工作组
+        assert_eq!(workgroup_count([0, 1, 1], [64, 1, 1]), [0, 1, 1]);
+        //@workgroup_size 不会是 0，但也不能除以 0
+        assert_eq!(workgroup_count([5, 1, 1], [0, 1, 1]), [5, 1, 1]);
+    }
+
+    #[test]
+    fn double_kernel() {
+        let (device, queue) = pollster::block_on(headless::request_device());
+        let kernel = Kernel::new(&device, include_str!("double.wgsl"), "cs_main").unwrap();
+        assert_eq!(kernel.workgroup_size(), [64, 1, 1]);
+
+        //100 个元素需要 2 个工作组，第二个工作组有一部分调用越界
+        let input: Vec<f32> = (0..100).map(|i| i as f32).collect();
+        let data = StorageBuffer::new(&device, "data", &input);
+        let bind_group = kernel.bind_group(&device, 0, &[data.buffer()]);
+        kernel.run(&device, &queue, &[&bind_group], [data.len() as u32, 1, 1]).unwrap();
+
+        let result = pollster::block_on(data.read(&device, &queue)).unwrap();
+        let expected: Vec<f32> = input.iter().map(|x| x * 2.0).collect();
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn parse_error() {
+        let (device, _queue) = pollster::block_on(headless::request_device());
+        let source = "@compute @workgroup_size(1)\nfn cs_main() {\n    let x = ;\n}\n";
+        match Kernel::new(&device, source, "cs_main") {
+            //错误信息带有出错的源码行
+            Err(ComputeError::Parse(message)) => assert!(message.contains("let x = ;"), "{}", message),
+            Err(e) => panic!("应该是解析错误: {}", e),
+            Ok(_) => panic!("应该是解析错误"),
+        }
+    }
+
+    #[test]
+    fn missing_entry_point() {
+        let (device, _queue) = pollster::block_on(headless::request_device());
+        let source = include_str!("double.wgsl");
+        assert!(matches!(Kernel::new(&device, source, "main"), Err(ComputeError::EntryPoint(name)) if name == "main"));
+
+        //同名的顶点入口点不是计算入口点
+        let source = "@vertex\nfn cs_main() -> @builtin(position) vec4f {\n    return vec4f(0.0);\n}\n";
+        assert!(matches!(Kernel::new(&device, source, "cs_main"), Err(ComputeError::EntryPoint(_))));
+    }
+}
@@ -0,0 +1,29 @@
+
+//把深度缓冲区显示为灰度图：近处（深度小）为黑色，远处为白色
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4f
+};
+
+//用 3 个顶点画一个覆盖整个屏幕的大三角形
+@vertex
+fn vs_main(
+    @builtin(vertex_index) in_vertex_index: u32
+) -> VertexOutput {
+    var out: VertexOutput;
+    let uv = vec2f(f32((in_vertex_index << 1u) & 2u), f32(in_vertex_index & 2u));
+    out.clip_position = vec4f(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    return out;
+}
+
+//深度纹理以不可过滤的浮点纹理（unfilterable float）绑定，深度值在 r 通道中。
+//OpenGL 后端不支持对 texture_depth_2d 使用 textureLoad，所以这里不使用深度纹理类型。
+@group(0) @binding(0)
+var t_depth: texture_2d<f32>;
+
+//不可过滤的纹理不能用过滤采样器采样，这里直接用 textureLoad 按像素坐标读取
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4f {
+    let depth = textureLoad(t_depth, vec2i(in.clip_position.xy), 0).r;
+    return vec4f(vec3f(depth), 1.0);
+}
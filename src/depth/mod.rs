@@ -0,0 +1,98 @@
+/*
+深度缓冲区调试视图
+把深度缓冲区的内容画到颜色附件上，用来检查深度测试是否按预期工作。
+*/
+use std::collections::HashMap;
+
+use wgpu::{BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, CommandEncoder, Device, LoadOp, Operations, PipelineLayout, PipelineLayoutDescriptor, RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline, ShaderModule, ShaderStages, TextureAspect, TextureFormat, TextureSampleType, TextureView, TextureViewDescriptor, TextureViewDimension};
+
+use crate::pipeline::RenderPipelineBuilder;
+use crate::texture::Texture;
+
+pub struct DepthDebug {
+    shader: ShaderModule,
+    bind_group_layout: BindGroupLayout,
+    pipeline_layout: PipelineLayout,
+    //管线的颜色格式必须与渲染目标一致，所以按格式缓存
+    pipelines: HashMap<TextureFormat, RenderPipeline>,
+}
+
+impl DepthDebug {
+    pub fn new(device: &Device) -> Self {
+        //深度纹理按不可过滤的浮点纹理绑定，这需要显式地写出绑定组布局（自动推导的布局会要求可过滤）
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Depth Debug Bind Group Layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: TextureViewDimension::D2,
+                        sample_type: TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Depth Debug Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        Self {
+            shader: device.create_shader_module(wgpu::include_wgsl!("depth_debug.wgsl")),
+            bind_group_layout,
+            pipeline_layout,
+            pipelines: HashMap::new(),
+        }
+    }
+
+    //用深度图覆盖整个颜色附件
+    pub fn draw(&mut self, device: &Device, encoder: &mut CommandEncoder, view: &TextureView, format: TextureFormat, depth_texture: &Texture) {
+        let shader = &self.shader;
+        let pipeline_layout = &self.pipeline_layout;
+        let pipeline = self.pipelines.entry(format).or_insert_with(|| {
+            RenderPipelineBuilder::new(shader, format)
+                .label("Depth Debug Pipeline")
+                .layout(pipeline_layout)
+                .cull_mode(None)
+                .build(device)
+        });
+
+        //带模板的深度格式（如 Depth24PlusStencil8）只能把深度部分绑定到着色器
+        let depth_view = depth_texture.texture.create_view(&TextureViewDescriptor {
+            label: Some("Depth Debug View"),
+            aspect: TextureAspect::DepthOnly,
+            ..Default::default()
+        });
+
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Depth Debug Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&depth_view),
+                },
+            ],
+        });
+
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("Depth Debug Pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,245 @@
+/*
+HDR（高动态范围）渲染
+Rgba8UnormSrgb 这样的渲染目标只能存储 0..1 之间的颜色，几个光源叠加或者高光超过 1.0 的部分都会被截断成白色。
+所以场景先画到一个 Rgba16Float 的中间纹理（HdrTarget）上，它可以存储大于 1.0 的线性颜色；
+再用一个全屏的色调映射（tonemapping）通道把它压缩到 0..1 之间，画到真正的渲染目标上。
+
+曝光（exposure）在色调映射之前乘到颜色上，相当于相机的曝光量，可以在运行时调整。
+*/
+use std::collections::HashMap;
+
+use winit::dpi::PhysicalSize;
+use wgpu::util::DeviceExt;
+use wgpu::{BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferUsages, CommandEncoder, Device, LoadOp, Operations, PipelineLayout, PipelineLayoutDescriptor, Queue, RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline, ShaderModule, ShaderStages, TextureFormat, TextureSampleType, TextureUsages, TextureView, TextureViewDescriptor, TextureViewDimension};
+
+use crate::pipeline::RenderPipelineBuilder;
+
+//场景绘制到的中间纹理的格式
+pub const HDR_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+
+//色调映射的算法
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Tonemap {
+    //只截断到 0..1，与不使用 HDR 的效果相同
+    None,
+    //c / (1 + c)，简单，但高光偏灰
+    Reinhard,
+    //ACES 电影曲线的近似
+    #[default]
+    Aces,
+}
+
+impl Tonemap {
+    //按 None、Reinhard、Aces 的顺序切换到下一个
+    pub fn next(self) -> Self {
+        match self {
+            Tonemap::None => Tonemap::Reinhard,
+            Tonemap::Reinhard => Tonemap::Aces,
+            Tonemap::Aces => Tonemap::None,
+        }
+    }
+
+    //与 tonemap.wgsl 中的常量一致
+    fn id(self) -> u32 {
+        match self {
+            Tonemap::None => 0,
+            Tonemap::Reinhard => 1,
+            Tonemap::Aces => 2,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct TonemapUniform {
+    exposure: f32,
+    mode: u32,
+    //统一缓冲区的大小按 16 字节对齐
+    _padding: [u32; 2],
+}
+
+//HDR 中间纹理和读取它的绑定组，必须与渲染目标一样大，调整大小时重新创建
+pub struct HdrTarget {
+    pub texture: wgpu::Texture,
+    pub view: TextureView,
+    bind_group: BindGroup,
+}
+
+impl HdrTarget {
+    pub fn size(&self) -> PhysicalSize<u32> {
+        PhysicalSize::new(self.texture.width(), self.texture.height())
+    }
+}
+
+//色调映射的设置和管线
+pub struct Hdr {
+    tonemap: Tonemap,
+    exposure: f32,
+    dirty: bool,
+    uniform_buffer: Buffer,
+
+    shader: ShaderModule,
+    bind_group_layout: BindGroupLayout,
+    pipeline_layout: PipelineLayout,
+    //管线的颜色格式必须与渲染目标一致，所以按格式缓存
+    pipelines: HashMap<TextureFormat, RenderPipeline>,
+}
+
+impl Hdr {
+    pub fn new(device: &Device) -> Self {
+        let tonemap = Tonemap::default();
+        let exposure = 1.0;
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Tonemap Uniform Buffer"),
+            contents: bytemuck::bytes_of(&TonemapUniform {
+                exposure,
+                mode: tonemap.id(),
+                _padding: [0; 2],
+            }),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+
+        //HDR 纹理用 textureLoad 读取，按不可过滤的浮点纹理绑定
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Tonemap Bind Group Layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: TextureViewDimension::D2,
+                        sample_type: TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Tonemap Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        Self {
+            tonemap,
+            exposure,
+            dirty: false,
+            uniform_buffer,
+            shader: device.create_shader_module(wgpu::include_wgsl!("tonemap.wgsl")),
+            bind_group_layout,
+            pipeline_layout,
+            pipelines: HashMap::new(),
+        }
+    }
+
+    //创建与渲染目标一样大的 HDR 中间纹理
+    pub fn create_target(&self, device: &Device, size: PhysicalSize<u32>) -> HdrTarget {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("HDR Texture"),
+            size: wgpu::Extent3d {
+                width: size.width.max(1),
+                height: size.height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: HDR_FORMAT,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Tonemap Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: self.uniform_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        HdrTarget {
+            texture,
+            view,
+            bind_group,
+        }
+    }
+
+    pub fn tonemap(&self) -> Tonemap {
+        self.tonemap
+    }
+
+    pub fn set_tonemap(&mut self, tonemap: Tonemap) {
+        self.tonemap = tonemap;
+        self.dirty = true;
+    }
+
+    pub fn exposure(&self) -> f32 {
+        self.exposure
+    }
+
+    //曝光是乘到颜色上的系数，1.0 表示不变，不能为负数
+    pub fn set_exposure(&mut self, exposure: f32) {
+        self.exposure = exposure.max(0.0);
+        self.dirty = true;
+    }
+
+    //把 HDR 纹理经过色调映射画到 view 上，format 是 view 的格式
+    pub fn process(&mut self, device: &Device, queue: &Queue, encoder: &mut CommandEncoder, source: &HdrTarget, view: &TextureView, format: TextureFormat) {
+        if self.dirty {
+            self.dirty = false;
+            queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&TonemapUniform {
+                exposure: self.exposure,
+                mode: self.tonemap.id(),
+                _padding: [0; 2],
+            }));
+        }
+
+        let shader = &self.shader;
+        let pipeline_layout = &self.pipeline_layout;
+        let pipeline = self.pipelines.entry(format).or_insert_with(|| {
+            //非 sRGB 的目标（例如某些平台上展示平面的 Bgra8Unorm）需要在着色器中做伽马校正
+            let fs_entry = if format.is_srgb() { "fs_main" } else { "fs_encode_srgb" };
+            RenderPipelineBuilder::new(shader, format)
+                .label("Tonemap Pipeline")
+                .layout(pipeline_layout)
+                .entry_points("vs_main", fs_entry)
+                .cull_mode(None)
+                .build(device)
+        });
+
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("Tonemap Pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: Operations {
+                    //全屏三角形会覆盖每一个像素，使用 Clear 可以省去读取原来的内容
+                    load: LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, &source.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
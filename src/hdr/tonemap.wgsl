@@ -0,0 +1,75 @@
+//色调映射：把 HDR 纹理中可能大于 1.0 的线性颜色压缩到 0..1 之间，再画到渲染目标上
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4f
+};
+
+//用 3 个顶点画一个覆盖整个屏幕的大三角形
+@vertex
+fn vs_main(
+    @builtin(vertex_index) in_vertex_index: u32
+) -> VertexOutput {
+    var out: VertexOutput;
+    let uv = vec2f(f32((in_vertex_index << 1u) & 2u), f32(in_vertex_index & 2u));
+    out.clip_position = vec4f(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    return out;
+}
+
+//与 hdr 模块中的 Tonemap 一致
+const TONEMAP_NONE: u32 = 0u;
+const TONEMAP_REINHARD: u32 = 1u;
+const TONEMAP_ACES: u32 = 2u;
+
+struct TonemapUniform {
+    exposure: f32,
+    mode: u32
+};
+
+//HDR 纹理与渲染目标一样大，直接用 textureLoad 按像素坐标读取，不需要采样器
+@group(0) @binding(0)
+var t_hdr: texture_2d<f32>;
+
+@group(0) @binding(1)
+var<uniform> params: TonemapUniform;
+
+fn reinhard(color: vec3f) -> vec3f {
+    return color / (1.0 + color);
+}
+
+//Krzysztof Narkowicz 对 ACES 电影曲线的拟合，高光过渡比 Reinhard 更柔和，暗部对比度更高
+fn aces(color: vec3f) -> vec3f {
+    let a = 2.51;
+    let b = 0.03;
+    let c = 2.43;
+    let d = 0.59;
+    let e = 0.14;
+    return clamp((color * (a * color + b)) / (color * (c * color + d) + e), vec3f(0.0), vec3f(1.0));
+}
+
+fn tonemap(position: vec4f) -> vec4f {
+    let hdr = textureLoad(t_hdr, vec2i(position.xy), 0);
+    let color = hdr.rgb * params.exposure;
+    if params.mode == TONEMAP_REINHARD {
+        return vec4f(reinhard(color), hdr.a);
+    }
+    if params.mode == TONEMAP_ACES {
+        return vec4f(aces(color), hdr.a);
+    }
+    //TONEMAP_NONE
+    return vec4f(clamp(color, vec3f(0.0), vec3f(1.0)), hdr.a);
+}
+
+//sRGB 格式的渲染目标在写入时由硬件做伽马校正，直接输出线性颜色
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4f {
+    return tonemap(in.clip_position);
+}
+
+//非 sRGB 格式的渲染目标需要自己转换到 sRGB
+@fragment
+fn fs_encode_srgb(in: VertexOutput) -> @location(0) vec4f {
+    let color = tonemap(in.clip_position);
+    let c = color.rgb;
+    let srgb = select(1.055 * pow(c, vec3f(1.0 / 2.4)) - 0.055, c * 12.92, c <= vec3f(0.0031308));
+    return vec4f(srgb, color.a);
+}
@@ -0,0 +1,123 @@
+/*
+无窗口（离屏）渲染
+State 需要一个 winit 的 Window 来创建展示平面，这在 CI 或服务器上是做不到的。
+离屏渲染的思路很简单：不向展示平面请求帧，而是自己创建一个纹理作为颜色附件，把场景画到这个纹理上，
+然后把纹理复制到一个可以被 CPU 映射（map）的缓冲区里，读回像素数据并交给 image 包保存成 PNG。
+
+作为颜色附件的离屏纹理见 render_target 模块的 TextureTarget，State::new_headless 使用这里创建的设备和它来构建 State。
+*/
+use std::sync::mpsc;
+
+use image::RgbaImage;
+use winit::dpi::PhysicalSize;
+use wgpu::{Backends, BufferDescriptor, BufferUsages, CommandEncoderDescriptor, Device, DeviceDescriptor, Extent3d, Features, ImageCopyTexture, Instance, InstanceDescriptor, Limits, MapMode, Queue, RequestAdapterOptions, TextureFormat};
+
+//离屏纹理的格式。与大多数图像文件一样使用 sRGB，这样读回的字节可以直接写入 PNG。
+pub const HEADLESS_FORMAT: TextureFormat = TextureFormat::Rgba8UnormSrgb;
+
+//创建不依赖展示平面的逻辑设备和命令队列
+pub async fn request_device() -> (Device, Queue) {
+    let instance = Instance::new(InstanceDescriptor {
+        backends: Backends::all(),
+        ..Default::default()
+    });
+
+    //没有展示平面，所以 compatible_surface 为 None。
+    //先尝试普通的适配器，如果系统上没有（例如没有显卡的服务器），再强制使用 fallback adapter（软渲染）。
+    let adapter = match instance.request_adapter(&RequestAdapterOptions {
+        compatible_surface: None,
+        ..Default::default()
+    }).await {
+        Some(adapter) => adapter,
+        None => instance.request_adapter(&RequestAdapterOptions {
+            compatible_surface: None,
+            force_fallback_adapter: true,
+            ..Default::default()
+        }).await.expect("找不到可用的适配器（包括 fallback adapter）"),
+    };
+    log::info!("离屏渲染使用的适配器: {:?}", adapter.get_info());
+
+    adapter.request_device(
+        &DeviceDescriptor {
+            features: Features::empty(),
+            //软渲染适配器通常只满足 downlevel 的限制
+            limits: Limits::downlevel_defaults().using_resolution(adapter.limits()),
+            label: None,
+        },
+        None,
+    ).await.expect("无法创建逻辑设备")
+}
+
+//把纹理（或纹理数组的某一层）读回为 RgbaImage。只支持每像素 4 字节的 RGBA/BGRA 格式，其他格式返回 None。
+pub fn read_texture(device: &Device, queue: &Queue, source: ImageCopyTexture, size: PhysicalSize<u32>, format: TextureFormat) -> Option<RgbaImage> {
+    let swap_red_blue = match format {
+        TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb => false,
+        TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb => true,
+        _ => return None,
+    };
+
+    /*
+    复制纹理到缓冲区时，每一行的字节数（bytes_per_row）必须是 COPY_BYTES_PER_ROW_ALIGNMENT（256）的倍数，
+    所以缓冲区的每一行末尾可能会有填充，读回时要把它们去掉。
+    */
+    let unpadded_bytes_per_row = 4 * size.width;
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    let padded_bytes_per_row = unpadded_bytes_per_row.div_ceil(align) * align;
+
+    let output_buffer = device.create_buffer(&BufferDescriptor {
+        label: Some("Headless Output Buffer"),
+        size: (padded_bytes_per_row * size.height) as wgpu::BufferAddress,
+        // MAP_READ 表示 CPU 可以读取这个缓冲区
+        usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("Headless Readback Encoder")
+    });
+
+    encoder.copy_texture_to_buffer(
+        source,
+        wgpu::ImageCopyBuffer {
+            buffer: &output_buffer,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_bytes_per_row),
+                rows_per_image: Some(size.height),
+            },
+        },
+        Extent3d {
+            width: size.width,
+            height: size.height,
+            depth_or_array_layers: 1,
+        },
+    );
+
+    queue.submit(std::iter::once(encoder.finish()));
+
+    //映射缓冲区是异步的，map_async 的回调会在 device.poll 时被调用
+    let buffer_slice = output_buffer.slice(..);
+    let (tx, rx) = mpsc::channel();
+    buffer_slice.map_async(MapMode::Read, move |result| {
+        tx.send(result).unwrap();
+    });
+    device.poll(wgpu::Maintain::Wait);
+    rx.recv().unwrap().expect("无法映射离屏输出缓冲区");
+
+    let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * size.height) as usize);
+    {
+        let data = buffer_slice.get_mapped_range();
+        for row in data.chunks(padded_bytes_per_row as usize) {
+            pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+        }
+    }
+    output_buffer.unmap();
+
+    if swap_red_blue {
+        for pixel in pixels.chunks_mut(4) {
+            pixel.swap(0, 2);
+        }
+    }
+
+    RgbaImage::from_raw(size.width, size.height, pixels)
+}
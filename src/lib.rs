@@ -1,3 +1,6 @@
+//#[derive(VertexLayout)] 生成的代码通过 ::wgpu_01 引用 VertexLayout trait，在本 crate 内也要能这样引用
+extern crate self as wgpu_01;
+
 pub mod wasm;
 
 pub mod surface;
@@ -6,4 +9,34 @@ pub mod pipeline;
 
 pub mod buffer;
 
-pub mod texture;
\ No newline at end of file
+pub mod texture;
+
+pub mod headless;
+
+pub mod render_target;
+
+pub mod depth;
+
+pub mod camera;
+
+pub mod model;
+
+pub mod light;
+
+pub mod hdr;
+
+pub mod skybox;
+
+pub mod shadow;
+
+pub mod compute;
+
+pub mod particles;
+
+pub mod text;
+
+pub mod sprite;
+
+pub mod painter;
+
+pub mod shader;
@@ -0,0 +1,390 @@
+/*
+光照
+使用 Blinn-Phong 光照模型，物体的颜色由三部分相加：
+环境光（ambient）：来自四面八方的间接光，让背光面不至于全黑，所有光源共用一个环境光颜色。
+漫反射（diffuse）：与表面法线和光线方向的夹角有关，正对光源最亮。
+镜面反射（specular）：与法线和半程向量（光线方向与视线方向的中间方向）的夹角有关，产生高光。
+
+支持三种光源：
+点光源（Point）：从一个位置向所有方向发光，强度随距离衰减。
+平行光（Directional）：只有方向没有位置，例如太阳光，不衰减。
+聚光灯（Spot）：从一个位置向一个方向发出锥形的光，锥形边缘在内外两个角度之间逐渐变暗，也随距离衰减。
+
+所有光源放在一个统一缓冲区的定长数组中（WebGL 不支持存储缓冲区），着色器中对应 @group(2) @binding(0)。
+
+平行光和聚光灯可以投射阴影，见 shadow 模块。点光源的阴影需要立方体阴影贴图，目前不支持。
+*/
+use bytemuck::Zeroable;
+use cgmath::{Deg, InnerSpace, Rad, Vector3};
+use wgpu::util::DeviceExt;
+use wgpu::{BindGroup, BindGroupLayout, Buffer, BufferUsages, Device, Queue, ShaderStages};
+
+use crate::shadow::MAX_SHADOWS;
+
+//统一缓冲区中光源数组的长度，必须与着色器中的 MAX_LIGHTS 一致
+pub const MAX_LIGHTS: usize = 16;
+
+/*
+距离衰减：attenuation = 1 / (constant + linear * d + quadratic * d²)
+超过 range 的部分直接为 0，这样光源只影响附近的物体。
+*/
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Attenuation {
+    pub constant: f32,
+    pub linear: f32,
+    pub quadratic: f32,
+    pub range: f32,
+}
+
+impl Attenuation {
+    //常用的经验值：大约在 range 处衰减到很暗
+    pub fn with_range(range: f32) -> Self {
+        Self {
+            constant: 1.0,
+            linear: 4.5 / range,
+            quadratic: 75.0 / (range * range),
+            range,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Light {
+    Point {
+        position: Vector3<f32>,
+        color: [f32; 3],
+        intensity: f32,
+        attenuation: Attenuation,
+    },
+    Directional {
+        //光线前进的方向（从光源指向物体）
+        direction: Vector3<f32>,
+        color: [f32; 3],
+        intensity: f32,
+        cast_shadows: bool,
+    },
+    Spot {
+        position: Vector3<f32>,
+        direction: Vector3<f32>,
+        color: [f32; 3],
+        intensity: f32,
+        attenuation: Attenuation,
+        //内角以内是全亮，外角以外没有光
+        inner_angle: Rad<f32>,
+        outer_angle: Rad<f32>,
+        cast_shadows: bool,
+    },
+}
+
+impl Light {
+    pub fn point(position: Vector3<f32>, color: [f32; 3], range: f32) -> Self {
+        Light::Point {
+            position,
+            color,
+            intensity: 1.0,
+            attenuation: Attenuation::with_range(range),
+        }
+    }
+
+    pub fn directional(direction: Vector3<f32>, color: [f32; 3]) -> Self {
+        Light::Directional {
+            direction,
+            color,
+            intensity: 1.0,
+            cast_shadows: false,
+        }
+    }
+
+    pub fn spot(position: Vector3<f32>, direction: Vector3<f32>, color: [f32; 3], range: f32, angle: Deg<f32>) -> Self {
+        Light::Spot {
+            position,
+            direction,
+            color,
+            intensity: 1.0,
+            attenuation: Attenuation::with_range(range),
+            inner_angle: (angle * 0.8).into(),
+            outer_angle: angle.into(),
+            cast_shadows: false,
+        }
+    }
+
+    //让平行光或聚光灯投射阴影。点光源不支持阴影，调用后没有变化
+    pub fn with_shadows(mut self) -> Self {
+        self.set_cast_shadows(true);
+        self
+    }
+
+    pub fn casts_shadows(&self) -> bool {
+        match self {
+            Light::Directional { cast_shadows, .. } | Light::Spot { cast_shadows, .. } => *cast_shadows,
+            Light::Point { .. } => false,
+        }
+    }
+
+    pub fn set_cast_shadows(&mut self, enabled: bool) {
+        if let Light::Directional { cast_shadows, .. } | Light::Spot { cast_shadows, .. } = self {
+            *cast_shadows = enabled;
+        }
+    }
+
+    //光源的位置，平行光没有位置
+    pub fn position(&self) -> Option<Vector3<f32>> {
+        match self {
+            Light::Point { position, .. } | Light::Spot { position, .. } => Some(*position),
+            Light::Directional { .. } => None,
+        }
+    }
+
+    pub fn set_position(&mut self, new_position: Vector3<f32>) {
+        if let Light::Point { position, .. } | Light::Spot { position, .. } = self {
+            *position = new_position;
+        }
+    }
+
+    pub fn to_raw(&self) -> LightRaw {
+        let no_attenuation = Attenuation {
+            constant: 1.0,
+            linear: 0.0,
+            quadratic: 0.0,
+            range: 0.0,
+        };
+        let (kind, position, direction, color, intensity, attenuation, cone) = match *self {
+            Light::Point { position, color, intensity, attenuation } => {
+                (LIGHT_POINT, position, Vector3::unit_z(), color, intensity, attenuation, [-1.0, -1.0])
+            }
+            Light::Directional { direction, color, intensity, .. } => {
+                (LIGHT_DIRECTIONAL, Vector3::new(0.0, 0.0, 0.0), direction, color, intensity, no_attenuation, [-1.0, -1.0])
+            }
+            Light::Spot { position, direction, color, intensity, attenuation, inner_angle, outer_angle, .. } => {
+                (LIGHT_SPOT, position, direction, color, intensity, attenuation, [inner_angle.0.cos(), outer_angle.0.cos()])
+            }
+        };
+        let direction = if direction.magnitude2() > 0.0 { direction.normalize() } else { -Vector3::unit_y() };
+
+        LightRaw {
+            position: [position.x, position.y, position.z, kind],
+            direction: [direction.x, direction.y, direction.z, attenuation.range],
+            color: [color[0] * intensity, color[1] * intensity, color[2] * intensity, 0.0],
+            attenuation: [attenuation.constant, attenuation.linear, attenuation.quadratic, 0.0],
+            cone: [cone[0], cone[1], NO_SHADOW, 0.0],
+        }
+    }
+}
+
+//LightRaw::position 的 w 分量表示光源类型，与着色器中的常量一致
+const LIGHT_POINT: f32 = 0.0;
+const LIGHT_DIRECTIONAL: f32 = 1.0;
+const LIGHT_SPOT: f32 = 2.0;
+//LightRaw::cone 的 z 分量是阴影贴图的层，没有阴影时为 -1
+const NO_SHADOW: f32 = -1.0;
+
+/*
+着色器中的一个光源。统一缓冲区中数组元素要按 16 字节对齐，所以每个字段都用 vec4。
+position.w 是类型，direction.w 是 range（0 表示没有范围限制），color 已经乘上了强度，
+attenuation 是 (constant, linear, quadratic, _)，cone 是 (cos 内角, cos 外角, 阴影贴图的层, _)。
+*/
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LightRaw {
+    pub position: [f32; 4],
+    pub direction: [f32; 4],
+    pub color: [f32; 4],
+    pub attenuation: [f32; 4],
+    pub cone: [f32; 4],
+}
+
+//整个光照统一缓冲区：环境光、光源数量和光源数组
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LightsUniform {
+    //rgb 是环境光颜色
+    pub ambient: [f32; 4],
+    //x 是光源数量，其余用于对齐
+    pub count: [u32; 4],
+    pub lights: [LightRaw; MAX_LIGHTS],
+}
+
+//光源的标识，添加光源时返回。移除光源后标识不会被重新使用
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct LightId(u64);
+
+/*
+光源列表和它的统一缓冲区。
+在 State::update 中添加、移除或移动光源，修改后会在下一次 upload 时写入缓冲区。
+*/
+pub struct Lights {
+    ambient: [f32; 3],
+    lights: Vec<(LightId, Light)>,
+    next_id: u64,
+    dirty: bool,
+    buffer: Buffer,
+    pub bind_group_layout: BindGroupLayout,
+    pub bind_group: BindGroup,
+}
+
+impl Lights {
+    //绑定组布局的条目，与 CameraBinding::LAYOUT_ENTRIES 一样用于和着色器比较。
+    //顶点着色器不需要光源，只有片元着色器使用
+    pub const LAYOUT_ENTRIES: &'static [wgpu::BindGroupLayoutEntry] = &[
+        wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        },
+    ];
+
+    pub fn new(device: &Device) -> Self {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Light Buffer"),
+            contents: bytemuck::bytes_of(&LightsUniform::zeroed()),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: Self::LAYOUT_ENTRIES,
+            label: Some("light_bind_group_layout"),
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("light_bind_group"),
+        });
+
+        Self {
+            ambient: [0.1, 0.1, 0.1],
+            lights: Vec::new(),
+            next_id: 0,
+            dirty: true,
+            buffer,
+            bind_group_layout,
+            bind_group,
+        }
+    }
+
+    //添加一个光源。超过 MAX_LIGHTS 的光源不会被绘制
+    pub fn add(&mut self, light: Light) -> LightId {
+        if self.lights.len() >= MAX_LIGHTS {
+            log::warn!("光源数量超过 {}，多出的光源不会生效", MAX_LIGHTS);
+        }
+        let id = LightId(self.next_id);
+        self.next_id += 1;
+        self.lights.push((id, light));
+        self.dirty = true;
+        id
+    }
+
+    pub fn remove(&mut self, id: LightId) -> Option<Light> {
+        let index = self.lights.iter().position(|(light_id, _)| *light_id == id)?;
+        self.dirty = true;
+        Some(self.lights.remove(index).1)
+    }
+
+    pub fn get(&self, id: LightId) -> Option<&Light> {
+        self.lights.iter().find(|(light_id, _)| *light_id == id).map(|(_, light)| light)
+    }
+
+    //取得可修改的光源，缓冲区会在下一次 upload 时更新
+    pub fn get_mut(&mut self, id: LightId) -> Option<&mut Light> {
+        self.lights.iter_mut().find(|(light_id, _)| *light_id == id).map(|(_, light)| {
+            self.dirty = true;
+            light
+        })
+    }
+
+    pub fn ambient(&self) -> [f32; 3] {
+        self.ambient
+    }
+
+    pub fn set_ambient(&mut self, ambient: [f32; 3]) {
+        self.ambient = ambient;
+        self.dirty = true;
+    }
+
+    pub fn len(&self) -> usize {
+        self.lights.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (LightId, &Light)> {
+        self.lights.iter().map(|(id, light)| (*id, light))
+    }
+
+    //投射阴影的光源，按添加的顺序依次使用阴影贴图的第 0、1、2... 层，超过 MAX_SHADOWS 的没有阴影
+    pub fn shadow_casters(&self) -> impl Iterator<Item = &Light> {
+        self.lights.iter()
+            .take(MAX_LIGHTS)
+            .map(|(_, light)| light)
+            .filter(|light| light.casts_shadows())
+            .take(MAX_SHADOWS)
+    }
+
+    //光源有变化时写入统一缓冲区
+    pub fn upload(&mut self, queue: &Queue) {
+        if !self.dirty {
+            return;
+        }
+        self.dirty = false;
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(&self.uniform()));
+    }
+
+    //写入统一缓冲区的数据
+    pub(crate) fn uniform(&self) -> LightsUniform {
+        let mut uniform = LightsUniform::zeroed();
+        uniform.ambient = [self.ambient[0], self.ambient[1], self.ambient[2], 0.0];
+        let count = self.lights.len().min(MAX_LIGHTS);
+        uniform.count[0] = count as u32;
+        let mut shadow_layer = 0;
+        for (raw, (_, light)) in uniform.lights.iter_mut().zip(&self.lights) {
+            *raw = light.to_raw();
+            //与 shadow_casters 的顺序一致
+            if light.casts_shadows() && shadow_layer < MAX_SHADOWS {
+                raw.cone[2] = shadow_layer as f32;
+                shadow_layer += 1;
+            }
+        }
+        uniform
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headless;
+
+    #[test]
+    fn only_changes_mark_lights_dirty() {
+        let (device, queue) = pollster::block_on(headless::request_device());
+        let mut lights = Lights::new(&device);
+        let id = lights.add(Light::point(Vector3::new(0.0, 1.0, 0.0), [1.0; 3], 5.0));
+        lights.upload(&queue);
+        assert!(!lights.dirty);
+
+        //找不到的光源不需要重新上传
+        assert!(lights.remove(id).is_some());
+        lights.upload(&queue);
+        assert!(lights.get_mut(id).is_none());
+        assert!(!lights.dirty);
+        assert!(lights.remove(id).is_none());
+        assert!(!lights.dirty);
+
+        let id = lights.add(Light::directional(-Vector3::unit_y(), [1.0; 3]));
+        lights.upload(&queue);
+        lights.get_mut(id).unwrap().set_cast_shadows(true);
+        assert!(lights.dirty);
+        assert_eq!(lights.uniform().lights[0].cone[2], 0.0);
+    }
+}
@@ -0,0 +1,220 @@
+
+//带光照的着色器：纹理采样之外加入法线贴图和 Blinn-Phong 光照，光源的数据见 light 模块，阴影贴图见 shadow 模块。
+
+struct VertexInput {
+    @location(0) position: vec3f,
+    @location(1) tex_coords: vec2f,
+    @location(2) normal: vec3f,
+    @location(3) tangent: vec3f,
+    @location(4) bitangent: vec3f
+}
+
+//实例数据，见 buffer::instance 模块的 InstanceRaw
+struct InstanceInput {
+    @location(5) model_matrix_0: vec4f,
+    @location(6) model_matrix_1: vec4f,
+    @location(7) model_matrix_2: vec4f,
+    @location(8) model_matrix_3: vec4f,
+    @location(9) normal_matrix_0: vec3f,
+    @location(10) normal_matrix_1: vec3f,
+    @location(11) normal_matrix_2: vec3f,
+    @location(12) tint: vec4f
+}
+
+struct CameraUniform {
+    view_position: vec4f,
+    view_proj: mat4x4f,
+    inv_proj: mat4x4f,
+    inv_view: mat4x4f
+};
+
+@group(1) @binding(0)
+var<uniform> camera: CameraUniform;
+
+//光源类型，与 light 模块中的常量一致
+const LIGHT_POINT: f32 = 0.0;
+const LIGHT_DIRECTIONAL: f32 = 1.0;
+const LIGHT_SPOT: f32 = 2.0;
+const MAX_LIGHTS: u32 = 16u;
+
+struct Light {
+    //w 是光源类型
+    position: vec4f,
+    //w 是照射范围，0 表示没有限制
+    direction: vec4f,
+    color: vec4f,
+    //(constant, linear, quadratic, _)
+    attenuation: vec4f,
+    //(cos 内角, cos 外角, 阴影贴图的层（负数表示没有阴影）, _)
+    cone: vec4f
+};
+
+struct Lights {
+    ambient: vec4f,
+    count: vec4u,
+    lights: array<Light, MAX_LIGHTS>
+};
+
+@group(2) @binding(0)
+var<uniform> lights: Lights;
+
+//与 shadow 模块中的 MAX_SHADOWS 一致
+const MAX_SHADOWS: u32 = 4u;
+
+struct Shadows {
+    //每一层对应的光源的视图投影矩阵
+    view_proj: array<mat4x4f, MAX_SHADOWS>,
+    //(1 / 分辨率, PCF 半径, 深度偏移, _)
+    params: vec4f
+};
+
+@group(3) @binding(0)
+var t_shadow: texture_depth_2d_array;
+
+@group(3) @binding(1)
+var s_shadow: sampler_comparison;
+
+@group(3) @binding(2)
+var<uniform> shadows: Shadows;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4f,
+    @location(0) tex_coords: vec2f,
+    @location(1) tint: vec4f,
+    //光照在世界空间中计算
+    @location(2) world_position: vec3f,
+    @location(3) world_normal: vec3f,
+    @location(4) world_tangent: vec3f,
+    @location(5) world_bitangent: vec3f
+};
+
+@vertex
+fn vs_main (
+    model: VertexInput,
+    instance: InstanceInput
+) -> VertexOutput {
+    let model_matrix = mat4x4f(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3
+    );
+    let normal_matrix = mat3x3f(
+        instance.normal_matrix_0,
+        instance.normal_matrix_1,
+        instance.normal_matrix_2
+    );
+
+    var out: VertexOutput;
+    out.tex_coords = model.tex_coords;
+    out.tint = instance.tint;
+    let world_position = model_matrix * vec4f(model.position, 1.0);
+    out.world_position = world_position.xyz;
+    out.world_normal = normal_matrix * model.normal;
+    //切线和副切线在表面上，跟随模型矩阵变换
+    out.world_tangent = (model_matrix * vec4f(model.tangent, 0.0)).xyz;
+    out.world_bitangent = (model_matrix * vec4f(model.bitangent, 0.0)).xyz;
+    out.clip_position = camera.view_proj * world_position;
+    return out;
+}
+
+@group(0) @binding(0)
+var t_diffuse: texture_2d<f32>;
+
+@group(0) @binding(1)
+var s_diffuse: sampler;
+
+//切线空间的法线贴图，线性格式
+@group(0) @binding(2)
+var t_normal: texture_2d<f32>;
+
+@group(0) @binding(3)
+var s_normal: sampler;
+
+//高光的集中程度，越大高光越小越亮
+const SHININESS: f32 = 32.0;
+const SPECULAR_STRENGTH: f32 = 0.5;
+
+//距离衰减，超过照射范围时为 0
+fn attenuate(light: Light, distance: f32) -> f32 {
+    let range = light.direction.w;
+    if range > 0.0 && distance > range {
+        return 0.0;
+    }
+    let k = light.attenuation;
+    return 1.0 / (k.x + k.y * distance + k.z * distance * distance);
+}
+
+//没有被挡住的比例，1 表示完全照亮，0 表示完全在阴影中
+fn shadow_factor(light: Light, position: vec3f) -> f32 {
+    let layer = i32(light.cone.z);
+    if layer < 0 {
+        return 1.0;
+    }
+    let clip = shadows.view_proj[layer] * vec4f(position, 1.0);
+    let ndc = clip.xyz / clip.w;
+    //标准化设备坐标的 y 轴向上，纹理坐标的 v 轴向下
+    let uv = ndc.xy * vec2f(0.5, -0.5) + 0.5;
+    //超出阴影贴图覆盖范围的地方当作没有阴影
+    if clip.w <= 0.0 || any(uv < vec2f(0.0)) || any(uv > vec2f(1.0)) || ndc.z > 1.0 {
+        return 1.0;
+    }
+    let depth = ndc.z - shadows.params.z;
+    let texel = shadows.params.x;
+    let radius = i32(shadows.params.y);
+    //PCF：对周围 (2r + 1)^2 个纹素的比较结果取平均
+    var lit = 0.0;
+    for (var y = -radius; y <= radius; y++) {
+        for (var x = -radius; x <= radius; x++) {
+            lit += textureSampleCompareLevel(t_shadow, s_shadow, uv + vec2f(f32(x), f32(y)) * texel, layer, depth);
+        }
+    }
+    let samples = f32((2 * radius + 1) * (2 * radius + 1));
+    return lit / samples;
+}
+
+//一个光源照到表面上的漫反射和镜面反射颜色（还没有乘上物体的颜色）
+fn shade(light: Light, position: vec3f, normal: vec3f, view_dir: vec3f, albedo: vec3f) -> vec3f {
+    var light_dir: vec3f;
+    var strength = 1.0;
+    if light.position.w == LIGHT_DIRECTIONAL {
+        light_dir = -light.direction.xyz;
+    } else {
+        let to_light = light.position.xyz - position;
+        let distance = length(to_light);
+        light_dir = to_light / distance;
+        strength = attenuate(light, distance);
+        if light.position.w == LIGHT_SPOT {
+            //在内角和外角之间平滑过渡
+            let theta = dot(-light_dir, light.direction.xyz);
+            strength *= clamp((theta - light.cone.y) / max(light.cone.x - light.cone.y, 0.0001), 0.0, 1.0);
+        }
+    }
+
+    if strength > 0.0 {
+        strength *= shadow_factor(light, position);
+    }
+
+    let diffuse = max(dot(normal, light_dir), 0.0) * albedo;
+    //Blinn-Phong 用半程向量代替反射向量，计算更简单，掠射角下的高光也更自然
+    let half_dir = normalize(light_dir + view_dir);
+    let specular = pow(max(dot(normal, half_dir), 0.0), SHININESS) * SPECULAR_STRENGTH;
+    return (diffuse + specular) * light.color.rgb * strength;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4f {
+    let base = textureSample(t_diffuse, s_diffuse, in.tex_coords) * in.tint;
+    //法线贴图的值在 0..1 之间，转换到 -1..1 后用 TBN 矩阵从切线空间变换到世界空间
+    let tangent_normal = textureSample(t_normal, s_normal, in.tex_coords).xyz * 2.0 - 1.0;
+    let tbn = mat3x3f(normalize(in.world_tangent), normalize(in.world_bitangent), normalize(in.world_normal));
+    let normal = normalize(tbn * tangent_normal);
+    let view_dir = normalize(camera.view_position.xyz - in.world_position);
+
+    var color = lights.ambient.rgb * base.rgb;
+    let count = min(lights.count.x, MAX_LIGHTS);
+    for (var i = 0u; i < count; i++) {
+        color += shade(lights.lights[i], in.world_position, normal, view_dir, base.rgb);
+    }
+    return vec4f(color, base.a);
+}
@@ -3,18 +3,206 @@ use winit::{
     event_loop::{ControlFlow, EventLoop},
     window::WindowBuilder,
 };
-use wgpu_01::surface::State;
+use std::time::{Duration, Instant};
+
+use winit::dpi::PhysicalSize;
+use wgpu_01::particles::{Emitter, ParticleBlend};
+use wgpu_01::surface::{State, SCENE_SHADER_PATH};
+use wgpu_01::text::TextStyle;
 
 use pollster::block_on;
 
+//命令行参数。不带参数时打开窗口；--headless 时不创建窗口，渲染一帧并保存为图片：
+// cargo run -- --headless --output out.png [--width 800] [--height 600]
+//--model 加载一个 OBJ 或 glTF 模型代替默认的五边形
+//--skybox 加载天空盒：一张全景图，或者用逗号分隔的 6 张面的图像（+X,-X,+Y,-Y,+Z,-Z）
+//--particles 在场景下方添加一个粒子喷泉，无窗口时先模拟一段时间再渲染
+//--font 加载一个 BMFont 字体（.fnt），在左上角显示帧率（无窗口时显示画面大小）
+//--hot-reload 从源码目录读取场景的着色器，修改并保存后自动重新编译，不需要重新编译程序
+const USAGE: &str = "用法: wgpu_01 [--headless] [--output 文件] [--width 宽] [--height 高] [--model 文件] [--skybox 文件[,文件...]] [--particles] [--font 文件.fnt] [--hot-reload]";
+
+struct Args {
+    headless: bool,
+    model: Option<String>,
+    skybox: Option<String>,
+    particles: bool,
+    font: Option<String>,
+    hot_reload: bool,
+    output: String,
+    width: u32,
+    height: u32,
+}
+
+impl Args {
+    fn parse() -> Self {
+        let mut args = Args {
+            headless: false,
+            model: None,
+            skybox: None,
+            particles: false,
+            font: None,
+            hot_reload: false,
+            output: String::from("out.png"),
+            width: 800,
+            height: 600,
+        };
+
+        let mut iter = std::env::args().skip(1);
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--headless" => args.headless = true,
+                "--model" => args.model = Some(iter.next().unwrap_or_else(|| usage_error("--model 需要一个文件路径"))),
+                "--skybox" => args.skybox = Some(iter.next().unwrap_or_else(|| usage_error("--skybox 需要一个或 6 个文件路径"))),
+                "--particles" => args.particles = true,
+                "--font" => args.font = Some(iter.next().unwrap_or_else(|| usage_error("--font 需要一个 .fnt 文件路径"))),
+                "--hot-reload" => args.hot_reload = true,
+                "--output" => args.output = iter.next().unwrap_or_else(|| usage_error("--output 需要一个文件路径")),
+                "--width" => args.width = parse_dimension(iter.next(), "--width"),
+                "--height" => args.height = parse_dimension(iter.next(), "--height"),
+                "--help" | "-h" => {
+                    println!("{}", USAGE);
+                    std::process::exit(0);
+                }
+                other => usage_error(&format!("未知参数: {}", other)),
+            }
+        }
+        args
+    }
+}
+
+fn parse_dimension(value: Option<String>, name: &str) -> u32 {
+    value.and_then(|v| v.parse().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or_else(|| usage_error(&format!("{} 需要一个正整数", name)))
+}
+
+//参数错误时打印原因和用法，以非零状态退出
+fn usage_error(message: &str) -> ! {
+    eprintln!("{}\n{}", message, USAGE);
+    std::process::exit(2);
+}
+
 fn main() {
+    let args = Args::parse();
+
     //WASM 环境中不能在异步函数里使用 block_on。
     // Future（异步函数的返回对象）必须使用浏览器的执行器来运行。如果你试图使用自己的执行器，一旦遇到没有立即执行的 Future 时代码就会崩溃。
-    block_on(run());
+    if args.headless {
+        block_on(run_headless(args));
+    } else {
+        block_on(run(args));
+    }
+}
+
+//无窗口渲染：适用于 CI 或服务器，可以运行在软渲染（fallback）适配器上
+async fn run_headless(args: Args) {
+    env_logger::init();
+
+    let mut state = State::new_headless(PhysicalSize::new(args.width, args.height)).await;
+    load_model(&mut state, &args);
+    load_skybox(&mut state, &args);
+    watch_shader(&mut state, &args);
+    if add_particles(&mut state, &args) {
+        //粒子从无到有，先模拟一段时间，让喷泉成形
+        for _ in 0..PARTICLE_WARMUP_FRAMES {
+            state.update(Duration::from_secs_f32(1.0 / 60.0));
+        }
+    }
+    load_font(&mut state, &args);
+    state.draw_text(&format!("{}x{}", args.width, args.height), TEXT_POSITION, &TextStyle::default());
+    state.update(Duration::ZERO);
+    state.render().expect("离屏渲染失败");
+    state.capture()
+        .expect("离屏渲染目标无法读回")
+        .save(&args.output)
+        .expect("无法保存渲染结果");
+    log::info!("渲染结果已保存到 {}", args.output);
+}
+
+//模型加载失败时输出错误（包含文件名和行号）并继续绘制默认的五边形
+fn load_model(state: &mut State, args: &Args) {
+    if let Some(path) = &args.model {
+        if let Err(e) = state.load_model(path) {
+            log::error!("{}", e);
+        }
+    }
+}
+
+//全景图转换成的立方体贴图每个面的边长
+const SKYBOX_FACE_SIZE: u32 = 512;
+
+fn load_skybox(state: &mut State, args: &Args) {
+    let Some(skybox) = &args.skybox else {
+        return;
+    };
+    let paths: Vec<&str> = skybox.split(',').collect();
+    let result = match <[&str; 6]>::try_from(paths.as_slice()) {
+        Ok(faces) => state.load_skybox_faces(&faces),
+        Err(_) if paths.len() == 1 => state.load_skybox_equirect(paths[0], SKYBOX_FACE_SIZE),
+        Err(_) => {
+            log::error!("--skybox 需要一张全景图或 6 张面的图像，实际有 {} 个路径", paths.len());
+            return;
+        }
+    };
+    if let Err(e) = result {
+        log::error!("{}", e);
+    }
+}
+
+//无窗口时只渲染一帧，但仍然会使用磁盘上的着色器，可以用来检查修改后的着色器
+fn watch_shader(state: &mut State, args: &Args) {
+    if args.hot_reload {
+        state.watch_scene_shader(SCENE_SHADER_PATH);
+    }
+}
+
+//文字的左上角在屏幕上的位置
+const TEXT_POSITION: [f32; 2] = [8.0, 8.0];
+
+fn load_font(state: &mut State, args: &Args) {
+    if let Some(path) = &args.font {
+        if let Err(e) = state.load_font(path) {
+            log::error!("{}", e);
+        }
+    }
+}
+
+//每半秒更新一次的帧率，避免数字跳得太快看不清
+#[derive(Default)]
+struct FpsCounter {
+    fps: f32,
+    frames: u32,
+    elapsed: Duration,
+}
+
+impl FpsCounter {
+    fn tick(&mut self, dt: Duration) {
+        self.frames += 1;
+        self.elapsed += dt;
+        if self.elapsed >= Duration::from_millis(500) {
+            self.fps = self.frames as f32 / self.elapsed.as_secs_f32();
+            self.frames = 0;
+            self.elapsed = Duration::ZERO;
+        }
+    }
+}
+
+//无窗口渲染之前模拟的帧数（按每秒 60 帧）
+const PARTICLE_WARMUP_FRAMES: u32 = 90;
+
+//返回是否添加了粒子
+fn add_particles(state: &mut State, args: &Args) -> bool {
+    if args.particles {
+        state.add_particles(Emitter {
+            position: (0.0, -1.0, 0.5).into(),
+            ..Default::default()
+        }, 4096, ParticleBlend::Additive);
+    }
+    args.particles
 }
 
 //现在 run() 是异步的了，main() 需要某种方式来等待它执行完成。我们可以使用 tokio 或 async-std 等异步包，但我打算使用更轻量级的 pollster
-pub async fn run() {
+async fn run(args: Args) {
     //初始化日志输出
     env_logger::init();
 
@@ -23,6 +211,14 @@ pub async fn run() {
     let window = WindowBuilder::new().build(&event_loop).unwrap();
 
     let mut state = State::new(&window).await;
+    load_model(&mut state, &args);
+    load_skybox(&mut state, &args);
+    add_particles(&mut state, &args);
+    load_font(&mut state, &args);
+    watch_shader(&mut state, &args);
+    //上一帧的时间，用来计算帧间隔
+    let mut last_render_time = Instant::now();
+    let mut fps = FpsCounter::default();
 
     //运行窗口
     event_loop.run(move |event, _, control_flow| {
@@ -30,7 +226,7 @@ pub async fn run() {
             Event::WindowEvent {
                 ref event,
                 window_id
-            } if window_id == window.id() => if !state.input(event) {
+            } if window_id == window.id() && !state.input(event) => {
                 match event {
                     WindowEvent::CloseRequested | WindowEvent::KeyboardInput {
                         input: KeyboardInput {
@@ -55,7 +251,12 @@ pub async fn run() {
             }
 
             Event::RedrawRequested(window_id) if window_id == window.id() => {
-                state.update();
+                let now = Instant::now();
+                let dt = now - last_render_time;
+                last_render_time = now;
+                fps.tick(dt);
+                state.draw_text(&format!("FPS: {:.0}", fps.fps), TEXT_POSITION, &TextStyle::default());
+                state.update(dt);
                 match state.render() {
                     Ok(_) => {}
                     // 当展示平面的上下文丢失，就需重新配置
@@ -67,10 +268,10 @@ pub async fn run() {
                 }
             }
 
-            /*Event::MainEventsCleared => {
-                 // 除非我们手动请求，RedrawRequested 将只会触发一次。
-                surface.request_redraw();
-            }*/
+            Event::MainEventsCleared => {
+                // 除非我们手动请求，RedrawRequested 将只会触发一次。相机控制器需要每一帧都更新
+                window.request_redraw();
+            }
 
             _ => {}
         }
@@ -0,0 +1,473 @@
+/*
+glTF 2.0 模型的加载
+glTF 由一个 JSON 文档和若干二进制缓冲区组成：
+.gltf 是 JSON 文本，缓冲区和图像是单独的文件（.bin、.png 等）或者以 base64 的 data URI 内嵌在 JSON 中；
+.glb 把 JSON 和一个二进制块（BIN）打包成一个文件。
+
+文档的解析由 gltf 包完成。缓冲区和图像由这里加载：图像交给 texture 模块解码，这样与 OBJ 的贴图使用相同的格式和 mipmap。
+
+glTF 的网格（mesh）由若干图元（primitive）组成，每个图元有自己的顶点、索引和材质，所以每个图元对应一个 Mesh。
+glTF 的节点与 Model::nodes 一一对应，索引相同。
+*/
+use std::path::Path;
+
+use ::gltf::buffer::Source as BufferSource;
+use ::gltf::image::Source as ImageSource;
+use ::gltf::mesh::Mode;
+use ::gltf::texture::{MagFilter, MinFilter, WrappingMode};
+use base64::Engine;
+use cgmath::{Quaternion, Vector3};
+use image::{DynamicImage, RgbaImage};
+use wgpu::{AddressMode, BindGroupLayout, Device, FilterMode, Queue, TextureFormat};
+
+use super::{default_material, solid_color_texture, Material, Mesh, MeshData, Model, ModelError, Node, PbrMaterial};
+use crate::texture::{linear_to_srgb, srgb_to_linear, Texture, TextureError, TextureOptions};
+
+impl Model {
+    //加载 .gltf 或 .glb 文件。layout 是材质绑定组的布局，基础颜色贴图作为漫反射纹理绑定
+    pub fn load_gltf<P: AsRef<Path>>(device: &Device, queue: &Queue, path: P, layout: &BindGroupLayout) -> Result<Self, ModelError> {
+        let path = path.as_ref();
+        let ::gltf::Gltf { document, blob } = ::gltf::Gltf::open(path)?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+
+        //缓冲区
+        let mut buffers: Vec<Vec<u8>> = Vec::new();
+        for buffer in document.buffers() {
+            let data = match buffer.source() {
+                BufferSource::Bin => blob.clone().ok_or_else(|| invalid(path, "缓冲区引用了 BIN 块，但文件中没有 BIN 块"))?,
+                BufferSource::Uri(uri) => read_uri(dir, uri, path)?,
+            };
+            if data.len() < buffer.length() {
+                return Err(invalid(path, &format!("缓冲区 {} 需要 {} 字节，实际只有 {} 字节", buffer.index(), buffer.length(), data.len())));
+            }
+            buffers.push(data);
+        }
+
+        //材质
+        let mut materials = Vec::new();
+        for material in document.materials() {
+            let name = material.name().map(str::to_string).unwrap_or_else(|| format!("material_{}", materials.len()));
+            let pbr = material.pbr_metallic_roughness();
+
+            //着色器只读取漫反射纹理，所以基础颜色系数直接乘到贴图上
+            let diffuse_texture = match pbr.base_color_texture() {
+                Some(info) => load_tinted_texture(device, queue, &info.texture(), &buffers, dir, path, pbr.base_color_factor())?,
+                None => solid_color_texture(device, queue, pbr.base_color_factor(), &name)?,
+            };
+            let metallic_roughness_texture = pbr.metallic_roughness_texture()
+                .map(|info| load_texture(device, queue, &info.texture(), &buffers, dir, path, false))
+                .transpose()?;
+            let normal_texture = match material.normal_texture() {
+                Some(info) => load_texture(device, queue, &info.texture(), &buffers, dir, path, false)?,
+                None => Texture::flat_normal(device, queue)?,
+            };
+            let occlusion_texture = material.occlusion_texture()
+                .map(|info| load_texture(device, queue, &info.texture(), &buffers, dir, path, false))
+                .transpose()?;
+            let emissive_texture = material.emissive_texture()
+                .map(|info| load_texture(device, queue, &info.texture(), &buffers, dir, path, true))
+                .transpose()?;
+
+            let mut material_out = Material::new(device, &name, diffuse_texture, normal_texture, layout);
+            material_out.pbr = Some(PbrMaterial {
+                base_color_factor: pbr.base_color_factor(),
+                metallic_factor: pbr.metallic_factor(),
+                roughness_factor: pbr.roughness_factor(),
+                emissive_factor: material.emissive_factor(),
+                metallic_roughness_texture,
+                occlusion_texture,
+                emissive_texture,
+            });
+            materials.push(material_out);
+        }
+
+        //网格：每个图元一个 Mesh，mesh_primitives[i] 是第 i 个 glTF 网格的所有图元在 meshes 中的索引
+        let mut meshes = Vec::new();
+        let mut mesh_primitives = Vec::new();
+        let mut fallback = None;
+        for mesh in document.meshes() {
+            let mesh_name = mesh.name().map(str::to_string).unwrap_or_else(|| format!("mesh_{}", mesh.index()));
+            let mut primitives = Vec::new();
+            for primitive in mesh.primitives() {
+                if primitive.mode() != Mode::Triangles {
+                    log::warn!("{}: 网格 {} 的图元 {} 不是三角形列表（{:?}），已忽略", path.display(), mesh_name, primitive.index(), primitive.mode());
+                    continue;
+                }
+
+                let Some(data) = mesh_data(&primitive, &buffers) else {
+                    log::warn!("{}: 网格 {} 的图元 {} 没有顶点位置，已忽略", path.display(), mesh_name, primitive.index());
+                    continue;
+                };
+
+                //没有材质的图元使用默认材质
+                let material = match primitive.material().index() {
+                    Some(index) => index,
+                    None => default_material(device, queue, &mut materials, &mut fallback, layout)?,
+                };
+                primitives.push(meshes.len());
+                meshes.push(Mesh::new(device, &format!("{}.{}", mesh_name, primitive.index()), &data, material));
+            }
+            mesh_primitives.push(primitives);
+        }
+
+        //节点
+        let nodes = document.nodes()
+            .map(|node| {
+                //旋转是 [x, y, z, w] 顺序的四元数，而 cgmath 的构造函数是 (w, x, y, z)
+                let (translation, [x, y, z, w], scale) = node.transform().decomposed();
+                Node {
+                    name: node.name().map(str::to_string).unwrap_or_else(|| format!("node_{}", node.index())),
+                    translation: Vector3::from(translation),
+                    rotation: Quaternion::new(w, x, y, z),
+                    scale: Vector3::from(scale),
+                    meshes: node.mesh().map(|mesh| mesh_primitives[mesh.index()].clone()).unwrap_or_default(),
+                    children: node.children().map(|child| child.index()).collect(),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        //根节点来自默认场景；没有指定默认场景时使用第一个场景；一个场景都没有时，所有不是子节点的节点都是根节点
+        let roots = match document.default_scene().or_else(|| document.scenes().next()) {
+            Some(scene) => scene.nodes().map(|node| node.index()).collect(),
+            None => (0..nodes.len())
+                .filter(|index| !nodes.iter().any(|node| node.children.contains(index)))
+                .collect(),
+        };
+
+        Ok(Self {
+            meshes,
+            materials,
+            nodes,
+            roots,
+        })
+    }
+}
+
+//读取图元的顶点属性和索引，没有顶点位置时返回 None。没有索引时按顶点的顺序绘制
+fn mesh_data(primitive: &::gltf::Primitive, buffers: &[Vec<u8>]) -> Option<MeshData> {
+    let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(Vec::as_slice));
+    let positions: Vec<[f32; 3]> = reader.read_positions()?.collect();
+    let indices = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect(),
+        None => (0..positions.len() as u32).collect(),
+    };
+    Some(MeshData {
+        normals: reader.read_normals().map(Iterator::collect).unwrap_or_default(),
+        tex_coords: reader.read_tex_coords(0).map(|t| t.into_f32().collect()).unwrap_or_default(),
+        tangents: reader.read_tangents().map(Iterator::collect).unwrap_or_default(),
+        positions,
+        indices,
+    })
+}
+
+fn invalid(path: &Path, message: &str) -> ModelError {
+    ModelError::InvalidData {
+        path: path.to_path_buf(),
+        message: message.to_string(),
+    }
+}
+
+//读取缓冲区或图像的 URI：data URI 直接解码，其余的当作相对于 glTF 文件所在目录的路径
+fn read_uri(dir: &Path, uri: &str, gltf_path: &Path) -> Result<Vec<u8>, ModelError> {
+    if let Some(data) = uri.strip_prefix("data:") {
+        let Some((_, encoded)) = data.split_once(";base64,") else {
+            return Err(invalid(gltf_path, "只支持 base64 编码的 data URI"));
+        };
+        return base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .map_err(|e| invalid(gltf_path, &format!("无法解码 data URI: {}", e)));
+    }
+
+    let path = dir.join(uri);
+    std::fs::read(&path).map_err(|source| ModelError::Io { path, source })
+}
+
+//加载纹理，采样方式来自 glTF 的采样器。srgb 为 false 时是线性的数据纹理（法线、金属度-粗糙度、遮蔽）
+fn load_texture(device: &Device, queue: &Queue, texture: &::gltf::Texture, buffers: &[Vec<u8>], dir: &Path, gltf_path: &Path, srgb: bool) -> Result<Texture, ModelError> {
+    let (bytes, label) = image_bytes(texture, buffers, dir, gltf_path)?;
+    let options = sampler_options(&texture.sampler(), srgb);
+    Ok(Texture::from_bytes(device, queue, &bytes, &label, &options)?)
+}
+
+//加载 sRGB 颜色纹理并乘上线性的颜色系数（glTF 的 baseColorFactor）。相乘在线性空间中进行，alpha 直接相乘
+fn load_tinted_texture(device: &Device, queue: &Queue, texture: &::gltf::Texture, buffers: &[Vec<u8>], dir: &Path, gltf_path: &Path, factor: [f32; 4]) -> Result<Texture, ModelError> {
+    if factor == [1.0; 4] {
+        return load_texture(device, queue, texture, buffers, dir, gltf_path, true);
+    }
+    let (bytes, label) = image_bytes(texture, buffers, dir, gltf_path)?;
+    let mut img = image::load_from_memory(&bytes).map_err(TextureError::from)?.to_rgba8();
+    tint_srgb(&mut img, factor);
+    let options = sampler_options(&texture.sampler(), true);
+    Ok(Texture::from_image(device, queue, &DynamicImage::ImageRgba8(img), Some(&label), &options)?)
+}
+
+fn tint_srgb(img: &mut RgbaImage, factor: [f32; 4]) {
+    for pixel in img.pixels_mut() {
+        for (i, c) in pixel.0.iter_mut().enumerate() {
+            let value = *c as f32 / 255.0;
+            let tinted = if i < 3 {
+                linear_to_srgb(srgb_to_linear(value) * factor[i].clamp(0.0, 1.0))
+            } else {
+                value * factor[3].clamp(0.0, 1.0)
+            };
+            *c = (tinted * 255.0).round() as u8;
+        }
+    }
+}
+
+//纹理的图像文件的字节和标签，图像可以在缓冲区视图中，也可以是 URI
+fn image_bytes(texture: &::gltf::Texture, buffers: &[Vec<u8>], dir: &Path, gltf_path: &Path) -> Result<(Vec<u8>, String), ModelError> {
+    let image = texture.source();
+    let label = image.name().map(str::to_string).unwrap_or_else(|| format!("image_{}", image.index()));
+    let bytes = match image.source() {
+        ImageSource::View { view, .. } => {
+            //缓冲区的长度在加载时已经检查过，但缓冲区视图可能超出缓冲区的范围
+            let range = view.offset()..view.offset().saturating_add(view.length());
+            buffers.get(view.buffer().index())
+                .and_then(|buffer| buffer.get(range.clone()))
+                .ok_or_else(|| invalid(gltf_path, &format!("图像 {} 的缓冲区视图 {} 超出了缓冲区 {} 的范围（{:?}）", image.index(), view.index(), view.buffer().index(), range)))?
+                .to_vec()
+        }
+        ImageSource::Uri { uri, .. } => read_uri(dir, uri, gltf_path)?,
+    };
+    Ok((bytes, label))
+}
+
+//把 glTF 的采样器转换为 TextureOptions。wrap_s 是 u 方向的寻址模式，wrap_t 是 v 方向的
+fn sampler_options(sampler: &::gltf::texture::Sampler, srgb: bool) -> TextureOptions {
+    let address_mode = |mode| match mode {
+        WrappingMode::ClampToEdge => AddressMode::ClampToEdge,
+        WrappingMode::MirroredRepeat => AddressMode::MirrorRepeat,
+        WrappingMode::Repeat => AddressMode::Repeat,
+    };
+    let mag_filter = match sampler.mag_filter() {
+        Some(MagFilter::Nearest) => FilterMode::Nearest,
+        Some(MagFilter::Linear) | None => FilterMode::Linear,
+    };
+    //没有 mipmap 的过滤方式不需要生成 mip 链
+    let (min_filter, mipmap_filter, generate_mipmaps) = match sampler.min_filter() {
+        Some(MinFilter::Nearest) => (FilterMode::Nearest, FilterMode::Nearest, false),
+        Some(MinFilter::Linear) => (FilterMode::Linear, FilterMode::Nearest, false),
+        Some(MinFilter::NearestMipmapNearest) => (FilterMode::Nearest, FilterMode::Nearest, true),
+        Some(MinFilter::LinearMipmapNearest) => (FilterMode::Linear, FilterMode::Nearest, true),
+        Some(MinFilter::NearestMipmapLinear) => (FilterMode::Nearest, FilterMode::Linear, true),
+        Some(MinFilter::LinearMipmapLinear) | None => (FilterMode::Linear, FilterMode::Linear, true),
+    };
+
+    TextureOptions {
+        format: if srgb { TextureFormat::Rgba8UnormSrgb } else { TextureFormat::Rgba8Unorm },
+        address_mode_u: address_mode(sampler.wrap_s()),
+        address_mode_v: address_mode(sampler.wrap_t()),
+        mag_filter,
+        min_filter,
+        mipmap_filter,
+        generate_mipmaps,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::Matrix4;
+    use crate::headless;
+    use crate::model::tests::material_layout;
+
+    //一个三角形的两个图元：第一个有法线、纹理坐标、索引和带贴图的材质，第二个只有位置，使用默认材质。
+    //三角形挂在子节点上，子节点放大 2 倍，根节点平移 (1, 0, 0)
+    const ATTRIBUTES_LEN: usize = 104;
+
+    //二进制缓冲区：位置、法线、纹理坐标、u16 索引（补齐到 4 字节），之后是 2x2 的 PNG 贴图
+    fn binary() -> Vec<u8> {
+        let floats: [f32; 24] = [
+            0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0,
+            0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0,
+            0.0, 0.0, 1.0, 0.0, 0.0, 1.0,
+        ];
+        let mut data: Vec<u8> = bytemuck::cast_slice(&floats).to_vec();
+        data.extend(bytemuck::cast_slice(&[0u16, 1, 2, 0]));
+        assert_eq!(data.len(), ATTRIBUTES_LEN);
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(2, 2, image::Rgba([255, 255, 255, 255])));
+        let mut png = std::io::Cursor::new(Vec::new());
+        image.write_to(&mut png, image::ImageOutputFormat::Png).unwrap();
+        data.extend(png.into_inner());
+        data
+    }
+
+    //uri 为 None 时缓冲区是 GLB 的 BIN 块。image_len 是贴图的缓冲区视图的长度
+    fn document(uri: Option<String>, buffer_len: usize, image_len: usize) -> String {
+        let uri = uri.map(|uri| format!(r#""uri": "{}", "#, uri)).unwrap_or_default();
+        format!(r#"{{
+            "asset": {{ "version": "2.0" }},
+            "buffers": [{{ {uri}"byteLength": {buffer_len} }}],
+            "bufferViews": [
+                {{ "buffer": 0, "byteOffset": 0, "byteLength": 36 }},
+                {{ "buffer": 0, "byteOffset": 36, "byteLength": 36 }},
+                {{ "buffer": 0, "byteOffset": 72, "byteLength": 24 }},
+                {{ "buffer": 0, "byteOffset": 96, "byteLength": 6 }},
+                {{ "buffer": 0, "byteOffset": {ATTRIBUTES_LEN}, "byteLength": {image_len} }}
+            ],
+            "accessors": [
+                {{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0] }},
+                {{ "bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC3" }},
+                {{ "bufferView": 2, "componentType": 5126, "count": 3, "type": "VEC2" }},
+                {{ "bufferView": 3, "componentType": 5123, "count": 3, "type": "SCALAR" }}
+            ],
+            "images": [{{ "bufferView": 4, "mimeType": "image/png" }}],
+            "samplers": [{{ "magFilter": 9728, "wrapS": 33071, "wrapT": 33648 }}],
+            "textures": [{{ "source": 0, "sampler": 0 }}],
+            "materials": [{{
+                "name": "tinted",
+                "pbrMetallicRoughness": {{
+                    "baseColorTexture": {{ "index": 0 }},
+                    "baseColorFactor": [1.0, 0.5, 0.5, 1.0],
+                    "metallicFactor": 0.25,
+                    "roughnessFactor": 0.75
+                }}
+            }}],
+            "meshes": [{{ "name": "triangle", "primitives": [
+                {{ "attributes": {{ "POSITION": 0, "NORMAL": 1, "TEXCOORD_0": 2 }}, "indices": 3, "material": 0 }},
+                {{ "attributes": {{ "POSITION": 0 }} }}
+            ] }}],
+            "nodes": [
+                {{ "name": "root", "translation": [1.0, 0.0, 0.0], "children": [1] }},
+                {{ "name": "child", "scale": [2.0, 2.0, 2.0], "mesh": 0 }}
+            ],
+            "scenes": [{{ "nodes": [0] }}],
+            "scene": 0
+        }}"#)
+    }
+
+    //.gltf：缓冲区以 base64 的 data URI 内嵌
+    fn embedded(image_len: Option<usize>) -> String {
+        let data = binary();
+        let uri = format!("data:application/octet-stream;base64,{}", base64::engine::general_purpose::STANDARD.encode(&data));
+        document(Some(uri), data.len(), image_len.unwrap_or(data.len() - ATTRIBUTES_LEN))
+    }
+
+    //.glb：12 字节的文件头，之后是 JSON 块和 BIN 块，每块的长度都补齐到 4 的倍数
+    fn glb() -> Vec<u8> {
+        let mut bin = binary();
+        let json = document(None, bin.len(), bin.len() - ATTRIBUTES_LEN);
+        let mut json = json.into_bytes();
+        json.resize(json.len().next_multiple_of(4), b' ');
+        bin.resize(bin.len().next_multiple_of(4), 0);
+
+        let mut glb = Vec::new();
+        glb.extend(b"glTF");
+        glb.extend(2u32.to_le_bytes());
+        glb.extend(((12 + 8 + json.len() + 8 + bin.len()) as u32).to_le_bytes());
+        glb.extend((json.len() as u32).to_le_bytes());
+        glb.extend(b"JSON");
+        glb.extend(json);
+        glb.extend((bin.len() as u32).to_le_bytes());
+        glb.extend(b"BIN\0");
+        glb.extend(bin);
+        glb
+    }
+
+    fn load(name: &str, contents: &[u8]) -> Result<Model, ModelError> {
+        let dir = std::env::temp_dir().join(format!("wgpu_01_gltf_{}_{}", name.replace('.', "_"), std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(name), contents).unwrap();
+        let (device, queue) = pollster::block_on(headless::request_device());
+        let model = Model::load(&device, &queue, dir.join(name), &material_layout(&device));
+        std::fs::remove_dir_all(&dir).unwrap();
+        model
+    }
+
+    fn check_model(model: &Model) {
+        assert_eq!(model.meshes.len(), 2);
+        assert_eq!(model.meshes.iter().map(|mesh| mesh.num_elements).collect::<Vec<_>>(), [3, 3]);
+
+        //第二个图元没有材质，使用添加在末尾的默认材质
+        assert_eq!(model.meshes.iter().map(|mesh| mesh.material).collect::<Vec<_>>(), [0, 1]);
+        assert_eq!(model.materials.len(), 2);
+        assert_eq!(model.materials[0].name, "tinted");
+        let pbr = model.materials[0].pbr.as_ref().unwrap();
+        assert_eq!(pbr.base_color_factor, [1.0, 0.5, 0.5, 1.0]);
+        assert_eq!((pbr.metallic_factor, pbr.roughness_factor), (0.25, 0.75));
+        assert!(pbr.metallic_roughness_texture.is_none());
+        assert!(model.materials[1].pbr.is_none());
+
+        //两个图元都在子节点上，变换是根节点的平移乘上子节点的缩放
+        let names: Vec<&str> = model.nodes.iter().map(|node| node.name.as_str()).collect();
+        assert_eq!(names, ["root", "child"]);
+        assert_eq!(model.roots, [0]);
+        let world = Matrix4::from_translation(Vector3::new(1.0, 0.0, 0.0)) * Matrix4::from_scale(2.0);
+        assert_eq!(model.mesh_transforms(), [(0, world), (1, world)]);
+    }
+
+    #[test]
+    fn load_embedded_gltf() {
+        check_model(&load("triangle.gltf", embedded(None).as_bytes()).unwrap());
+    }
+
+    #[test]
+    fn load_glb() {
+        check_model(&load("triangle.glb", &glb()).unwrap());
+    }
+
+    #[test]
+    fn image_view_outside_the_buffer() {
+        let json = embedded(Some(10_000));
+        match load("broken.gltf", json.as_bytes()) {
+            Err(ModelError::InvalidData { message, .. }) => assert!(message.contains("缓冲区视图"), "{}", message),
+            Err(e) => panic!("应该是无效的缓冲区视图: {}", e),
+            Ok(_) => panic!("应该是无效的缓冲区视图"),
+        }
+    }
+
+    #[test]
+    fn mesh_attributes_and_sampler() {
+        let gltf = ::gltf::Gltf::from_slice(&glb()).unwrap();
+        let buffers = [gltf.blob.clone().unwrap()];
+        let mesh = gltf.document.meshes().next().unwrap();
+        let primitives: Vec<_> = mesh.primitives().collect();
+
+        let data = mesh_data(&primitives[0], &buffers).unwrap();
+        assert_eq!(data.positions, [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]);
+        assert_eq!(data.normals, [[0.0, 0.0, 1.0]; 3]);
+        assert_eq!(data.tex_coords, [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]]);
+        assert!(data.tangents.is_empty());
+        assert_eq!(data.indices, [0, 1, 2]);
+
+        //没有索引时按顶点的顺序绘制，没有的属性是空的
+        let data = mesh_data(&primitives[1], &buffers).unwrap();
+        assert_eq!(data.indices, [0, 1, 2]);
+        assert!(data.normals.is_empty() && data.tex_coords.is_empty());
+
+        let texture = gltf.document.textures().next().unwrap();
+        let options = sampler_options(&texture.sampler(), true);
+        assert_eq!(options.address_mode_u, AddressMode::ClampToEdge);
+        assert_eq!(options.address_mode_v, AddressMode::MirrorRepeat);
+        assert_eq!(options.mag_filter, FilterMode::Nearest);
+        //没有指定缩小过滤方式时使用三线性过滤
+        assert!(options.generate_mipmaps);
+    }
+
+    #[test]
+    fn sampler_wrap_s_and_wrap_t_map_to_u_and_v() {
+        //33071 = CLAMP_TO_EDGE，33648 = MIRRORED_REPEAT
+        let json = r#"{
+            "asset": { "version": "2.0" },
+            "samplers": [{ "wrapS": 33071, "wrapT": 33648, "minFilter": 9729 }]
+        }"#;
+        let gltf = ::gltf::Gltf::from_slice(json.as_bytes()).unwrap();
+        let sampler = gltf.document.samplers().next().unwrap();
+
+        let options = sampler_options(&sampler, true);
+        assert_eq!(options.address_mode_u, AddressMode::ClampToEdge);
+        assert_eq!(options.address_mode_v, AddressMode::MirrorRepeat);
+        assert_eq!(options.format, TextureFormat::Rgba8UnormSrgb);
+        //LINEAR 没有 mipmap
+        assert!(!options.generate_mipmaps);
+    }
+
+    #[test]
+    fn base_color_factor_tints_in_linear_space() {
+        let mut img = RgbaImage::from_pixel(1, 1, image::Rgba([255, 128, 0, 255]));
+        tint_srgb(&mut img, [0.5, 1.0, 1.0, 0.5]);
+        //线性的 0.5 在 sRGB 中是 188，而不是直接相乘的 128；系数为 1 的通道不变，alpha 直接相乘
+        assert_eq!(img.get_pixel(0, 0).0, [188, 128, 0, 128]);
+    }
+}
@@ -0,0 +1,613 @@
+/*
+模型
+模型由若干网格（Mesh）、材质（Material）和节点（Node）组成。网格是一组顶点和索引，每个网格使用一种材质。
+同一种材质的所有三角形放在一个网格中，这样每种材质只需要一次绘制命令。
+节点组成一棵树，每个节点有相对于父节点的变换，并引用若干网格。OBJ 没有层级，所有网格都挂在一个根节点上。
+
+顶点缓冲区使用 buffer::Vertex 的布局，索引是 u32（模型的顶点数量很容易超过 u16 的范围）。
+材质的绑定组使用与漫反射纹理相同的绑定组布局：@binding(0)、@binding(1) 是漫反射纹理和采样器，@binding(2)、@binding(3) 是法线贴图和采样器。
+*/
+pub mod gltf;
+pub mod obj;
+
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use cgmath::{InnerSpace, Matrix4, Quaternion, SquareMatrix, Vector2, Vector3};
+use image::{DynamicImage, RgbaImage};
+use wgpu::util::DeviceExt;
+use wgpu::{BindGroup, BindGroupLayout, Buffer, BufferUsages, Device, Queue, RenderPass};
+
+use crate::buffer::Vertex;
+use crate::texture::{self, Texture, TextureError, TextureOptions};
+
+//加载模型时可能出现的错误
+#[derive(Debug)]
+pub enum ModelError {
+    //读取文件失败
+    Io { path: PathBuf, source: std::io::Error },
+    //文件内容有误，line 从 1 开始
+    Parse { path: PathBuf, line: usize, message: String },
+    //加载贴图失败
+    Texture(TextureError),
+    //glTF 文件格式有误
+    Gltf(::gltf::Error),
+    //文件引用的数据无效，例如无法解码的 data URI、不存在的缓冲区
+    InvalidData { path: PathBuf, message: String },
+}
+
+impl fmt::Display for ModelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModelError::Io { path, source } => write!(f, "无法读取模型文件 {}: {}", path.display(), source),
+            ModelError::Parse { path, line, message } => write!(f, "{}:{}: {}", path.display(), line, message),
+            ModelError::Texture(e) => write!(f, "无法加载模型的贴图: {}", e),
+            ModelError::Gltf(e) => write!(f, "无法解析 glTF 文件: {}", e),
+            ModelError::InvalidData { path, message } => write!(f, "{}: {}", path.display(), message),
+        }
+    }
+}
+
+impl Error for ModelError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ModelError::Io { source, .. } => Some(source),
+            ModelError::Parse { .. } => None,
+            ModelError::Texture(e) => Some(e),
+            ModelError::Gltf(e) => Some(e),
+            ModelError::InvalidData { .. } => None,
+        }
+    }
+}
+
+impl From<TextureError> for ModelError {
+    fn from(e: TextureError) -> Self {
+        ModelError::Texture(e)
+    }
+}
+
+impl From<::gltf::Error> for ModelError {
+    fn from(e: ::gltf::Error) -> Self {
+        ModelError::Gltf(e)
+    }
+}
+
+fn read_to_string(path: &Path) -> Result<String, ModelError> {
+    std::fs::read_to_string(path).map_err(|source| ModelError::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/*
+glTF 的 PBR 金属度-粗糙度材质参数。
+基础颜色贴图乘上 base_color_factor（没有贴图时是 base_color_factor 的纯色纹理）作为 Material 的 diffuse_texture 绑定到着色器，
+法线贴图放在 Material 中，其余贴图是线性格式，留给基于物理的光照使用。
+*/
+pub struct PbrMaterial {
+    pub base_color_factor: [f32; 4],
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    pub emissive_factor: [f32; 3],
+    //b 通道是金属度，g 通道是粗糙度
+    pub metallic_roughness_texture: Option<Texture>,
+    pub occlusion_texture: Option<Texture>,
+    //自发光贴图是颜色，使用 sRGB 格式
+    pub emissive_texture: Option<Texture>,
+}
+
+pub struct Material {
+    pub name: String,
+    pub diffuse_texture: Texture,
+    //线性格式的切线空间法线贴图，没有法线贴图时是 Texture::flat_normal
+    pub normal_texture: Texture,
+    pub bind_group: BindGroup,
+    //只有 glTF 的材质有 PBR 参数
+    pub pbr: Option<PbrMaterial>,
+}
+
+impl Material {
+    pub fn new(device: &Device, name: &str, diffuse_texture: Texture, normal_texture: Texture, layout: &BindGroupLayout) -> Self {
+        let bind_group = Self::bind_group(device, name, &diffuse_texture, &normal_texture, layout);
+        Self {
+            name: name.to_string(),
+            diffuse_texture,
+            normal_texture,
+            bind_group,
+            pbr: None,
+        }
+    }
+
+    //材质的绑定组：漫反射纹理、它的采样器、法线贴图和它的采样器。纹理由调用者持有，可以在多个绑定组之间共享
+    pub fn bind_group(device: &Device, name: &str, diffuse_texture: &Texture, normal_texture: &Texture, layout: &BindGroupLayout) -> BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&normal_texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(&normal_texture.sampler),
+                },
+            ],
+            label: Some(name),
+        })
+    }
+}
+
+/*
+网格在 CPU 上的数据，OBJ 和 glTF 的加载器都先解析成这种形式。
+除了 indices 和 positions 之外都可以为空，不为空时长度与 positions 相同。
+法线和切线为空时在 vertices 中根据三角形计算。
+*/
+#[derive(Clone, Debug, Default)]
+pub struct MeshData {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub tex_coords: Vec<[f32; 2]>,
+    //xyz 是切线方向，w 是副切线的方向（1 或 -1）
+    pub tangents: Vec<[f32; 4]>,
+    pub indices: Vec<u32>,
+}
+
+impl MeshData {
+    //打包成顶点缓冲区的格式。没有纹理坐标时使用 (0, 0)，没有法线或切线时根据三角形计算
+    pub fn vertices(&self) -> Vec<Vertex> {
+        let computed_normals;
+        let normals = if self.normals.len() == self.positions.len() {
+            &self.normals
+        } else {
+            computed_normals = self.compute_normals();
+            &computed_normals
+        };
+        let computed_tangents;
+        let tangents = if self.tangents.len() == self.positions.len() {
+            &self.tangents
+        } else {
+            computed_tangents = self.compute_tangents(normals);
+            &computed_tangents
+        };
+        self.positions.iter()
+            .zip(normals)
+            .zip(tangents)
+            .enumerate()
+            .map(|(i, ((position, normal), tangent))| {
+                //与 glTF 的约定相同：副切线 = 法线 × 切线 × w
+                let [x, y, z, w] = *tangent;
+                let bitangent = Vector3::from(*normal).cross(Vector3::new(x, y, z)) * w;
+                Vertex {
+                    position: *position,
+                    tex_coords: self.tex_coords.get(i).copied().unwrap_or([0.0, 0.0]),
+                    normal: *normal,
+                    tangent: [x, y, z],
+                    bitangent: bitangent.into(),
+                }
+            })
+            .collect()
+    }
+
+    /*
+    平滑法线：每个顶点的法线是所有使用它的三角形的法线之和再归一化。
+    叉积的长度是三角形面积的两倍，所以不归一化直接相加，大的三角形影响就更大。
+    */
+    pub fn compute_normals(&self) -> Vec<[f32; 3]> {
+        let mut normals = vec![Vector3::new(0.0f32, 0.0, 0.0); self.positions.len()];
+        for triangle in self.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| Vector3::from(self.positions[triangle[i] as usize]));
+            let normal = (b - a).cross(c - a);
+            for &index in triangle {
+                normals[index as usize] += normal;
+            }
+        }
+        normals.into_iter()
+            .map(|n| if n.magnitude2() > 0.0 { n.normalize().into() } else { [0.0, 0.0, 1.0] })
+            .collect()
+    }
+
+    /*
+    切线：纹理坐标 u 增大的方向。对每个三角形，两条边 e1、e2 与纹理坐标的差 (du1, dv1)、(du2, dv2) 满足
+    e1 = du1 * T + dv1 * B
+    e2 = du2 * T + dv2 * B
+    解这个方程组得到切线 T 和副切线 B。与法线一样把共用顶点的三角形的结果加起来，
+    最后去掉切线在法线方向上的分量（Gram-Schmidt 正交化），w 记录副切线相对于 法线 × 切线 是否镜像（纹理镜像时为 -1）。
+    normals 是每个顶点的法线，长度与 positions 相同。
+    */
+    pub fn compute_tangents(&self, normals: &[[f32; 3]]) -> Vec<[f32; 4]> {
+        let mut tangents = vec![Vector3::new(0.0f32, 0.0, 0.0); self.positions.len()];
+        let mut bitangents = tangents.clone();
+        if self.tex_coords.len() == self.positions.len() {
+            for triangle in self.indices.chunks_exact(3) {
+                let [p0, p1, p2] = [0, 1, 2].map(|i| Vector3::from(self.positions[triangle[i] as usize]));
+                let [uv0, uv1, uv2] = [0, 1, 2].map(|i| Vector2::from(self.tex_coords[triangle[i] as usize]));
+                let (e1, e2) = (p1 - p0, p2 - p0);
+                let (d1, d2) = (uv1 - uv0, uv2 - uv0);
+                let det = d1.x * d2.y - d2.x * d1.y;
+                //纹理坐标退化成一条线或一个点，无法确定方向
+                if det.abs() <= f32::EPSILON {
+                    continue;
+                }
+                let tangent = (e1 * d2.y - e2 * d1.y) / det;
+                let bitangent = (e2 * d1.x - e1 * d2.x) / det;
+                for &index in triangle {
+                    tangents[index as usize] += tangent;
+                    bitangents[index as usize] += bitangent;
+                }
+            }
+        }
+
+        normals.iter()
+            .zip(tangents.into_iter().zip(bitangents))
+            .map(|(normal, (tangent, bitangent))| {
+                let n = Vector3::from(*normal);
+                let mut t = tangent - n * n.dot(tangent);
+                //没有纹理坐标时随便取一个与法线垂直的方向
+                if t.magnitude2() <= f32::EPSILON {
+                    let axis = if n.x.abs() < 0.9 { Vector3::unit_x() } else { Vector3::unit_y() };
+                    t = axis - n * n.dot(axis);
+                }
+                let t = t.normalize();
+                let w = if n.cross(t).dot(bitangent) < 0.0 { -1.0 } else { 1.0 };
+                [t.x, t.y, t.z, w]
+            })
+            .collect()
+    }
+}
+
+pub struct Mesh {
+    pub name: String,
+    pub vertex_buffer: Buffer,
+    pub index_buffer: Buffer,
+    pub num_elements: u32,
+    //在 Model::materials 中的索引
+    pub material: usize,
+}
+
+impl Mesh {
+    pub fn new(device: &Device, name: &str, data: &MeshData, material: usize) -> Self {
+        let vertices = data.vertices();
+        let indices = &data.indices;
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{} Vertex Buffer", name)),
+            contents: bytemuck::cast_slice(&vertices),
+            usage: BufferUsages::VERTEX,
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{} Index Buffer", name)),
+            contents: bytemuck::cast_slice(indices),
+            usage: BufferUsages::INDEX,
+        });
+
+        Self {
+            name: name.to_string(),
+            vertex_buffer,
+            index_buffer,
+            num_elements: indices.len() as u32,
+            material,
+        }
+    }
+}
+
+//场景树中的一个节点，变换相对于父节点
+#[derive(Clone, Debug)]
+pub struct Node {
+    pub name: String,
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+    //在 Model::meshes 中的索引
+    pub meshes: Vec<usize>,
+    //在 Model::nodes 中的索引
+    pub children: Vec<usize>,
+}
+
+impl Node {
+    //不带变换的节点
+    pub fn new(name: &str, meshes: Vec<usize>) -> Self {
+        Self {
+            name: name.to_string(),
+            translation: Vector3::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::new(1.0, 0.0, 0.0, 0.0),
+            scale: Vector3::new(1.0, 1.0, 1.0),
+            meshes,
+            children: Vec::new(),
+        }
+    }
+
+    //相对于父节点的变换矩阵：先缩放，再旋转，最后平移
+    pub fn local_matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.translation)
+            * Matrix4::from(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+}
+
+pub struct Model {
+    pub meshes: Vec<Mesh>,
+    pub materials: Vec<Material>,
+    pub nodes: Vec<Node>,
+    //场景树的根节点
+    pub roots: Vec<usize>,
+}
+
+impl Model {
+    //根据扩展名选择加载器：.obj 或 .gltf/.glb
+    pub fn load<P: AsRef<Path>>(device: &Device, queue: &Queue, path: P, layout: &BindGroupLayout) -> Result<Self, ModelError> {
+        let path = path.as_ref();
+        let extension = path.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("gltf") | Some("glb") => Self::load_gltf(device, queue, path, layout),
+            _ => Self::load_obj(device, queue, path, layout),
+        }
+    }
+
+    /*
+    加载 OBJ 模型和它引用的 MTL 材质。
+    layout 是材质绑定组的布局（漫反射纹理、法线贴图和它们的采样器）。没有漫反射贴图的材质使用 Kd 颜色的 1x1 纹理，
+    没有指定材质的面使用白色的默认材质。
+    */
+    pub fn load_obj<P: AsRef<Path>>(device: &Device, queue: &Queue, path: P, layout: &BindGroupLayout) -> Result<Self, ModelError> {
+        let path = path.as_ref();
+        let data = obj::parse_obj(&read_to_string(path)?, path)?;
+
+        let mut mtl_materials = Vec::new();
+        for lib in &data.material_libs {
+            mtl_materials.extend(obj::parse_mtl(&read_to_string(lib)?, lib)?);
+        }
+
+        //模型的纹理坐标经常超出 0..1，需要重复平铺
+        let options = TextureOptions {
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            ..Default::default()
+        };
+        //法线贴图存储的是方向而不是颜色，必须用线性格式读取
+        let normal_options = TextureOptions {
+            format: TextureOptions::linear().format,
+            ..options
+        };
+        let mut materials = Vec::with_capacity(mtl_materials.len() + 1);
+        for mtl in &mtl_materials {
+            let texture = match &mtl.diffuse_map {
+                Some(map) => Texture::from_path(device, queue, map, &options)?,
+                None => {
+                    let [r, g, b] = mtl.diffuse;
+                    solid_color_texture(device, queue, [r, g, b, mtl.dissolve], &mtl.name)?
+                }
+            };
+            let normal_texture = match &mtl.normal_map {
+                Some(map) => Texture::from_path(device, queue, map, &normal_options)?,
+                None => Texture::flat_normal(device, queue)?,
+            };
+            materials.push(Material::new(device, &mtl.name, texture, normal_texture, layout));
+        }
+
+        let mut meshes = Vec::with_capacity(data.meshes.len());
+        let mut fallback = None;
+        for mesh in &data.meshes {
+            let material = match &mesh.material {
+                Some(name) => match mtl_materials.iter().position(|m| &m.name == name) {
+                    Some(index) => index,
+                    None => {
+                        log::warn!("{}: 找不到材质 {}，使用默认材质", path.display(), name);
+                        default_material(device, queue, &mut materials, &mut fallback, layout)?
+                    }
+                },
+                None => default_material(device, queue, &mut materials, &mut fallback, layout)?,
+            };
+
+            let name = mesh.material.as_deref().unwrap_or("default");
+            meshes.push(Mesh::new(device, name, &mesh.data, material));
+        }
+
+        let root = Node::new("root", (0..meshes.len()).collect());
+        Ok(Self {
+            meshes,
+            materials,
+            nodes: vec![root],
+            roots: vec![0],
+        })
+    }
+
+    //每个要绘制的网格，以及它所在节点相对于模型的变换（父节点的变换乘以自己的变换）
+    pub fn mesh_transforms(&self) -> Vec<(usize, Matrix4<f32>)> {
+        let mut result = Vec::new();
+        let mut stack: Vec<(usize, Matrix4<f32>)> = self.roots.iter().rev().map(|&root| (root, Matrix4::identity())).collect();
+        while let Some((index, parent)) = stack.pop() {
+            let node = &self.nodes[index];
+            let world = parent * node.local_matrix();
+            result.extend(node.meshes.iter().map(|&mesh| (mesh, world)));
+            stack.extend(node.children.iter().rev().map(|&child| (child, world)));
+        }
+        result
+    }
+}
+
+//默认材质只创建一次，放在材质列表的末尾。fallback 记录它的索引（不能按名字查找，文件中的材质也可能叫 default）
+fn default_material(device: &Device, queue: &Queue, materials: &mut Vec<Material>, fallback: &mut Option<usize>, layout: &BindGroupLayout) -> Result<usize, ModelError> {
+    if let Some(index) = *fallback {
+        return Ok(index);
+    }
+    const NAME: &str = "default";
+    let texture = solid_color_texture(device, queue, [1.0; 4], NAME)?;
+    let normal_texture = Texture::flat_normal(device, queue)?;
+    materials.push(Material::new(device, NAME, texture, normal_texture, layout));
+    *fallback = Some(materials.len() - 1);
+    Ok(materials.len() - 1)
+}
+
+//只有一个像素的纹理。MTL 和 glTF 中的颜色是线性的，而纹理是 sRGB 格式，所以先转换到 sRGB
+fn solid_color_texture(device: &Device, queue: &Queue, color: [f32; 4], label: &str) -> Result<Texture, ModelError> {
+    let to_srgb = |c: f32| (texture::linear_to_srgb(c.clamp(0.0, 1.0)) * 255.0).round() as u8;
+    //alpha 不是颜色，不需要转换
+    let alpha = (color[3].clamp(0.0, 1.0) * 255.0).round() as u8;
+    let pixel = image::Rgba([to_srgb(color[0]), to_srgb(color[1]), to_srgb(color[2]), alpha]);
+    let img = DynamicImage::ImageRgba8(RgbaImage::from_pixel(1, 1, pixel));
+    Ok(Texture::from_image(device, queue, &img, Some(label), &TextureOptions::default())?)
+}
+
+/*
+在渲染通道上绘制模型。
+调用前需要设置好管线、相机的绑定组（@group(1)）和实例缓冲区（槽 1），这里设置每个网格的顶点、索引缓冲区和材质的绑定组（@group(0)）。
+draw_model_instanced 不考虑节点的变换，适用于只有一个根节点的模型（例如 OBJ）；带层级的模型用 mesh_transforms 为每个网格准备实例。
+*/
+pub trait DrawModel<'a> {
+    fn draw_mesh_instanced(&mut self, mesh: &'a Mesh, material: &'a Material, instances: Range<u32>);
+    //只设置顶点和索引缓冲区，不设置材质，用于阴影贴图等只写入深度的渲染通道
+    fn draw_mesh_geometry_instanced(&mut self, mesh: &'a Mesh, instances: Range<u32>);
+    fn draw_model_instanced(&mut self, model: &'a Model, instances: Range<u32>);
+}
+
+impl<'a, 'b> DrawModel<'b> for RenderPass<'a>
+where
+    'b: 'a,
+{
+    fn draw_mesh_instanced(&mut self, mesh: &'b Mesh, material: &'b Material, instances: Range<u32>) {
+        self.set_bind_group(0, &material.bind_group, &[]);
+        self.draw_mesh_geometry_instanced(mesh, instances);
+    }
+
+    fn draw_mesh_geometry_instanced(&mut self, mesh: &'b Mesh, instances: Range<u32>) {
+        self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+        self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        self.draw_indexed(0..mesh.num_elements, 0, instances);
+    }
+
+    fn draw_model_instanced(&mut self, model: &'b Model, instances: Range<u32>) {
+        for mesh in &model.meshes {
+            let material = &model.materials[mesh.material];
+            self.draw_mesh_instanced(mesh, material, instances.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headless;
+    use crate::pipeline::PipelineLayoutBuilder;
+
+    //材质绑定组的布局与场景使用的一致，来自光照着色器的第 0 组
+    pub(super) fn material_layout(device: &Device) -> BindGroupLayout {
+        let reflected = PipelineLayoutBuilder::reflect(include_str!("../light/shader.wgsl")).expect("光照着色器可以解析");
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("material_layout"),
+            entries: reflected.entries(0),
+        })
+    }
+
+    fn assert_close(actual: [f32; 3], expected: [f32; 3]) {
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-5, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    //xy 平面上的正方形，两个三角形共用 0 和 2 两个顶点，逆时针时法线是 +Z
+    fn quad(tex_coords: [[f32; 2]; 4]) -> MeshData {
+        MeshData {
+            positions: vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]],
+            tex_coords: tex_coords.to_vec(),
+            indices: vec![0, 1, 2, 0, 2, 3],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn axis_aligned_quad_tangents() {
+        //u 沿 +X 增大，v 沿 +Y 增大
+        let vertices = quad([[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]]).vertices();
+        for vertex in &vertices {
+            assert_close(vertex.normal, [0.0, 0.0, 1.0]);
+            assert_close(vertex.tangent, [1.0, 0.0, 0.0]);
+            assert_close(vertex.bitangent, [0.0, 1.0, 0.0]);
+        }
+    }
+
+    #[test]
+    fn mirrored_tex_coords_flip_handedness() {
+        //u 沿 -X 增大：切线是 -X，法线 × 切线 是 -Y，而 v 仍然沿 +Y 增大，所以 w 是 -1
+        let data = quad([[1.0, 0.0], [0.0, 0.0], [0.0, 1.0], [1.0, 1.0]]);
+        let tangents = data.compute_tangents(&data.compute_normals());
+        for tangent in &tangents {
+            assert_close([tangent[0], tangent[1], tangent[2]], [-1.0, 0.0, 0.0]);
+            assert_eq!(tangent[3], -1.0);
+        }
+        for vertex in data.vertices() {
+            assert_close(vertex.bitangent, [0.0, 1.0, 0.0]);
+        }
+    }
+
+    #[test]
+    fn degenerate_tex_coords_do_not_produce_nan() {
+        //所有顶点的纹理坐标相同，没有纹理坐标时也一样
+        for tex_coords in [vec![[0.5, 0.5]; 3], Vec::new()] {
+            let data = MeshData {
+                positions: vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+                tex_coords,
+                indices: vec![0, 1, 2],
+                ..Default::default()
+            };
+            for vertex in data.vertices() {
+                let tangent = Vector3::from(vertex.tangent);
+                assert!(vertex.tangent.iter().chain(&vertex.bitangent).all(|c| c.is_finite()), "{:?}", vertex);
+                assert!((tangent.magnitude() - 1.0).abs() < 1e-5);
+                assert!(tangent.dot(Vector3::from(vertex.normal)).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn shared_vertices_accumulate_area_weighted_normals() {
+        //三角形 0 1 2 在 xy 平面上，法线 +Z；三角形 0 2 3 在 yz 平面上，法线 +X，面积是前者的两倍
+        let data = MeshData {
+            positions: vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 2.0]],
+            indices: vec![0, 1, 2, 0, 2, 3],
+            ..Default::default()
+        };
+        let normals = data.compute_normals();
+        let shared = 1.0 / 5.0f32.sqrt();
+        assert_close(normals[0], [2.0 * shared, 0.0, shared]);
+        assert_close(normals[1], [0.0, 0.0, 1.0]);
+        assert_close(normals[2], [2.0 * shared, 0.0, shared]);
+        assert_close(normals[3], [1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn default_material_is_tracked_by_index_not_name() {
+        //文件中的材质恰好也叫 default，不能把它当成默认材质
+        let dir = std::env::temp_dir().join(format!("wgpu_01_default_material_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("scene.mtl"), "newmtl default\nKd 1 0 0\n").unwrap();
+        std::fs::write(
+            dir.join("scene.obj"),
+            "mtllib scene.mtl\n\
+             v 0 0 0\nv 1 0 0\nv 0 1 0\n\
+             f 1 2 3\n\
+             usemtl default\nf 1 2 3\n\
+             usemtl missing\nf 1 2 3\n",
+        )
+        .unwrap();
+
+        let (device, queue) = pollster::block_on(headless::request_device());
+        let layout = material_layout(&device);
+        let model = Model::load_obj(&device, &queue, dir.join("scene.obj"), &layout);
+        std::fs::remove_dir_all(&dir).unwrap();
+        let model = model.unwrap();
+
+        //MTL 中的 default 和一个白色的默认材质；没有材质和找不到材质的面共用后者
+        assert_eq!(model.materials.len(), 2);
+        let materials: Vec<usize> = model.meshes.iter().map(|m| m.material).collect();
+        assert_eq!(materials, [1, 0, 1]);
+    }
+}
@@ -0,0 +1,390 @@
+/*
+Wavefront OBJ 与 MTL 文件的解析
+OBJ 是一种文本格式，每行一条语句，第一个单词是语句的类型：
+v x y z          顶点位置
+vt u v           纹理坐标
+vn x y z         法线
+f v/vt/vn ...    面，每个顶点由位置、纹理坐标和法线的索引组成（从 1 开始，负数表示从末尾倒数），纹理坐标和法线可以省略
+usemtl name      之后的面使用名为 name 的材质
+mtllib file.mtl  材质定义所在的文件
+
+OBJ 中的位置、纹理坐标和法线是分别编号的，而 GPU 的顶点缓冲区中一个顶点必须包含所有属性，
+所以要把每个不同的（位置, 纹理坐标, 法线）组合变成一个顶点，相同的组合只保留一个，再用索引引用它。
+
+这里只做解析，不涉及 GPU，创建缓冲区和纹理见 model 模块。
+*/
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use super::{MeshData, ModelError};
+
+//解析后的网格：使用同一种材质的所有面。OBJ 没有切线，data.tangents 总是空的
+#[derive(Debug, Default)]
+pub struct ObjMesh {
+    //材质名，没有 usemtl 的面为 None
+    pub material: Option<String>,
+    pub data: MeshData,
+}
+
+#[derive(Debug)]
+pub struct ObjData {
+    pub meshes: Vec<ObjMesh>,
+    //mtllib 引用的材质文件，相对于 OBJ 文件所在的目录
+    pub material_libs: Vec<PathBuf>,
+}
+
+//MTL 文件中的一个材质
+#[derive(Clone, Debug)]
+pub struct MtlMaterial {
+    pub name: String,
+    pub ambient: [f32; 3],
+    pub diffuse: [f32; 3],
+    pub specular: [f32; 3],
+    pub shininess: f32,
+    //不透明度，1.0 为完全不透明
+    pub dissolve: f32,
+    //漫反射贴图与法线贴图的路径，相对于 MTL 文件所在的目录
+    pub diffuse_map: Option<PathBuf>,
+    pub normal_map: Option<PathBuf>,
+}
+
+impl MtlMaterial {
+    fn new(name: String) -> Self {
+        Self {
+            name,
+            ambient: [0.0; 3],
+            diffuse: [1.0; 3],
+            specular: [0.0; 3],
+            shininess: 0.0,
+            dissolve: 1.0,
+            diffuse_map: None,
+            normal_map: None,
+        }
+    }
+}
+
+//一行语句的解析上下文，用来生成带行号的错误
+struct Line<'a> {
+    path: &'a Path,
+    number: usize,
+}
+
+impl Line<'_> {
+    fn error(&self, message: String) -> ModelError {
+        ModelError::Parse {
+            path: self.path.to_path_buf(),
+            line: self.number,
+            message,
+        }
+    }
+
+    fn floats<const N: usize>(&self, keyword: &str, args: &[&str]) -> Result<[f32; N], ModelError> {
+        //vt 的第三个分量和 v 的 w 分量是可选的，多余的参数忽略
+        if args.len() < N {
+            return Err(self.error(format!("{} 需要 {} 个数值，实际只有 {} 个", keyword, N, args.len())));
+        }
+        let mut values = [0.0; N];
+        for (value, arg) in values.iter_mut().zip(args) {
+            *value = arg.parse().map_err(|_| self.error(format!("{} 的参数 \"{}\" 不是有效的数值", keyword, arg)))?;
+        }
+        Ok(values)
+    }
+
+    //把 OBJ 的索引（从 1 开始，负数从末尾倒数）转换为从 0 开始的索引
+    fn index(&self, kind: &str, value: &str, count: usize) -> Result<usize, ModelError> {
+        let index: i64 = value.parse().map_err(|_| self.error(format!("{}索引 \"{}\" 不是有效的整数", kind, value)))?;
+        let resolved = if index > 0 { index - 1 } else { count as i64 + index };
+        if index == 0 || resolved < 0 || resolved >= count as i64 {
+            return Err(self.error(format!("{}索引 {} 超出范围（共 {} 个）", kind, index, count)));
+        }
+        Ok(resolved as usize)
+    }
+}
+
+//面的一个顶点：位置、纹理坐标和法线的索引
+type VertexKey = (usize, Option<usize>, Option<usize>);
+
+//正在构建的网格，以及顶点去重用的表
+#[derive(Default)]
+struct MeshBuilder {
+    mesh: ObjMesh,
+    unique: HashMap<VertexKey, u32>,
+}
+
+impl MeshBuilder {
+    fn vertex(&mut self, key: VertexKey, positions: &[[f32; 3]], tex_coords: &[[f32; 2]], normals: &[[f32; 3]]) -> u32 {
+        let mesh = &mut self.mesh.data;
+        *self.unique.entry(key).or_insert_with(|| {
+            let (position, tex_coord, normal) = key;
+            mesh.positions.push(positions[position]);
+            //OBJ 的纹理坐标原点在左下角，而 wgpu 的在左上角，所以翻转 v
+            let [u, v] = tex_coord.map_or([0.0, 0.0], |i| tex_coords[i]);
+            mesh.tex_coords.push([u, 1.0 - v]);
+            if let Some(normal) = normal {
+                mesh.normals.push(normals[normal]);
+            }
+            mesh.positions.len() as u32 - 1
+        })
+    }
+}
+
+//解析 OBJ 文本。path 只用于错误信息和解析 mtllib 的相对路径
+pub fn parse_obj(source: &str, path: &Path) -> Result<ObjData, ModelError> {
+    let mut positions: Vec<[f32; 3]> = Vec::new();
+    let mut tex_coords: Vec<[f32; 2]> = Vec::new();
+    let mut normals: Vec<[f32; 3]> = Vec::new();
+    let mut material_libs = Vec::new();
+
+    //按材质分组的网格，保持材质第一次出现的顺序
+    let mut builders: Vec<MeshBuilder> = Vec::new();
+    let mut current: Option<usize> = None;
+    let mut current_material: Option<String> = None;
+
+    for (i, text) in source.lines().enumerate() {
+        let line = Line { path, number: i + 1 };
+        let text = text.split('#').next().unwrap_or("").trim();
+        let mut words = text.split_whitespace();
+        let Some(keyword) = words.next() else {
+            continue;
+        };
+        let args: Vec<&str> = words.collect();
+
+        match keyword {
+            "v" => positions.push(line.floats("v", &args)?),
+            "vt" => {
+                //有些导出工具只写一个分量
+                let [u] = line.floats::<1>("vt", &args)?;
+                let v = if args.len() > 1 { line.floats::<2>("vt", &args)?[1] } else { 0.0 };
+                tex_coords.push([u, v]);
+            }
+            "vn" => normals.push(line.floats("vn", &args)?),
+            "f" => {
+                if args.len() < 3 {
+                    return Err(line.error(format!("面至少需要 3 个顶点，实际只有 {} 个", args.len())));
+                }
+                //这个材质的第一个面：新建一个网格
+                let builder_index = *current.get_or_insert_with(|| {
+                    let mut builder = MeshBuilder::default();
+                    builder.mesh.material = current_material.clone();
+                    builders.push(builder);
+                    builders.len() - 1
+                });
+                let builder = &mut builders[builder_index];
+
+                let mut face = Vec::with_capacity(args.len());
+                for arg in &args {
+                    let mut parts = arg.split('/');
+                    let position = line.index("位置", parts.next().unwrap_or(""), positions.len())?;
+                    let tex_coord = match parts.next() {
+                        Some("") | None => None,
+                        Some(value) => Some(line.index("纹理坐标", value, tex_coords.len())?),
+                    };
+                    let normal = match parts.next() {
+                        Some("") | None => None,
+                        Some(value) => Some(line.index("法线", value, normals.len())?),
+                    };
+                    face.push(builder.vertex((position, tex_coord, normal), &positions, &tex_coords, &normals));
+                }
+                //多边形按扇形拆分为三角形：(0, 1, 2), (0, 2, 3) ...
+                for j in 1..face.len() - 1 {
+                    builder.mesh.data.indices.extend_from_slice(&[face[0], face[j], face[j + 1]]);
+                }
+            }
+            "usemtl" => {
+                let name = args.join(" ");
+                current = builders.iter().position(|b| b.mesh.material.as_deref() == Some(name.as_str()));
+                current_material = Some(name);
+            }
+            "mtllib" => {
+                if args.is_empty() {
+                    return Err(line.error("mtllib 缺少文件名".to_string()));
+                }
+                let dir = path.parent().unwrap_or(Path::new(""));
+                material_libs.push(dir.join(args.join(" ")));
+            }
+            //对象、分组和平滑组不影响按材质拆分网格；线和点不是三角形，忽略
+            "o" | "g" | "s" | "l" | "p" => {}
+            _ => log::debug!("{}:{}: 忽略不支持的语句 {}", path.display(), line.number, keyword),
+        }
+    }
+
+    //如果一个网格中只有部分顶点带法线，法线就无法与顶点一一对应，这时丢弃法线
+    let meshes = builders.into_iter()
+        .map(|b| b.mesh)
+        .filter(|m| !m.data.indices.is_empty())
+        .map(|mut m| {
+            if m.data.normals.len() != m.data.positions.len() {
+                m.data.normals.clear();
+            }
+            m
+        })
+        .collect();
+
+    Ok(ObjData { meshes, material_libs })
+}
+
+//解析 MTL 文本，贴图路径相对于 path 所在的目录
+pub fn parse_mtl(source: &str, path: &Path) -> Result<Vec<MtlMaterial>, ModelError> {
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let mut materials: Vec<MtlMaterial> = Vec::new();
+
+    for (i, text) in source.lines().enumerate() {
+        let line = Line { path, number: i + 1 };
+        let text = text.split('#').next().unwrap_or("").trim();
+        let mut words = text.split_whitespace();
+        let Some(keyword) = words.next() else {
+            continue;
+        };
+        let args: Vec<&str> = words.collect();
+
+        if keyword == "newmtl" {
+            if args.is_empty() {
+                return Err(line.error("newmtl 缺少材质名".to_string()));
+            }
+            materials.push(MtlMaterial::new(args.join(" ")));
+            continue;
+        }
+
+        let Some(material) = materials.last_mut() else {
+            return Err(line.error(format!("{} 出现在第一个 newmtl 之前", keyword)));
+        };
+
+        match keyword {
+            "Ka" => material.ambient = line.floats("Ka", &args)?,
+            "Kd" => material.diffuse = line.floats("Kd", &args)?,
+            "Ks" => material.specular = line.floats("Ks", &args)?,
+            "Ns" => material.shininess = line.floats::<1>("Ns", &args)?[0],
+            "d" => material.dissolve = line.floats::<1>("d", &args)?[0],
+            //Tr 是透明度，与 d 相反
+            "Tr" => material.dissolve = 1.0 - line.floats::<1>("Tr", &args)?[0],
+            "map_Kd" => material.diffuse_map = Some(dir.join(texture_file(&line, keyword, &args)?)),
+            "map_Bump" | "map_bump" | "bump" | "norm" => material.normal_map = Some(dir.join(texture_file(&line, keyword, &args)?)),
+            _ => log::debug!("{}:{}: 忽略不支持的材质属性 {}", path.display(), line.number, keyword),
+        }
+    }
+
+    Ok(materials)
+}
+
+//贴图语句可以在文件名之前带选项（例如 -bm 1.0、-o 0.5 0.5），选项之后的所有参数是文件名（文件名中可以有空格）
+fn texture_file(line: &Line, keyword: &str, args: &[&str]) -> Result<String, ModelError> {
+    let mut rest = args;
+    while let Some((option, tail)) = rest.split_first().filter(|(option, _)| option.starts_with('-')) {
+        rest = match *option {
+            //-mm base gain
+            "-mm" => tail.get(2..).unwrap_or_default(),
+            //-o/-s/-t u [v [w]]：后面最多 3 个数值
+            "-o" | "-s" | "-t" => {
+                let numbers = tail.iter().take(3).take_while(|arg| arg.parse::<f32>().is_ok()).count().max(1);
+                tail.get(numbers..).unwrap_or_default()
+            }
+            //-blendu on、-bm 1.0、-clamp on、-imfchan m、-texres 512 等只有一个参数
+            _ => tail.get(1..).unwrap_or_default(),
+        };
+    }
+    if rest.is_empty() {
+        return Err(line.error(format!("{} 缺少文件名", keyword)));
+    }
+    Ok(rest.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn obj(source: &str) -> ObjData {
+        parse_obj(source, Path::new("models/test.obj")).unwrap()
+    }
+
+    //解析失败时的行号和错误信息
+    fn obj_error(source: &str) -> (usize, String) {
+        match parse_obj(source, Path::new("test.obj")) {
+            Err(ModelError::Parse { line, message, .. }) => (line, message),
+            other => panic!("应该解析失败: {:?}", other.map(|data| data.meshes.len())),
+        }
+    }
+
+    const QUAD: &str = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n";
+
+    #[test]
+    fn polygons_are_triangulated_as_fans() {
+        let data = obj(&format!("{}v 0.5 2 0\nf 1 2 3 4\nf 1 2 3 4 5\n", QUAD));
+        assert_eq!(data.meshes.len(), 1);
+        let indices = &data.meshes[0].data.indices;
+        assert_eq!(indices, &[0, 1, 2, 0, 2, 3, 0, 1, 2, 0, 2, 3, 0, 3, 4]);
+    }
+
+    #[test]
+    fn negative_indices_count_from_the_end() {
+        let data = obj(&format!("{}f -4 -3 -2 -1\nv 5 5 5\nf 1 -1 -2\n", QUAD));
+        let mesh = &data.meshes[0].data;
+        assert_eq!(mesh.indices, [0, 1, 2, 0, 2, 3, 0, 4, 3]);
+        assert_eq!(mesh.positions[4], [5.0, 5.0, 5.0]);
+    }
+
+    #[test]
+    fn identical_vertices_are_shared() {
+        let data = obj(&format!("{}vt 0 0\nvt 1 1\nf 1/1 2/1 3/1\nf 1/1 3/1 4/1\nf 1/2 2/1 3/1\n", QUAD));
+        let mesh = &data.meshes[0].data;
+        //1/1、2/1、3/1、4/1 和 1/2 五个不同的组合
+        assert_eq!(mesh.positions.len(), 5);
+        assert_eq!(mesh.indices, [0, 1, 2, 0, 2, 3, 4, 1, 2]);
+        //v 被翻转
+        assert_eq!(mesh.tex_coords[4], [1.0, 0.0]);
+    }
+
+    #[test]
+    fn normals_are_dropped_when_only_some_vertices_have_them() {
+        let data = obj(&format!("{}vn 0 0 1\nf 1//1 2//1 3//1\n", QUAD));
+        assert_eq!(data.meshes[0].data.normals.len(), 3);
+        let data = obj(&format!("{}vn 0 0 1\nf 1//1 2//1 3//1\nf 1 3 4\n", QUAD));
+        assert!(data.meshes[0].data.normals.is_empty());
+    }
+
+    #[test]
+    fn faces_are_grouped_by_material() {
+        let data = obj(&format!("mtllib my materials.mtl\n{}f 1 2 3\nusemtl red\nf 1 3 4\nusemtl blue\nf 1 2 3\nusemtl red\nf 2 3 4\n", QUAD));
+        let materials: Vec<_> = data.meshes.iter().map(|m| (m.material.as_deref(), m.data.indices.len())).collect();
+        assert_eq!(materials, [(None, 3), (Some("red"), 6), (Some("blue"), 3)]);
+        assert_eq!(data.material_libs, [PathBuf::from("models/my materials.mtl")]);
+    }
+
+    #[test]
+    fn errors_report_the_line() {
+        assert_eq!(obj_error("v 0 0 0\nv 1 0\n").0, 2);
+        assert_eq!(obj_error("v 0 0 0\n# comment\nv 1 x 0\n").0, 3);
+        let (line, message) = obj_error(&format!("{}\nf 1 2 5\n", QUAD));
+        assert_eq!(line, 6);
+        assert!(message.contains("5"), "{}", message);
+        assert_eq!(obj_error(&format!("{}f 0 1 2\n", QUAD)).0, 5);
+        assert_eq!(obj_error(&format!("{}f -5 1 2\n", QUAD)).0, 5);
+        assert_eq!(obj_error(&format!("{}f 1 2\n", QUAD)).0, 5);
+        assert_eq!(obj_error(&format!("{}f 1/1 2 3\n", QUAD)).0, 5);
+    }
+
+    #[test]
+    fn mtl_materials() {
+        let source = "# comment\nnewmtl red paint\nKd 1 0 0\nNs 32\nTr 0.25\nmap_Kd -o 0.5 0.5 -bm 1.0 my texture.png\nbump -mm 0 1 normal map.png\nnewmtl plain\nmap_Kd -clamp on plain.png\n";
+        let materials = parse_mtl(source, Path::new("models/test.mtl")).unwrap();
+        assert_eq!(materials.len(), 2);
+        let red = &materials[0];
+        assert_eq!(red.name, "red paint");
+        assert_eq!(red.diffuse, [1.0, 0.0, 0.0]);
+        assert_eq!(red.shininess, 32.0);
+        assert_eq!(red.dissolve, 0.75);
+        assert_eq!(red.diffuse_map, Some(PathBuf::from("models/my texture.png")));
+        assert_eq!(red.normal_map, Some(PathBuf::from("models/normal map.png")));
+        assert_eq!(materials[1].diffuse_map, Some(PathBuf::from("models/plain.png")));
+    }
+
+    #[test]
+    fn mtl_errors_report_the_line() {
+        let error_line = |source: &str| match parse_mtl(source, Path::new("test.mtl")) {
+            Err(ModelError::Parse { line, .. }) => line,
+            other => panic!("应该解析失败: {:?}", other),
+        };
+        assert_eq!(error_line("Kd 1 1 1\n"), 1);
+        assert_eq!(error_line("newmtl a\nKd 1 1\n"), 2);
+        assert_eq!(error_line("newmtl a\n\nmap_Kd -bm 1.0\n"), 3);
+    }
+}
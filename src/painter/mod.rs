@@ -0,0 +1,248 @@
+/*
+立即模式的 2D 图形
+叠加层和简单的可视化需要画矩形、圆、线段和多边形，为它们准备纹理或模型太麻烦了。
+
+Painter 每一帧重新收集要画的图形：调用 draw_rect、draw_circle 等方法时在 CPU 上把图形转换为带颜色的三角形（buffer::ColorVertex），
+prepare 时一次性上传到一个按需扩大的顶点/索引缓冲区中，draw 时只需要一次绘制调用。
+图形按调用的顺序画，后画的在上面。坐标是屏幕像素，原点在左上角，y 轴向下。
+*/
+mod tessellate;
+
+use std::collections::HashMap;
+
+use winit::dpi::PhysicalSize;
+use wgpu::{BindGroup, BlendState, Buffer, BufferUsages, Device, PipelineLayout, Queue, RenderPass, RenderPipeline, ShaderModule, ShaderStages, TextureFormat};
+
+use crate::buffer::{ColorVertex, VertexLayout};
+use crate::pipeline::RenderPipelineBuilder;
+use tessellate::Geometry;
+
+//描边：颜色和线宽（像素）
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Stroke {
+    pub color: [f32; 4],
+    pub width: f32,
+}
+
+//图形的填充颜色和描边，两者都可以没有。描边以轮廓为中心，两侧各占一半线宽
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct ShapeStyle {
+    pub fill: Option<[f32; 4]>,
+    pub stroke: Option<Stroke>,
+}
+
+impl ShapeStyle {
+    pub fn fill(color: [f32; 4]) -> Self {
+        Self {
+            fill: Some(color),
+            stroke: None,
+        }
+    }
+
+    pub fn stroke(color: [f32; 4], width: f32) -> Self {
+        Self {
+            fill: None,
+            stroke: Some(Stroke { color, width }),
+        }
+    }
+
+    //在填充之外再加上描边
+    pub fn with_stroke(mut self, color: [f32; 4], width: f32) -> Self {
+        self.stroke = Some(Stroke { color, width });
+        self
+    }
+}
+
+pub struct Painter {
+    geometry: Geometry,
+
+    screen_buffer: Buffer,
+    screen_bind_group: BindGroup,
+    shader: ShaderModule,
+    pipeline_layout: PipelineLayout,
+    pipelines: HashMap<TextureFormat, RenderPipeline>,
+
+    vertex_buffer: Buffer,
+    index_buffer: Buffer,
+    //缓冲区能容纳的顶点和索引数量
+    vertex_capacity: usize,
+    index_capacity: usize,
+    //上一次 prepare 上传的索引数量
+    num_indices: u32,
+}
+
+impl Painter {
+    pub fn new(device: &Device) -> Self {
+        let screen_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Painter Screen Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let screen_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Painter Screen Buffer"),
+            size: std::mem::size_of::<[f32; 4]>() as u64,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let screen_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Painter Screen Bind Group"),
+            layout: &screen_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: screen_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("painter.wgsl"));
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Painter Pipeline Layout"),
+            bind_group_layouts: &[&screen_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let vertex_capacity = 1024;
+        let index_capacity = 4096;
+        Self {
+            geometry: Geometry::default(),
+            screen_buffer,
+            screen_bind_group,
+            shader,
+            pipeline_layout,
+            pipelines: HashMap::new(),
+            vertex_buffer: Self::create_vertex_buffer(device, vertex_capacity),
+            index_buffer: Self::create_index_buffer(device, index_capacity),
+            vertex_capacity,
+            index_capacity,
+            num_indices: 0,
+        }
+    }
+
+    fn create_vertex_buffer(device: &Device, capacity: usize) -> Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Painter Vertex Buffer"),
+            size: (capacity * std::mem::size_of::<ColorVertex>()) as u64,
+            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_index_buffer(device: &Device, capacity: usize) -> Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Painter Index Buffer"),
+            size: (capacity * std::mem::size_of::<u32>()) as u64,
+            usage: BufferUsages::INDEX | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    //先填充再描边
+    fn draw_outline(&mut self, points: &[[f32; 2]], convex: bool, style: &ShapeStyle) {
+        if let Some(color) = style.fill {
+            if convex {
+                self.geometry.fill_convex(points, color);
+            } else {
+                self.geometry.fill_polygon(points, color);
+            }
+        }
+        if let Some(stroke) = style.stroke {
+            self.geometry.stroke(points, true, stroke.width, stroke.color);
+        }
+    }
+
+    //rect 是 (x, y, 宽, 高)
+    pub fn draw_rect(&mut self, rect: [f32; 4], style: &ShapeStyle) {
+        self.draw_outline(&tessellate::rect_points(rect), true, style);
+    }
+
+    //radius 超过宽或高的一半时取一半
+    pub fn draw_rounded_rect(&mut self, rect: [f32; 4], radius: f32, style: &ShapeStyle) {
+        self.draw_outline(&tessellate::rounded_rect_points(rect, radius), true, style);
+    }
+
+    pub fn draw_circle(&mut self, center: [f32; 2], radius: f32, style: &ShapeStyle) {
+        if radius > 0.0 {
+            self.draw_outline(&tessellate::circle_points(center, radius), true, style);
+        }
+    }
+
+    //从 from 到 to、宽度为 thickness 的线段
+    pub fn draw_line(&mut self, from: [f32; 2], to: [f32; 2], thickness: f32, color: [f32; 4]) {
+        self.geometry.stroke(&[from, to], false, thickness, color);
+    }
+
+    //折线，拐角使用斜接
+    pub fn draw_polyline(&mut self, points: &[[f32; 2]], thickness: f32, color: [f32; 4]) {
+        self.geometry.stroke(points, false, thickness, color);
+    }
+
+    //简单多边形，可以是凹的，但边不能自相交
+    pub fn draw_polygon(&mut self, points: &[[f32; 2]], style: &ShapeStyle) {
+        self.draw_outline(points, false, style);
+    }
+
+    //这一帧是否画了图形
+    pub fn is_empty(&self) -> bool {
+        self.geometry.is_empty()
+    }
+
+    //上传这一帧的图形并准备管线，需要在渲染通道开始之前调用。size 是渲染目标的大小
+    pub fn prepare(&mut self, device: &Device, queue: &Queue, format: TextureFormat, size: PhysicalSize<u32>) {
+        let shader = &self.shader;
+        let pipeline_layout = &self.pipeline_layout;
+        self.pipelines.entry(format).or_insert_with(|| {
+            RenderPipelineBuilder::new(shader, format)
+                .label("Painter Pipeline")
+                .layout(pipeline_layout)
+                .source(include_str!("painter.wgsl"))
+                .vertex_buffer(ColorVertex::desc())
+                .blend(Some(BlendState::ALPHA_BLENDING))
+                .cull_mode(None)
+                .build(device)
+        });
+        queue.write_buffer(&self.screen_buffer, 0, bytemuck::cast_slice(&[size.width as f32, size.height as f32, 0.0, 0.0]));
+
+        let Geometry { vertices, indices } = &self.geometry;
+        if vertices.len() > self.vertex_capacity {
+            self.vertex_capacity = vertices.len().next_power_of_two();
+            self.vertex_buffer = Self::create_vertex_buffer(device, self.vertex_capacity);
+        }
+        if indices.len() > self.index_capacity {
+            self.index_capacity = indices.len().next_power_of_two();
+            self.index_buffer = Self::create_index_buffer(device, self.index_capacity);
+        }
+        if !indices.is_empty() {
+            queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(vertices));
+            queue.write_buffer(&self.index_buffer, 0, bytemuck::cast_slice(indices));
+        }
+        self.num_indices = indices.len() as u32;
+        self.geometry.clear();
+    }
+
+    //绘制上一次 prepare 的图形。会替换管线和 @group(0)
+    pub fn draw<'a>(&'a self, render_pass: &mut RenderPass<'a>, format: TextureFormat) {
+        if self.num_indices == 0 {
+            return;
+        }
+        let Some(pipeline) = self.pipelines.get(&format) else {
+            log::warn!("图形的管线还没有创建（{:?}），跳过绘制", format);
+            return;
+        };
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, &self.screen_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
+    }
+}
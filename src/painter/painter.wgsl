@@ -0,0 +1,35 @@
+//2D 图形：顶点坐标是屏幕像素，原点在左上角
+
+struct Screen {
+    //(宽, 高, _, _)
+    size: vec4f
+};
+
+@group(0) @binding(0)
+var<uniform> screen: Screen;
+
+//buffer::ColorVertex
+struct VertexInput {
+    @location(0) position: vec2f,
+    @location(1) color: vec4f
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4f,
+    @location(0) color: vec4f
+};
+
+@vertex
+fn vs_main(model: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    //像素坐标转换为标准化设备坐标，y 轴翻转
+    let ndc = model.position / screen.size.xy * vec2f(2.0, -2.0) + vec2f(-1.0, 1.0);
+    out.clip_position = vec4f(ndc, 0.0, 1.0);
+    out.color = model.color;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4f {
+    return in.color;
+}
@@ -0,0 +1,346 @@
+//在 CPU 上把图形转换为三角形。所有坐标都是屏幕像素
+
+use std::f32::consts::PI;
+
+use crate::buffer::ColorVertex;
+
+//圆弧的弦与圆弧之间的最大距离（像素），决定圆分成多少段
+const ARC_TOLERANCE: f32 = 0.25;
+const MIN_ARC_SEGMENTS: usize = 8;
+const MAX_ARC_SEGMENTS: usize = 256;
+
+//拐角很尖时斜接（miter）会非常长，超过线宽的这个倍数时截断
+const MITER_LIMIT: f32 = 4.0;
+
+//一帧中所有图形的三角形
+#[derive(Default)]
+pub struct Geometry {
+    pub vertices: Vec<ColorVertex>,
+    pub indices: Vec<u32>,
+}
+
+impl Geometry {
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+        self.indices.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    fn push_vertex(&mut self, position: [f32; 2], color: [f32; 4]) -> u32 {
+        self.vertices.push(ColorVertex { position, color });
+        self.vertices.len() as u32 - 1
+    }
+
+    //填充凸多边形：以第一个点为中心的三角扇
+    pub fn fill_convex(&mut self, points: &[[f32; 2]], color: [f32; 4]) {
+        if points.len() < 3 {
+            return;
+        }
+        let base = self.vertices.len() as u32;
+        for &point in points {
+            self.push_vertex(point, color);
+        }
+        for i in 1..points.len() as u32 - 1 {
+            self.indices.extend_from_slice(&[base, base + i, base + i + 1]);
+        }
+    }
+
+    //填充任意简单多边形（可以是凹的，但边不能自相交）
+    pub fn fill_polygon(&mut self, points: &[[f32; 2]], color: [f32; 4]) {
+        let points = dedup(points, true);
+        if points.len() < 3 {
+            return;
+        }
+        let base = self.vertices.len() as u32;
+        for &point in &points {
+            self.push_vertex(point, color);
+        }
+        for index in triangulate(&points) {
+            self.indices.push(base + index);
+        }
+    }
+
+    //沿着点画一条宽度为 width 的线，closed 为 true 时首尾相连。线以点为中心，两侧各一半宽度
+    pub fn stroke(&mut self, points: &[[f32; 2]], closed: bool, width: f32, color: [f32; 4]) {
+        let points = dedup(points, closed);
+        let closed = closed && points.len() > 2;
+        if points.len() < 2 || width <= 0.0 {
+            return;
+        }
+        let half = width * 0.5;
+        let count = points.len();
+        let base = self.vertices.len() as u32;
+        for i in 0..count {
+            //与前后两条边的法线
+            let prev = if i > 0 { Some(points[i - 1]) } else if closed { Some(points[count - 1]) } else { None };
+            let next = if i + 1 < count { Some(points[i + 1]) } else if closed { Some(points[0]) } else { None };
+            let normal_in = prev.map(|prev| normal(prev, points[i]));
+            let normal_out = next.map(|next| normal(points[i], next));
+            let offset = match (normal_in, normal_out) {
+                (Some(a), Some(b)) => miter(a, b, half),
+                (Some(n), None) | (None, Some(n)) => [n[0] * half, n[1] * half],
+                (None, None) => unreachable!(),
+            };
+            let [x, y] = points[i];
+            self.push_vertex([x + offset[0], y + offset[1]], color);
+            self.push_vertex([x - offset[0], y - offset[1]], color);
+        }
+        let segments = if closed { count } else { count - 1 };
+        for i in 0..segments {
+            let a = base + 2 * i as u32;
+            let b = base + 2 * ((i + 1) % count) as u32;
+            self.indices.extend_from_slice(&[a, a + 1, b + 1, a, b + 1, b]);
+        }
+    }
+}
+
+//去掉相邻的重复点，closed 时也去掉与第一个点重复的最后一个点
+fn dedup(points: &[[f32; 2]], closed: bool) -> Vec<[f32; 2]> {
+    let mut result: Vec<[f32; 2]> = Vec::with_capacity(points.len());
+    for &point in points {
+        if result.last() != Some(&point) {
+            result.push(point);
+        }
+    }
+    if closed && result.len() > 1 && result.first() == result.last() {
+        result.pop();
+    }
+    result
+}
+
+//从 a 到 b 的边的单位法线
+fn normal(a: [f32; 2], b: [f32; 2]) -> [f32; 2] {
+    let dx = b[0] - a[0];
+    let dy = b[1] - a[1];
+    let length = (dx * dx + dy * dy).sqrt();
+    [-dy / length, dx / length]
+}
+
+//两条边在拐角处的斜接偏移，使两侧的线宽都保持 half
+fn miter(a: [f32; 2], b: [f32; 2], half: f32) -> [f32; 2] {
+    let sum = [a[0] + b[0], a[1] + b[1]];
+    let length = (sum[0] * sum[0] + sum[1] * sum[1]).sqrt();
+    //两条边方向相反（折回）时没有斜接，使用其中一条边的法线
+    if length < 1e-6 {
+        return [b[0] * half, b[1] * half];
+    }
+    let direction = [sum[0] / length, sum[1] / length];
+    let cos = direction[0] * b[0] + direction[1] * b[1];
+    let distance = (half / cos).min(half * MITER_LIMIT);
+    [direction[0] * distance, direction[1] * distance]
+}
+
+fn cross(o: [f32; 2], a: [f32; 2], b: [f32; 2]) -> f32 {
+    (a[0] - o[0]) * (b[1] - o[1]) - (a[1] - o[1]) * (b[0] - o[0])
+}
+
+//耳切法三角化，返回 points 中的索引。points 的方向可以是顺时针或逆时针
+fn triangulate(points: &[[f32; 2]]) -> Vec<u32> {
+    let area: f32 = (0..points.len())
+        .map(|i| cross([0.0, 0.0], points[i], points[(i + 1) % points.len()]))
+        .sum();
+    let mut remaining: Vec<usize> = (0..points.len()).collect();
+    //统一为面积为正的方向，这样凸的顶点的叉积总是正的
+    if area < 0.0 {
+        remaining.reverse();
+    }
+
+    let mut indices = Vec::with_capacity((points.len() - 2) * 3);
+    while remaining.len() > 3 {
+        let count = remaining.len();
+        let ear = (0..count).find(|&i| {
+            let a = points[remaining[(i + count - 1) % count]];
+            let b = points[remaining[i]];
+            let c = points[remaining[(i + 1) % count]];
+            if cross(a, b, c) <= 0.0 {
+                return false;
+            }
+            //其他顶点都不能在这个三角形里
+            remaining.iter()
+                .map(|&j| points[j])
+                .filter(|&p| p != a && p != b && p != c)
+                .all(|p| !(cross(a, b, p) >= 0.0 && cross(b, c, p) >= 0.0 && cross(c, a, p) >= 0.0))
+        });
+        //边自相交等情况下找不到耳朵，剩下的部分按三角扇处理
+        let Some(i) = ear else {
+            break;
+        };
+        indices.extend_from_slice(&[
+            remaining[(i + count - 1) % count] as u32,
+            remaining[i] as u32,
+            remaining[(i + 1) % count] as u32,
+        ]);
+        remaining.remove(i);
+    }
+    for i in 1..remaining.len() - 1 {
+        indices.extend_from_slice(&[remaining[0] as u32, remaining[i] as u32, remaining[i + 1] as u32]);
+    }
+    indices
+}
+
+//把圆分成多少段，使弦与圆弧之间的距离不超过 ARC_TOLERANCE
+fn arc_segments(radius: f32, angle: f32) -> usize {
+    let step = if radius > ARC_TOLERANCE {
+        2.0 * (1.0 - ARC_TOLERANCE / radius).acos()
+    } else {
+        PI / 2.0
+    };
+    let full = ((2.0 * PI / step).ceil() as usize).clamp(MIN_ARC_SEGMENTS, MAX_ARC_SEGMENTS);
+    ((full as f32 * angle / (2.0 * PI)).ceil() as usize).max(1)
+}
+
+//从 start 到 end（弧度，顺时针，因为 y 轴向下）的圆弧上的点，包含两个端点
+fn arc(points: &mut Vec<[f32; 2]>, center: [f32; 2], radius: f32, start: f32, end: f32) {
+    let segments = arc_segments(radius, end - start);
+    for i in 0..=segments {
+        let angle = start + (end - start) * i as f32 / segments as f32;
+        points.push([center[0] + radius * angle.cos(), center[1] + radius * angle.sin()]);
+    }
+}
+
+pub fn circle_points(center: [f32; 2], radius: f32) -> Vec<[f32; 2]> {
+    let mut points = Vec::new();
+    arc(&mut points, center, radius, 0.0, 2.0 * PI);
+    //最后一个点与第一个点重合
+    points.pop();
+    points
+}
+
+pub fn rect_points(rect: [f32; 4]) -> Vec<[f32; 2]> {
+    let [x, y, width, height] = rect;
+    vec![[x, y], [x + width, y], [x + width, y + height], [x, y + height]]
+}
+
+//圆角矩形的轮廓。radius 超过宽或高的一半时取一半
+pub fn rounded_rect_points(rect: [f32; 4], radius: f32) -> Vec<[f32; 2]> {
+    let [x, y, width, height] = rect;
+    let radius = radius.min(width * 0.5).min(height * 0.5).max(0.0);
+    if radius <= 0.0 {
+        return rect_points(rect);
+    }
+    let mut points = Vec::new();
+    arc(&mut points, [x + width - radius, y + radius], radius, -PI / 2.0, 0.0);
+    arc(&mut points, [x + width - radius, y + height - radius], radius, 0.0, PI / 2.0);
+    arc(&mut points, [x + radius, y + height - radius], radius, PI / 2.0, PI);
+    arc(&mut points, [x + radius, y + radius], radius, PI, PI * 1.5);
+    //半径正好是一半时相邻圆弧的端点重合
+    dedup(&points, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: [f32; 4] = [1.0; 4];
+
+    //多边形的有向面积（鞋带公式）
+    fn signed_area(points: &[[f32; 2]]) -> f32 {
+        (0..points.len())
+            .map(|i| cross([0.0, 0.0], points[i], points[(i + 1) % points.len()]))
+            .sum::<f32>()
+            * 0.5
+    }
+
+    //检查三角化的结果：n - 2 个三角形，不论多边形的方向如何，三角形都统一为面积为正的方向且不退化，
+    //面积之和等于多边形的面积（没有重叠，也没有盖到多边形外面）
+    fn check_triangulation(points: &[[f32; 2]]) {
+        let indices = triangulate(points);
+        assert_eq!(indices.len(), (points.len() - 2) * 3);
+        let area = signed_area(points).abs();
+        let mut total = 0.0;
+        for triangle in indices.chunks(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| points[triangle[i] as usize]);
+            let triangle_area = signed_area(&[a, b, c]);
+            assert!(triangle_area > 0.0, "三角形 {:?} 的方向相反或退化", triangle);
+            total += triangle_area;
+        }
+        assert!((total - area).abs() < 1e-3, "三角形的面积之和 {} 不等于多边形的面积 {}", total, area);
+    }
+
+    fn reversed(points: &[[f32; 2]]) -> Vec<[f32; 2]> {
+        points.iter().rev().copied().collect()
+    }
+
+    #[test]
+    fn ear_clipping_concave_polygons() {
+        //L 形
+        let l_shape = [[0.0, 0.0], [10.0, 0.0], [10.0, 4.0], [4.0, 4.0], [4.0, 10.0], [0.0, 10.0]];
+        //箭头：凹进去的顶点在第一个
+        let arrow = [[5.0, 6.0], [0.0, 10.0], [5.0, 0.0], [10.0, 10.0]];
+        //梳子：有好几个凹的顶点
+        let comb = [[0.0, 0.0], [9.0, 0.0], [9.0, 9.0], [7.0, 9.0], [6.0, 2.0], [5.0, 9.0], [4.0, 9.0], [3.0, 2.0], [2.0, 9.0], [0.0, 9.0]];
+        for polygon in [&l_shape[..], &arrow, &comb] {
+            check_triangulation(polygon);
+            check_triangulation(&reversed(polygon));
+        }
+    }
+
+    #[test]
+    fn collinear_points() {
+        //矩形的边上有多余的点
+        let points = [[0.0, 0.0], [5.0, 0.0], [10.0, 0.0], [10.0, 5.0], [10.0, 10.0], [0.0, 10.0]];
+        check_triangulation(&points);
+        check_triangulation(&reversed(&points));
+
+        //填充时重复的点被去掉
+        let mut geometry = Geometry::default();
+        geometry.fill_polygon(&[[0.0, 0.0], [0.0, 0.0], [10.0, 0.0], [10.0, 10.0], [0.0, 0.0]], WHITE);
+        assert_eq!(geometry.vertices.len(), 3);
+        assert_eq!(geometry.indices.len(), 3);
+    }
+
+    #[test]
+    fn closed_stroke() {
+        let square = rect_points([0.0, 0.0, 10.0, 10.0]);
+        let mut geometry = Geometry::default();
+        geometry.stroke(&square, true, 2.0, WHITE);
+        //每个点两侧各一个顶点，4 条边
+        assert_eq!(geometry.vertices.len(), 8);
+        assert_eq!(geometry.indices.len(), 4 * 6);
+        //拐角处斜接，内外两圈正好离边 1 像素
+        let mut positions: Vec<[f32; 2]> = geometry.vertices.iter().map(|v| v.position).collect();
+        for position in &mut positions {
+            *position = position.map(|p| (p * 1000.0).round() / 1000.0);
+        }
+        for corner in [[-1.0, -1.0], [11.0, -1.0], [11.0, 11.0], [-1.0, 11.0], [1.0, 1.0], [9.0, 1.0], [9.0, 9.0], [1.0, 9.0]] {
+            assert!(positions.contains(&corner), "{:?} 中没有 {:?}", positions, corner);
+        }
+        //最后一条边连回第一个点
+        assert!(geometry.indices[18..].contains(&0));
+
+        //不闭合时少一条边；首尾重复的点被去掉
+        let mut closed_square = square.clone();
+        closed_square.push(square[0]);
+        let mut geometry = Geometry::default();
+        geometry.stroke(&closed_square, false, 2.0, WHITE);
+        assert_eq!(geometry.indices.len(), 4 * 6);
+        geometry.clear();
+        geometry.stroke(&square, false, 2.0, WHITE);
+        assert_eq!(geometry.indices.len(), 3 * 6);
+    }
+
+    #[test]
+    fn rounded_rect_with_half_size_radius() {
+        for rect in [[0.0, 0.0, 10.0, 10.0], [0.0, 0.0, 20.0, 10.0], [0.0, 0.0, 10.0, 20.0]] {
+            let points = rounded_rect_points(rect, 100.0);
+            //相邻的圆弧首尾重合的点只保留一个，包括最后一个点和第一个点
+            for i in 0..points.len() {
+                assert_ne!(points[i], points[(i + 1) % points.len()], "{:?} 中有重复的点", rect);
+            }
+            let [x, y, width, height] = rect;
+            assert!(points.iter().all(|&[px, py]| px >= x - 1e-4 && px <= x + width + 1e-4 && py >= y - 1e-4 && py <= y + height + 1e-4));
+
+            let mut geometry = Geometry::default();
+            geometry.fill_polygon(&points, WHITE);
+            geometry.stroke(&points, true, 1.0, WHITE);
+            assert!(geometry.vertices.iter().all(|v| v.position.iter().all(|p| p.is_finite())));
+        }
+
+        //正方形的圆角半径是一半时就是圆
+        let points = rounded_rect_points([0.0, 0.0, 10.0, 10.0], 5.0);
+        assert!(points.iter().all(|&[px, py]| (((px - 5.0).powi(2) + (py - 5.0).powi(2)).sqrt() - 5.0).abs() < 1e-4));
+    }
+}
@@ -0,0 +1,70 @@
+//把每个粒子画成一个始终面向相机的正方形（公告板），粒子缓冲区直接作为实例缓冲区
+
+struct CameraUniform {
+    view_position: vec4f,
+    view_proj: mat4x4f,
+    inv_proj: mat4x4f,
+    inv_view: mat4x4f
+};
+
+//粒子的管线只有相机一个绑定组
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+//见 particles 模块的 ParticleRaw::desc
+struct ParticleInput {
+    @location(0) position: vec3f,
+    @location(1) life: f32,
+    @location(2) color: vec4f,
+    @location(3) size: f32
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4f,
+    //-1..1，正方形的中心为 0
+    @location(0) offset: vec2f,
+    @location(1) color: vec4f
+};
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) in_vertex_index: u32,
+    particle: ParticleInput
+) -> VertexOutput {
+    var out: VertexOutput;
+    //死亡的粒子退化成一个点，不产生任何片元
+    if particle.life <= 0.0 {
+        out.clip_position = vec4f(0.0, 0.0, 0.0, 1.0);
+        return out;
+    }
+
+    //两个三角形组成的正方形
+    var corners = array<vec2f, 6>(
+        vec2f(-1.0, -1.0),
+        vec2f(1.0, -1.0),
+        vec2f(1.0, 1.0),
+        vec2f(-1.0, -1.0),
+        vec2f(1.0, 1.0),
+        vec2f(-1.0, 1.0)
+    );
+    let corner = corners[in_vertex_index];
+    //视图矩阵的逆的前两列就是相机的右方和上方在世界空间中的方向
+    let right = camera.inv_view[0].xyz;
+    let up = camera.inv_view[1].xyz;
+    let world_position = particle.position + (right * corner.x + up * corner.y) * particle.size * 0.5;
+
+    out.clip_position = camera.view_proj * vec4f(world_position, 1.0);
+    out.offset = corner;
+    out.color = particle.color;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4f {
+    //圆形，边缘逐渐变透明
+    let falloff = 1.0 - smoothstep(0.5, 1.0, length(in.offset));
+    if falloff <= 0.0 {
+        discard;
+    }
+    return vec4f(in.color.rgb, in.color.a * falloff);
+}
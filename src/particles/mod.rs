@@ -0,0 +1,346 @@
+/*
+GPU 粒子
+粒子的状态（位置、速度、寿命、颜色）保存在存储缓冲区中，CPU 从不读取它们：
+每一帧由计算着色器推进所有粒子，并按发射器的参数发射新的粒子，然后同一个缓冲区直接作为实例缓冲区，把每个粒子画成一个面向相机的公告板。
+
+粒子缓冲区是一个环形缓冲区：每一帧根据发射速率算出要发射的数量，从上次结束的位置开始依次覆盖。
+容量至少要有 发射速率 × 最长寿命，否则还活着的粒子会被新粒子提前替换。
+
+新粒子的方向、速度、寿命和大小由着色器中的哈希函数生成，每一帧的随机种子来自 rand。
+
+粒子是半透明的，按 ParticleBlend 选择的方式与背景混合，只做深度测试不写入深度，所以需要在不透明的物体之后绘制。
+混合模式为 Alpha 时粒子之间没有按距离排序，重叠的地方可能前后颠倒；Additive 与顺序无关，适合火焰、火花这类发光的效果。
+*/
+use std::collections::HashMap;
+use std::ops::Range;
+
+use cgmath::{Deg, Point3, Vector3, Angle};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use wgpu::{BindGroup, BindGroupLayout, BlendComponent, BlendFactor, BlendOperation, BlendState, Device, PipelineLayout, Queue, RenderPass, RenderPipeline, ShaderModule, TextureFormat};
+
+use crate::buffer::VertexLayout;
+use crate::compute::{Kernel, StorageBuffer, UniformBuffer};
+use crate::pipeline::{DepthConfig, RenderPipelineBuilder};
+
+//粒子如何与背景混合
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ParticleBlend {
+    //颜色乘上不透明度后累加到背景上，越叠越亮
+    #[default]
+    Additive,
+    //普通的半透明混合
+    Alpha,
+}
+
+impl ParticleBlend {
+    fn blend_state(&self) -> BlendState {
+        match self {
+            ParticleBlend::Additive => BlendState {
+                color: BlendComponent {
+                    src_factor: BlendFactor::SrcAlpha,
+                    dst_factor: BlendFactor::One,
+                    operation: BlendOperation::Add,
+                },
+                alpha: BlendComponent {
+                    src_factor: BlendFactor::Zero,
+                    dst_factor: BlendFactor::One,
+                    operation: BlendOperation::Add,
+                },
+            },
+            ParticleBlend::Alpha => BlendState::ALPHA_BLENDING,
+        }
+    }
+}
+
+//发射器：在哪里、以什么速率、朝什么方向发射粒子
+#[derive(Clone, Debug)]
+pub struct Emitter {
+    pub position: Point3<f32>,
+    //锥形的轴
+    pub direction: Vector3<f32>,
+    //锥形的半角，0 表示只沿着 direction 发射
+    pub cone_angle: Deg<f32>,
+    //每秒发射的粒子数量
+    pub rate: f32,
+    //初始速度的大小（每秒移动的距离）
+    pub speed: Range<f32>,
+    //寿命（秒）
+    pub lifetime: Range<f32>,
+    //公告板的边长
+    pub size: Range<f32>,
+    //粒子的颜色从 color_start 随寿命过渡到 color_end，a 是不透明度
+    pub color_start: [f32; 4],
+    pub color_end: [f32; 4],
+    //每秒速度的变化
+    pub gravity: Vector3<f32>,
+}
+
+impl Default for Emitter {
+    //从原点向上喷出、落回下方的橙色火花
+    fn default() -> Self {
+        Self {
+            position: (0.0, 0.0, 0.0).into(),
+            direction: Vector3::unit_y(),
+            cone_angle: Deg(20.0),
+            rate: 500.0,
+            speed: 1.0..2.0,
+            lifetime: 1.0..2.0,
+            size: 0.03..0.06,
+            color_start: [1.0, 0.6, 0.2, 1.0],
+            color_end: [1.0, 0.1, 0.0, 0.0],
+            gravity: (0.0, -2.0, 0.0).into(),
+        }
+    }
+}
+
+//一个粒子，与 simulate.wgsl 中的 Particle 一致。绘制时每个粒子是一个实例，只读取位置、剩余寿命、颜色和大小
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, VertexLayout)]
+#[vertex(instance)]
+pub struct ParticleRaw {
+    position: [f32; 3],
+    life: f32,
+    #[vertex(skip)]
+    velocity: [f32; 3],
+    #[vertex(skip)]
+    max_life: f32,
+    color: [f32; 4],
+    size: f32,
+    #[vertex(skip)]
+    _padding: [f32; 3],
+}
+
+//与 simulate.wgsl 中的 Simulation 一致
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct SimulationUniform {
+    position: [f32; 4],
+    direction: [f32; 4],
+    speed: [f32; 2],
+    lifetime: [f32; 2],
+    size: [f32; 2],
+    _padding: [f32; 2],
+    color_start: [f32; 4],
+    color_end: [f32; 4],
+    gravity: [f32; 4],
+    spawn: [u32; 4],
+}
+
+pub struct ParticleSystem {
+    //可以随时修改，下一次 update 时生效
+    pub emitter: Emitter,
+    //为 false 时不再发射新粒子，已有的粒子继续运动直到死亡
+    pub emitting: bool,
+    blend: ParticleBlend,
+
+    particles: StorageBuffer<ParticleRaw>,
+    simulation: UniformBuffer<SimulationUniform>,
+    kernel: Kernel,
+    bind_group: BindGroup,
+    //下一个要发射的粒子在环形缓冲区中的位置
+    cursor: u32,
+    //不足一个的发射数量累积到下一帧
+    spawn_remainder: f32,
+    rng: StdRng,
+
+    shader: ShaderModule,
+    pipeline_layout: PipelineLayout,
+    pipelines: HashMap<(TextureFormat, DepthConfig), RenderPipeline>,
+}
+
+impl ParticleSystem {
+    //capacity 是同时存在的粒子的最大数量。camera_bind_group_layout 是相机的绑定组布局，绘制时使用
+    pub fn new(device: &Device, emitter: Emitter, capacity: u32, blend: ParticleBlend, camera_bind_group_layout: &BindGroupLayout) -> Self {
+        //寿命全为 0，所有粒子一开始都是死亡的。空的缓冲区不能绑定，所以至少有一个粒子
+        let particles = StorageBuffer::zeroed(device, "Particle Buffer", capacity.max(1) as usize);
+        let simulation = UniformBuffer::new(device, "Particle Simulation Buffer", &bytemuck::Zeroable::zeroed());
+        let kernel = Kernel::new(device, include_str!("simulate.wgsl"), "cs_main")
+            .expect("粒子的计算着色器无效");
+        let bind_group = kernel.bind_group(device, 0, &[particles.buffer(), simulation.buffer()]);
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("billboard.wgsl"));
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Particle Pipeline Layout"),
+            bind_group_layouts: &[camera_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        Self {
+            emitter,
+            emitting: true,
+            blend,
+            particles,
+            simulation,
+            kernel,
+            bind_group,
+            cursor: 0,
+            spawn_remainder: 0.0,
+            rng: StdRng::from_entropy(),
+            shader,
+            pipeline_layout,
+            pipelines: HashMap::new(),
+        }
+    }
+
+    //使用固定的随机种子，每次运行发射的粒子都一样
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+
+    pub fn capacity(&self) -> u32 {
+        self.particles.len() as u32
+    }
+
+    pub fn blend(&self) -> ParticleBlend {
+        self.blend
+    }
+
+    //混合模式是管线的一部分，修改后重新创建管线
+    pub fn set_blend(&mut self, blend: ParticleBlend) {
+        if self.blend != blend {
+            self.blend = blend;
+            self.pipelines.clear();
+        }
+    }
+
+    //粒子缓冲区，可以作为存储缓冲区或实例缓冲区使用
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        self.particles.buffer()
+    }
+
+    //推进 dt 秒：发射新粒子并移动所有粒子。计算命令会立即提交
+    pub fn update(&mut self, device: &Device, queue: &Queue, dt: f32) {
+        let capacity = self.capacity();
+        let mut spawn_count = 0;
+        if self.emitting && dt > 0.0 {
+            let spawn = self.spawn_remainder + self.emitter.rate.max(0.0) * dt;
+            spawn_count = (spawn.floor() as u32).min(capacity);
+            self.spawn_remainder = spawn.fract();
+        }
+
+        let emitter = &self.emitter;
+        let uniform = SimulationUniform {
+            position: [emitter.position.x, emitter.position.y, emitter.position.z, emitter.cone_angle.cos()],
+            direction: [emitter.direction.x, emitter.direction.y, emitter.direction.z, dt],
+            speed: [emitter.speed.start, emitter.speed.end],
+            lifetime: [emitter.lifetime.start, emitter.lifetime.end],
+            size: [emitter.size.start, emitter.size.end],
+            _padding: [0.0; 2],
+            color_start: emitter.color_start,
+            color_end: emitter.color_end,
+            gravity: [emitter.gravity.x, emitter.gravity.y, emitter.gravity.z, 0.0],
+            spawn: [self.cursor, spawn_count, self.rng.gen(), capacity],
+        };
+        self.simulation.write(queue, &uniform);
+        if capacity > 0 {
+            self.cursor = (self.cursor + spawn_count) % capacity;
+        }
+
+        if let Err(e) = self.kernel.run(device, queue, &[&self.bind_group], [capacity, 1, 1]) {
+            log::error!("{}", e);
+        }
+    }
+
+    //为给定的颜色格式和深度设置创建管线，需要在 draw 之前调用（渲染通道开始之前）
+    pub fn prepare(&mut self, device: &Device, format: TextureFormat, depth_config: DepthConfig) {
+        let shader = &self.shader;
+        let pipeline_layout = &self.pipeline_layout;
+        let blend = self.blend;
+        self.pipelines.entry((format, depth_config)).or_insert_with(|| {
+            RenderPipelineBuilder::new(shader, format)
+                .label("Particle Pipeline")
+                .layout(pipeline_layout)
+                .source(include_str!("billboard.wgsl"))
+                .vertex_buffer(ParticleRaw::desc())
+                .blend(Some(blend.blend_state()))
+                .cull_mode(None)
+                //被不透明的物体挡住的粒子不画，但粒子自己不写入深度，互相之间不会遮挡
+                .depth(DepthConfig {
+                    write_enabled: false,
+                    ..depth_config
+                })
+                .build(device)
+        });
+    }
+
+    //在渲染通道中绘制所有粒子。会替换管线和 @group(0)
+    pub fn draw<'a>(&'a self, render_pass: &mut RenderPass<'a>, camera_bind_group: &'a BindGroup, format: TextureFormat, depth_config: DepthConfig) {
+        let Some(pipeline) = self.pipelines.get(&(format, depth_config)) else {
+            log::warn!("粒子的管线还没有创建（{:?}, {:?}），跳过绘制", format, depth_config);
+            return;
+        };
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.particles.buffer().slice(..));
+        render_pass.draw(0..6, 0..self.capacity());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::CameraBinding;
+    use crate::headless;
+
+    fn read(device: &Device, queue: &Queue, system: &ParticleSystem) -> Vec<ParticleRaw> {
+        pollster::block_on(system.particles.read(device, queue)).unwrap()
+    }
+
+    #[test]
+    fn update_spawns_wraps_and_moves() {
+        let (device, queue) = pollster::block_on(headless::request_device());
+        let camera_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: CameraBinding::LAYOUT_ENTRIES,
+        });
+        //锥形半角为 0，所有范围都是一个值，新粒子的状态是确定的
+        let emitter = Emitter {
+            position: (1.0, 2.0, 3.0).into(),
+            direction: Vector3::unit_y(),
+            cone_angle: Deg(0.0),
+            rate: 80.0,
+            speed: 2.0..2.0,
+            lifetime: 1.0..1.0,
+            size: 0.1..0.1,
+            color_start: [1.0, 1.0, 1.0, 1.0],
+            color_end: [0.0, 0.0, 0.0, 0.0],
+            gravity: Vector3::new(0.0, 0.0, 0.0),
+        };
+        let mut system = ParticleSystem::new(&device, emitter, 8, ParticleBlend::Additive, &camera_layout).with_seed(7);
+        let dt = 0.0625;
+        let alive = |particles: &[ParticleRaw]| particles.iter().map(|p| p.life > 0.0).collect::<Vec<_>>();
+
+        //80 × 0.0625 = 5 个粒子从 0 开始发射
+        system.update(&device, &queue, dt);
+        assert_eq!(system.cursor, 5);
+        let particles = read(&device, &queue, &system);
+        assert_eq!(alive(&particles), [true, true, true, true, true, false, false, false]);
+        let spawned = particles[0];
+        assert_eq!(spawned.position, [1.0, 2.0, 3.0]);
+        assert_eq!(spawned.life, 1.0);
+        assert_eq!(spawned.max_life, 1.0);
+        assert_eq!(spawned.size, 0.1);
+        assert_eq!(spawned.color, [1.0; 4]);
+        for c in spawned.velocity.iter().zip([0.0, 2.0, 0.0]).map(|(a, b)| (a - b).abs()) {
+            assert!(c < 1e-5, "{:?}", spawned.velocity);
+        }
+
+        //下一帧的 5 个粒子是 5、6、7，再绕回 0、1；2..5 是上一帧的粒子，按速度前进了 dt
+        system.update(&device, &queue, dt);
+        assert_eq!(system.cursor, 2);
+        let particles = read(&device, &queue, &system);
+        assert_eq!(alive(&particles), [true; 8]);
+        for index in [5, 6, 7, 0, 1] {
+            assert_eq!(particles[index].life, 1.0, "粒子 {} 应该是新发射的", index);
+        }
+        for particle in &particles[2..5] {
+            assert_eq!(particle.life, 1.0 - dt);
+            assert!((particle.position[1] - (2.0 + 2.0 * dt)).abs() < 1e-5, "{:?}", particle.position);
+            assert!((particle.position[0] - 1.0).abs() < 1e-5 && (particle.position[2] - 3.0).abs() < 1e-5);
+            //颜色随剩余寿命从 color_start 向 color_end 过渡
+            assert!((particle.color[0] - (1.0 - dt)).abs() < 1e-5, "{:?}", particle.color);
+        }
+    }
+}
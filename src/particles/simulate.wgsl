@@ -0,0 +1,109 @@
+//粒子模拟：每个调用处理一个粒子。环形缓冲区中从 spawn.x 开始的 spawn.y 个粒子重新发射，其余活着的粒子按速度和重力前进
+
+const PI: f32 = 3.14159265359;
+
+//与 particles 模块的 ParticleRaw 一致
+struct Particle {
+    position: vec3f,
+    //剩余的寿命（秒），小于等于 0 表示已经死亡
+    life: f32,
+    velocity: vec3f,
+    //发射时的寿命
+    max_life: f32,
+    color: vec4f,
+    size: f32,
+    _padding0: f32,
+    _padding1: f32,
+    _padding2: f32
+};
+
+//与 particles 模块的 SimulationUniform 一致
+struct Simulation {
+    //w 是锥形的半角的余弦
+    position: vec4f,
+    //w 是这一帧的时间间隔（秒）
+    direction: vec4f,
+    //初始速度、寿命和大小的范围 (min, max)
+    speed: vec2f,
+    lifetime: vec2f,
+    size: vec2f,
+    _padding: vec2f,
+    color_start: vec4f,
+    color_end: vec4f,
+    gravity: vec4f,
+    //(开始发射的位置, 发射的数量, 随机种子, 容量)
+    spawn: vec4u
+};
+
+@group(0) @binding(0)
+var<storage, read_write> particles: array<Particle>;
+
+@group(0) @binding(1)
+var<uniform> sim: Simulation;
+
+//PCG 哈希，把一个整数打散成伪随机数
+fn hash(value: u32) -> u32 {
+    let state = value * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+//0..1 之间的随机数，每次调用后更新 seed
+fn random(seed: ptr<function, u32>) -> f32 {
+    *seed = hash(*seed);
+    return f32(*seed) / 4294967295.0;
+}
+
+fn spawn(index: u32) -> Particle {
+    var seed = hash(index ^ hash(sim.spawn.z));
+
+    //在锥形内均匀地选择一个方向：cos θ 在 1 和 cos 半角之间均匀分布，绕轴的角度在 0..2π 之间均匀分布
+    let cos_theta = mix(1.0, sim.position.w, random(&seed));
+    let sin_theta = sqrt(max(1.0 - cos_theta * cos_theta, 0.0));
+    let phi = 2.0 * PI * random(&seed);
+    let axis = normalize(sim.direction.xyz);
+    var up = vec3f(0.0, 1.0, 0.0);
+    if abs(axis.y) > 0.99 {
+        up = vec3f(1.0, 0.0, 0.0);
+    }
+    let tangent = normalize(cross(up, axis));
+    let bitangent = cross(axis, tangent);
+    let direction = tangent * (cos(phi) * sin_theta) + bitangent * (sin(phi) * sin_theta) + axis * cos_theta;
+
+    var particle: Particle;
+    particle.position = sim.position.xyz;
+    particle.velocity = direction * mix(sim.speed.x, sim.speed.y, random(&seed));
+    particle.max_life = max(mix(sim.lifetime.x, sim.lifetime.y, random(&seed)), 0.0001);
+    particle.life = particle.max_life;
+    particle.size = mix(sim.size.x, sim.size.y, random(&seed));
+    particle.color = sim.color_start;
+    return particle;
+}
+
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3u) {
+    let capacity = sim.spawn.w;
+    let index = id.x;
+    if index >= capacity {
+        return;
+    }
+
+    //在环形缓冲区中相对于发射位置的偏移
+    let offset = (index + capacity - sim.spawn.x) % capacity;
+    if offset < sim.spawn.y {
+        particles[index] = spawn(index);
+        return;
+    }
+
+    var particle = particles[index];
+    if particle.life <= 0.0 {
+        return;
+    }
+    let dt = sim.direction.w;
+    particle.life -= dt;
+    particle.velocity += sim.gravity.xyz * dt;
+    particle.position += particle.velocity * dt;
+    //颜色随寿命从 color_start 过渡到 color_end
+    particle.color = mix(sim.color_end, sim.color_start, clamp(particle.life / particle.max_life, 0.0, 1.0));
+    particles[index] = particle;
+}
@@ -24,4 +24,280 @@ WebGPU 着色器语言: WGSL
 WGSL (WebGPU Shading Language) 是 WebGPU 的着色器语言。 WGSL 的开发重点是让它轻松转换为与后端对应的着色器语言；
 例如，Vulkan 的 SPIR-V、Metal 的 MSL、DX12 的 HLSL 和 OpenGL 的 GLSL。 这种转换是在内部完成的，我们不需要关心这些细节。
 就 wgpu 而言，它是由名为 naga 的包完成的。
-*/
\ No newline at end of file
+*/
+mod reflect;
+mod validate;
+
+use wgpu::{BlendState, ColorTargetState, ColorWrites, CompareFunction, ComputePipeline, ComputePipelineDescriptor, DepthBiasState, DepthStencilState, Device, Face, FragmentState, FrontFace, MultisampleState, PipelineLayout, PolygonMode, PrimitiveState, PrimitiveTopology, RenderPipeline, RenderPipelineDescriptor, ShaderModule, StencilState, TextureFormat, VertexBufferLayout, VertexState};
+
+pub use reflect::{PipelineLayoutBuilder, ReflectError, ReflectedLayout};
+pub use validate::{check_vertex_inputs, VertexInputError};
+
+/*
+深度缓冲区
+没有深度缓冲区时，重叠的几何体按提交的顺序绘制，后画的总会盖住先画的。
+深度缓冲区为每个像素保存一个深度值，新片元只有通过深度测试（与已保存的值比较）才会被写入。
+*/
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DepthConfig {
+    //深度纹理的格式，例如 Depth32Float、Depth24Plus
+    pub format: TextureFormat,
+    //深度比较函数。Less 表示离相机更近（深度值更小）的片元会覆盖更远的
+    pub compare: CompareFunction,
+    //是否把通过测试的片元深度写入缓冲区。半透明物体通常只测试不写入
+    pub write_enabled: bool,
+}
+
+impl Default for DepthConfig {
+    fn default() -> Self {
+        Self {
+            format: TextureFormat::Depth32Float,
+            compare: CompareFunction::Less,
+            write_enabled: true,
+        }
+    }
+}
+
+impl DepthConfig {
+    //深度缓冲区每一帧开始时清除成的值：对于 Less/LessEqual 是最远处 1.0，对于 Greater/GreaterEqual（反向 Z）是 0.0
+    pub fn clear_value(&self) -> f32 {
+        match self.compare {
+            CompareFunction::Greater | CompareFunction::GreaterEqual => 0.0,
+            _ => 1.0,
+        }
+    }
+}
+
+/*
+渲染管线构建器
+RenderPipelineDescriptor 的字段很多，而大多数管线只有着色器、顶点布局、颜色格式和深度设置不同。
+构建器为其余字段提供默认值，只需要设置不同的部分。
+*/
+pub struct RenderPipelineBuilder<'a> {
+    label: Option<&'a str>,
+    layout: Option<&'a PipelineLayout>,
+    shader: &'a ShaderModule,
+    //着色器的 WGSL 源码，设置后创建管线之前检查顶点输入
+    source: Option<&'a str>,
+    vs_entry: &'a str,
+    fs_entry: &'a str,
+    vertex_buffers: Vec<VertexBufferLayout<'a>>,
+    //为 None 时没有片元着色器和颜色附件，只写入深度（阴影贴图）
+    color_format: Option<TextureFormat>,
+    blend: Option<BlendState>,
+    topology: PrimitiveTopology,
+    cull_mode: Option<Face>,
+    depth: Option<DepthConfig>,
+    depth_bias: DepthBiasState,
+}
+
+impl<'a> RenderPipelineBuilder<'a> {
+    //入口点默认为 vs_main 和 fs_main
+    pub fn new(shader: &'a ShaderModule, color_format: TextureFormat) -> Self {
+        Self {
+            label: None,
+            layout: None,
+            shader,
+            source: None,
+            vs_entry: "vs_main",
+            fs_entry: "fs_main",
+            vertex_buffers: Vec::new(),
+            color_format: Some(color_format),
+            blend: Some(BlendState::REPLACE),
+            topology: PrimitiveTopology::TriangleList,
+            cull_mode: Some(Face::Back),
+            depth: None,
+            depth_bias: DepthBiasState::default(),
+        }
+    }
+
+    //只有顶点着色器、只写入深度的管线，需要再用 depth 设置深度格式
+    pub fn depth_only(shader: &'a ShaderModule) -> Self {
+        Self {
+            color_format: None,
+            ..Self::new(shader, TextureFormat::Rgba8Unorm)
+        }
+    }
+
+    pub fn label(mut self, label: &'a str) -> Self {
+        self.label = Some(label);
+        self
+    }
+
+    //不设置时由 wgpu 根据着色器自动推导
+    pub fn layout(mut self, layout: &'a PipelineLayout) -> Self {
+        self.layout = Some(layout);
+        self
+    }
+
+    //ShaderModule 中取不到源码，需要检查顶点输入时另外提供，通常是创建着色器模块时用的同一个字符串
+    pub fn source(mut self, source: &'a str) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    pub fn entry_points(mut self, vs_entry: &'a str, fs_entry: &'a str) -> Self {
+        self.vs_entry = vs_entry;
+        self.fs_entry = fs_entry;
+        self
+    }
+
+    pub fn vertex_buffer(mut self, layout: VertexBufferLayout<'a>) -> Self {
+        self.vertex_buffers.push(layout);
+        self
+    }
+
+    pub fn blend(mut self, blend: Option<BlendState>) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    pub fn topology(mut self, topology: PrimitiveTopology) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    pub fn cull_mode(mut self, cull_mode: Option<Face>) -> Self {
+        self.cull_mode = cull_mode;
+        self
+    }
+
+    //启用深度测试
+    pub fn depth(mut self, depth: DepthConfig) -> Self {
+        self.depth = Some(depth);
+        self
+    }
+
+    //深度偏移：写入深度缓冲区之前加到深度值上，阴影贴图用它避免表面自己遮挡自己（阴影失真）
+    pub fn depth_bias(mut self, depth_bias: DepthBiasState) -> Self {
+        self.depth_bias = depth_bias;
+        self
+    }
+
+    //设置了 source 时，顶点输入与顶点缓冲区布局不一致会 panic 并给出具体的 location，需要处理错误时使用 try_build
+    pub fn build(self, device: &Device) -> RenderPipeline {
+        self.try_build(device).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    //先检查顶点输入（见 validate 子模块），再创建管线
+    pub fn try_build(self, device: &Device) -> Result<RenderPipeline, VertexInputError> {
+        if let Some(source) = self.source {
+            check_vertex_inputs(source, self.vs_entry, &self.vertex_buffers)?;
+        }
+        /*
+        可以在这里指定着色器中的哪个函数应该是入口点（ entry_point）。那是我们用 @vertex 和 @fragment 标记的函数。
+        buffers 字段告诉 wgpu 要把什么类型的顶点数据传递给顶点着色器。
+        fragment 字段是 Option 类型，所以必须用 Some() 来包装 FragmentState 实例。如果想把颜色数据存储到 surface 就需要用到它 。
+        targets 字段告诉 wgpu 应该设置哪些颜色输出目标。目前只需设置一个输出目标。格式指定为渲染目标的格式，混合模式默认为仅用新的像素数据替换旧的。
+        我们还告诉 wgpu 可写入全部 4 个颜色通道：红、蓝、绿和透明度。
+        */
+        let targets = [self.color_format.map(|format| ColorTargetState {
+            format,
+            blend: self.blend,
+            write_mask: ColorWrites::ALL,
+        })];
+        Ok(device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: self.label,
+            layout: self.layout,
+            vertex: VertexState {
+                module: self.shader,
+                entry_point: self.vs_entry,
+                buffers: &self.vertex_buffers,
+            },
+            fragment: self.color_format.map(|_| FragmentState {
+                module: self.shader,
+                entry_point: self.fs_entry,
+                targets: &targets,
+            }),
+            /*
+            图元（primitive）字段描述了将如何解释顶点来转换为三角形。
+
+            PrimitiveTopology::TriangleList 意味着每三个顶点组成一个三角形。
+            front_face 字段告诉 wgpu 如何确定三角形的朝向。FrontFace::Ccw 指定顶点的帧缓冲区坐标（framebuffer coordinates）
+                按逆时针顺序给出的三角形为朝前（面向屏幕外）。
+            cull_mode 字段告诉 wgpu 如何做三角形剔除。CullMode::Back 指定朝后（面向屏幕内）的三角形会被剔除（不被渲染）。
+            */
+            primitive: PrimitiveState {
+                topology: self.topology,
+                strip_index_format: None,
+                front_face: FrontFace::Ccw,
+                cull_mode: self.cull_mode,
+                // 将此设置为 Fill 以外的任何值都要需要开启 Feature::NON_FILL_POLYGON_MODE
+                polygon_mode: PolygonMode::Fill,
+                // 需要开启 Features::DEPTH_CLIP_CONTROL
+                unclipped_depth: false,
+                // 需要开启 Features::CONSERVATIVE_RASTERIZATION
+                conservative: false,
+            },
+            /*
+            depth_stencil 描述深度/模板测试，见 DepthConfig。模板测试目前没有用到。
+            count 确定管线将使用多少个采样。多重采样是一个复杂的主题，因此不会在这里展开讨论。
+            mask 指定哪些采样应处于活动状态。目前我们使用全部采样。
+            alpha_to_coverage_enabled 与抗锯齿有关。在这里不介绍抗锯齿，因此将其保留为 false。
+            multiview 表示渲染附件可以有多少数组层。我们不会渲染到数组纹理，因此将其设置为 None。
+            */
+            depth_stencil: self.depth.map(|depth| DepthStencilState {
+                format: depth.format,
+                depth_write_enabled: depth.write_enabled,
+                depth_compare: depth.compare,
+                stencil: StencilState::default(),
+                bias: self.depth_bias,
+            }),
+            multisample: MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        }))
+    }
+}
+
+/*
+计算管线构建器
+计算管线只有一个计算着色器，没有顶点、光栅化和颜色附件，所以比渲染管线简单得多。
+调度（dispatch）计算任务、绑定存储缓冲区和读回结果见 compute 模块。
+*/
+pub struct ComputePipelineBuilder<'a> {
+    label: Option<&'a str>,
+    layout: Option<&'a PipelineLayout>,
+    shader: &'a ShaderModule,
+    entry: &'a str,
+}
+
+impl<'a> ComputePipelineBuilder<'a> {
+    //入口点默认为 cs_main
+    pub fn new(shader: &'a ShaderModule) -> Self {
+        Self {
+            label: None,
+            layout: None,
+            shader,
+            entry: "cs_main",
+        }
+    }
+
+    pub fn label(mut self, label: &'a str) -> Self {
+        self.label = Some(label);
+        self
+    }
+
+    //不设置时由 wgpu 根据着色器自动推导，之后用 ComputePipeline::get_bind_group_layout 取得绑定组布局
+    pub fn layout(mut self, layout: &'a PipelineLayout) -> Self {
+        self.layout = Some(layout);
+        self
+    }
+
+    pub fn entry_point(mut self, entry: &'a str) -> Self {
+        self.entry = entry;
+        self
+    }
+
+    pub fn build(self, device: &Device) -> ComputePipeline {
+        device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: self.label,
+            layout: self.layout,
+            module: self.shader,
+            entry_point: self.entry,
+        })
+    }
+}
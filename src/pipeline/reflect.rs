@@ -0,0 +1,583 @@
+/*
+根据着色器生成绑定组布局和管线布局
+手写的 BindGroupLayout 必须与着色器中的 @group/@binding 声明一一对应：纹理的维度和采样类型、采样器是否比较、缓冲区的类型、哪些着色器阶段可见，
+改了着色器忘了改布局，创建管线时才会报错。这里用 naga 解析 WGSL，从全局变量的地址空间和类型得到每个绑定的 BindingType，
+再根据每个入口点（直接或通过调用的函数）用到了哪些全局变量得到 visibility。
+
+多个管线共享的绑定组（例如相机、光源）的布局由它们自己的模块创建，可以用 bind_group_layout 指定，这些组不再生成。
+wgpu 的 BindGroupLayout 创建后无法查看它的条目，所以同时要传入创建它时使用的条目，build 时检查着色器的每个绑定在其中都有兼容的条目：
+    缓冲区的类型（uniform、只读或可写的 storage）相同，不比较 has_dynamic_offset 和 min_binding_size
+    采样器是否比较相同，Filtering 和 NonFiltering 都可以
+    纹理的维度、是否多重采样相同，浮点纹理是否可过滤不比较，其他采样类型相同；存储纹理完全相同
+    visibility 包含着色器中用到这个绑定的阶段
+提供的布局中多出来的条目不影响。
+
+反射无法知道的信息使用默认值：
+    浮点纹理假定是可过滤的（filterable），非比较采样器是 Filtering。要使用 Rgba32Float 这类不可过滤的纹理时需要手写布局
+    缓冲区的 min_binding_size 是 None，has_dynamic_offset 是 false
+    声明了但没有入口点使用的绑定也会生成，visibility 为 NONE，这样绑定组仍然可以提供全部资源
+*/
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+
+use naga::{AddressSpace, Block, Handle, ImageClass, ImageDimension, Module, ScalarKind, ShaderStage, Statement, StorageAccess, StorageFormat, TypeInner};
+use wgpu::{BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingType, BufferBindingType, Device, PipelineLayout, PipelineLayoutDescriptor, SamplerBindingType, ShaderStages, StorageTextureAccess, TextureFormat, TextureSampleType, TextureViewDimension};
+
+#[derive(Debug)]
+pub enum ReflectError {
+    //WGSL 解析失败，内容是带有源码位置的错误信息
+    Parse(String),
+    //绑定的类型没有对应的 BindingType（例如绑定数组、push constant）
+    Unsupported { group: u32, binding: u32, name: String },
+    //调用者提供的布局中没有着色器用到的绑定
+    MissingBinding { group: u32, binding: u32, name: String },
+    //调用者提供的布局中绑定的类型与着色器不兼容
+    BindingMismatch { group: u32, binding: u32, name: String, shader: BindingType, provided: BindingType },
+    //调用者提供的布局中绑定的 visibility 没有包含用到它的着色器阶段
+    Visibility { group: u32, binding: u32, name: String, required: ShaderStages, provided: ShaderStages },
+}
+
+impl fmt::Display for ReflectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReflectError::Parse(message) => write!(f, "着色器解析失败:\n{}", message),
+            ReflectError::Unsupported { group, binding, name } =>
+                write!(f, "无法为 {}（@group({}) @binding({})）生成绑定组布局，不支持它的类型", name, group, binding),
+            ReflectError::MissingBinding { group, binding, name } =>
+                write!(f, "第 {} 组提供的布局中没有着色器用到的 {}（@binding({})）", group, name, binding),
+            ReflectError::BindingMismatch { group, binding, name, shader, provided } =>
+                write!(f, "{}（@group({}) @binding({})）的类型不匹配：着色器中是 {:?}，提供的布局中是 {:?}", name, group, binding, shader, provided),
+            ReflectError::Visibility { group, binding, name, required, provided } =>
+                write!(f, "{}（@group({}) @binding({})）在提供的布局中只对 {:?} 可见，着色器中 {:?} 用到了它", name, group, binding, provided, required),
+        }
+    }
+}
+
+impl Error for ReflectError {}
+
+/*
+管线布局构建器
+PipelineLayoutBuilder::reflect(source)
+    .label("Render Pipeline Layout")
+    .bind_group_layout(1, &camera_layout, CameraBinding::LAYOUT_ENTRIES)
+    .build(device)?
+*/
+pub struct PipelineLayoutBuilder<'a> {
+    label: Option<&'a str>,
+    //每个组的布局条目，下标是组号。着色器中没有的组是空的
+    groups: Vec<Vec<BindGroupLayoutEntry>>,
+    //每个绑定在着色器中的变量名，用于错误信息
+    names: HashMap<(u32, u32), String>,
+    //调用者提供的布局和创建它时使用的条目，这些组不再生成
+    provided: Vec<(u32, &'a BindGroupLayout, &'a [BindGroupLayoutEntry])>,
+}
+
+//生成的布局。bind_group_layouts 的下标是组号，调用者提供的组是 None
+pub struct ReflectedLayout {
+    pub bind_group_layouts: Vec<Option<BindGroupLayout>>,
+    pub pipeline_layout: PipelineLayout,
+}
+
+impl<'a> PipelineLayoutBuilder<'a> {
+    pub fn reflect(source: &str) -> Result<Self, ReflectError> {
+        let module = naga::front::wgsl::parse_str(source)
+            .map_err(|e| ReflectError::Parse(e.emit_to_string(source)))?;
+        Self::from_module(&module)
+    }
+
+    pub fn from_module(module: &Module) -> Result<Self, ReflectError> {
+        //每个全局变量被哪些阶段用到
+        let mut visibility = vec![ShaderStages::NONE; module.global_variables.len()];
+        for entry_point in &module.entry_points {
+            let stage = match entry_point.stage {
+                ShaderStage::Vertex => ShaderStages::VERTEX,
+                ShaderStage::Fragment => ShaderStages::FRAGMENT,
+                ShaderStage::Compute => ShaderStages::COMPUTE,
+            };
+            let mut used = HashSet::new();
+            used_globals(module, &entry_point.function, &mut used);
+            for global in used {
+                visibility[global.index()] |= stage;
+            }
+        }
+
+        let mut groups: Vec<Vec<BindGroupLayoutEntry>> = Vec::new();
+        let mut names = HashMap::new();
+        for (handle, global) in module.global_variables.iter() {
+            let Some(resource) = &global.binding else {
+                continue;
+            };
+            let ty = binding_type(module, global.space, global.ty).ok_or_else(|| ReflectError::Unsupported {
+                group: resource.group,
+                binding: resource.binding,
+                name: global.name.clone().unwrap_or_default(),
+            })?;
+            names.insert((resource.group, resource.binding), global.name.clone().unwrap_or_default());
+            let group = resource.group as usize;
+            if groups.len() <= group {
+                groups.resize_with(group + 1, Vec::new);
+            }
+            groups[group].push(BindGroupLayoutEntry {
+                binding: resource.binding,
+                visibility: visibility[handle.index()],
+                ty,
+                count: None,
+            });
+        }
+        for entries in &mut groups {
+            entries.sort_by_key(|entry| entry.binding);
+        }
+        Ok(Self {
+            label: None,
+            groups,
+            names,
+            provided: Vec::new(),
+        })
+    }
+
+    pub fn label(mut self, label: &'a str) -> Self {
+        self.label = Some(label);
+        self
+    }
+
+    //使用已有的布局作为第 group 组（多个管线共享的绑定组），不再从着色器生成。entries 是创建 layout 时使用的条目，build 时与着色器比较
+    pub fn bind_group_layout(mut self, group: u32, layout: &'a BindGroupLayout, entries: &'a [BindGroupLayoutEntry]) -> Self {
+        self.provided.retain(|(other, _, _)| *other != group);
+        self.provided.push((group, layout, entries));
+        self
+    }
+
+    //某一组生成的布局条目
+    pub fn entries(&self, group: u32) -> &[BindGroupLayoutEntry] {
+        self.groups.get(group as usize).map_or(&[], Vec::as_slice)
+    }
+
+    //检查调用者提供的布局能否用于着色器中这一组的每个绑定
+    fn check_provided(&self, group: u32, provided: &[BindGroupLayoutEntry]) -> Result<(), ReflectError> {
+        for entry in self.entries(group) {
+            let name = || self.names.get(&(group, entry.binding)).cloned().unwrap_or_default();
+            let Some(other) = provided.iter().find(|other| other.binding == entry.binding) else {
+                return Err(ReflectError::MissingBinding { group, binding: entry.binding, name: name() });
+            };
+            if !compatible(entry.ty, other.ty) {
+                return Err(ReflectError::BindingMismatch {
+                    group,
+                    binding: entry.binding,
+                    name: name(),
+                    shader: entry.ty,
+                    provided: other.ty,
+                });
+            }
+            if !other.visibility.contains(entry.visibility) {
+                return Err(ReflectError::Visibility {
+                    group,
+                    binding: entry.binding,
+                    name: name(),
+                    required: entry.visibility,
+                    provided: other.visibility,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn build(self, device: &Device) -> Result<ReflectedLayout, ReflectError> {
+        for (group, _, entries) in &self.provided {
+            self.check_provided(*group, entries)?;
+        }
+        //组号必须连续，中间没有用到的组使用空布局
+        let count = self.provided.iter().map(|(group, _, _)| *group as usize + 1)
+            .chain(std::iter::once(self.groups.len()))
+            .max()
+            .unwrap_or(0);
+        let bind_group_layouts: Vec<Option<BindGroupLayout>> = (0..count)
+            .map(|group| {
+                if self.provided.iter().any(|(provided, _, _)| *provided as usize == group) {
+                    return None;
+                }
+                let label = self.label.map(|label| format!("{} Group {}", label, group));
+                Some(device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                    label: label.as_deref(),
+                    entries: self.groups.get(group).map_or(&[], Vec::as_slice),
+                }))
+            })
+            .collect();
+        let layouts: Vec<&BindGroupLayout> = bind_group_layouts.iter().enumerate()
+            .map(|(group, layout)| match layout {
+                Some(layout) => layout,
+                None => self.provided.iter().find(|(provided, _, _)| *provided as usize == group).expect("调用者提供的布局").1,
+            })
+            .collect();
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: self.label,
+            bind_group_layouts: &layouts,
+            push_constant_ranges: &[],
+        });
+        Ok(ReflectedLayout {
+            bind_group_layouts,
+            pipeline_layout,
+        })
+    }
+}
+
+//提供的绑定类型能否用于着色器中反射出的绑定类型，规则见模块开头
+fn compatible(shader: BindingType, provided: BindingType) -> bool {
+    match (shader, provided) {
+        (BindingType::Buffer { ty: a, .. }, BindingType::Buffer { ty: b, .. }) => a == b,
+        (BindingType::Sampler(a), BindingType::Sampler(b)) =>
+            (a == SamplerBindingType::Comparison) == (b == SamplerBindingType::Comparison),
+        (
+            BindingType::Texture { sample_type: a, view_dimension: a_dimension, multisampled: a_multi },
+            BindingType::Texture { sample_type: b, view_dimension: b_dimension, multisampled: b_multi },
+        ) => a_dimension == b_dimension && a_multi == b_multi && match (a, b) {
+            (TextureSampleType::Float { .. }, TextureSampleType::Float { .. }) => true,
+            (a, b) => a == b,
+        },
+        (a @ BindingType::StorageTexture { .. }, b) => a == b,
+        _ => false,
+    }
+}
+
+//函数（包括它调用的函数）用到的全局变量
+fn used_globals(module: &Module, function: &naga::Function, used: &mut HashSet<Handle<naga::GlobalVariable>>) {
+    for (_, expression) in function.expressions.iter() {
+        if let naga::Expression::GlobalVariable(global) = *expression {
+            used.insert(global);
+        }
+    }
+    let mut calls = Vec::new();
+    collect_calls(&function.body, &mut calls);
+    for callee in calls {
+        used_globals(module, &module.functions[callee], used);
+    }
+}
+
+fn collect_calls(block: &Block, calls: &mut Vec<Handle<naga::Function>>) {
+    for statement in block.iter() {
+        match statement {
+            Statement::Call { function, .. } => calls.push(*function),
+            Statement::Block(body) => collect_calls(body, calls),
+            Statement::If { accept, reject, .. } => {
+                collect_calls(accept, calls);
+                collect_calls(reject, calls);
+            }
+            Statement::Switch { cases, .. } => {
+                for case in cases {
+                    collect_calls(&case.body, calls);
+                }
+            }
+            Statement::Loop { body, continuing, .. } => {
+                collect_calls(body, calls);
+                collect_calls(continuing, calls);
+            }
+            _ => {}
+        }
+    }
+}
+
+//全局变量对应的绑定类型
+fn binding_type(module: &Module, space: AddressSpace, ty: Handle<naga::Type>) -> Option<BindingType> {
+    let buffer = |ty| Some(BindingType::Buffer {
+        ty,
+        has_dynamic_offset: false,
+        min_binding_size: None,
+    });
+    match space {
+        AddressSpace::Uniform => buffer(BufferBindingType::Uniform),
+        AddressSpace::Storage { access } => buffer(BufferBindingType::Storage { read_only: !access.contains(StorageAccess::STORE) }),
+        AddressSpace::Handle => match module.types[ty].inner {
+            TypeInner::Sampler { comparison } => Some(BindingType::Sampler(if comparison {
+                SamplerBindingType::Comparison
+            } else {
+                SamplerBindingType::Filtering
+            })),
+            TypeInner::Image { dim, arrayed, class } => {
+                let view_dimension = match (dim, arrayed) {
+                    (ImageDimension::D1, false) => TextureViewDimension::D1,
+                    (ImageDimension::D2, false) => TextureViewDimension::D2,
+                    (ImageDimension::D2, true) => TextureViewDimension::D2Array,
+                    (ImageDimension::D3, false) => TextureViewDimension::D3,
+                    (ImageDimension::Cube, false) => TextureViewDimension::Cube,
+                    (ImageDimension::Cube, true) => TextureViewDimension::CubeArray,
+                    _ => return None,
+                };
+                Some(match class {
+                    ImageClass::Sampled { kind, multi } => BindingType::Texture {
+                        sample_type: match kind {
+                            ScalarKind::Float => TextureSampleType::Float { filterable: !multi },
+                            ScalarKind::Sint => TextureSampleType::Sint,
+                            ScalarKind::Uint => TextureSampleType::Uint,
+                            ScalarKind::Bool => return None,
+                        },
+                        view_dimension,
+                        multisampled: multi,
+                    },
+                    ImageClass::Depth { multi } => BindingType::Texture {
+                        sample_type: TextureSampleType::Depth,
+                        view_dimension,
+                        multisampled: multi,
+                    },
+                    ImageClass::Storage { format, access } => BindingType::StorageTexture {
+                        access: match (access.contains(StorageAccess::LOAD), access.contains(StorageAccess::STORE)) {
+                            (true, true) => StorageTextureAccess::ReadWrite,
+                            (true, false) => StorageTextureAccess::ReadOnly,
+                            _ => StorageTextureAccess::WriteOnly,
+                        },
+                        format: storage_format(format),
+                        view_dimension,
+                    },
+                })
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn storage_format(format: StorageFormat) -> TextureFormat {
+    use StorageFormat as S;
+    match format {
+        S::R8Unorm => TextureFormat::R8Unorm,
+        S::R8Snorm => TextureFormat::R8Snorm,
+        S::R8Uint => TextureFormat::R8Uint,
+        S::R8Sint => TextureFormat::R8Sint,
+        S::R16Uint => TextureFormat::R16Uint,
+        S::R16Sint => TextureFormat::R16Sint,
+        S::R16Float => TextureFormat::R16Float,
+        S::Rg8Unorm => TextureFormat::Rg8Unorm,
+        S::Rg8Snorm => TextureFormat::Rg8Snorm,
+        S::Rg8Uint => TextureFormat::Rg8Uint,
+        S::Rg8Sint => TextureFormat::Rg8Sint,
+        S::R32Uint => TextureFormat::R32Uint,
+        S::R32Sint => TextureFormat::R32Sint,
+        S::R32Float => TextureFormat::R32Float,
+        S::Rg16Uint => TextureFormat::Rg16Uint,
+        S::Rg16Sint => TextureFormat::Rg16Sint,
+        S::Rg16Float => TextureFormat::Rg16Float,
+        S::Rgba8Unorm => TextureFormat::Rgba8Unorm,
+        S::Rgba8Snorm => TextureFormat::Rgba8Snorm,
+        S::Rgba8Uint => TextureFormat::Rgba8Uint,
+        S::Rgba8Sint => TextureFormat::Rgba8Sint,
+        S::Rgb10a2Unorm => TextureFormat::Rgb10a2Unorm,
+        S::Rg11b10Float => TextureFormat::Rg11b10Float,
+        S::Rg32Uint => TextureFormat::Rg32Uint,
+        S::Rg32Sint => TextureFormat::Rg32Sint,
+        S::Rg32Float => TextureFormat::Rg32Float,
+        S::Rgba16Uint => TextureFormat::Rgba16Uint,
+        S::Rgba16Sint => TextureFormat::Rgba16Sint,
+        S::Rgba16Float => TextureFormat::Rgba16Float,
+        S::Rgba32Uint => TextureFormat::Rgba32Uint,
+        S::Rgba32Sint => TextureFormat::Rgba32Sint,
+        S::Rgba32Float => TextureFormat::Rgba32Float,
+        S::R16Unorm => TextureFormat::R16Unorm,
+        S::R16Snorm => TextureFormat::R16Snorm,
+        S::Rg16Unorm => TextureFormat::Rg16Unorm,
+        S::Rg16Snorm => TextureFormat::Rg16Snorm,
+        S::Rgba16Unorm => TextureFormat::Rgba16Unorm,
+        S::Rgba16Snorm => TextureFormat::Rgba16Snorm,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::CameraBinding;
+    use crate::headless;
+    use crate::light::Lights;
+    use crate::shadow::ShadowMaps;
+
+    const SHADER: &str = "
+@group(0) @binding(0) var t_color: texture_2d<f32>;
+@group(0) @binding(1) var s_color: sampler;
+@group(0) @binding(2) var t_ids: texture_2d<u32>;
+@group(0) @binding(3) var t_shadow: texture_depth_2d_array;
+@group(0) @binding(4) var s_shadow: sampler_comparison;
+@group(0) @binding(5) var t_sky: texture_cube<f32>;
+@group(1) @binding(0) var<uniform> camera: mat4x4f;
+@group(1) @binding(1) var<storage> offsets: array<vec4f>;
+@group(1) @binding(2) var<storage, read_write> counter: atomic<u32>;
+@group(1) @binding(3) var<uniform> unused: vec4f;
+
+fn transform(position: vec4f) -> vec4f {
+    return camera * (position + offsets[0]);
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4f {
+    return transform(vec4f(f32(index), 0.0, 0.0, 1.0));
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4f) -> @location(0) vec4f {
+    let color = textureSample(t_color, s_color, position.xy);
+    let id = textureLoad(t_ids, vec2u(position.xy), 0).x;
+    let shadow = textureSampleCompare(t_shadow, s_shadow, position.xy, 0, 0.5);
+    let sky = textureSample(t_sky, s_color, position.xyz);
+    return color * shadow + sky + camera[0] * f32(id);
+}
+
+@compute @workgroup_size(1)
+fn cs_main() {
+    atomicAdd(&counter, 1u);
+}
+";
+
+    fn builder() -> PipelineLayoutBuilder<'static> {
+        PipelineLayoutBuilder::reflect(SHADER).unwrap()
+    }
+
+    fn entry(builder: &PipelineLayoutBuilder, group: u32, binding: u32) -> BindGroupLayoutEntry {
+        *builder.entries(group).iter().find(|entry| entry.binding == binding).unwrap()
+    }
+
+    fn texture(sample_type: TextureSampleType, view_dimension: TextureViewDimension) -> BindingType {
+        BindingType::Texture {
+            sample_type,
+            view_dimension,
+            multisampled: false,
+        }
+    }
+
+    fn buffer(ty: BufferBindingType) -> BindingType {
+        BindingType::Buffer {
+            ty,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        }
+    }
+
+    #[test]
+    fn texture_and_sampler_types() {
+        let builder = builder();
+        let float = TextureSampleType::Float { filterable: true };
+        assert_eq!(entry(&builder, 0, 0).ty, texture(float, TextureViewDimension::D2));
+        assert_eq!(entry(&builder, 0, 1).ty, BindingType::Sampler(SamplerBindingType::Filtering));
+        assert_eq!(entry(&builder, 0, 2).ty, texture(TextureSampleType::Uint, TextureViewDimension::D2));
+        assert_eq!(entry(&builder, 0, 3).ty, texture(TextureSampleType::Depth, TextureViewDimension::D2Array));
+        assert_eq!(entry(&builder, 0, 4).ty, BindingType::Sampler(SamplerBindingType::Comparison));
+        assert_eq!(entry(&builder, 0, 5).ty, texture(float, TextureViewDimension::Cube));
+    }
+
+    #[test]
+    fn uniform_and_storage_buffers() {
+        let builder = builder();
+        assert_eq!(entry(&builder, 1, 0).ty, buffer(BufferBindingType::Uniform));
+        assert_eq!(entry(&builder, 1, 1).ty, buffer(BufferBindingType::Storage { read_only: true }));
+        assert_eq!(entry(&builder, 1, 2).ty, buffer(BufferBindingType::Storage { read_only: false }));
+    }
+
+    #[test]
+    fn visibility_per_stage() {
+        let builder = builder();
+        //顶点着色器通过 transform 用到了 camera，片元着色器直接用到
+        assert_eq!(entry(&builder, 1, 0).visibility, ShaderStages::VERTEX | ShaderStages::FRAGMENT);
+        assert_eq!(entry(&builder, 1, 1).visibility, ShaderStages::VERTEX);
+        assert_eq!(entry(&builder, 0, 1).visibility, ShaderStages::FRAGMENT);
+        assert_eq!(entry(&builder, 1, 2).visibility, ShaderStages::COMPUTE);
+        //声明了但没有用到的绑定仍然生成
+        assert_eq!(entry(&builder, 1, 3).visibility, ShaderStages::NONE);
+    }
+
+    #[test]
+    fn entries_are_sorted_and_missing_groups_are_empty() {
+        let builder = builder();
+        let bindings: Vec<u32> = builder.entries(0).iter().map(|entry| entry.binding).collect();
+        assert_eq!(bindings, [0, 1, 2, 3, 4, 5]);
+        assert!(builder.entries(2).is_empty());
+        assert!(matches!(PipelineLayoutBuilder::reflect("fn broken( {"), Err(ReflectError::Parse(_))));
+    }
+
+    #[test]
+    fn compatible_bindings() {
+        let float = |filterable| texture(TextureSampleType::Float { filterable }, TextureViewDimension::D2);
+        assert!(compatible(float(true), float(false)));
+        assert!(!compatible(float(true), texture(TextureSampleType::Float { filterable: true }, TextureViewDimension::D2Array)));
+        assert!(!compatible(float(true), texture(TextureSampleType::Depth, TextureViewDimension::D2)));
+        assert!(compatible(
+            BindingType::Sampler(SamplerBindingType::Filtering),
+            BindingType::Sampler(SamplerBindingType::NonFiltering),
+        ));
+        assert!(!compatible(
+            BindingType::Sampler(SamplerBindingType::Filtering),
+            BindingType::Sampler(SamplerBindingType::Comparison),
+        ));
+        //动态偏移和最小大小不比较
+        assert!(compatible(buffer(BufferBindingType::Uniform), BindingType::Buffer {
+            ty: BufferBindingType::Uniform,
+            has_dynamic_offset: true,
+            min_binding_size: std::num::NonZeroU64::new(64),
+        }));
+        assert!(!compatible(buffer(BufferBindingType::Storage { read_only: true }), buffer(BufferBindingType::Storage { read_only: false })));
+        assert!(!compatible(buffer(BufferBindingType::Uniform), float(true)));
+    }
+
+    #[test]
+    fn provided_layouts_are_checked() {
+        let (device, _queue) = pollster::block_on(headless::request_device());
+        let source = "
+@group(0) @binding(0) var<uniform> camera: mat4x4f;
+@group(0) @binding(1) var<uniform> tint: vec4f;
+
+@vertex
+fn vs_main() -> @builtin(position) vec4f {
+    return camera[0];
+}
+
+@fragment
+fn fs_main() -> @location(0) vec4f {
+    return camera[1] * tint;
+}
+";
+        let uniform = |binding, visibility| BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: buffer(BufferBindingType::Uniform),
+            count: None,
+        };
+        let build = |entries: &[BindGroupLayoutEntry]| {
+            let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: None,
+                entries,
+            });
+            PipelineLayoutBuilder::reflect(source).unwrap()
+                .bind_group_layout(0, &layout, entries)
+                .build(&device)
+                .map(|reflected| reflected.bind_group_layouts.len())
+        };
+
+        //多出来的条目和更大的 visibility 都可以
+        let stages = ShaderStages::VERTEX | ShaderStages::FRAGMENT;
+        assert_eq!(build(&[uniform(0, stages), uniform(1, stages), uniform(2, stages)]).unwrap(), 1);
+        match build(&[uniform(0, stages)]) {
+            Err(ReflectError::MissingBinding { group: 0, binding: 1, name }) => assert_eq!(name, "tint"),
+            other => panic!("应该缺少 tint: {:?}", other.map(|_| ())),
+        }
+        match build(&[uniform(0, ShaderStages::VERTEX), uniform(1, stages)]) {
+            Err(ReflectError::Visibility { binding: 0, required, .. }) => assert_eq!(required, stages),
+            other => panic!("camera 应该对片元着色器不可见: {:?}", other.map(|_| ())),
+        }
+        let storage = BindGroupLayoutEntry {
+            ty: buffer(BufferBindingType::Storage { read_only: true }),
+            ..uniform(1, stages)
+        };
+        assert!(matches!(build(&[uniform(0, stages), storage]), Err(ReflectError::BindingMismatch { binding: 1, .. })));
+
+        //与 Scene::new 一样，相机、光源和阴影的条目可以用于光照着色器的 @group(1..3)
+        let shared = [CameraBinding::LAYOUT_ENTRIES, Lights::LAYOUT_ENTRIES, ShadowMaps::LAYOUT_ENTRIES];
+        let layouts: Vec<BindGroupLayout> = shared.iter()
+            .map(|entries| device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: None,
+                entries,
+            }))
+            .collect();
+        let reflected = PipelineLayoutBuilder::reflect(include_str!("../light/shader.wgsl")).unwrap()
+            .bind_group_layout(1, &layouts[0], shared[0])
+            .bind_group_layout(2, &layouts[1], shared[1])
+            .bind_group_layout(3, &layouts[2], shared[2])
+            .build(&device)
+            .unwrap();
+        assert!(reflected.bind_group_layouts[0].is_some());
+        assert!(reflected.bind_group_layouts[1..].iter().all(Option::is_none));
+    }
+}
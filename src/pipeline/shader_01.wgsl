@@ -1,20 +1,7 @@
 
-//顶点着色器
-struct VertexOutput {
-    @builtin(position) clip_position: vec4f
-};
-
-@vertex
-fn vs_main (
-    @builtin(vertex_index) in_vertex_index: u32
-) -> VertexOutput {
-    var out: VertexOutput;
-    let x = f32(1 - i32(in_vertex_index)) * 0.5;
-    let y = f32(i32(in_vertex_index & 1u) * 2 - 1) * 0.5;
-    out.clip_position = vec4f(x, y, 1.0, 1.0);
-    return out;
-}
-
+//与 shader.wgsl 共用顶点着色器，只是三角形的深度不同
+#define TRIANGLE_DEPTH 1.0
+#include "pipeline/triangle.wgsl"
 
 //接下来是片元着色器。还是在 shader.wgsl 中添加以下代码：
 @fragment
@@ -0,0 +1,68 @@
+//pipeline/shader.wgsl、pipeline/shader_01.wgsl 和 buffer/shader.wgsl 共用的顶点着色器，用 #include "pipeline/triangle.wgsl" 插入。
+//定义了 VERTEX_COLOR 时从顶点缓冲区读取位置和纹理坐标（见 buffer 模块的 Vertex），把纹理坐标当作颜色输出；否则根据顶点索引算出一个三角形。
+//TRIANGLE_DEPTH 是三角形的深度，默认为 0.0。
+
+#ifndef TRIANGLE_DEPTH
+#define TRIANGLE_DEPTH 0.0
+#endif
+
+//顶点着色器
+//首先，声明一个 struct 来存储顶点着色器的输出。目前只有一个字段，即 clip_position。
+//@builtin(position) 属性标记了此字段将作为顶点在裁剪坐标系中的位置来使用。这类似于 GLSL 的 gl_Position 变量。
+//
+//形如 vec4 的向量类型是泛型。目前你必须指定向量将包含的值的类型。因此一个使用 32 位浮点数的 3 维向量写做 vec3f。
+struct VertexOutput {
+    @builtin(position) clip_position: vec4f,
+#ifdef VERTEX_COLOR
+    @location(0) color: vec3f
+#endif
+};
+
+#ifdef VERTEX_COLOR
+//与 Vertex::desc() 的前两个属性一致。Vertex 的颜色已经换成了纹理坐标
+struct VertexInput {
+    @location(0) position: vec3f,
+    @location(1) tex_coords: vec2f
+}
+
+@vertex
+fn vs_main (
+    model: VertexInput
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.color = vec3f(model.tex_coords, 0.0);
+    out.clip_position = vec4f(model.position, 1.0);
+    return out;
+}
+#else
+/*
+着色器代码的下一部分是 vs_main 函数。@vertex 属性标记了这个函数是顶点着色器的有效入口。
+我们预期有一个 u32 类型的变量 in_vertex_index，它的值来自 @builtin(vertex_index)。
+
+然后使用 VertexOutput 结构体声明一个名为 out 的变量。我们为顶点的裁剪空间坐标创建另外两个 x y 变量。
+
+f32() 和 i32() 表示类型强制转换，将括号里的值转换为此类型。
+
+现在我们可以把 clip_position 保存到 out。然后只需返回 out 就完成了顶点着色器的工作!
+*/
+@vertex
+fn vs_main (
+    @builtin(vertex_index) in_vertex_index: u32
+) -> VertexOutput {
+    var out: VertexOutput;
+    let x = f32(1 - i32(in_vertex_index)) * 0.5;
+    let y = f32(i32(in_vertex_index & 1u) * 2 - 1) * 0.5;
+    out.clip_position = vec4f(x, y, TRIANGLE_DEPTH, 1.0);
+    return out;
+}
+
+/*
+//我们也可以不使用 stuct，直接按以下代码来实现：
+@vertex
+fn vs_main(
+    @builtin(vertex_index) in_vertex_index: u32
+) -> @builtin(position) vec4f {
+    // 顶点着色器 code...
+}
+*/
+#endif
@@ -0,0 +1,243 @@
+/*
+检查顶点着色器的输入与顶点缓冲区布局是否一致
+Rust 中的顶点结构体和 WGSL 中的 VertexInput 是分开维护的，两边不一致时（例如 location 1 在 Rust 中是 Float32x2，着色器中却是 vec3f），
+wgpu 只会在创建管线时报告一个很难看懂的验证错误。这里用 naga 解析着色器，找到顶点入口点的所有 @location 输入，
+在创建管线之前逐个与顶点缓冲区的属性比较，错误信息中指出是哪个 location、哪个输入。
+
+比较的是分量的类型（浮点数、有符号整数、无符号整数）、宽度和分量的数量。Unorm/Snorm/Float16 格式在着色器中读出来是 f32，
+Float64 格式只能对应 f64。
+顶点缓冲区提供了但着色器没有使用的属性不算错误。
+*/
+use std::error::Error;
+use std::fmt;
+
+use naga::{Binding, ScalarKind, TypeInner, VectorSize};
+use wgpu::{VertexBufferLayout, VertexFormat};
+
+#[derive(Debug)]
+pub enum VertexInputError {
+    //WGSL 解析失败，内容是带有源码位置的错误信息
+    Parse(String),
+    //着色器中没有这个名字的顶点入口点
+    EntryPoint(String),
+    //着色器需要的 location 没有任何顶点缓冲区提供
+    Missing { entry: String, location: u32, name: String, shader_type: String },
+    //类型、宽度或分量数量不一致
+    Mismatch { entry: String, location: u32, name: String, shader_type: String, buffer: usize, format: VertexFormat },
+    //两个属性使用了同一个 location
+    Duplicate { location: u32, buffers: [usize; 2] },
+}
+
+impl fmt::Display for VertexInputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VertexInputError::Parse(message) => write!(f, "着色器解析失败:\n{}", message),
+            VertexInputError::EntryPoint(name) => write!(f, "着色器中没有名为 {} 的顶点入口点", name),
+            VertexInputError::Missing { entry, location, name, shader_type } =>
+                write!(f, "顶点着色器 {} 的输入 {}（@location({})，{}）没有对应的顶点属性", entry, name, location, shader_type),
+            VertexInputError::Mismatch { entry, location, name, shader_type, buffer, format } =>
+                write!(f, "顶点着色器 {} 的输入 {}（@location({})）是 {}，但顶点缓冲区 {} 提供的是 {:?}", entry, name, location, shader_type, buffer, format),
+            VertexInputError::Duplicate { location, buffers } =>
+                write!(f, "顶点缓冲区 {} 和 {} 都提供了 @location({})", buffers[0], buffers[1], location),
+        }
+    }
+}
+
+impl Error for VertexInputError {}
+
+//分量的类型、宽度（字节）和数量
+type InputType = (ScalarKind, u8, u32);
+
+//着色器中的一个顶点输入
+struct ShaderInput {
+    location: u32,
+    name: String,
+    ty: InputType,
+}
+
+//顶点格式在着色器中读出来的分量类型、宽度和数量
+fn format_type(format: VertexFormat) -> InputType {
+    use VertexFormat::*;
+    match format {
+        Uint8x2 | Uint16x2 | Uint32x2 => (ScalarKind::Uint, 4, 2),
+        Uint8x4 | Uint16x4 | Uint32x4 => (ScalarKind::Uint, 4, 4),
+        Uint32 => (ScalarKind::Uint, 4, 1),
+        Uint32x3 => (ScalarKind::Uint, 4, 3),
+        Sint8x2 | Sint16x2 | Sint32x2 => (ScalarKind::Sint, 4, 2),
+        Sint8x4 | Sint16x4 | Sint32x4 => (ScalarKind::Sint, 4, 4),
+        Sint32 => (ScalarKind::Sint, 4, 1),
+        Sint32x3 => (ScalarKind::Sint, 4, 3),
+        Unorm8x2 | Snorm8x2 | Unorm16x2 | Snorm16x2 | Float16x2 | Float32x2 => (ScalarKind::Float, 4, 2),
+        Unorm8x4 | Snorm8x4 | Unorm16x4 | Snorm16x4 | Float16x4 | Float32x4 => (ScalarKind::Float, 4, 4),
+        Float32 => (ScalarKind::Float, 4, 1),
+        Float32x3 => (ScalarKind::Float, 4, 3),
+        Float64 => (ScalarKind::Float, 8, 1),
+        Float64x2 => (ScalarKind::Float, 8, 2),
+        Float64x3 => (ScalarKind::Float, 8, 3),
+        Float64x4 => (ScalarKind::Float, 8, 4),
+    }
+}
+
+fn type_name((kind, width, components): InputType) -> String {
+    let bits = width as u32 * 8;
+    let scalar = match kind {
+        ScalarKind::Float => format!("f{}", bits),
+        ScalarKind::Sint => format!("i{}", bits),
+        ScalarKind::Uint => format!("u{}", bits),
+        ScalarKind::Bool => "bool".to_string(),
+    };
+    if components == 1 {
+        scalar
+    } else {
+        format!("vec{}<{}>", components, scalar)
+    }
+}
+
+//参数或结构体成员的类型，只关心标量和向量（顶点输入只能是这两种）
+fn input_type(inner: &TypeInner) -> Option<InputType> {
+    match *inner {
+        TypeInner::Scalar { kind, width } => Some((kind, width, 1)),
+        TypeInner::Vector { size, kind, width } => Some((kind, width, match size {
+            VectorSize::Bi => 2,
+            VectorSize::Tri => 3,
+            VectorSize::Quad => 4,
+        })),
+        _ => None,
+    }
+}
+
+//顶点入口点的所有 @location 输入，包括结构体参数的成员
+fn shader_inputs(source: &str, entry: &str) -> Result<Vec<ShaderInput>, VertexInputError> {
+    let module = naga::front::wgsl::parse_str(source)
+        .map_err(|e| VertexInputError::Parse(e.emit_to_string(source)))?;
+    let entry_point = module.entry_points.iter()
+        .find(|ep| ep.name == entry && ep.stage == naga::ShaderStage::Vertex)
+        .ok_or_else(|| VertexInputError::EntryPoint(entry.to_string()))?;
+
+    let mut inputs = Vec::new();
+    let mut push = |binding: &Option<Binding>, name: &Option<String>, ty: naga::Handle<naga::Type>| {
+        if let (Some(Binding::Location { location, .. }), Some(ty)) = (binding, input_type(&module.types[ty].inner)) {
+            inputs.push(ShaderInput {
+                location: *location,
+                name: name.clone().unwrap_or_else(|| format!("@location({})", location)),
+                ty,
+            });
+        }
+    };
+    for argument in &entry_point.function.arguments {
+        match &module.types[argument.ty].inner {
+            TypeInner::Struct { members, .. } if argument.binding.is_none() => {
+                for member in members {
+                    push(&member.binding, &member.name, member.ty);
+                }
+            }
+            _ => push(&argument.binding, &argument.name, argument.ty),
+        }
+    }
+    Ok(inputs)
+}
+
+//检查顶点着色器 entry 的输入是否都由 buffers 提供，并且类型一致
+pub fn check_vertex_inputs(source: &str, entry: &str, buffers: &[VertexBufferLayout]) -> Result<(), VertexInputError> {
+    //每个 location 由哪个缓冲区的什么格式提供
+    let mut provided: Vec<(u32, usize, VertexFormat)> = Vec::new();
+    for (index, buffer) in buffers.iter().enumerate() {
+        for attribute in buffer.attributes {
+            if let Some(&(_, other, _)) = provided.iter().find(|(location, _, _)| *location == attribute.shader_location) {
+                return Err(VertexInputError::Duplicate { location: attribute.shader_location, buffers: [other, index] });
+            }
+            provided.push((attribute.shader_location, index, attribute.format));
+        }
+    }
+
+    for input in shader_inputs(source, entry)? {
+        let shader_type = type_name(input.ty);
+        let Some(&(_, buffer, format)) = provided.iter().find(|(location, _, _)| *location == input.location) else {
+            return Err(VertexInputError::Missing { entry: entry.to_string(), location: input.location, name: input.name, shader_type });
+        };
+        if format_type(format) != input.ty {
+            return Err(VertexInputError::Mismatch { entry: entry.to_string(), location: input.location, name: input.name, shader_type, buffer, format });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::instance::InstanceRaw;
+    use crate::buffer::{Vertex, VertexLayout};
+    use crate::shader::{Preprocessor, SHADER_ROOT};
+    use wgpu::{vertex_attr_array, VertexAttribute, VertexStepMode};
+
+    fn layout(attributes: &[VertexAttribute]) -> VertexBufferLayout<'_> {
+        VertexBufferLayout {
+            array_stride: 64,
+            step_mode: VertexStepMode::Vertex,
+            attributes,
+        }
+    }
+
+    #[test]
+    fn tutorial_and_scene_shaders_match_their_buffers() {
+        let processed = Preprocessor::new(SHADER_ROOT).process("buffer/shader.wgsl").unwrap();
+        check_vertex_inputs(processed.source(), "vs_main", &[Vertex::desc()]).unwrap();
+        check_vertex_inputs(include_str!("../light/shader.wgsl"), "vs_main", &[Vertex::desc(), InstanceRaw::desc()]).unwrap();
+    }
+
+    #[test]
+    fn vertex_input_errors() {
+        //着色器中的输入：位置是结构体的成员，其余是入口点的参数
+        const SHADER: &str = "
+            struct VertexInput {
+                @location(0) position: vec3f,
+            }
+            @vertex
+            fn vs_main(model: VertexInput, @location(1) uv: vec2f, @location(2) id: u32) -> @builtin(position) vec4f {
+                return vec4f(model.position, 1.0);
+            }
+        ";
+        let ok = vertex_attr_array![0 => Float32x3, 1 => Unorm16x2, 2 => Uint32, 3 => Float32x4];
+        let missing = vertex_attr_array![0 => Float32x3, 2 => Uint32];
+        let components = vertex_attr_array![0 => Float32x3, 1 => Float32x3, 2 => Uint32];
+        let kind = vertex_attr_array![0 => Float32x3, 1 => Float32x2, 2 => Sint32];
+        let float64 = vertex_attr_array![0 => Float64x3, 1 => Float32x2, 2 => Uint32];
+        let first = vertex_attr_array![0 => Float32x3, 1 => Float32x2];
+        let second = vertex_attr_array![2 => Uint32, 1 => Float32x2];
+        const DOUBLE: &str = "@vertex fn vs_main(@location(0) p: vec2<f64>) -> @builtin(position) vec4f { return vec4f(0.0); }";
+        let double = vertex_attr_array![0 => Float64x2];
+        let single = vertex_attr_array![0 => Float32x2];
+
+        //(着色器, 入口点, 顶点缓冲区, 期望的结果)
+        let cases: Vec<(&str, &str, Vec<VertexBufferLayout>, &str)> = vec![
+            (SHADER, "vs_main", vec![layout(&ok)], "Ok"),
+            //没有用到的 location 3 不算错误，两个缓冲区一起提供也可以
+            (SHADER, "vs_main", vec![layout(&ok[..2]), layout(&ok[2..])], "Ok"),
+            (SHADER, "vs_main", vec![layout(&missing)], "Missing 1 uv vec2<f32>"),
+            (SHADER, "vs_main", vec![], "Missing 0 position vec3<f32>"),
+            (SHADER, "vs_main", vec![layout(&components)], "Mismatch 1 uv vec2<f32> Float32x3"),
+            (SHADER, "vs_main", vec![layout(&kind)], "Mismatch 2 id u32 Sint32"),
+            //Float64 不能读成 f32
+            (SHADER, "vs_main", vec![layout(&float64)], "Mismatch 0 position vec3<f32> Float64x3"),
+            (DOUBLE, "vs_main", vec![layout(&double)], "Ok"),
+            (DOUBLE, "vs_main", vec![layout(&single)], "Mismatch 0 p vec2<f64> Float32x2"),
+            (SHADER, "vs_main", vec![layout(&first), layout(&second)], "Duplicate 1 [0, 1]"),
+            (SHADER, "main", vec![layout(&ok)], "EntryPoint main"),
+            (SHADER, "fs_main", vec![layout(&ok)], "EntryPoint fs_main"),
+            ("@vertex fn vs_main( -> @builtin(position) vec4f {}", "vs_main", vec![], "Parse"),
+        ];
+        for (source, entry, buffers, expected) in cases {
+            let result = match check_vertex_inputs(source, entry, &buffers) {
+                Ok(()) => "Ok".to_string(),
+                Err(VertexInputError::Parse(_)) => "Parse".to_string(),
+                Err(VertexInputError::EntryPoint(name)) => format!("EntryPoint {}", name),
+                Err(VertexInputError::Missing { location, name, shader_type, .. }) => format!("Missing {} {} {}", location, name, shader_type),
+                Err(VertexInputError::Mismatch { location, name, shader_type, format, .. }) => {
+                    format!("Mismatch {} {} {} {:?}", location, name, shader_type, format)
+                }
+                Err(VertexInputError::Duplicate { location, buffers }) => format!("Duplicate {} {:?}", location, buffers),
+            };
+            assert_eq!(result, expected, "入口点 {}，缓冲区 {:?}", entry, buffers);
+        }
+    }
+}
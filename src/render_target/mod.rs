@@ -0,0 +1,255 @@
+/*
+渲染目标
+State::render 不再直接向展示平面（Surface）请求帧，而是向一个渲染目标（RenderTarget）请求一个可以绘制的纹理视图。
+这样同一份绘制代码就可以画到窗口、离屏纹理（无窗口渲染、测试）或者纹理数组的某一层（缩略图）上。
+
+每一帧的流程是：acquire() 得到一个 Frame，把场景画到 frame.view 上，提交命令后调用 frame.present()。
+对于展示平面，present() 会把画面呈现到屏幕上；对于纹理，它什么也不做。
+*/
+use std::sync::Arc;
+
+use winit::dpi::PhysicalSize;
+use wgpu::{Adapter, Device, Extent3d, ImageCopyTexture, Origin3d, Surface, SurfaceConfiguration, SurfaceError, SurfaceTexture, Texture, TextureAspect, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages, TextureView, TextureViewDescriptor, TextureViewDimension, PresentMode};
+
+pub trait RenderTarget {
+    //颜色附件的格式，管线的 ColorTargetState 需要与之一致
+    fn format(&self) -> TextureFormat;
+
+    fn size(&self) -> PhysicalSize<u32>;
+
+    fn resize(&mut self, device: &Device, new_size: PhysicalSize<u32>);
+
+    //获取这一帧要绘制的纹理视图
+    fn acquire(&mut self) -> Result<Frame, SurfaceError>;
+
+    //如果目标的内容可以被复制回 CPU（离屏纹理），返回复制的来源
+    fn copy_source(&self) -> Option<ImageCopyTexture<'_>> {
+        None
+    }
+}
+
+pub struct Frame {
+    pub view: TextureView,
+    surface_texture: Option<SurfaceTexture>,
+}
+
+impl Frame {
+    pub fn present(self) {
+        if let Some(surface_texture) = self.surface_texture {
+            surface_texture.present();
+        }
+    }
+}
+
+//窗口的展示平面
+pub struct SurfaceTarget {
+    surface: Surface,
+    config: SurfaceConfiguration,
+}
+
+impl SurfaceTarget {
+    pub fn new(surface: Surface, adapter: &Adapter, device: &Device, size: PhysicalSize<u32>) -> Self {
+        let caps = surface.get_capabilities(adapter);
+
+        // usage 字段描述了 SurfaceTexture 如何被使用。RENDER_ATTACHMENT 指定将被用来渲染到屏幕的纹理（我们将在后面讨论更多的 TextureUsages 枚举值）。
+        //
+        // format 定义了 SurfaceTexture 在 GPU 内存上如何被存储。不同的显示设备偏好不同的纹理格式。
+        // 我们使用surface.get_capabilities(&adapter).formats 来获取当前显示设备的最佳格式。
+        //
+        // width 和 height 指定 SurfaceTexture 的宽度和高度（物理像素，等于逻辑像素乘以屏幕缩放因子）。这通常就是窗口的宽和高。
+        //
+        // 需要确保 SurfaceTexture 的宽高不能为 0，这会导致你的应用程序崩溃。
+        //
+        // present_mode 指定的 wgpu::PresentMode 枚举值决定了展示平面如何与显示设备同步。我们选择的PresentMode::Fifo 指定了显示设备的刷新率做为渲染的帧速率，
+        // 这本质上就是垂直同步（VSync），所有平台都得支持这种呈现模式（PresentMode）。你可以在文档中查看所有的模式。
+        //当你想让用户来选择他们使用的呈现模式时，可以使用 surface.get_capabilities(&adapter) 获取展示平面支持的所有呈现模式的列表:
+        //
+        // let modes = surface.get_capabilities(&adapter).present_modes;
+        //
+        // PresentMode::Fifo 模式无论如何都是被支持的，PresentMode::AutoVsync 和 PresentMode::AutoNoVsync 支持回退，因此也能工作在所有平台上。
+        let config = SurfaceConfiguration {
+            usage: TextureUsages::RENDER_ATTACHMENT,
+            format: caps.formats[0],
+            width: size.width,
+            height: size.height,
+            present_mode: PresentMode::Fifo,
+            alpha_mode: caps.alpha_modes[0],
+            view_formats: vec![],
+        };
+
+        surface.configure(device, &config);
+
+        Self {
+            surface,
+            config,
+        }
+    }
+
+    pub fn config(&self) -> &SurfaceConfiguration {
+        &self.config
+    }
+}
+
+impl RenderTarget for SurfaceTarget {
+    fn format(&self) -> TextureFormat {
+        self.config.format
+    }
+
+    fn size(&self) -> PhysicalSize<u32> {
+        PhysicalSize::new(self.config.width, self.config.height)
+    }
+
+    fn resize(&mut self, device: &Device, new_size: PhysicalSize<u32>) {
+        self.config.width = new_size.width;
+        self.config.height = new_size.height;
+        self.surface.configure(device, &self.config);
+    }
+
+    fn acquire(&mut self) -> Result<Frame, SurfaceError> {
+        //首先，我们需要获取一个帧（Frame）对象以供渲染
+        let output = self.surface.get_current_texture()?;
+
+        //这一行创建了一个默认设置的纹理视图（TextureView），渲染代码需要利用纹理视图来与纹理交互。
+        let view = output.texture.create_view(&TextureViewDescriptor::default());
+
+        Ok(Frame {
+            view,
+            surface_texture: Some(output),
+        })
+    }
+}
+
+//离屏纹理，用于无窗口渲染和测试
+pub struct TextureTarget {
+    texture: Texture,
+    format: TextureFormat,
+}
+
+impl TextureTarget {
+    pub fn new(device: &Device, size: PhysicalSize<u32>, format: TextureFormat) -> Self {
+        Self {
+            texture: create_target_texture(device, size, 1, format),
+            format,
+        }
+    }
+
+    pub fn texture(&self) -> &Texture {
+        &self.texture
+    }
+}
+
+impl RenderTarget for TextureTarget {
+    fn format(&self) -> TextureFormat {
+        self.format
+    }
+
+    fn size(&self) -> PhysicalSize<u32> {
+        PhysicalSize::new(self.texture.width(), self.texture.height())
+    }
+
+    fn resize(&mut self, device: &Device, new_size: PhysicalSize<u32>) {
+        //纹理的大小不能修改，只能重新创建
+        self.texture = create_target_texture(device, new_size, 1, self.format);
+    }
+
+    fn acquire(&mut self) -> Result<Frame, SurfaceError> {
+        Ok(Frame {
+            view: self.texture.create_view(&TextureViewDescriptor::default()),
+            surface_texture: None,
+        })
+    }
+
+    fn copy_source(&self) -> Option<ImageCopyTexture<'_>> {
+        Some(ImageCopyTexture {
+            texture: &self.texture,
+            mip_level: 0,
+            origin: Origin3d::ZERO,
+            aspect: TextureAspect::All,
+        })
+    }
+}
+
+//纹理数组中的某一层。多个目标可以共享同一个纹理数组，例如把多张缩略图画到同一个数组的不同层上。
+pub struct TextureLayerTarget {
+    texture: Arc<Texture>,
+    layer: u32,
+}
+
+impl TextureLayerTarget {
+    pub fn new(texture: Arc<Texture>, layer: u32) -> Self {
+        assert!(layer < texture.depth_or_array_layers(), "纹理数组只有 {} 层，无法使用第 {} 层", texture.depth_or_array_layers(), layer);
+        Self {
+            texture,
+            layer,
+        }
+    }
+
+    //创建一个可以作为渲染目标的纹理数组
+    pub fn create_array(device: &Device, size: PhysicalSize<u32>, layers: u32, format: TextureFormat) -> Arc<Texture> {
+        Arc::new(create_target_texture(device, size, layers, format))
+    }
+
+    pub fn layer(&self) -> u32 {
+        self.layer
+    }
+}
+
+impl RenderTarget for TextureLayerTarget {
+    fn format(&self) -> TextureFormat {
+        self.texture.format()
+    }
+
+    fn size(&self) -> PhysicalSize<u32> {
+        PhysicalSize::new(self.texture.width(), self.texture.height())
+    }
+
+    fn resize(&mut self, _device: &Device, new_size: PhysicalSize<u32>) {
+        //纹理数组由多个目标共享，不能只为其中一层改变大小
+        if new_size != self.size() {
+            log::warn!("纹理数组的层不支持调整大小，忽略 {:?}", new_size);
+        }
+    }
+
+    fn acquire(&mut self) -> Result<Frame, SurfaceError> {
+        //只包含一层的 2D 视图，可以像普通纹理一样作为颜色附件
+        let view = self.texture.create_view(&TextureViewDescriptor {
+            label: Some("Texture Layer Target View"),
+            dimension: Some(TextureViewDimension::D2),
+            base_array_layer: self.layer,
+            array_layer_count: Some(1),
+            ..Default::default()
+        });
+
+        Ok(Frame {
+            view,
+            surface_texture: None,
+        })
+    }
+
+    fn copy_source(&self) -> Option<ImageCopyTexture<'_>> {
+        Some(ImageCopyTexture {
+            texture: &self.texture,
+            mip_level: 0,
+            origin: Origin3d { x: 0, y: 0, z: self.layer },
+            aspect: TextureAspect::All,
+        })
+    }
+}
+
+fn create_target_texture(device: &Device, size: PhysicalSize<u32>, layers: u32, format: TextureFormat) -> Texture {
+    device.create_texture(&TextureDescriptor {
+        label: Some("Render Target Texture"),
+        size: Extent3d {
+            width: size.width,
+            height: size.height,
+            depth_or_array_layers: layers,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format,
+        // RENDER_ATTACHMENT 用于渲染，COPY_SRC 用于把结果读回 CPU，TEXTURE_BINDING 让结果可以在着色器中继续使用（例如缩略图）
+        usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC | TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    })
+}
@@ -0,0 +1,276 @@
+/*
+着色器热重载
+着色器通过 include_str! 编译进程序，每次修改 WGSL 都要重新编译整个程序。开发时可以改为从磁盘读取 .wgsl 文件：
+ShaderWatcher 定期检查文件的修改时间，文件变化后重新预处理（见 preprocess 子模块）；compile 先用 naga 解析和验证，再创建着色器模块，
+checked 把创建管线的代码包在 wgpu 的错误作用域中。这样着色器中的错误会作为 Err 返回，调用者记录错误并继续使用之前的管线，
+而不是让 wgpu 的默认错误处理直接 panic。
+
+错误作用域的结果是异步返回的，这里用 pollster 等待，所以热重载只能在原生平台上使用。
+*/
+pub mod preprocess;
+
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use wgpu::{Device, ShaderModule, ShaderModuleDescriptor, ShaderSource};
+
+use crate::pipeline::VertexInputError;
+
+pub use preprocess::{Preprocessor, PreprocessError, Processed};
+
+//着色器源码的根目录（源码中的 src 目录），#include 的路径相对于它
+pub const SHADER_ROOT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src");
+
+//两次检查文件之间的最短间隔，避免每一帧都访问文件系统
+pub(crate) const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Debug)]
+pub enum ShaderError {
+    Preprocess(PreprocessError),
+    //WGSL 解析或验证失败，message 是带有原来的文件名和行号的错误信息
+    Compile { path: PathBuf, message: String },
+    //顶点着色器的输入与顶点缓冲区布局不一致
+    VertexInput(VertexInputError),
+    //naga 没有发现、但 wgpu 拒绝了的错误（例如与管线布局不匹配）
+    Device(String),
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShaderError::Preprocess(e) => write!(f, "{}", e),
+            ShaderError::Compile { path, message } => write!(f, "着色器 {} 编译失败:\n{}", path.display(), message),
+            ShaderError::VertexInput(e) => write!(f, "{}", e),
+            ShaderError::Device(message) => write!(f, "wgpu 拒绝了着色器或管线: {}", message),
+        }
+    }
+}
+
+impl From<PreprocessError> for ShaderError {
+    fn from(e: PreprocessError) -> Self {
+        ShaderError::Preprocess(e)
+    }
+}
+
+impl From<VertexInputError> for ShaderError {
+    fn from(e: VertexInputError) -> Self {
+        ShaderError::VertexInput(e)
+    }
+}
+
+impl Error for ShaderError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ShaderError::Preprocess(e) => Some(e),
+            ShaderError::VertexInput(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+//编译预处理后的 WGSL
+pub fn compile(device: &Device, processed: &Processed) -> Result<ShaderModule, ShaderError> {
+    let source = processed.source();
+    let compile_error = |message: String| ShaderError::Compile { path: processed.path().to_path_buf(), message };
+    let module = naga::front::wgsl::parse_str(source)
+        .map_err(|e| {
+            let labels = e.labels().map(|(span, label)| (span, label.to_string())).collect();
+            compile_error(diagnostic(processed, e.message(), labels))
+        })?;
+    //设备的能力在这里未知，全部允许，超出设备能力的部分由 wgpu 在创建模块时报告
+    naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::all())
+        .validate(&module)
+        .map_err(|e| {
+            let labels = e.spans().map(|(span, label)| (*span, label.clone())).collect();
+            //验证错误是一层层嵌套的，例如“入口点无效”的原因是“返回值的类型不匹配”
+            let mut message = e.as_inner().to_string();
+            let mut cause = e.as_inner().source();
+            while let Some(inner) = cause {
+                message.push_str(&format!(": {}", inner));
+                cause = inner.source();
+            }
+            compile_error(diagnostic(processed, &message, labels))
+        })?;
+
+    let label = processed.path().display().to_string();
+    checked(device, || device.create_shader_module(ShaderModuleDescriptor {
+        label: Some(&label),
+        source: ShaderSource::Wgsl(Cow::Borrowed(source)),
+    }))
+}
+
+//把 naga 错误中的位置转换为原来的文件和行号，并附上那一行的代码
+fn diagnostic(processed: &Processed, message: &str, labels: Vec<(naga::Span, String)>) -> String {
+    let source = processed.source();
+    let mut result = message.to_string();
+    for (span, label) in labels {
+        if !span.is_defined() {
+            continue;
+        }
+        let location = span.location(source);
+        let code = source.lines().nth(location.line_number as usize - 1).unwrap_or_default();
+        match processed.origin(location.line_number) {
+            Some((path, line)) => result.push_str(&format!("\n  --> {}:{}:{}", path.display(), line, location.line_position)),
+            None => result.push_str(&format!("\n  --> {}:?", processed.path().display())),
+        }
+        result.push_str(&format!("\n   | {}", code.trim_end()));
+        if !label.is_empty() {
+            result.push_str(&format!("\n   = {}", label));
+        }
+    }
+    result
+}
+
+//在错误作用域中执行 create（创建着色器模块或管线），wgpu 报告的验证错误作为 Err 返回
+pub fn checked<T>(device: &Device, create: impl FnOnce() -> T) -> Result<T, ShaderError> {
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let value = create();
+    match pollster::block_on(device.pop_error_scope()) {
+        None => Ok(value),
+        Some(e) => Err(ShaderError::Device(e.to_string())),
+    }
+}
+
+//监视一个着色器文件和它包含的文件
+pub struct ShaderWatcher {
+    path: PathBuf,
+    //监视的文件和上一次检查时的修改时间（读取失败时是 None）
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+    last_poll: Option<Instant>,
+}
+
+impl ShaderWatcher {
+    //文件存在时第一次 poll 总会返回 true，因为磁盘上的文件可能与编译进程序的版本不同
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        let path = path.as_ref().to_path_buf();
+        Self {
+            files: vec![(path.clone(), None)],
+            path,
+            last_poll: None,
+        }
+    }
+
+    //入口文件
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    //预处理成功后更新要监视的文件（入口文件和它包含的文件）
+    pub fn set_files(&mut self, files: &[PathBuf]) {
+        self.files = files.iter().map(|path| (path.clone(), modified(path))).collect();
+    }
+
+    //有文件变化（包括被删除或重新出现）时返回 true。距离上次检查不到 POLL_INTERVAL 时直接返回 false
+    pub fn poll(&mut self) -> bool {
+        let now = Instant::now();
+        if self.last_poll.is_some_and(|last| now - last < POLL_INTERVAL) {
+            return false;
+        }
+        self.last_poll = Some(now);
+
+        let mut changed = false;
+        for (path, time) in &mut self.files {
+            let current = modified(path);
+            if current != *time {
+                *time = current;
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::{Vertex, VertexLayout};
+    use crate::headless;
+    use crate::pipeline::RenderPipelineBuilder;
+
+    //triangle.wgsl 的几个变体经过预处理、编译，再用来创建管线（创建之前检查顶点输入）
+    #[test]
+    fn triangle_variants_build_pipelines() {
+        let (device, _queue) = pollster::block_on(headless::request_device());
+        let format = wgpu::TextureFormat::Rgba8Unorm;
+        let variants = [
+            (Preprocessor::new(SHADER_ROOT), "pipeline/shader.wgsl", None),
+            (Preprocessor::new(SHADER_ROOT).define("TRIANGLE_DEPTH", "0.5"), "pipeline/shader.wgsl", None),
+            (Preprocessor::new(SHADER_ROOT), "pipeline/shader_01.wgsl", None),
+            (Preprocessor::new(SHADER_ROOT), "buffer/shader.wgsl", Some(Vertex::desc())),
+            (Preprocessor::new(SHADER_ROOT).define("VERTEX_COLOR", ""), "pipeline/shader.wgsl", Some(Vertex::desc())),
+        ];
+        for (preprocessor, path, vertex_buffer) in variants {
+            let processed = preprocessor.process(path).unwrap();
+            let shader = compile(&device, &processed).unwrap_or_else(|e| panic!("{}", e));
+            let mut builder = RenderPipelineBuilder::new(&shader, format).source(processed.source());
+            if let Some(layout) = vertex_buffer {
+                builder = builder.vertex_buffer(layout);
+            }
+            checked(&device, || builder.try_build(&device))
+                .unwrap_or_else(|e| panic!("{}: {}", path, e))
+                .unwrap_or_else(|e| panic!("{}: {}", path, e));
+        }
+
+        let processed = Preprocessor::new(SHADER_ROOT).define("TRIANGLE_DEPTH", "0.5").process("pipeline/shader.wgsl").unwrap();
+        assert!(processed.source().contains("vec4f(x, y, 0.5, 1.0)"));
+        //shader_01.wgsl 自己的 #define 优先于 triangle.wgsl 中的默认值
+        let processed = Preprocessor::new(SHADER_ROOT).process("pipeline/shader_01.wgsl").unwrap();
+        assert!(processed.source().contains("vec4f(x, y, 1.0, 1.0)"));
+    }
+
+    #[test]
+    fn watcher_detects_changed_and_included_files() {
+        let dir = std::env::temp_dir().join(format!("wgpu_01_shader_watcher_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let main = dir.join("main.wgsl");
+        let common = dir.join("common.wgsl");
+        let wait = || std::thread::sleep(POLL_INTERVAL + Duration::from_millis(50));
+
+        //文件还不存在
+        let mut watcher = ShaderWatcher::new(&main);
+        assert!(!watcher.poll());
+        std::fs::write(&main, "// 1\n").unwrap();
+        //距离上次检查太近
+        assert!(!watcher.poll());
+        wait();
+        assert!(watcher.poll());
+        wait();
+        assert!(!watcher.poll());
+
+        std::fs::write(&main, "// 2\n").unwrap();
+        wait();
+        assert!(watcher.poll());
+
+        //包含的文件变化也要重新加载
+        std::fs::write(&common, "// 1\n").unwrap();
+        watcher.set_files(&[main.clone(), common.clone()]);
+        wait();
+        assert!(!watcher.poll());
+        std::fs::write(&common, "// 2\n").unwrap();
+        wait();
+        assert!(watcher.poll());
+
+        std::fs::remove_file(&common).unwrap();
+        wait();
+        assert!(watcher.poll());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn vertex_color_variant_needs_a_vertex_buffer() {
+        let (device, _queue) = pollster::block_on(headless::request_device());
+        let processed = Preprocessor::new(SHADER_ROOT).process("buffer/shader.wgsl").unwrap();
+        let shader = compile(&device, &processed).unwrap();
+        let result = RenderPipelineBuilder::new(&shader, wgpu::TextureFormat::Rgba8Unorm)
+            .source(processed.source())
+            .try_build(&device);
+        assert!(matches!(result, Err(VertexInputError::Missing { location: 0, .. })));
+    }
+}
@@ -0,0 +1,451 @@
+/*
+WGSL 预处理器
+WGSL 没有 #include，同样的结构体和入口点只能在每个着色器中复制一份。Preprocessor 在交给 naga/wgpu 之前处理以下指令（# 必须是一行中第一个非空白字符）：
+
+    #include "common.wgsl"   插入另一个文件，路径相对于着色器根目录。同一个文件只会插入一次
+    #define NAME [value]     定义一个名字。有值时，之后的代码中的 NAME 会被替换为 value
+    #undef NAME
+    #ifdef NAME / #ifndef NAME / #else / #endif   条件编译，可以嵌套
+
+/* */ 块注释中以 # 开头的行不是指令，原样输出。
+
+同一个源文件配合 Preprocessor::define 可以生成不同的变体（例如带不带顶点颜色）。
+预处理的结果记录了每一行来自哪个文件的哪一行，编译错误可以据此指回原来的文件，见 Processed::origin。
+*/
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum PreprocessError {
+    Io { path: PathBuf, source: io::Error },
+    //指令有错误，line 从 1 开始
+    Syntax { path: PathBuf, line: u32, message: String },
+}
+
+impl fmt::Display for PreprocessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PreprocessError::Io { path, source } => write!(f, "无法读取着色器 {}: {}", path.display(), source),
+            PreprocessError::Syntax { path, line, message } => write!(f, "{}:{}: {}", path.display(), line, message),
+        }
+    }
+}
+
+impl Error for PreprocessError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PreprocessError::Io { source, .. } => Some(source),
+            PreprocessError::Syntax { .. } => None,
+        }
+    }
+}
+
+//预处理的结果
+pub struct Processed {
+    source: String,
+    //用到的所有文件，第一个是入口文件
+    files: Vec<PathBuf>,
+    //输出的每一行对应的 (文件在 files 中的下标, 原来的行号)
+    lines: Vec<(usize, u32)>,
+}
+
+impl Processed {
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    //入口文件的路径
+    pub fn path(&self) -> &Path {
+        &self.files[0]
+    }
+
+    //入口文件和它包含的所有文件，热重载时需要监视这些文件
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    //输出的第 line 行（从 1 开始）来自哪个文件的哪一行
+    pub fn origin(&self, line: u32) -> Option<(&Path, u32)> {
+        let &(file, line) = self.lines.get((line as usize).checked_sub(1)?)?;
+        Some((&self.files[file], line))
+    }
+}
+
+//#ifdef 等条件块的状态
+struct Condition {
+    //外层的代码是否有效
+    parent: bool,
+    //这个块当前的分支是否有效
+    active: bool,
+    seen_else: bool,
+    line: u32,
+}
+
+pub struct Preprocessor {
+    root: PathBuf,
+    defines: HashMap<String, String>,
+}
+
+impl Preprocessor {
+    //#include 的路径相对于 root
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        Self {
+            root: root.as_ref().to_path_buf(),
+            defines: HashMap::new(),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    //在处理之前定义一个名字，相当于入口文件开头的 #define。value 为空时只用于 #ifdef
+    pub fn define(mut self, name: &str, value: &str) -> Self {
+        self.defines.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    //处理 path（相对于根目录）及其包含的文件
+    pub fn process<P: AsRef<Path>>(&self, path: P) -> Result<Processed, PreprocessError> {
+        let path = canonical(self.root.join(path));
+        let source = std::fs::read_to_string(&path)
+            .map_err(|source| PreprocessError::Io { path: path.clone(), source })?;
+        let mut state = State {
+            root: &self.root,
+            defines: self.defines.clone(),
+            processed: Processed {
+                source: String::new(),
+                files: Vec::new(),
+                lines: Vec::new(),
+            },
+            including: Vec::new(),
+            included: HashSet::new(),
+        };
+        state.process_file(path, &source)?;
+        Ok(state.processed)
+    }
+}
+
+struct State<'a> {
+    root: &'a Path,
+    defines: HashMap<String, String>,
+    processed: Processed,
+    //正在处理的文件，用来发现循环包含
+    including: Vec<PathBuf>,
+    //已经处理完的文件，不再重复包含
+    included: HashSet<PathBuf>,
+}
+
+impl State<'_> {
+    fn process_file(&mut self, path: PathBuf, source: &str) -> Result<(), PreprocessError> {
+        let file = self.processed.files.len();
+        self.processed.files.push(path.clone());
+        self.including.push(path.clone());
+
+        let mut conditions: Vec<Condition> = Vec::new();
+        //行首所在的块注释的嵌套层数
+        let mut comment_depth = 0;
+        for (index, text) in source.lines().enumerate() {
+            let line = index as u32 + 1;
+            let error = |message: String| PreprocessError::Syntax { path: path.clone(), line, message };
+            let active = conditions.last().is_none_or(|condition| condition.active);
+            let line_depth = comment_depth;
+            comment_depth = block_comment_depth(text, comment_depth);
+
+            let Some(directive) = text.trim_start().strip_prefix('#').filter(|_| line_depth == 0) else {
+                if active {
+                    let text = self.substitute(text, line_depth);
+                    self.processed.source.push_str(&text);
+                    self.processed.source.push('\n');
+                    self.processed.lines.push((file, line));
+                }
+                continue;
+            };
+            //指令后面可以有 // 注释
+            let directive = directive.split("//").next().unwrap_or_default().trim();
+            let (name, argument) = directive.split_once(char::is_whitespace).unwrap_or((directive, ""));
+            let argument = argument.trim();
+
+            match name {
+                "ifdef" | "ifndef" => {
+                    let defined = self.defines.contains_key(identifier(argument).ok_or_else(|| error(format!("#{} 需要一个名字", name)))?);
+                    conditions.push(Condition {
+                        parent: active,
+                        active: active && defined == (name == "ifdef"),
+                        seen_else: false,
+                        line,
+                    });
+                }
+                "else" => {
+                    let condition = conditions.last_mut().ok_or_else(|| error("#else 前面没有 #ifdef".to_string()))?;
+                    if condition.seen_else {
+                        return Err(error("同一个 #ifdef 中有多个 #else".to_string()));
+                    }
+                    condition.seen_else = true;
+                    condition.active = condition.parent && !condition.active;
+                }
+                "endif" => {
+                    conditions.pop().ok_or_else(|| error("#endif 前面没有 #ifdef".to_string()))?;
+                }
+                //条件不成立的块中只跟踪嵌套，忽略其他指令
+                _ if !active => {}
+                "define" => {
+                    let (name, value) = argument.split_once(char::is_whitespace).unwrap_or((argument, ""));
+                    let name = identifier(name).ok_or_else(|| error("#define 需要一个名字".to_string()))?;
+                    //值中已经定义的名字在定义时展开
+                    let value = self.substitute(value.trim(), 0);
+                    self.defines.insert(name.to_string(), value);
+                }
+                "undef" => {
+                    let name = identifier(argument).ok_or_else(|| error("#undef 需要一个名字".to_string()))?;
+                    self.defines.remove(name);
+                }
+                "include" => {
+                    let include = argument.strip_prefix('"')
+                        .and_then(|argument| argument.strip_suffix('"'))
+                        .ok_or_else(|| error("#include 的路径需要用双引号括起来".to_string()))?;
+                    let include = canonical(self.root.join(include));
+                    if self.including.contains(&include) {
+                        return Err(error(format!("循环包含 {}", include.display())));
+                    }
+                    if self.included.contains(&include) {
+                        continue;
+                    }
+                    let source = std::fs::read_to_string(&include)
+                        .map_err(|e| error(format!("无法包含 {}: {}", include.display(), e)))?;
+                    self.process_file(include, &source)?;
+                }
+                _ => return Err(error(format!("未知的预处理指令 #{}", name))),
+            }
+        }
+        if let Some(condition) = conditions.last() {
+            return Err(PreprocessError::Syntax { path, line: condition.line, message: "#ifdef 缺少对应的 #endif".to_string() });
+        }
+
+        self.including.pop();
+        self.included.insert(path);
+        Ok(())
+    }
+
+    //把有值的名字替换为它的值。只替换完整的标识符，不替换 // 和 /* */ 注释中的内容，depth 是行首所在的块注释的嵌套层数
+    fn substitute(&self, text: &str, mut depth: usize) -> String {
+        let mut result = String::with_capacity(text.len());
+        let mut rest = text;
+        //上一个字符，数字后面的字母（例如 1.0f、0u）不是标识符，. 后面的是结构体的成员，也不替换
+        let mut previous = None;
+        while let Some(c) = rest.chars().next() {
+            let length = if rest.starts_with("/*") {
+                depth += 1;
+                2
+            } else if depth > 0 && rest.starts_with("*/") {
+                depth -= 1;
+                2
+            } else if depth == 0 && rest.starts_with("//") {
+                rest.len()
+            } else if depth == 0 && (c.is_ascii_alphabetic() || c == '_') {
+                let end = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(rest.len());
+                let word = &rest[..end];
+                let in_number = previous.is_some_and(|c: char| c.is_ascii_digit() || c == '.');
+                match self.defines.get(word) {
+                    Some(value) if !value.is_empty() && !in_number => result.push_str(value),
+                    _ => result.push_str(word),
+                }
+                previous = word.chars().last();
+                rest = &rest[end..];
+                continue;
+            } else {
+                c.len_utf8()
+            };
+            result.push_str(&rest[..length]);
+            previous = rest[..length].chars().last();
+            rest = &rest[length..];
+        }
+        result
+    }
+}
+
+//一行结束时块注释的嵌套层数，depth 是行首的层数。WGSL 的块注释可以嵌套，块注释外 // 之后的内容是行注释
+fn block_comment_depth(text: &str, mut depth: usize) -> usize {
+    let mut rest = text;
+    while !rest.is_empty() {
+        if rest.starts_with("/*") {
+            depth += 1;
+            rest = &rest[2..];
+        } else if depth > 0 && rest.starts_with("*/") {
+            depth -= 1;
+            rest = &rest[2..];
+        } else if depth == 0 && rest.starts_with("//") {
+            break;
+        } else {
+            let next = rest.chars().next().map_or(1, char::len_utf8);
+            rest = &rest[next..];
+        }
+    }
+    depth
+}
+
+//规范化路径（解析 .、.. 和符号链接），通过不同的路径包含同一个文件时也只插入一次。文件不存在时保持原样，读取时再报告错误
+fn canonical(path: PathBuf) -> PathBuf {
+    std::fs::canonicalize(&path).unwrap_or(path)
+}
+
+//检查 text 是一个合法的标识符
+fn identifier(text: &str) -> Option<&str> {
+    let mut chars = text.chars();
+    let first = chars.next()?;
+    ((first.is_ascii_alphabetic() || first == '_') && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')).then_some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //在临时目录中写入着色器文件，返回目录。name 区分并行运行的测试
+    fn write_files(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("wgpu_01_preprocess_{}_{}", name, std::process::id()));
+        for (path, source) in files {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, source).unwrap();
+        }
+        root
+    }
+
+    fn process(name: &str, files: &[(&str, &str)], defines: &[(&str, &str)]) -> Result<Processed, PreprocessError> {
+        let root = write_files(name, files);
+        let preprocessor = defines.iter().fold(Preprocessor::new(&root), |p, (name, value)| p.define(name, value));
+        let result = preprocessor.process(files[0].0);
+        std::fs::remove_dir_all(&root).unwrap();
+        result
+    }
+
+    fn lines(processed: &Processed) -> Vec<&str> {
+        processed.source().lines().collect()
+    }
+
+    #[test]
+    fn include_once_and_line_origins() {
+        let processed = process("include", &[
+            ("main.wgsl", "#include \"lib/common.wgsl\"\nmain\n#include \"lib/common.wgsl\"\n#include \"lib/other.wgsl\"\n"),
+            ("lib/common.wgsl", "common 1\ncommon 2\n"),
+            ("lib/other.wgsl", "// 注释\n#include \"lib/common.wgsl\"\nother\n"),
+        ], &[]).unwrap();
+        //common.wgsl 只插入一次
+        assert_eq!(lines(&processed), ["common 1", "common 2", "main", "// 注释", "other"]);
+        let files: Vec<String> = processed.files().iter().map(|f| f.file_name().unwrap().to_string_lossy().into_owned()).collect();
+        assert_eq!(files, ["main.wgsl", "common.wgsl", "other.wgsl"]);
+
+        //每一行指回原来的文件和行号
+        let origin = |line| processed.origin(line).map(|(path, line)| (path.file_name().unwrap().to_string_lossy().into_owned(), line));
+        assert_eq!(origin(1), Some(("common.wgsl".to_string(), 1)));
+        assert_eq!(origin(2), Some(("common.wgsl".to_string(), 2)));
+        assert_eq!(origin(3), Some(("main.wgsl".to_string(), 2)));
+        assert_eq!(origin(5), Some(("other.wgsl".to_string(), 3)));
+        assert_eq!(origin(0), None);
+        assert_eq!(origin(6), None);
+    }
+
+    #[test]
+    fn include_paths_are_canonicalized() {
+        let processed = process("canonical", &[
+            ("main.wgsl", "#include \"lib/../common.wgsl\"\n#include \"common.wgsl\"\n#include \"./lib/other.wgsl\"\nmain\n"),
+            ("common.wgsl", "common\n"),
+            ("lib/other.wgsl", "#include \"lib/./../common.wgsl\"\nother\n"),
+        ], &[]).unwrap();
+        assert_eq!(lines(&processed), ["common", "other", "main"]);
+        assert_eq!(processed.files().len(), 3);
+        //热重载监视的是规范化之后的路径
+        let relative = |path: &PathBuf| path.components().any(|c| matches!(c, std::path::Component::CurDir | std::path::Component::ParentDir));
+        assert!(!processed.files().iter().any(relative), "{:?}", processed.files());
+
+        //换一种写法包含正在处理的文件也是循环包含
+        let cycle = process("canonical_cycle", &[("a.wgsl", "#include \"lib/../b.wgsl\"\n"), ("b.wgsl", "#include \"./a.wgsl\"\n"), ("lib/x.wgsl", "")], &[]);
+        assert!(matches!(cycle, Err(PreprocessError::Syntax { message, .. }) if message.contains("循环包含")));
+    }
+
+    #[test]
+    fn include_errors() {
+        let cycle = process("cycle", &[("a.wgsl", "#include \"b.wgsl\"\n"), ("b.wgsl", "\n#include \"a.wgsl\"\n")], &[]);
+        match cycle {
+            Err(PreprocessError::Syntax { path, line, message }) => {
+                assert!(path.ends_with("b.wgsl"));
+                assert_eq!(line, 2);
+                assert!(message.contains("循环包含"), "{}", message);
+            }
+            other => panic!("应该是循环包含: {:?}", other.map(|p| p.source().to_string())),
+        }
+        assert!(matches!(process("missing", &[("a.wgsl", "#include \"none.wgsl\"\n")], &[]), Err(PreprocessError::Syntax { line: 1, .. })));
+        assert!(matches!(process("unquoted", &[("a.wgsl", "#include none.wgsl\n")], &[]), Err(PreprocessError::Syntax { line: 1, .. })));
+    }
+
+    #[test]
+    fn define_and_substitute() {
+        let processed = process("define", &[(
+            "main.wgsl",
+            "#define SIZE 4u\n#define DOUBLE SIZE * 2u\n\
+             let a = SIZE; // SIZE\n\
+             let b = DOUBLE + in.SIZE + 1SIZE + SIZE_2;\n\
+             let d = /* SIZE /* SIZE */ SIZE */ SIZE; /* SIZE\n\
+             SIZE */ SIZE\n\
+             #undef SIZE\nlet c = SIZE + VALUE;\n",
+        )], &[("VALUE", "1.5")]).unwrap();
+        assert_eq!(lines(&processed), [
+            "let a = 4u; // SIZE",
+            //值中的名字在定义时展开；成员、数字后缀和更长的标识符不替换
+            "let b = 4u * 2u + in.SIZE + 1SIZE + SIZE_2;",
+            //块注释中的名字不替换，块注释可以嵌套，也可以跨行
+            "let d = /* SIZE /* SIZE */ SIZE */ 4u; /* SIZE",
+            "SIZE */ 4u",
+            "let c = SIZE + 1.5;",
+        ]);
+    }
+
+    #[test]
+    fn conditionals() {
+        let source = "#ifdef A\na\n#ifndef B\nnot b\n#else\nb\n#endif\n#else\nnot a\n#ifdef B\nb in not a\n#endif\n#endif\nend\n";
+        let files = [("main.wgsl", source)];
+        assert_eq!(lines(&process("if_a", &files, &[("A", "")]).unwrap()), ["a", "not b", "end"]);
+        assert_eq!(lines(&process("if_ab", &files, &[("A", ""), ("B", "")]).unwrap()), ["a", "b", "end"]);
+        //外层不成立时，内层的 #ifdef 即使成立也不输出
+        assert_eq!(lines(&process("if_b", &files, &[("B", "")]).unwrap()), ["not a", "b in not a", "end"]);
+
+        //不成立的块中的 #define 不生效，未知的指令也不报错
+        let source = "#ifdef A\n#define X 1\n#unknown\n#endif\nX\n";
+        assert_eq!(lines(&process("inactive", &[("main.wgsl", source)], &[]).unwrap()), ["X"]);
+
+        let error_line = |name, source| match process(name, &[("main.wgsl", source)], &[]) {
+            Err(PreprocessError::Syntax { line, .. }) => line,
+            other => panic!("{:?} 应该有错误: {:?}", source, other.map(|p| p.source().to_string())),
+        };
+        assert_eq!(error_line("unclosed", "\n#ifdef A\nx\n"), 2);
+        assert_eq!(error_line("else", "x\n#else\n"), 2);
+        assert_eq!(error_line("endif", "#endif\n"), 1);
+        assert_eq!(error_line("two_else", "#ifdef A\n#else\n#else\n#endif\n"), 3);
+        assert_eq!(error_line("no_name", "#ifdef\n#endif\n"), 1);
+    }
+
+    #[test]
+    fn block_comments_are_not_directives() {
+        let source = "/*\n#include \"none.wgsl\"\n  /* 嵌套 */\n#ifdef A\n*/\n#ifdef A\na\n#endif\n/* # 一行 */ code\n// /*\n#ifndef A\nnot a\n#endif\n";
+        let processed = process("comments", &[("main.wgsl", source)], &[("A", "")]).unwrap();
+        assert_eq!(lines(&processed), [
+            "/*",
+            "#include \"none.wgsl\"",
+            "  /* 嵌套 */",
+            "#ifdef A",
+            "*/",
+            "a",
+            "/* # 一行 */ code",
+            "// /*",
+        ]);
+
+        assert_eq!(block_comment_depth("a /* b /* c */", 0), 1);
+        assert_eq!(block_comment_depth("*/ // /*", 1), 0);
+        assert_eq!(block_comment_depth("// /*", 0), 0);
+        //块注释中的 // 不是行注释
+        assert_eq!(block_comment_depth("// */", 1), 0);
+    }
+}
@@ -0,0 +1,469 @@
+/*
+阴影贴图
+对每个投射阴影的光源，先从光源的位置、沿着光的方向把场景画一遍，只保留深度，这张深度图就是阴影贴图（shadow map）。
+正式绘制时把片元变换到光源的裁剪空间，与阴影贴图中保存的深度比较：更远的片元被别的物体挡住了，处在阴影中。
+
+所有光源的阴影贴图放在一个深度纹理数组中，每个光源一层。着色器中对应 @group(3)：
+@binding(0) 是深度纹理数组，@binding(1) 是比较采样器，@binding(2) 是每一层的光源矩阵和阴影参数。
+
+比较采样器在采样时直接返回比较的结果（0 或 1），线性过滤时会对相邻的 2x2 个结果插值；
+再在周围采样多次取平均（PCF，percentage-closer filtering），阴影的边缘就不会有明显的锯齿。
+
+平行光使用正交投影，覆盖以原点为中心的一个范围（ShadowConfig::directional_extent）；聚光灯使用透视投影，视野是锥形的外角。
+*/
+use cgmath::{InnerSpace, Matrix4, Point3, Rad, Vector3};
+use wgpu::{AddressMode, BindGroup, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingType, Buffer, BufferUsages, CommandEncoder, CompareFunction, DepthBiasState, Device, FilterMode, LoadOp, Operations, PipelineLayout, PipelineLayoutDescriptor, Queue, RenderPass, RenderPassDepthStencilAttachment, RenderPassDescriptor, RenderPipeline, Sampler, SamplerBindingType, ShaderModule, ShaderStages, TextureFormat, TextureSampleType, TextureUsages, TextureView, TextureViewDescriptor, TextureViewDimension};
+
+use crate::buffer::{Vertex, VertexLayout};
+use crate::buffer::instance::InstanceRaw;
+use crate::camera::OPENGL_TO_WGPU_MATRIX;
+use crate::light::{Light, Lights};
+use crate::pipeline::{DepthConfig, RenderPipelineBuilder};
+
+//阴影贴图的层数，也就是同时投射阴影的光源的最大数量，必须与着色器中的 MAX_SHADOWS 一致
+pub const MAX_SHADOWS: usize = 4;
+
+pub const SHADOW_FORMAT: TextureFormat = TextureFormat::Depth32Float;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ShadowConfig {
+    //阴影贴图每一层的边长（像素），越大阴影越清晰
+    pub resolution: u32,
+    //比较之前从片元的深度中减去的值（光源裁剪空间中的深度），用来避免表面自己遮挡自己产生的条纹（阴影失真）
+    pub depth_bias: f32,
+    //渲染阴影贴图时按表面的倾斜程度增加的深度偏移，倾斜的表面更容易出现阴影失真
+    pub slope_bias: f32,
+    //PCF 的采样半径（纹素），0 只采样一次，1 采样 3x3 次，2 采样 5x5 次
+    pub pcf_radius: u32,
+    //平行光的阴影覆盖以原点为中心、边长为 2 * directional_extent 的立方体
+    pub directional_extent: f32,
+}
+
+impl Default for ShadowConfig {
+    fn default() -> Self {
+        Self {
+            resolution: 2048,
+            depth_bias: 0.002,
+            slope_bias: 2.0,
+            pcf_radius: 1,
+            directional_extent: 5.0,
+        }
+    }
+}
+
+//光源的视图投影矩阵，点光源没有
+pub fn light_view_proj(light: &Light, config: &ShadowConfig) -> Option<Matrix4<f32>> {
+    let (eye, direction, projection) = match *light {
+        Light::Directional { direction, .. } => {
+            let extent = config.directional_extent;
+            let direction = direction.normalize();
+            //光源放在范围之外，沿着光的方向看向原点
+            let eye = Point3::new(0.0, 0.0, 0.0) - direction * extent * 2.0;
+            let projection = cgmath::ortho(-extent, extent, -extent, extent, 0.0, extent * 4.0);
+            (eye, direction, projection)
+        }
+        Light::Spot { position, direction, outer_angle, attenuation, .. } => {
+            let far = if attenuation.range > 0.0 { attenuation.range } else { 100.0 };
+            let fovy = Rad((outer_angle.0 * 2.0).min(std::f32::consts::PI * 0.95));
+            (Point3::new(position.x, position.y, position.z), direction.normalize(), cgmath::perspective(fovy, 1.0, 0.05, far))
+        }
+        Light::Point { .. } => return None,
+    };
+    //光的方向接近竖直时不能再用 y 轴作为向上的方向
+    let up = if direction.y.abs() > 0.99 { Vector3::unit_z() } else { Vector3::unit_y() };
+    let view = Matrix4::look_at_rh(eye, eye + direction, up);
+    Some(OPENGL_TO_WGPU_MATRIX * projection * view)
+}
+
+//着色器中的阴影数据
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ShadowUniform {
+    view_proj: [[[f32; 4]; 4]; MAX_SHADOWS],
+    //(1 / resolution, pcf_radius, depth_bias, _)
+    params: [f32; 4],
+}
+
+pub struct ShadowMaps {
+    config: ShadowConfig,
+    //这一帧投射阴影的光源数量
+    count: usize,
+
+    texture: wgpu::Texture,
+    layer_views: Vec<TextureView>,
+    sampler: Sampler,
+    uniform_buffer: Buffer,
+    //正式绘制时使用的绑定组，对应着色器中的 @group(3)
+    pub bind_group_layout: BindGroupLayout,
+    pub bind_group: BindGroup,
+
+    //渲染阴影贴图的管线，每一层有自己的光源矩阵
+    shader: ShaderModule,
+    pipeline_layout: PipelineLayout,
+    pipeline: RenderPipeline,
+    layer_buffers: Vec<Buffer>,
+    layer_bind_groups: Vec<BindGroup>,
+}
+
+impl ShadowMaps {
+    //主着色器 @group(3) 的布局条目：阴影贴图数组、比较采样器和每一层的光源矩阵，与 CameraBinding::LAYOUT_ENTRIES 一样用于和着色器比较
+    pub const LAYOUT_ENTRIES: &'static [BindGroupLayoutEntry] = &[
+        BindGroupLayoutEntry {
+            binding: 0,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Texture {
+                multisampled: false,
+                view_dimension: TextureViewDimension::D2Array,
+                sample_type: TextureSampleType::Depth,
+            },
+            count: None,
+        },
+        BindGroupLayoutEntry {
+            binding: 1,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Sampler(SamplerBindingType::Comparison),
+            count: None,
+        },
+        BindGroupLayoutEntry {
+            binding: 2,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        },
+    ];
+
+    pub fn new(device: &Device, config: ShadowConfig) -> Self {
+        let config = Self::clamp_config(device, config);
+
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Shadow Bind Group Layout"),
+            entries: Self::LAYOUT_ENTRIES,
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Shadow Sampler"),
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            mipmap_filter: FilterMode::Nearest,
+            //片元的深度小于等于阴影贴图中的深度时没有被挡住，结果为 1
+            compare: Some(CompareFunction::LessEqual),
+            ..Default::default()
+        });
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Shadow Uniform Buffer"),
+            size: std::mem::size_of::<ShadowUniform>() as u64,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        //每一层的光源矩阵
+        let layer_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Shadow Layer Bind Group Layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let layer_buffers: Vec<Buffer> = (0..MAX_SHADOWS)
+            .map(|_| device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Shadow Layer Buffer"),
+                size: std::mem::size_of::<[[f32; 4]; 4]>() as u64,
+                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }))
+            .collect();
+        let layer_bind_groups = layer_buffers.iter()
+            .map(|buffer| device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Shadow Layer Bind Group"),
+                layout: &layer_bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: buffer.as_entire_binding(),
+                    },
+                ],
+            }))
+            .collect();
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Shadow Pipeline Layout"),
+            bind_group_layouts: &[&layer_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(wgpu::include_wgsl!("shadow.wgsl"));
+        let pipeline = Self::create_pipeline(device, &pipeline_layout, &shader, &config);
+
+        let (texture, layer_views, bind_group) = Self::create_texture(device, &config, &bind_group_layout, &sampler, &uniform_buffer);
+
+        Self {
+            config,
+            count: 0,
+            texture,
+            layer_views,
+            sampler,
+            uniform_buffer,
+            bind_group_layout,
+            bind_group,
+            shader,
+            pipeline_layout,
+            pipeline,
+            layer_buffers,
+            layer_bind_groups,
+        }
+    }
+
+    //阴影贴图不能超过设备支持的最大纹理尺寸
+    fn clamp_config(device: &Device, config: ShadowConfig) -> ShadowConfig {
+        let max = device.limits().max_texture_dimension_2d;
+        if config.resolution > max {
+            log::warn!("阴影贴图的分辨率 {} 超过了设备的限制，使用 {}", config.resolution, max);
+        }
+        ShadowConfig {
+            resolution: config.resolution.clamp(1, max),
+            ..config
+        }
+    }
+
+    fn create_pipeline(device: &Device, layout: &PipelineLayout, shader: &ShaderModule, config: &ShadowConfig) -> RenderPipeline {
+        //薄的物体（例如五边形）两面都要投射阴影，所以不剔除
+        RenderPipelineBuilder::depth_only(shader)
+            .label("Shadow Pipeline")
+            .layout(layout)
+            .source(include_str!("shadow.wgsl"))
+            .vertex_buffer(Vertex::desc())
+            .vertex_buffer(InstanceRaw::desc())
+            .cull_mode(None)
+            .depth(DepthConfig {
+                format: SHADOW_FORMAT,
+                compare: CompareFunction::LessEqual,
+                write_enabled: true,
+            })
+            .depth_bias(DepthBiasState {
+                constant: 0,
+                slope_scale: config.slope_bias,
+                clamp: 0.0,
+            })
+            .build(device)
+    }
+
+    fn create_texture(device: &Device, config: &ShadowConfig, layout: &BindGroupLayout, sampler: &Sampler, uniform_buffer: &Buffer) -> (wgpu::Texture, Vec<TextureView>, BindGroup) {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Shadow Texture"),
+            size: wgpu::Extent3d {
+                width: config.resolution,
+                height: config.resolution,
+                depth_or_array_layers: MAX_SHADOWS as u32,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: SHADOW_FORMAT,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        //渲染时每一层单独作为深度附件
+        let layer_views = (0..MAX_SHADOWS as u32)
+            .map(|layer| texture.create_view(&TextureViewDescriptor {
+                label: Some("Shadow Layer View"),
+                dimension: Some(TextureViewDimension::D2),
+                base_array_layer: layer,
+                array_layer_count: Some(1),
+                ..Default::default()
+            }))
+            .collect();
+        let view = texture.create_view(&TextureViewDescriptor {
+            label: Some("Shadow View"),
+            dimension: Some(TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Shadow Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+        });
+        (texture, layer_views, bind_group)
+    }
+
+    pub fn config(&self) -> ShadowConfig {
+        self.config
+    }
+
+    //修改设置。分辨率变化时重新创建阴影贴图，偏移变化时重新创建管线，其余设置在下一次 update 时生效
+    pub fn set_config(&mut self, device: &Device, config: ShadowConfig) {
+        let config = Self::clamp_config(device, config);
+        let old = std::mem::replace(&mut self.config, config);
+        if old.resolution != config.resolution {
+            let (texture, layer_views, bind_group) = Self::create_texture(device, &config, &self.bind_group_layout, &self.sampler, &self.uniform_buffer);
+            self.texture = texture;
+            self.layer_views = layer_views;
+            self.bind_group = bind_group;
+        }
+        if old.slope_bias != config.slope_bias {
+            self.pipeline = Self::create_pipeline(device, &self.pipeline_layout, &self.shader, &config);
+        }
+    }
+
+    //这一帧投射阴影的光源数量，也就是要渲染的层数
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    //根据光源计算每一层的矩阵并写入缓冲区，光源移动后需要调用
+    pub fn update(&mut self, queue: &Queue, lights: &Lights) {
+        let mut uniform = ShadowUniform {
+            view_proj: [[[0.0; 4]; 4]; MAX_SHADOWS],
+            params: [
+                1.0 / self.config.resolution as f32,
+                self.config.pcf_radius as f32,
+                self.config.depth_bias,
+                0.0,
+            ],
+        };
+        self.count = 0;
+        for (layer, light) in lights.shadow_casters().enumerate() {
+            let Some(view_proj) = light_view_proj(light, &self.config) else {
+                continue;
+            };
+            uniform.view_proj[layer] = view_proj.into();
+            queue.write_buffer(&self.layer_buffers[layer], 0, bytemuck::cast_slice(&uniform.view_proj[layer]));
+            self.count = layer + 1;
+        }
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniform));
+    }
+
+    //开始渲染第 layer 层阴影贴图，返回的渲染通道已经设置好管线和光源矩阵，调用者只需要设置顶点缓冲区并绘制
+    pub fn begin_layer<'a>(&'a self, encoder: &'a mut CommandEncoder, layer: usize) -> RenderPass<'a> {
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("Shadow Pass"),
+            color_attachments: &[],
+            depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                view: &self.layer_views[layer],
+                depth_ops: Some(Operations {
+                    load: LoadOp::Clear(1.0),
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.layer_bind_groups[layer], &[]);
+        render_pass
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Deg, Vector4};
+
+    use super::*;
+    use crate::headless;
+
+    //变换到 NDC
+    fn project(view_proj: Matrix4<f32>, point: Vector3<f32>) -> Vector3<f32> {
+        let clip = view_proj * Vector4::new(point.x, point.y, point.z, 1.0);
+        clip.truncate() / clip.w
+    }
+
+    #[test]
+    fn point_lights_have_no_shadow_matrix() {
+        let light = Light::point(Vector3::new(0.0, 2.0, 0.0), [1.0; 3], 10.0).with_shadows();
+        assert!(!light.casts_shadows());
+        assert!(light_view_proj(&light, &ShadowConfig::default()).is_none());
+    }
+
+    #[test]
+    fn directional_light_centres_the_origin() {
+        let config = ShadowConfig::default();
+        let light = Light::directional(Vector3::new(1.0, -2.0, 0.5), [1.0; 3]).with_shadows();
+        let view_proj = light_view_proj(&light, &config).unwrap();
+
+        //光源在原点之外 2 * extent 处，远平面在 4 * extent 处，原点正好在深度范围的中间
+        let origin = project(view_proj, Vector3::new(0.0, 0.0, 0.0));
+        assert!(origin.x.abs() < 1e-5 && origin.y.abs() < 1e-5, "{:?}", origin);
+        assert!((origin.z - 0.5).abs() < 1e-5, "{:?}", origin);
+
+        //垂直于光线方向、距离原点 extent 的点在阴影贴图的边缘
+        let side = Vector3::new(1.0, -2.0, 0.5).normalize().cross(Vector3::unit_y()).normalize() * config.directional_extent;
+        let edge = project(view_proj, side);
+        assert!((edge.x.abs().max(edge.y.abs()) - 1.0).abs() < 1e-4, "{:?}", edge);
+
+        //竖直向下的光不能用 y 轴作为向上的方向
+        let down = Light::directional(-Vector3::unit_y(), [1.0; 3]);
+        let origin = project(light_view_proj(&down, &config).unwrap(), Vector3::new(0.0, 0.0, 0.0));
+        assert!(origin.x.is_finite() && origin.x.abs() < 1e-5 && origin.y.abs() < 1e-5, "{:?}", origin);
+    }
+
+    #[test]
+    fn spot_light_looks_along_its_direction() {
+        let position = Vector3::new(0.0, 4.0, 0.0);
+        let direction = Vector3::new(0.0, -1.0, 1.0);
+        let light = Light::spot(position, direction, [1.0; 3], 10.0, Deg(30.0));
+        let view_proj = light_view_proj(&light, &ShadowConfig::default()).unwrap();
+
+        let ahead = project(view_proj, position + direction.normalize() * 5.0);
+        assert!(ahead.x.abs() < 1e-5 && ahead.y.abs() < 1e-5, "{:?}", ahead);
+        assert!(ahead.z > 0.0 && ahead.z < 1.0, "{:?}", ahead);
+        //光源背后的点 w 为负，不会被画进阴影贴图
+        let behind = position - direction;
+        let behind = view_proj * Vector4::new(behind.x, behind.y, behind.z, 1.0);
+        assert!(behind.w < 0.0);
+    }
+
+    #[test]
+    fn shadow_layers_follow_shadow_casters() {
+        let (device, queue) = pollster::block_on(headless::request_device());
+        let mut lights = Lights::new(&device);
+        let directional = Light::directional(Vector3::new(0.0, -1.0, 1.0), [1.0; 3]);
+        let spot = Light::spot(Vector3::new(0.0, 3.0, 0.0), -Vector3::unit_y(), [1.0; 3], 10.0, Deg(40.0));
+        lights.add(Light::point(Vector3::new(1.0, 1.0, 1.0), [1.0; 3], 5.0).with_shadows());
+        lights.add(directional.with_shadows());
+        lights.add(spot);
+        lights.add(spot.with_shadows());
+        lights.add(directional);
+        lights.add(directional.with_shadows());
+        lights.add(spot.with_shadows());
+        //超过 MAX_SHADOWS 的光源没有阴影
+        lights.add(directional.with_shadows());
+
+        let uniform = lights.uniform();
+        let mut layered: Vec<(usize, &Light)> = lights.iter()
+            .zip(uniform.lights)
+            .filter(|(_, raw)| raw.cone[2] >= 0.0)
+            .map(|((_, light), raw)| (raw.cone[2] as usize, light))
+            .collect();
+        layered.sort_by_key(|(layer, _)| *layer);
+        assert_eq!(layered.iter().map(|(layer, _)| *layer).collect::<Vec<_>>(), (0..MAX_SHADOWS).collect::<Vec<_>>());
+        let casters: Vec<&Light> = lights.shadow_casters().collect();
+        assert_eq!(layered.into_iter().map(|(_, light)| light).collect::<Vec<_>>(), casters);
+
+        let mut shadows = ShadowMaps::new(&device, ShadowConfig { resolution: 64, ..Default::default() });
+        shadows.update(&queue, &lights);
+        assert_eq!(shadows.count(), MAX_SHADOWS);
+    }
+}
@@ -0,0 +1,35 @@
+//阴影贴图：从光源的位置把场景画一遍，只写入深度
+
+struct ShadowLayer {
+    //光源的视图投影矩阵
+    view_proj: mat4x4f
+};
+
+@group(0) @binding(0)
+var<uniform> layer: ShadowLayer;
+
+struct VertexInput {
+    @location(0) position: vec3f
+}
+
+//只需要实例的模型矩阵，见 buffer::instance 模块的 InstanceRaw
+struct InstanceInput {
+    @location(5) model_matrix_0: vec4f,
+    @location(6) model_matrix_1: vec4f,
+    @location(7) model_matrix_2: vec4f,
+    @location(8) model_matrix_3: vec4f
+}
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+    instance: InstanceInput
+) -> @builtin(position) vec4f {
+    let model_matrix = mat4x4f(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3
+    );
+    return layer.view_proj * model_matrix * vec4f(model.position, 1.0);
+}
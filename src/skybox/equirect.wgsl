@@ -0,0 +1,59 @@
+//把等距柱状投影（equirectangular）的全景图转换为立方体贴图的一个面，每个面画一次
+
+const PI: f32 = 3.14159265359;
+
+struct FaceUniform {
+    //面的索引，顺序为 +X、-X、+Y、-Y、+Z、-Z
+    index: u32,
+    //面的边长（像素）
+    size: u32
+};
+
+@group(0) @binding(0)
+var t_src: texture_2d<f32>;
+
+@group(0) @binding(1)
+var s_src: sampler;
+
+@group(0) @binding(2)
+var<uniform> face: FaceUniform;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4f
+};
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) in_vertex_index: u32
+) -> VertexOutput {
+    var out: VertexOutput;
+    let uv = vec2f(f32((in_vertex_index << 1u) & 2u), f32(in_vertex_index & 2u));
+    out.clip_position = vec4f(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    return out;
+}
+
+//立方体贴图的面上的点 (s, t) 对应的方向，与 GPU 采样立方体贴图时选择面和坐标的规则互逆
+fn face_direction(index: u32, st: vec2f) -> vec3f {
+    let a = st.x * 2.0 - 1.0;
+    let b = st.y * 2.0 - 1.0;
+    switch index {
+        case 0u: { return vec3f(1.0, -b, -a); }
+        case 1u: { return vec3f(-1.0, -b, a); }
+        case 2u: { return vec3f(a, 1.0, b); }
+        case 3u: { return vec3f(a, -1.0, -b); }
+        case 4u: { return vec3f(a, -b, 1.0); }
+        default: { return vec3f(-a, -b, -1.0); }
+    }
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4f {
+    let direction = normalize(face_direction(face.index, in.clip_position.xy / f32(face.size)));
+    //经度对应全景图的 u，纬度对应 v（上方为 0）
+    let uv = vec2f(atan2(direction.z, direction.x) / (2.0 * PI) + 0.5, acos(clamp(direction.y, -1.0, 1.0)) / PI);
+    //经度在 ±180° 处不连续，自动选择 mip 级别会在接缝处出现一条线，所以手动选择：
+    //一个面覆盖全景图 1/4 的宽度，全景图比这更大时从更小的 mip 级别采样
+    let src_width = f32(textureDimensions(t_src).x);
+    let level = max(log2(src_width / (4.0 * f32(face.size))), 0.0);
+    return textureSampleLevel(t_src, s_src, uv, level);
+}
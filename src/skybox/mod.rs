@@ -0,0 +1,387 @@
+/*
+天空盒
+天空盒是一张立方体贴图（cube map）：6 个正方形的面围成一个立方体，着色器用一个方向（而不是纹理坐标）采样，
+GPU 根据方向选择对应的面和面上的位置。把相机的视线方向作为采样方向，就得到了无限远处的背景。
+
+立方体贴图可以从两种图像加载：
+6 张面的图像，顺序为 +X、-X、+Y、-Y、+Z、-Z，必须是大小相同的正方形；
+1 张等距柱状投影（equirectangular）的全景图，宽是高的两倍，经度和纬度分别对应 u 和 v。
+全景图在 GPU 上用一个渲染通道逐面转换为立方体贴图，见 equirect.wgsl。
+
+天空盒在场景的渲染通道中不透明物体之后绘制，深度固定为最远处，只测试不写入深度：
+被物体遮挡的像素不会执行片元着色器，也就不会采样立方体贴图。
+天空盒只使用相机的旋转，相机移动时背景不变，见 skybox.wgsl。
+*/
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use image::GenericImageView;
+use wgpu::util::DeviceExt;
+use wgpu::{AddressMode, BindGroup, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingType, BufferUsages, CommandEncoderDescriptor, CompareFunction, Device, Extent3d, FilterMode, ImageCopyTexture, ImageDataLayout, LoadOp, Operations, Origin3d, PipelineLayout, PipelineLayoutDescriptor, Queue, RenderPass, RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline, SamplerBindingType, ShaderModule, ShaderStages, TextureAspect, TextureFormat, TextureSampleType, TextureUsages, TextureViewDescriptor, TextureViewDimension};
+
+use crate::hdr::HDR_FORMAT;
+use crate::pipeline::{DepthConfig, RenderPipelineBuilder};
+use crate::texture::{Texture, TextureError, TextureOptions};
+
+//立方体贴图的面数
+const FACE_COUNT: u32 = 6;
+
+//加载天空盒时可能出现的错误
+#[derive(Debug)]
+pub enum SkyboxError {
+    //读取或解码图像失败
+    Texture(TextureError),
+    //面的图像不是正方形，或者与第一个面的大小不同
+    FaceSize { path: PathBuf, expected: u32, width: u32, height: u32 },
+}
+
+impl fmt::Display for SkyboxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SkyboxError::Texture(e) => write!(f, "{}", e),
+            SkyboxError::FaceSize { path, expected, width, height } => {
+                write!(f, "天空盒的面 {} 的大小是 {}x{}，应该是 {}x{}", path.display(), width, height, expected, expected)
+            }
+        }
+    }
+}
+
+impl Error for SkyboxError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SkyboxError::Texture(e) => Some(e),
+            SkyboxError::FaceSize { .. } => None,
+        }
+    }
+}
+
+impl From<TextureError> for SkyboxError {
+    fn from(e: TextureError) -> Self {
+        SkyboxError::Texture(e)
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct FaceUniform {
+    index: u32,
+    size: u32,
+    //统一缓冲区的大小按 16 字节对齐
+    _padding: [u32; 2],
+}
+
+pub struct Skybox {
+    //立方体贴图，view 的维度是 Cube
+    pub texture: Texture,
+    bind_group: BindGroup,
+
+    shader: ShaderModule,
+    pipeline_layout: PipelineLayout,
+    //按（颜色格式, 场景的深度设置）缓存管线
+    pipelines: HashMap<(TextureFormat, DepthConfig), RenderPipeline>,
+}
+
+impl Skybox {
+    //从 6 张面的图像加载，顺序为 +X、-X、+Y、-Y、+Z、-Z
+    pub fn from_faces<P: AsRef<Path>>(device: &Device, queue: &Queue, paths: &[P; 6], camera_bind_group_layout: &BindGroupLayout) -> Result<Self, SkyboxError> {
+        let mut faces = Vec::with_capacity(paths.len());
+        for path in paths {
+            let path = path.as_ref();
+            let bytes = std::fs::read(path).map_err(|source| TextureError::Io {
+                path: path.to_path_buf(),
+                source,
+            })?;
+            let img = image::load_from_memory(&bytes).map_err(TextureError::from)?;
+            let (width, height) = img.dimensions();
+            let expected = faces.first().map_or(width, |first: &image::RgbaImage| first.width());
+            if width != height || width != expected {
+                return Err(SkyboxError::FaceSize {
+                    path: path.to_path_buf(),
+                    expected,
+                    width,
+                    height,
+                });
+            }
+            faces.push(img.to_rgba8());
+        }
+
+        let size = faces[0].width();
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Skybox Texture"),
+            size: Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: FACE_COUNT,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: TextureFormat::Rgba8UnormSrgb,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        //每个面写入纹理数组的一层
+        for (layer, face) in faces.iter().enumerate() {
+            queue.write_texture(
+                ImageCopyTexture {
+                    texture: &texture,
+                    mip_level: 0,
+                    origin: Origin3d { x: 0, y: 0, z: layer as u32 },
+                    aspect: TextureAspect::All,
+                },
+                face,
+                ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(4 * size),
+                    rows_per_image: Some(size),
+                },
+                Extent3d {
+                    width: size,
+                    height: size,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+
+        Ok(Self::from_cube_texture(device, texture, camera_bind_group_layout))
+    }
+
+    /*
+    从等距柱状投影的全景图加载，face_size 是生成的立方体贴图每个面的边长。
+    生成的立方体贴图使用 HDR 格式，为以后加载 .hdr 全景图留出余地。
+    */
+    pub fn from_equirectangular<P: AsRef<Path>>(device: &Device, queue: &Queue, path: P, face_size: u32, camera_bind_group_layout: &BindGroupLayout) -> Result<Self, SkyboxError> {
+        let face_size = face_size.max(1);
+        //全景图有 mipmap，转换时按面的大小选择 mip 级别，避免缩小时的锯齿
+        let source = Texture::from_path(device, queue, path, &TextureOptions::default())?;
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Skybox Texture"),
+            size: Extent3d {
+                width: face_size,
+                height: face_size,
+                depth_or_array_layers: FACE_COUNT,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: HDR_FORMAT,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Equirect Bind Group Layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: TextureViewDimension::D2,
+                        sample_type: TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Equirect Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(wgpu::include_wgsl!("equirect.wgsl"));
+        let pipeline = RenderPipelineBuilder::new(&shader, HDR_FORMAT)
+            .label("Equirect Pipeline")
+            .layout(&pipeline_layout)
+            .cull_mode(None)
+            .build(device);
+
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("Equirect Encoder"),
+        });
+        for index in 0..FACE_COUNT {
+            let uniform = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Equirect Face Buffer"),
+                contents: bytemuck::bytes_of(&FaceUniform {
+                    index,
+                    size: face_size,
+                    _padding: [0; 2],
+                }),
+                usage: BufferUsages::UNIFORM,
+            });
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Equirect Bind Group"),
+                layout: &bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&source.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&source.sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: uniform.as_entire_binding(),
+                    },
+                ],
+            });
+            //每个面作为一个二维视图渲染
+            let face_view = texture.create_view(&TextureViewDescriptor {
+                label: Some("Equirect Face View"),
+                dimension: Some(TextureViewDimension::D2),
+                base_array_layer: index,
+                array_layer_count: Some(1),
+                ..Default::default()
+            });
+
+            let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("Equirect Pass"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: &face_view,
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Clear(wgpu::Color::BLACK),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_pipeline(&pipeline);
+            render_pass.set_bind_group(0, &bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+
+        Ok(Self::from_cube_texture(device, texture, camera_bind_group_layout))
+    }
+
+    //使用已经准备好的 6 层纹理
+    pub fn from_cube_texture(device: &Device, texture: wgpu::Texture, camera_bind_group_layout: &BindGroupLayout) -> Self {
+        let view = texture.create_view(&TextureViewDescriptor {
+            label: Some("Skybox View"),
+            dimension: Some(TextureViewDimension::Cube),
+            ..Default::default()
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            mipmap_filter: FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Skybox Bind Group Layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: TextureViewDimension::Cube,
+                        sample_type: TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Skybox Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        });
+
+        //与场景的管线一样，相机在 @group(1)
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Skybox Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout, camera_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        Self {
+            texture: Texture {
+                texture,
+                view,
+                sampler,
+            },
+            bind_group,
+            shader: device.create_shader_module(wgpu::include_wgsl!("skybox.wgsl")),
+            pipeline_layout,
+            pipelines: HashMap::new(),
+        }
+    }
+
+    //在开始渲染通道之前调用，创建与颜色附件格式和场景的深度设置对应的管线
+    pub fn prepare(&mut self, device: &Device, format: TextureFormat, depth: DepthConfig) {
+        let shader = &self.shader;
+        let pipeline_layout = &self.pipeline_layout;
+        self.pipelines.entry((format, depth)).or_insert_with(|| {
+            //深度清除为最远处，天空盒也在最远处，所以要用带等号的比较；不写入深度
+            let (vs_entry, compare) = match depth.compare {
+                CompareFunction::Greater | CompareFunction::GreaterEqual => ("vs_main_reversed", CompareFunction::GreaterEqual),
+                _ => ("vs_main", CompareFunction::LessEqual),
+            };
+            RenderPipelineBuilder::new(shader, format)
+                .label("Skybox Pipeline")
+                .layout(pipeline_layout)
+                .entry_points(vs_entry, "fs_main")
+                .cull_mode(None)
+                .depth(DepthConfig {
+                    format: depth.format,
+                    compare,
+                    write_enabled: false,
+                })
+                .build(device)
+        });
+    }
+
+    //在渲染通道中绘制天空盒，需要先用同样的格式调用 prepare，并在不透明物体之后调用。会替换 @group(0) 和 @group(1) 以及管线
+    pub fn draw<'a>(&'a self, render_pass: &mut RenderPass<'a>, camera_bind_group: &'a BindGroup, format: TextureFormat, depth: DepthConfig) {
+        let Some(pipeline) = self.pipelines.get(&(format, depth)) else {
+            log::warn!("天空盒的管线还没有创建（{:?}, {:?}），跳过绘制", format, depth);
+            return;
+        };
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_bind_group(1, camera_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,56 @@
+//天空盒：用一个全屏三角形覆盖整个画面，每个像素根据视线方向从立方体贴图中采样
+
+struct CameraUniform {
+    view_position: vec4f,
+    view_proj: mat4x4f,
+    inv_proj: mat4x4f,
+    inv_view: mat4x4f
+};
+
+@group(1) @binding(0)
+var<uniform> camera: CameraUniform;
+
+@group(0) @binding(0)
+var t_sky: texture_cube<f32>;
+
+@group(0) @binding(1)
+var s_sky: sampler;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4f,
+    //标准化设备坐标，x、y 在 -1..1 之间
+    @location(0) ndc: vec2f
+};
+
+//depth 是最远处的深度值：z = w 时透视除法后深度不变，天空盒总是在所有物体的后面
+fn fullscreen(in_vertex_index: u32, depth: f32) -> VertexOutput {
+    var out: VertexOutput;
+    let uv = vec2f(f32((in_vertex_index << 1u) & 2u), f32(in_vertex_index & 2u));
+    out.ndc = vec2f(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+    out.clip_position = vec4f(out.ndc, depth, 1.0);
+    return out;
+}
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) in_vertex_index: u32
+) -> VertexOutput {
+    return fullscreen(in_vertex_index, 1.0);
+}
+
+//反向 Z（深度比较为 Greater/GreaterEqual）时最远处是 0
+@vertex
+fn vs_main_reversed(
+    @builtin(vertex_index) in_vertex_index: u32
+) -> VertexOutput {
+    return fullscreen(in_vertex_index, 0.0);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4f {
+    //先用投影矩阵的逆还原出相机空间中的点，再用视图矩阵的逆把方向转换到世界空间。
+    //w 为 0 时平移不起作用，所以天空盒只随相机旋转，不随相机移动
+    let view_position = camera.inv_proj * vec4f(in.ndc, 1.0, 1.0);
+    let direction = (camera.inv_view * vec4f(view_position.xyz / view_position.w, 0.0)).xyz;
+    return textureSample(t_sky, s_sky, normalize(direction));
+}
@@ -0,0 +1,377 @@
+/*
+2D 精灵批处理
+2D 工具经常要画成千上万个带纹理的矩形。每个精灵单独绘制的话，绘制调用和切换绑定组的开销会远远超过绘制本身。
+
+SpriteBatch 收集一帧中所有的精灵，先按层、同一层中再按纹理排序，然后把所有的顶点写入一个顶点缓冲区、所有的索引写入一个索引缓冲区，
+连续使用同一个纹理的精灵合并成一次绘制调用。缓冲区不够大时按 2 的幂扩大，之后的帧继续复用。
+
+顶点使用 buffer::Vertex（精灵只用到位置和纹理坐标），每个顶点的颜色放在同一个缓冲区的后半部分。
+纹理的绑定组使用与场景相同的绑定组布局（漫反射纹理和法线贴图），用 Material::bind_group 创建，
+与 Model::load 一样，添加纹理时需要传入这个布局。精灵没有法线贴图，所有纹理共用一张 Texture::flat_normal。
+
+坐标是屏幕像素，原点在左上角，y 轴向下。层大的精灵画在上面，同一层中按纹理排序，所以同一层中互相重叠的精灵的前后顺序是不确定的。
+*/
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::Path;
+
+use winit::dpi::PhysicalSize;
+use wgpu::{BindGroup, BindGroupLayout, BlendState, Buffer, BufferAddress, BufferUsages, Device, PipelineLayout, Queue, RenderPass, RenderPipeline, ShaderModule, ShaderStages, TextureFormat};
+
+use crate::buffer::{Vertex, VertexLayout};
+use crate::model::Material;
+use crate::pipeline::RenderPipelineBuilder;
+use crate::texture::{Texture, TextureError, TextureOptions};
+
+//SpriteBatch 中的一个纹理，由 add_texture 返回
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SpriteTextureId(usize);
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Sprite {
+    pub texture: SpriteTextureId,
+    //纹理中要画的区域 (x, y, 宽, 高)，单位是纹理的像素，None 表示整个纹理
+    pub source: Option<[f32; 4]>,
+    //origin 在屏幕上的位置
+    pub position: [f32; 2],
+    //旋转和缩放的中心，相对于精灵的大小，(0, 0) 是左上角，(0.5, 0.5) 是中心
+    pub origin: [f32; 2],
+    //顺时针旋转的弧度
+    pub rotation: f32,
+    //精灵的大小是 source 的大小乘上 scale
+    pub scale: [f32; 2],
+    //与纹理的颜色相乘
+    pub tint: [f32; 4],
+    //层大的画在上面
+    pub layer: i32,
+}
+
+impl Sprite {
+    //整个纹理按原大小画在 position（中心）处
+    pub fn new(texture: SpriteTextureId, position: [f32; 2]) -> Self {
+        Self {
+            texture,
+            source: None,
+            position,
+            origin: [0.5, 0.5],
+            rotation: 0.0,
+            scale: [1.0, 1.0],
+            tint: [1.0; 4],
+            layer: 0,
+        }
+    }
+}
+
+//每个顶点的颜色，第二个顶点缓冲区
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, VertexLayout)]
+#[vertex(location = 5)]
+struct Tint {
+    color: [f32; 4],
+}
+
+const VERTICES_PER_SPRITE: usize = 4;
+const INDICES_PER_SPRITE: usize = 6;
+
+struct SpriteTexture {
+    //绑定组引用纹理的视图，纹理要和绑定组一起保留
+    _texture: Texture,
+    bind_group: BindGroup,
+    size: [f32; 2],
+}
+
+pub struct SpriteBatch {
+    textures: Vec<SpriteTexture>,
+    //所有纹理的绑定组共用的法线贴图
+    flat_normal: Texture,
+    //这一帧已添加、还没有上传的精灵
+    sprites: Vec<Sprite>,
+
+    screen_buffer: Buffer,
+    screen_bind_group: BindGroup,
+    shader: ShaderModule,
+    pipeline_layout: PipelineLayout,
+    pipelines: HashMap<TextureFormat, RenderPipeline>,
+
+    //前 capacity 个顶点是 Vertex，之后是同样数量的颜色
+    vertex_buffer: Buffer,
+    index_buffer: Buffer,
+    //缓冲区能容纳的精灵数量
+    capacity: usize,
+    //上一次 prepare 的结果：每个纹理在索引缓冲区中的范围
+    draws: Vec<(SpriteTextureId, Range<u32>)>,
+}
+
+impl SpriteBatch {
+    //texture_bind_group_layout 是场景的材质绑定组布局
+    pub fn new(device: &Device, queue: &Queue, texture_bind_group_layout: &BindGroupLayout) -> Self {
+        let screen_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Sprite Screen Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let screen_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Sprite Screen Buffer"),
+            size: std::mem::size_of::<[f32; 4]>() as u64,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let screen_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Sprite Screen Bind Group"),
+            layout: &screen_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: screen_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("sprite.wgsl"));
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Sprite Pipeline Layout"),
+            bind_group_layouts: &[texture_bind_group_layout, &screen_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let capacity = 256;
+        let (vertex_buffer, index_buffer) = Self::create_buffers(device, capacity);
+
+        Self {
+            textures: Vec::new(),
+            flat_normal: Texture::flat_normal(device, queue).expect("无法创建法线纹理"),
+            sprites: Vec::new(),
+            screen_buffer,
+            screen_bind_group,
+            shader,
+            pipeline_layout,
+            pipelines: HashMap::new(),
+            vertex_buffer,
+            index_buffer,
+            capacity,
+            draws: Vec::new(),
+        }
+    }
+
+    fn create_buffers(device: &Device, capacity: usize) -> (Buffer, Buffer) {
+        let vertex_count = capacity * VERTICES_PER_SPRITE;
+        let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Sprite Vertex Buffer"),
+            size: (vertex_count * (std::mem::size_of::<Vertex>() + std::mem::size_of::<Tint>())) as u64,
+            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let index_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Sprite Index Buffer"),
+            size: (capacity * INDICES_PER_SPRITE * std::mem::size_of::<u32>()) as u64,
+            usage: BufferUsages::INDEX | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        (vertex_buffer, index_buffer)
+    }
+
+    //添加一个纹理，之后的精灵用返回的 id 引用它
+    pub fn add_texture(&mut self, device: &Device, name: &str, texture: Texture, layout: &BindGroupLayout) -> SpriteTextureId {
+        let size = texture.size();
+        let bind_group = Material::bind_group(device, name, &texture, &self.flat_normal, layout);
+        self.textures.push(SpriteTexture {
+            _texture: texture,
+            bind_group,
+            size: [size.width as f32, size.height as f32],
+        });
+        SpriteTextureId(self.textures.len() - 1)
+    }
+
+    //从图像文件加载纹理
+    pub fn load_texture<P: AsRef<Path>>(&mut self, device: &Device, queue: &Queue, path: P, layout: &BindGroupLayout) -> Result<SpriteTextureId, TextureError> {
+        let path = path.as_ref();
+        let texture = Texture::from_path(device, queue, path, &TextureOptions::default())?;
+        Ok(self.add_texture(device, &path.display().to_string(), texture, layout))
+    }
+
+    //纹理的大小（像素）
+    pub fn texture_size(&self, texture: SpriteTextureId) -> Option<[f32; 2]> {
+        self.textures.get(texture.0).map(|texture| texture.size)
+    }
+
+    //添加一个精灵，在下一次 prepare 时上传。纹理 id 无效的精灵会被忽略
+    pub fn draw_sprite(&mut self, sprite: Sprite) {
+        if sprite.texture.0 < self.textures.len() {
+            self.sprites.push(sprite);
+        } else {
+            log::warn!("精灵的纹理 {:?} 不存在", sprite.texture);
+        }
+    }
+
+    //已添加、还没有上传的精灵数量
+    pub fn len(&self) -> usize {
+        self.sprites.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sprites.is_empty()
+    }
+
+    //精灵四个角的顶点：左上、左下、右下、右上
+    fn sprite_vertices(&self, sprite: &Sprite) -> [Vertex; VERTICES_PER_SPRITE] {
+        let texture_size = self.textures[sprite.texture.0].size;
+        let [sx, sy, sw, sh] = sprite.source.unwrap_or([0.0, 0.0, texture_size[0], texture_size[1]]);
+        let width = sw * sprite.scale[0];
+        let height = sh * sprite.scale[1];
+        let (sin, cos) = sprite.rotation.sin_cos();
+        let corner = |x: f32, y: f32, u: f32, v: f32| {
+            //相对于 origin 的偏移，旋转后加上位置
+            let dx = (x - sprite.origin[0]) * width;
+            let dy = (y - sprite.origin[1]) * height;
+            Vertex {
+                position: [sprite.position[0] + dx * cos - dy * sin, sprite.position[1] + dx * sin + dy * cos, 0.0],
+                tex_coords: [u / texture_size[0], v / texture_size[1]],
+                normal: [0.0, 0.0, 1.0],
+                tangent: [1.0, 0.0, 0.0],
+                bitangent: [0.0, 1.0, 0.0],
+            }
+        };
+        [
+            corner(0.0, 0.0, sx, sy),
+            corner(0.0, 1.0, sx, sy + sh),
+            corner(1.0, 1.0, sx + sw, sy + sh),
+            corner(1.0, 0.0, sx + sw, sy),
+        ]
+    }
+
+    //把已添加的精灵排序后上传并准备管线，需要在渲染通道开始之前调用。size 是渲染目标的大小
+    pub fn prepare(&mut self, device: &Device, queue: &Queue, format: TextureFormat, size: PhysicalSize<u32>) {
+        let shader = &self.shader;
+        let pipeline_layout = &self.pipeline_layout;
+        self.pipelines.entry(format).or_insert_with(|| {
+            RenderPipelineBuilder::new(shader, format)
+                .label("Sprite Pipeline")
+                .layout(pipeline_layout)
+                .source(include_str!("sprite.wgsl"))
+                .vertex_buffer(Vertex::desc())
+                .vertex_buffer(Tint::desc())
+                .blend(Some(BlendState::ALPHA_BLENDING))
+                .cull_mode(None)
+                .build(device)
+        });
+        queue.write_buffer(&self.screen_buffer, 0, bytemuck::cast_slice(&[size.width as f32, size.height as f32, 0.0, 0.0]));
+
+        //先按层，再按纹理排序。稳定排序保持同一层、同一纹理的精灵的添加顺序
+        let mut sprites = std::mem::take(&mut self.sprites);
+        sprites.sort_by_key(|sprite| (sprite.layer, sprite.texture));
+
+        self.draws.clear();
+        if sprites.len() > self.capacity {
+            self.capacity = sprites.len().next_power_of_two();
+            (self.vertex_buffer, self.index_buffer) = Self::create_buffers(device, self.capacity);
+        }
+        let mut vertices = Vec::with_capacity(sprites.len() * VERTICES_PER_SPRITE);
+        let mut tints = Vec::with_capacity(sprites.len() * VERTICES_PER_SPRITE);
+        let mut indices = Vec::with_capacity(sprites.len() * INDICES_PER_SPRITE);
+        for sprite in &sprites {
+            let base = vertices.len() as u32;
+            vertices.extend_from_slice(&self.sprite_vertices(sprite));
+            tints.extend_from_slice(&[Tint { color: sprite.tint }; VERTICES_PER_SPRITE]);
+            let start = indices.len() as u32;
+            indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+            let end = indices.len() as u32;
+            //与上一个精灵的纹理相同时合并到同一次绘制中
+            match self.draws.last_mut() {
+                Some((texture, range)) if *texture == sprite.texture => range.end = end,
+                _ => self.draws.push((sprite.texture, start..end)),
+            }
+        }
+        sprites.clear();
+        self.sprites = sprites;
+
+        if !vertices.is_empty() {
+            queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+            queue.write_buffer(&self.vertex_buffer, self.tint_offset(), bytemuck::cast_slice(&tints));
+            queue.write_buffer(&self.index_buffer, 0, bytemuck::cast_slice(&indices));
+        }
+    }
+
+    //颜色在顶点缓冲区中的起始位置
+    fn tint_offset(&self) -> BufferAddress {
+        (self.capacity * VERTICES_PER_SPRITE * std::mem::size_of::<Vertex>()) as BufferAddress
+    }
+
+    //上一次 prepare 需要的绘制调用次数
+    pub fn draw_calls(&self) -> usize {
+        self.draws.len()
+    }
+
+    //绘制上一次 prepare 的精灵。会替换管线和 @group(0)、@group(1)
+    pub fn draw<'a>(&'a self, render_pass: &mut RenderPass<'a>, format: TextureFormat) {
+        if self.draws.is_empty() {
+            return;
+        }
+        let Some(pipeline) = self.pipelines.get(&format) else {
+            log::warn!("精灵的管线还没有创建（{:?}），跳过绘制", format);
+            return;
+        };
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(1, &self.screen_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..self.tint_offset()));
+        render_pass.set_vertex_buffer(1, self.vertex_buffer.slice(self.tint_offset()..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        for (texture, range) in &self.draws {
+            render_pass.set_bind_group(0, &self.textures[texture.0].bind_group, &[]);
+            render_pass.draw_indexed(range.clone(), 0, 0..1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headless;
+    use crate::pipeline::PipelineLayoutBuilder;
+
+    #[test]
+    fn sprites_are_merged_by_layer_and_texture() {
+        let (device, queue) = pollster::block_on(headless::request_device());
+        let reflected = PipelineLayoutBuilder::reflect(include_str!("../light/shader.wgsl")).unwrap();
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: reflected.entries(0),
+        });
+        let mut batch = SpriteBatch::new(&device, &queue, &layout);
+        let texture = |name| {
+            let img = image::DynamicImage::ImageRgba8(image::RgbaImage::new(4, 4));
+            Texture::from_image(&device, &queue, &img, Some(name), &TextureOptions::default()).unwrap()
+        };
+        let a = batch.add_texture(&device, "a", texture("a"), &layout);
+        let b = batch.add_texture(&device, "b", texture("b"), &layout);
+
+        //交替添加两个纹理和三个层
+        for (layer, texture) in [(0, b), (1, a), (0, a), (0, b), (1, a), (0, a), (2, b)] {
+            batch.draw_sprite(Sprite {
+                layer,
+                ..Sprite::new(texture, [10.0, 10.0])
+            });
+        }
+        batch.prepare(&device, &queue, headless::HEADLESS_FORMAT, PhysicalSize::new(64, 64));
+
+        //每个精灵 6 个索引，同一层中同一纹理的精灵合并成一次绘制
+        assert_eq!(batch.draws, [(a, 0..12), (b, 12..24), (a, 24..36), (b, 36..42)]);
+        assert_eq!(batch.draw_calls(), 4);
+        assert!(batch.is_empty());
+
+        //下一帧重新收集
+        batch.draw_sprite(Sprite::new(b, [0.0, 0.0]));
+        batch.draw_sprite(Sprite::new(b, [0.0, 0.0]));
+        batch.prepare(&device, &queue, headless::HEADLESS_FORMAT, PhysicalSize::new(64, 64));
+        assert_eq!(batch.draws, [(b, 0..12)]);
+    }
+}
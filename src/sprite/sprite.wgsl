@@ -0,0 +1,49 @@
+//2D 精灵：顶点坐标是屏幕像素，原点在左上角
+
+struct Screen {
+    //(宽, 高, _, _)
+    size: vec4f
+};
+
+//与 light/shader.wgsl 的材质使用同一个绑定组布局，法线贴图（binding 2、3）不使用
+@group(0) @binding(0)
+var t_diffuse: texture_2d<f32>;
+
+@group(0) @binding(1)
+var s_diffuse: sampler;
+
+@group(1) @binding(0)
+var<uniform> screen: Screen;
+
+//buffer::Vertex 中只用到位置和纹理坐标
+struct VertexInput {
+    @location(0) position: vec3f,
+    @location(1) tex_coords: vec2f
+};
+
+//颜色放在同一个缓冲区的后半部分，作为第二个顶点缓冲区
+struct TintInput {
+    @location(5) tint: vec4f
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4f,
+    @location(0) tex_coords: vec2f,
+    @location(1) tint: vec4f
+};
+
+@vertex
+fn vs_main(model: VertexInput, color: TintInput) -> VertexOutput {
+    var out: VertexOutput;
+    //像素坐标转换为标准化设备坐标，y 轴翻转
+    let ndc = model.position.xy / screen.size.xy * vec2f(2.0, -2.0) + vec2f(-1.0, 1.0);
+    out.clip_position = vec4f(ndc, 0.0, 1.0);
+    out.tex_coords = model.tex_coords;
+    out.tint = color.tint;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4f {
+    return textureSample(t_diffuse, s_diffuse, in.tex_coords) * in.tint;
+}
@@ -1,44 +1,141 @@
 //将所有字段封装在一个结构体内，并在其上添加一些函数
 
+use std::collections::HashMap;
 use std::default::Default;
-use std::fmt::format;
-use rand::Rng;
-use web_sys::console::count;
+use std::time::Duration;
+use image::{DynamicImage, RgbaImage};
 use winit::{window::Window, dpi::PhysicalSize};
-use winit::event::WindowEvent;
+use winit::event::{ElementState, KeyboardInput, VirtualKeyCode, WindowEvent};
 
-use wgpu::{Surface, SurfaceConfiguration, SurfaceError, Device, DeviceDescriptor, Features, Limits, Queue, Instance, InstanceDescriptor, Backends, RequestAdapterOptions, TextureUsages, TextureViewDescriptor, PresentMode, CommandEncoderDescriptor, RenderPassDescriptor, RenderPassColorAttachment, Operations, LoadOp, Color, RenderPipeline, RenderPipelineDescriptor, ShaderModuleDescriptor, ShaderSource, include_wgsl, PipelineLayout, PipelineLayoutDescriptor, VertexState, FragmentState, ColorTargetState, BlendState, ColorWrites, PrimitiveState, PrimitiveTopology, FrontFace, Face, PolygonMode, MultisampleState, Buffer, BufferUsages, VertexFormat, vertex_attr_array, BindGroup};
+use wgpu::{SurfaceError, Device, DeviceDescriptor, Features, Limits, Queue, Instance, InstanceDescriptor, Backends, RequestAdapterOptions, CommandEncoderDescriptor, RenderPassDescriptor, RenderPassColorAttachment, RenderPassDepthStencilAttachment, Operations, LoadOp, Color, RenderPipeline, ShaderModuleDescriptor, ShaderSource, Buffer, BufferUsages, BindGroup, BindGroupLayout, CommandEncoder, RenderPass, TextureView, TextureFormat, PipelineLayout, ShaderModule};
 use wgpu::util::DeviceExt;
-use wgpu::VertexBufferLayout;
-use wgpu::BufferAddress;
-use wgpu::VertexStepMode;
-use wgpu::VertexAttribute;
+
+use crate::camera::{Camera, CameraBinding};
+use crate::camera::controller::{CameraController, FlyController, OrbitController, PanZoomController};
+use crate::depth::DepthDebug;
+use crate::hdr::{Hdr, HdrTarget, HDR_FORMAT};
+use crate::headless;
+use crate::light::{Light, LightId, Lights};
+use crate::model::{DrawModel, Model, ModelError};
+use crate::pipeline::{DepthConfig, PipelineLayoutBuilder, ReflectedLayout, RenderPipelineBuilder};
+use crate::texture::{Texture, TextureError, TextureOptions};
+use crate::render_target::{RenderTarget, SurfaceTarget, TextureTarget};
+use crate::particles::{Emitter, ParticleBlend, ParticleSystem};
+use crate::shadow::{ShadowConfig, ShadowMaps};
+use crate::painter::Painter;
+use crate::shader::{self, Preprocessor, ShaderError, ShaderWatcher, SHADER_ROOT};
+use crate::sprite::{Sprite, SpriteBatch, SpriteTextureId};
+use crate::text::{Font, FontError, TextRenderer, TextStyle};
+use crate::skybox::{Skybox, SkyboxError};
 
 pub struct State {
-    pub surface: Surface,
     pub device: Device,
     pub queue: Queue,
-    pub config: SurfaceConfiguration,
     pub size: PhysicalSize<u32>,
 
+    //渲染目标：窗口的展示平面、离屏纹理或纹理数组的某一层
+    pub target: Box<dyn RenderTarget>,
+
+    //与绘制目标无关的场景资源
+    scene: Scene,
+
+    //相机，以及保存视图投影矩阵的统一缓冲区
+    pub camera: Camera,
+    camera_binding: CameraBinding,
+
+    //相机控制器，在 update 中根据输入移动相机（按 1/2/3 切换轨道、飞行、平移缩放）
+    pub controller: Box<dyn CameraController>,
+
+    //场景中的光源，在 update 中上传到统一缓冲区
+    pub lights: Lights,
+    //update 中绕 y 轴旋转的演示点光源
+    orbit_light: Option<LightId>,
+    //投射阴影的光源的阴影贴图，每帧在绘制场景之前更新
+    pub shadows: ShadowMaps,
+
+    //深度缓冲区，与渲染目标一样大，调整大小时重新创建
+    depth_config: DepthConfig,
+    depth_texture: Texture,
+
+    //场景先画到 HDR 纹理上，再经过色调映射画到渲染目标（按 T 切换算法，按 +/- 调整曝光）
+    pub hdr: Hdr,
+    hdr_target: HdrTarget,
+
+    //为 true 时把深度缓冲区画到屏幕上（按 F1 切换）
+    pub show_depth: bool,
+    depth_debug: DepthDebug,
+
+    //屏幕上的 2D 图形，在色调映射之后画在场景上面、精灵和文字下面
+    pub painter: Painter,
+
+    //屏幕上的精灵，在色调映射之后画在场景上面、文字下面
+    pub sprites: SpriteBatch,
+
+    //屏幕上的文字，加载字体之后才能使用
+    text: Option<TextRenderer>,
+}
+
+//场景：管线、顶点/索引缓冲区和绑定组。
+//它只关心要画什么，不关心画到哪里。管线的颜色目标格式必须与渲染目标一致，所以按格式缓存管线。
+pub struct Scene {
     //使用着色器
-    render_pipeline: RenderPipeline,
+    shader: ShaderModule,
+    //着色器的源码（热重载后是预处理后的源码），创建管线时用来检查顶点输入
+    shader_source: String,
+    render_pipeline_layout: PipelineLayout,
+    render_pipelines: HashMap<TextureFormat, RenderPipeline>,
+    depth_config: DepthConfig,
+    //开发时从磁盘重新加载着色器，见 watch_shader
+    shader_watcher: Option<ShaderWatcher>,
 
     //现在有了顶点数据，需要将其存储在一个缓冲区中
     vertex_buffer: Buffer,
 
-    num_vertices: u32,
-
     //索缓冲区
     index_buffer: Buffer,
     num_indices: u32,
 
+    //实例：同一个五边形画多次，每个实例有自己的变换和颜色
+    instances: InstanceBuffer,
+
     //绑定组
+    texture_bind_group_layout: BindGroupLayout,
     diffuse_bind_group: BindGroup,
+
+    //加载的模型，设置后代替五边形绘制
+    model: Option<Model>,
+    //模型的每个网格都要画在所有实例上，并带有节点的变换。
+    //model_draws 是要绘制的网格和它的节点变换，model_instances 中依次存放每个网格的所有实例（节点变换已经合并到实例的模型矩阵中）
+    model_draws: Vec<(usize, cgmath::Matrix4<f32>)>,
+    model_instances: Option<Buffer>,
+    //model_instances 中每个网格的实例数量，以及缓冲区能容纳的 InstanceRaw 数量
+    model_instance_count: usize,
+    model_instance_capacity: usize,
+
+    //天空盒，设置后代替清屏颜色作为背景
+    skybox: Option<Skybox>,
+
+    //粒子系统，在不透明的物体之后绘制
+    particles: Vec<ParticleSystem>,
+}
+
+//Scene 之外的绑定组布局，创建管线时使用。它们必须由各自模块的 LAYOUT_ENTRIES 创建
+pub struct SceneLayouts<'a> {
+    pub camera: &'a BindGroupLayout,
+    pub lights: &'a BindGroupLayout,
+    pub shadows: &'a BindGroupLayout,
+}
+
+//Scene 之外的绑定组，每帧绘制时传入：@group(1) 是相机，@group(2) 是光源，@group(3) 是阴影贴图
+pub struct SceneBindings<'a> {
+    pub camera: &'a BindGroup,
+    pub lights: &'a BindGroup,
+    pub shadows: &'a BindGroup,
 }
 
 //三角形实际顶点数据
-use crate::buffer::Vertex;
+use crate::buffer::{Vertex, VertexLayout};
+use crate::buffer::instance::{self, InstanceBuffer, InstanceRaw};
 
 /*
 按逆时针顺序排列顶点：上、左下、右下。这样做的部分理由是出于惯例，
@@ -71,11 +168,12 @@ use crate::buffer::Vertex;
 // Changed
 const VERTICES: &[Vertex] = &[
     // 修改后的
-    Vertex { position: [-0.0868241, 0.49240386, 0.0], tex_coords: [0.4131759, 0.00759614], }, // A
-    Vertex { position: [-0.49513406, 0.06958647, 0.0], tex_coords: [0.0048659444, 0.43041354], }, // B
-    Vertex { position: [-0.21918549, -0.44939706, 0.0], tex_coords: [0.28081453, 0.949397], }, // C
-    Vertex { position: [0.35966998, -0.3473291, 0.0], tex_coords: [0.85967, 0.84732914], }, // D
-    Vertex { position: [0.44147372, 0.2347359, 0.0], tex_coords: [0.9414737, 0.2652641], }, // E
+    // 纹理坐标的 v 向下增大，而 y 向上，所以副切线是 -y
+    Vertex { position: [-0.0868241, 0.49240386, 0.0], tex_coords: [0.4131759, 0.00759614], normal: [0.0, 0.0, 1.0], tangent: [1.0, 0.0, 0.0], bitangent: [0.0, -1.0, 0.0] }, // A
+    Vertex { position: [-0.49513406, 0.06958647, 0.0], tex_coords: [0.0048659444, 0.43041354], normal: [0.0, 0.0, 1.0], tangent: [1.0, 0.0, 0.0], bitangent: [0.0, -1.0, 0.0] }, // B
+    Vertex { position: [-0.21918549, -0.44939706, 0.0], tex_coords: [0.28081453, 0.949397], normal: [0.0, 0.0, 1.0], tangent: [1.0, 0.0, 0.0], bitangent: [0.0, -1.0, 0.0] }, // C
+    Vertex { position: [0.35966998, -0.3473291, 0.0], tex_coords: [0.85967, 0.84732914], normal: [0.0, 0.0, 1.0], tangent: [1.0, 0.0, 0.0], bitangent: [0.0, -1.0, 0.0] }, // D
+    Vertex { position: [0.44147372, 0.2347359, 0.0], tex_coords: [0.9414737, 0.2652641], normal: [0.0, 0.0, 1.0], tangent: [1.0, 0.0, 0.0], bitangent: [0.0, -1.0, 0.0] }, // E
 ];
 
 const INDICES: &[u16] = &[
@@ -84,6 +182,42 @@ const INDICES: &[u16] = &[
     2, 3, 4
 ];
 
+//漫反射纹理的路径
+const DIFFUSE_TEXTURE_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/res/texture.jpeg");
+
+//场景着色器在源码目录中的路径，热重载时从这里读取。编译进程序的是同一个文件
+pub const SCENE_SHADER_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/light/shader.wgsl");
+
+//默认的实例：3x3 的网格，每个实例绕 z 轴旋转不同的角度，颜色从左到右由红变蓝
+fn default_instances() -> Vec<instance::Instance> {
+    const GRID: i32 = 3;
+    const SPACING: f32 = 0.6;
+    let mut instances = Vec::new();
+    for y in 0..GRID {
+        for x in 0..GRID {
+            let mut instance = instance::Instance::new(cgmath::Vector3::new((x - 1) as f32 * SPACING, (y - 1) as f32 * SPACING, 0.0));
+            let angle = cgmath::Deg(((y * GRID + x) * 20) as f32);
+            instance.rotation = cgmath::Rotation3::from_angle_z(angle);
+            instance.scale = cgmath::Vector3::new(0.5, 0.5, 0.5);
+            let t = x as f32 / (GRID - 1) as f32;
+            instance.tint = Some([1.0 - t * 0.5, 0.75, 0.5 + t * 0.5, 1.0]);
+            instances.push(instance);
+        }
+    }
+    instances
+}
+
+//找不到纹理文件时使用的 8x8 黑白棋盘格
+fn checkerboard_image() -> DynamicImage {
+    DynamicImage::ImageRgba8(RgbaImage::from_fn(8, 8, |x, y| {
+        if (x + y) % 2 == 0 {
+            image::Rgba([255, 255, 255, 255])
+        } else {
+            image::Rgba([40, 40, 40, 255])
+        }
+    }))
+}
+
 impl State {
     //创建某些wgpu类型需要使用异步
     pub async fn new(window: &Window) -> Self {
@@ -155,199 +289,374 @@ impl State {
             None, //追踪API调用路径
         ).await.unwrap();
 
-        let caps = surface.get_capabilities(&adapter);
-
-        // usage 字段描述了 SurfaceTexture 如何被使用。RENDER_ATTACHMENT 指定将被用来渲染到屏幕的纹理（我们将在后面讨论更多的 TextureUsages 枚举值）。
-        //
-        // format 定义了 SurfaceTexture 在 GPU 内存上如何被存储。不同的显示设备偏好不同的纹理格式。
-        // 我们使用surface.get_capabilities(&adapter).formats 来获取当前显示设备的最佳格式。
-        //
-        // width 和 height 指定 SurfaceTexture 的宽度和高度（物理像素，等于逻辑像素乘以屏幕缩放因子）。这通常就是窗口的宽和高。
-        //
-        // 需要确保 SurfaceTexture 的宽高不能为 0，这会导致你的应用程序崩溃。
-        //
-        // present_mode 指定的 wgpu::PresentMode 枚举值决定了展示平面如何与显示设备同步。我们选择的PresentMode::Fifo 指定了显示设备的刷新率做为渲染的帧速率，
-        // 这本质上就是垂直同步（VSync），所有平台都得支持这种呈现模式（PresentMode）。你可以在文档中查看所有的模式。
-        //当你想让用户来选择他们使用的呈现模式时，可以使用 surface.get_capabilities(&adapter) 获取展示平面支持的所有呈现模式的列表:
-        //
-        // let modes = surface.get_capabilities(&adapter).present_modes;
-        //
-        // PresentMode::Fifo 模式无论如何都是被支持的，PresentMode::AutoVsync 和 PresentMode::AutoNoVsync 支持回退，因此也能工作在所有平台上。
-        let config = SurfaceConfiguration {
-            usage: TextureUsages::RENDER_ATTACHMENT,
-            format: caps.formats[0],
-            width: size.width,
-            height: size.height,
-            present_mode: PresentMode::Fifo,
-            alpha_mode: caps.alpha_modes[0],
-            view_formats: vec![],
-        };
+        let target = SurfaceTarget::new(surface, &adapter, &device, size);
 
-        surface.configure(&device, &config);
+        Self::with_target(device, queue, Box::new(target))
+    }
 
-        //纹理
-        /*
-        此处代码从图像文件中读取字节，并将其加载到 image 对象中，然后转换为 rgba 动态数组。我们还保存了图像的尺寸信息以便在创建实际纹理时使用。
-        */
-        let diffuse_bytes = include_bytes!("../texture.jpeg");
-        let diffuse_image = image::load_from_memory(diffuse_bytes).unwrap();
-        let diffuse_rgba = diffuse_image.to_rgba8();
-
-        use image::GenericImageView;
-        let dimensions = diffuse_image.dimensions();
-
-        //创建纹理
-        let texture_size = wgpu::Extent3d {
-            width: dimensions.0,
-            height: dimensions.1,
-            depth_or_array_layers: 1,
-        };
-        let diffuse_texture = device.create_texture(
-            &wgpu::TextureDescriptor {
-                // 所有纹理都是以 3D 形式存储的，我们通过设置深度 1 来表示 2D 纹理
-                size: texture_size,
-                mip_level_count: 1,
-                sample_count: 1,
-                dimension: wgpu::TextureDimension::D2,
-                // 大多数图像都是使用 sRGB 来存储的，我们需要在这里指定。
-                format: wgpu::TextureFormat::Rgba8UnormSrgb,
-                // TEXTURE_BINDING 表示我们要在着色器中使用这个纹理。
-                // COPY_DST 表示我们能将数据复制到这个纹理上。
-                usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
-                label: Some("diffuse_texture"),
-                view_formats: &[],
-            }
-        );
+    //不需要窗口的构造函数：渲染到离屏纹理，可以运行在软渲染（fallback）适配器上
+    pub async fn new_headless(size: PhysicalSize<u32>) -> Self {
+        let (device, queue) = headless::request_device().await;
+        let target = TextureTarget::new(&device, size, headless::HEADLESS_FORMAT);
 
-        /*
-        填充数据到纹理中
-        Texture 结构体没有函数可以直接与数据交互。但我们可以使用之前创建的命令队列上的 write_texture 命令来填充纹理数据。下边是具体代码：
-        */
-        queue.write_texture(
-            // 告诉 wgpu 从何处复制像素数据
-            wgpu::ImageCopyTexture {
-                texture: &diffuse_texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: wgpu::TextureAspect::All,
-            },
-            //实际像素数据
-            &diffuse_rgba,
-            //纹理的内存布局
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * dimensions.0),
-                rows_per_image: Some(dimensions.1),
+        Self::with_target(device, queue, Box::new(target))
+    }
+
+    //使用任意渲染目标构建 State
+    pub fn with_target(device: Device, queue: Queue, target: Box<dyn RenderTarget>) -> Self {
+        let size = target.size();
+        let depth_config = DepthConfig::default();
+
+        //相机在 z 轴正方向上看向原点，y 轴向上
+        let camera = Camera::perspective((0.0, 0.0, 2.0).into(), size.width as f32 / size.height as f32);
+        let camera_binding = CameraBinding::new(&device, &camera);
+
+        //一个斜上方的平行光作为主光源并投射阴影，再加一个在场景前方绕圈的点光源
+        let mut lights = Lights::new(&device);
+        lights.add(Light::directional((-0.3, -0.6, -1.0).into(), [0.6, 0.6, 0.6]).with_shadows());
+        let orbit_light = Some(lights.add(Light::point((1.0, 0.5, 1.0).into(), [1.0, 0.9, 0.7], 5.0)));
+        lights.upload(&queue);
+        let mut shadows = ShadowMaps::new(&device, ShadowConfig::default());
+        shadows.update(&queue, &lights);
+
+        let hdr = Hdr::new(&device);
+        let hdr_target = hdr.create_target(&device, size);
+
+        let scene = Scene::new(&device, &queue, HDR_FORMAT, depth_config, SceneLayouts {
+            camera: &camera_binding.bind_group_layout,
+            lights: &lights.bind_group_layout,
+            shadows: &shadows.bind_group_layout,
+        });
+        let depth_texture = Texture::create_depth_texture(&device, size.width, size.height, depth_config.format, "depth_texture");
+        let depth_debug = DepthDebug::new(&device);
+        let painter = Painter::new(&device);
+        let sprites = SpriteBatch::new(&device, &queue, &scene.texture_bind_group_layout);
+
+        Self {
+            device,
+            queue,
+            size,
+
+            target,
+
+            scene,
+
+            camera,
+            camera_binding,
+
+            controller: Box::new(OrbitController::new()),
+
+            lights,
+            orbit_light,
+            shadows,
+
+            depth_config,
+            depth_texture,
+
+            hdr,
+            hdr_target,
+
+            show_depth: false,
+            depth_debug,
+
+            painter,
+
+            sprites,
+
+            text: None,
+        }
+    }
+
+    //调整宽高
+    pub fn resize(&mut self, new_size: PhysicalSize<u32>) {
+        //窗口最小化时宽高为 0，这时不能创建纹理
+        if new_size.width == 0 || new_size.height == 0 {
+            return;
+        }
+        self.size = new_size;
+        self.target.resize(&self.device, new_size);
+        //保持相机的宽高比与渲染目标一致，否则画面会被拉伸
+        self.camera.set_aspect(new_size.width as f32 / new_size.height as f32);
+        //深度纹理必须与颜色附件一样大
+        self.depth_texture = Texture::create_depth_texture(&self.device, new_size.width, new_size.height, self.depth_config.format, "depth_texture");
+        self.hdr_target = self.hdr.create_target(&self.device, new_size);
+    }
+
+    pub fn depth_config(&self) -> DepthConfig {
+        self.depth_config
+    }
+
+    //修改深度格式或比较函数，会重新创建深度纹理和管线
+    pub fn set_depth_config(&mut self, depth_config: DepthConfig) {
+        self.depth_config = depth_config;
+        self.depth_texture = Texture::create_depth_texture(&self.device, self.size.width, self.size.height, depth_config.format, "depth_texture");
+        self.scene.set_depth_config(depth_config);
+    }
+
+    pub fn input(&mut self, event: &WindowEvent) -> bool {
+        if let WindowEvent::KeyboardInput {
+            input: KeyboardInput {
+                state: ElementState::Pressed,
+                virtual_keycode: Some(keycode),
+                ..
             },
-            texture_size,
-        );
+            ..
+        } = event {
+            match keycode {
+                VirtualKeyCode::F1 => self.show_depth = !self.show_depth,
+                VirtualKeyCode::Key1 => self.controller = Box::new(OrbitController::new()),
+                VirtualKeyCode::Key2 => self.controller = Box::new(FlyController::default()),
+                VirtualKeyCode::Key3 => self.controller = Box::new(PanZoomController::new()),
+                VirtualKeyCode::T => {
+                    self.hdr.set_tonemap(self.hdr.tonemap().next());
+                    log::info!("色调映射: {:?}", self.hdr.tonemap());
+                }
+                //每次调整半档曝光
+                VirtualKeyCode::Equals | VirtualKeyCode::Plus | VirtualKeyCode::NumpadAdd => self.hdr.set_exposure(self.hdr.exposure() * std::f32::consts::SQRT_2),
+                VirtualKeyCode::Minus | VirtualKeyCode::NumpadSubtract => self.hdr.set_exposure(self.hdr.exposure() / std::f32::consts::SQRT_2),
+                _ => return self.controller.process_event(event),
+            }
+            return true;
+        }
+        self.controller.process_event(event)
+    }
 
-        /*
-        填充纹理数据的经典方式是将像素数据先复制到一个缓冲区，然后再从缓冲区复制到纹理中。
-        使用 write_texture 更有效率，因为它少用了一个缓冲区 -- 不过这里还是介绍一下，以防读者有需要：
-        */
-        /*let buffer = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Temp Buffer"),
-                contents: &diffuse_rgba,
-                usage: wgpu::BufferUsages::COPY_SRC,
+    //dt 是距离上一帧的时间，控制器用它让相机的移动速度与帧率无关
+    pub fn update(&mut self, dt: Duration) {
+        self.controller.update_camera(&mut self.camera, self.size, dt);
+        self.camera_binding.update(&self.queue, &self.camera);
+        //演示用的点光源每秒绕 y 轴转 60°
+        if let Some(light) = self.orbit_light.and_then(|id| self.lights.get_mut(id)) {
+            if let Some(position) = light.position() {
+                let rotation: cgmath::Quaternion<f32> = cgmath::Rotation3::from_angle_y(cgmath::Deg(60.0 * dt.as_secs_f32()));
+                light.set_position(rotation * position);
             }
-        );
+        }
+        self.lights.upload(&self.queue);
+        self.shadows.update(&self.queue, &self.lights);
+        self.scene.reload_shader(&self.device);
+        for particles in &mut self.scene.particles {
+            particles.update(&self.device, &self.queue, dt.as_secs_f32());
+        }
+        self.scene.upload_instances(&self.device, &self.queue);
+    }
 
-        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: Some("texture_buffer_copy_encoder"),
-        });
+    //开发模式：从磁盘加载场景的着色器，文件修改后自动重新编译。通常传入 SCENE_SHADER_PATH
+    pub fn watch_scene_shader<P: AsRef<std::path::Path>>(&mut self, path: P) {
+        self.scene.watch_shader(path);
+    }
 
-        encoder.copy_buffer_to_texture(
-            wgpu::ImageCopyBuffer {
-                buffer: &buffer,
-                offset: 0,
-                bytes_per_row: 4 * dimensions.0,
-                rows_per_image: dimensions.1,
-            },
-            wgpu::ImageCopyTexture {
-                texture: &diffuse_texture,
-                mip_level: 0,
-                array_layer: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: Default::default(),
-            },
-            size,
-        );
+    //加载 OBJ 或 glTF 模型，代替默认的五边形
+    pub fn load_model<P: AsRef<std::path::Path>>(&mut self, path: P) -> Result<(), ModelError> {
+        let model = Model::load(&self.device, &self.queue, path, &self.scene.texture_bind_group_layout)?;
+        self.scene.set_model(&self.device, &self.queue, model);
+        Ok(())
+    }
 
-        queue.submit(std::iter::once(encoder.finish()));*/
+    //加载 6 张面的图像作为天空盒，顺序为 +X、-X、+Y、-Y、+Z、-Z
+    pub fn load_skybox_faces<P: AsRef<std::path::Path>>(&mut self, paths: &[P; 6]) -> Result<(), SkyboxError> {
+        let skybox = Skybox::from_faces(&self.device, &self.queue, paths, &self.camera_binding.bind_group_layout)?;
+        self.scene.skybox = Some(skybox);
+        Ok(())
+    }
 
-        /*
-        纹理视图与采样器
-        现在纹理中已经有了数据，我们需要一种方法来使用它。这，就是纹理视图（TextureView）和采样器（Sampler）的用处。
+    //加载一张等距柱状投影的全景图作为天空盒，face_size 是立方体贴图每个面的边长
+    pub fn load_skybox_equirect<P: AsRef<std::path::Path>>(&mut self, path: P, face_size: u32) -> Result<(), SkyboxError> {
+        let skybox = Skybox::from_equirectangular(&self.device, &self.queue, path, face_size, &self.camera_binding.bind_group_layout)?;
+        self.scene.skybox = Some(skybox);
+        Ok(())
+    }
 
-        纹理视图描述纹理及其关联的元数据。采样器控制纹理如何被 采样。采样工作类似于 GIMP/Photoshop 中的滴管工具。
-        我们的程序在纹理上提供一个坐标（被称为 纹理坐标 ），然后采样器根据纹理和一些内部参数返回相应的颜色。
+    //移除天空盒，恢复清屏颜色
+    pub fn clear_skybox(&mut self) {
+        self.scene.skybox = None;
+    }
 
-        现在我们来定义 diffuse_texture_view 和 diffuse_sampler：
-        */
-        let diffuse_texture_view = diffuse_texture.create_view(&TextureViewDescriptor::default());
-        /*
-        address_mode_* 参数指定了如果采样器得到的纹理坐标超出了纹理边界时该如何处理。我们有几个选项可供选择：
+    //加载精灵纹理，之后可以用 draw_sprite 画这个纹理
+    pub fn load_sprite_texture<P: AsRef<std::path::Path>>(&mut self, path: P) -> Result<SpriteTextureId, TextureError> {
+        self.sprites.load_texture(&self.device, &self.queue, path, &self.scene.texture_bind_group_layout)
+    }
+
+    //在下一次 render 时画一个精灵
+    pub fn draw_sprite(&mut self, sprite: Sprite) {
+        self.sprites.draw_sprite(sprite);
+    }
+
+    //加载 BMFont 字体，之后可以用 draw_text 画文字
+    pub fn load_font<P: AsRef<std::path::Path>>(&mut self, path: P) -> Result<(), FontError> {
+        let font = Font::load(&self.device, &self.queue, path)?;
+        self.text = Some(TextRenderer::new(&self.device, font));
+        Ok(())
+    }
+
+    //在下一次 render 时画一段文字，position 是屏幕像素坐标（左上角为原点）。没有加载字体时什么都不做
+    pub fn draw_text(&mut self, text: &str, position: [f32; 2], style: &TextStyle) {
+        if let Some(renderer) = &mut self.text {
+            renderer.queue_text(text, position, style);
+        }
+    }
 
-        ClampToEdge：任何在纹理外的纹理坐标将返回离纹理边缘最近的像素的颜色。
-        Repeat。当纹理坐标超过纹理的尺寸时，纹理将重复。
-        MirrorRepeat。类似于Repeat，但图像在越过边界时将翻转。
+    //添加一个粒子系统，capacity 是同时存在的粒子的最大数量。返回的粒子系统可以继续修改发射器
+    pub fn add_particles(&mut self, emitter: Emitter, capacity: u32, blend: ParticleBlend) -> &mut ParticleSystem {
+        let system = ParticleSystem::new(&self.device, emitter, capacity, blend, &self.camera_binding.bind_group_layout);
+        self.scene.particles.push(system);
+        self.scene.particles.last_mut().unwrap()
+    }
 
-        mag_filter 与 min_filter 字段描述了当采样足迹小于或大于一个纹素（Texel）时该如何处理。当场景中的贴图远离或靠近 camera 时，这两个字段的设置通常会有效果。
+    pub fn particles_mut(&mut self) -> &mut [ParticleSystem] {
+        &mut self.scene.particles
+    }
 
-        有 2 个选项:
+    pub fn clear_particles(&mut self) {
+        self.scene.particles.clear();
+    }
 
-        Linear：在每个维度中选择两个纹素，并在它们的值之间返回线性插值。
-        Nearest：返回离纹理坐标最近的纹素的值。这创造了一个从远处看比较清晰但近处有像素的图像。然而，如果你的纹理被设计成像素化的，比如像素艺术游戏，或者像 Minecraft 这样的体素游戏，这可能是符合预期的。
-        Mipmaps 是一个复杂的话题，需要在未来单独写一个章节。现在，我们可以说 mipmap_filter 的功能有点类似于 (mag/min)_filter，因为它告诉采样器如何在 mipmaps 之间混合。
+    //场景中的实例，可以添加、移除和修改，修改会在下一次 update 时上传
+    pub fn instances_mut(&mut self) -> &mut InstanceBuffer {
+        &mut self.scene.instances
+    }
 
-        其他字段使用了默认值。如果想了解字段详情，请查看 wgpu 文档。
+    pub fn render(&mut self) -> Result<(), SurfaceError> {
+        //首先，我们需要获取一个帧（Frame）对象以供渲染
+        let frame = self.target.acquire()?;
+        let format = self.target.format();
 
-        现在，我们需要用到 BindGroup 和 PipelineLayout 来将所有这些不同的资源都接入。
-        */
-        let diffuse_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
-            ..Default::default()
+        self.draw_frame(&frame.view, format, None);
+        frame.present();
+
+        Ok(())
+    }
+
+    //把同一个场景画到另一个渲染目标上，例如缩略图
+    pub fn render_to(&mut self, target: &mut dyn RenderTarget) -> Result<(), SurfaceError> {
+        let frame = target.acquire()?;
+
+        //深度附件和 HDR 纹理必须与颜色附件一样大，目标大小不同时使用临时的纹理
+        let size = target.size();
+        let textures = if size == self.size {
+            None
+        } else {
+            Some((
+                Texture::create_depth_texture(&self.device, size.width, size.height, self.depth_config.format, "render_to_depth_texture"),
+                self.hdr.create_target(&self.device, size),
+            ))
+        };
+
+        self.draw_frame(&frame.view, target.format(), textures.as_ref().map(|(depth, hdr)| (depth, hdr)));
+        frame.present();
+
+        Ok(())
+    }
+
+    //把渲染目标当前的内容读回 CPU。展示平面或不支持读回的格式返回 None。
+    pub fn capture(&self) -> Option<RgbaImage> {
+        let source = self.target.copy_source()?;
+        headless::read_texture(&self.device, &self.queue, source, self.target.size(), self.target.format())
+    }
+
+    //绘制一帧并提交。textures 是深度纹理和 HDR 纹理，为 None 时使用 State 自己的
+    fn draw_frame(&mut self, view: &TextureView, format: TextureFormat, textures: Option<(&Texture, &HdrTarget)>) {
+        let (depth_texture, hdr_target) = textures.unwrap_or((&self.depth_texture, &self.hdr_target));
+
+        //我们还需要创建一个命令编码器（CommandEncoder）来记录实际的命令发送给 GPU。
+        // 大多数现代图形框架希望命令在被发送到 GPU 之前存储在一个命令缓冲区中。命令编码器创建了一个命令缓冲区，然后我们可以将其发送给 GPU。
+        let mut encoder = self.device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("Render Encoder")
         });
 
+        //先画阴影贴图，再把场景画到 HDR 纹理上，色调映射后再画到渲染目标上
+        self.scene.draw_shadows(&mut encoder, &self.shadows);
+        self.scene.draw(&self.device, &mut encoder, &hdr_target.view, HDR_FORMAT, &depth_texture.view, SceneBindings {
+            camera: &self.camera_binding.bind_group,
+            lights: &self.lights.bind_group,
+            shadows: &self.shadows.bind_group,
+        });
+        self.hdr.process(&self.device, &self.queue, &mut encoder, hdr_target, view, format);
+
+        if self.show_depth {
+            self.depth_debug.draw(&self.device, &mut encoder, view, format, depth_texture);
+        }
+
+        //图形、精灵和文字在色调映射之后直接画到渲染目标上，不受曝光影响
+        let size = depth_texture.size();
+        let size = PhysicalSize::new(size.width, size.height);
+        let shapes = !self.painter.is_empty();
+        self.painter.prepare(&self.device, &self.queue, format, size);
+        self.sprites.prepare(&self.device, &self.queue, format, size);
+        if let Some(text) = &mut self.text {
+            text.prepare(&self.device, &self.queue, format, size);
+        }
+        if shapes || self.sprites.draw_calls() > 0 || self.text.is_some() {
+            let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("Overlay Pass"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Load,
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            self.painter.draw(&mut render_pass, format);
+            self.sprites.draw(&mut render_pass, format);
+            if let Some(text) = &self.text {
+                text.draw(&mut render_pass, format);
+            }
+        }
+
+        // submit 命令能接受任何实现了 IntoIter trait 的参数
+        self.queue.submit(std::iter::once(encoder.finish()));
+    }
+}
+
+impl Scene {
+    pub fn new(device: &Device, queue: &Queue, format: TextureFormat, depth_config: DepthConfig, layouts: SceneLayouts) -> Self {
+        //纹理
+        //从磁盘加载漫反射纹理。文件不存在或无法解码时使用一张棋盘格图片代替，这样程序仍然可以运行。
+        let diffuse_texture = Texture::from_path(device, queue, DIFFUSE_TEXTURE_PATH, &TextureOptions::default())
+            .unwrap_or_else(|e| {
+                log::warn!("{}，使用棋盘格纹理代替", e);
+                Texture::from_image(device, queue, &checkerboard_image(), Some("diffuse_texture"), &TextureOptions::default())
+                    .expect("无法创建棋盘格纹理")
+            });
+
+        //五边形没有法线贴图，使用垂直于表面的法线
+        let normal_texture = Texture::flat_normal(device, queue).expect("无法创建法线纹理");
+
+        //加载shader
+        let shader_source = include_str!("../light/shader.wgsl");
+        let shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("Shader"),
+            source: ShaderSource::Wgsl(shader_source.into()),
+        });
+
+        //也可以使用 include_wgsl! 宏作为创建 ShaderModuleDescriptor 的快捷方式（只适用于没有预处理指令的着色器，pipeline/shader.wgsl 需要先经过 shader::Preprocessor）。
+        // let shader = device.create_shader_module(include_wgsl!("../pipeline/shader.wgsl"));
+
         /*
         绑定组
-        绑定组（BindGroup）描述了一组资源以及如何通过着色器访问它们。我们先来创建一个绑定组布局（BindGroupLayout）：
+        绑定组（BindGroup）描述了一组资源以及如何通过着色器访问它们。每个绑定组都需要一个绑定组布局（BindGroupLayout），
+        它的条目与着色器中的 @group(0) @binding(0..3) 声明一一对应：漫反射纹理、它的采样器、法线贴图和它的采样器，只在片元着色器中可见。
+
+        管线布局
+        还记得在管线章节创建的管线布局（PipelineLayout）吗？现在我们终于可以使用它了! 管线布局包含一个管线可以使用的绑定组布局的列表。
+
+        这两者都不再手写，而是由 PipelineLayoutBuilder 根据着色器的声明生成（见 pipeline::reflect）。
+        相机、光源和阴影的绑定组还要给其他管线使用，它们的布局由各自的模块创建，这里直接使用，并传入创建它们的条目，由 build 检查它们与着色器是否一致。
         */
-        let texture_bind_group_layout = device.create_bind_group_layout(
-            &wgpu::BindGroupLayoutDescriptor {
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            multisampled: false,
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        // This should match the filterable field of the
-                        // corresponding Texture entry above.
-                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                        count: None,
-                    },
-                ],
-                label: Some("texture_bind_group_layout"),
-            }
-        );
+        let ReflectedLayout { pipeline_layout: render_pipeline_layout, mut bind_group_layouts } = PipelineLayoutBuilder::reflect(shader_source)
+            .expect("light/shader.wgsl 应该能被 naga 解析")
+            .label("Render Pipeline Layout")
+            .bind_group_layout(1, layouts.camera, CameraBinding::LAYOUT_ENTRIES)
+            .bind_group_layout(2, layouts.lights, Lights::LAYOUT_ENTRIES)
+            .bind_group_layout(3, layouts.shadows, ShadowMaps::LAYOUT_ENTRIES)
+            .build(device)
+            .expect("相机、光源和阴影的绑定组布局应该与 light/shader.wgsl 中 @group(1..3) 的声明一致");
+        let texture_bind_group_layout = bind_group_layouts[0].take().expect("材质的绑定组布局由着色器生成");
         /*
-        texture_bind_group_layout 有两个条目：一个是绑定到 0 资源槽的纹理，另一个是绑定到 1 资源槽的采样器。
-        这两个绑定只对由 visibility 字段指定的片元着色器可见。这个字段的可选值是 NONE、VERTEX、FRAGMENT 或 COMPUTE 的任意按位或（|）组合。
+        生成的布局中，visibility 是用到这个绑定的着色器阶段，可选值是 NONE、VERTEX、FRAGMENT 或 COMPUTE 的任意按位或（|）组合。
 
         现在使用绑定组布局（texture_bind_group_layout）来创建绑定组：
         看着这个，你可能会有一点似曾相识的感觉! 这是因为绑定组是绑定组布局的一个更具体的声明。
@@ -360,106 +669,27 @@ impl State {
                 entries: &[
                     wgpu::BindGroupEntry {
                         binding: 0,
-                        resource: wgpu::BindingResource::TextureView(&diffuse_texture_view),
+                        resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
                     },
                     wgpu::BindGroupEntry {
                         binding: 1,
-                        resource: wgpu::BindingResource::Sampler(&diffuse_sampler),
-                    }
+                        resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::TextureView(&normal_texture.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: wgpu::BindingResource::Sampler(&normal_texture.sampler),
+                    },
                 ],
                 label: Some("diffuse_bind_group"),
             }
         );
 
-        //加载shader
-        let shader = device.create_shader_module(ShaderModuleDescriptor {
-            label: Some("Shader"),
-            source: ShaderSource::Wgsl(include_str!("../texture/shader.wgsl").into()),
-        });
-
-        //也可以使用 include_wgsl! 宏作为创建 ShaderModuleDescriptor 的快捷方式。
-        // let shader = device.create_shader_module(include_wgsl!("../pipeline/shader.wgsl"));
-
-        /*
-        管线布局
-        还记得在管线章节创建的管线布局（PipelineLayout）吗？现在我们终于可以使用它了! 管线布局包含一个管线可以使用的绑定组布局的列表。
-        修改 render_pipeline_layout 以使用
-        */
-
-        let render_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
-            label: Some(" Render Pipeline Layout"),
-            bind_group_layouts: &[&texture_bind_group_layout],
-            push_constant_ranges: &[],
-        });
-
-        /*
-        可以在这里指定着色器中的哪个函数应该是入口点（ entry_point）。那是我们用 @vertex 和 @fragment 标记的函数。
-        buffers 字段告诉 wgpu 要把什么类型的顶点数据传递给顶点着色器。我们会在顶点着色器中指定顶点，所以这里先留空。下一个教程中会在此加入一些数据。
-        fragment 字段是 Option 类型，所以必须用 Some() 来包装 FragmentState 实例。如果想把颜色数据存储到 surface 就需要用到它 。
-        targets 字段告诉 wgpu 应该设置哪些颜色输出目标。目前只需设置一个输出目标。格式指定为使用 surface 的格式，并且指定混合模式为仅用新的像素数据替换旧的。
-        我们还告诉 wgpu 可写入全部 4 个颜色通道：红、蓝、绿和透明度。
-        */
-        let render_pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-
-            /*vertex: VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[],
-            },*/
-            //使用缓存区顶点数据
-            vertex: VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[Vertex::desc()],
-            },
-
-            fragment: Some(FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(ColorTargetState {
-                    format: config.format,
-                    blend: Some(BlendState::REPLACE),
-                    write_mask: ColorWrites::ALL,
-                })],
-            }),
-            /*
-            图元（primitive）字段描述了将如何解释顶点来转换为三角形。
-
-            PrimitiveTopology::TriangleList 意味着每三个顶点组成一个三角形。
-            front_face 字段告诉 wgpu 如何确定三角形的朝向。FrontFace::Ccw 指定顶点的帧缓冲区坐标（framebuffer coordinates）
-                按逆时针顺序给出的三角形为朝前（面向屏幕外）。
-            cull_mode 字段告诉 wgpu 如何做三角形剔除。CullMode::Back 指定朝后（面向屏幕内）的三角形会被剔除（不被渲染）。
-                我们会在讨论缓冲区（Buffer）时详细介绍剔除问题。
-            */
-            primitive: PrimitiveState {
-                topology: PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: FrontFace::Ccw,
-                cull_mode: Some(Face::Back),
-                // 将此设置为 Fill 以外的任何值都要需要开启 Feature::NON_FILL_POLYGON_MODE
-                polygon_mode: PolygonMode::Fill,
-                // 需要开启 Features::DEPTH_CLIP_CONTROL
-                unclipped_depth: false,
-                // 需要开启 Features::CONSERVATIVE_RASTERIZATION
-                conservative: false,
-            },
-            /*
-            我们目前没有使用深度/模板缓冲区，因此将 depth_stencil 保留为 None。以后会用到。
-            count 确定管线将使用多少个采样。多重采样是一个复杂的主题，因此不会在这里展开讨论。
-            mask 指定哪些采样应处于活动状态。目前我们使用全部采样。
-            alpha_to_coverage_enabled 与抗锯齿有关。在这里不介绍抗锯齿，因此将其保留为 false。
-            multiview 表示渲染附件可以有多少数组层。我们不会渲染到数组纹理，因此将其设置为 None。
-            */
-            depth_stencil: None,
-            multisample: MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None,
-        });
+        let mut render_pipelines = HashMap::new();
+        render_pipelines.insert(format, Self::pipeline_builder(&render_pipeline_layout, &shader, shader_source, format, depth_config).build(device));
 
         //创建顶点缓冲区
         let vertex_buffer = device.create_buffer_init(
@@ -481,61 +711,170 @@ impl State {
         //我们不需要为索引实现 Pod 和 Zeroable，因为 bytemuck 已经为 u16 等基本类型实现了它们。只需将 index_buffer 和 num_indices 添加到 State 结构体中。
         let num_indices = INDICES.len() as u32;
 
-        let num_vertices = VERTICES.len() as u32;
+        let instances = InstanceBuffer::new(device, default_instances());
 
         Self {
-            surface,
-            device,
-            queue,
-            config,
-            size,
-
-            render_pipeline,
+            shader,
+            shader_source: shader_source.to_string(),
+            render_pipeline_layout,
+            render_pipelines,
+            depth_config,
+            shader_watcher: None,
 
             vertex_buffer,
 
-            num_vertices,
-
             index_buffer,
 
             num_indices,
 
-            diffuse_bind_group
+            instances,
+
+            texture_bind_group_layout,
+            diffuse_bind_group,
+
+            model: None,
+            model_draws: Vec::new(),
+            model_instances: None,
+            model_instance_count: 0,
+            model_instance_capacity: 0,
+
+            skybox: None,
+
+            particles: Vec::new(),
         }
     }
 
-    //调整宽高
-    pub fn resize(&mut self, new_size: PhysicalSize<u32>) {
-        self.size = new_size;
-        self.config.width = new_size.width;
-        self.config.height = new_size.height;
-        self.surface.configure(&self.device, &self.config);
+    //设置模型，计算每个网格的节点变换并写入全部实例
+    fn set_model(&mut self, device: &Device, queue: &Queue, model: Model) {
+        self.model_draws = model.mesh_transforms();
+        self.model = Some(model);
+        let all = 0..self.instances.len();
+        self.model_instance_count = self.instances.len();
+        self.write_model_instances(device, queue, std::slice::from_ref(&all), true);
     }
 
-    pub fn input(&mut self, event: &WindowEvent) -> bool {
-        match event {
-            WindowEvent::CursorMoved { .. } => {
-                // self.render().expect("failed to render!");
+    //只上传上一帧以来修改过的实例，模型的实例缓冲区也只写入这些区间。
+    //移除最后一个实例时 upload 不写入任何区间，但实例数量变了，所以每帧都要调用 update_model_instances
+    fn upload_instances(&mut self, device: &Device, queue: &Queue) {
+        let changed = self.instances.upload(device, queue);
+        self.update_model_instances(device, queue, &changed);
+    }
+
+    //实例改变后更新模型的实例缓冲区。changed 是 InstanceBuffer::upload 写入的区间
+    fn update_model_instances(&mut self, device: &Device, queue: &Queue, changed: &[std::ops::Range<usize>]) {
+        if self.model.is_none() {
+            return;
+        }
+        //实例数量变化后，每个网格的那一段的起点都变了，需要全部重写
+        let count = self.instances.len();
+        if count != self.model_instance_count {
+            self.model_instance_count = count;
+            self.write_model_instances(device, queue, std::slice::from_ref(&(0..count)), true);
+        } else if !changed.is_empty() {
+            self.write_model_instances(device, queue, changed, false);
+        }
+    }
+
+    //第 draw 个网格的实例在 model_instances 中的字节范围
+    fn model_instance_range(&self, draw: usize) -> std::ops::Range<u64> {
+        let size = (self.model_instance_count * std::mem::size_of::<InstanceRaw>()) as u64;
+        draw as u64 * size..(draw as u64 + 1) * size
+    }
+
+    //把 ranges 中的实例写入每个网格的那一段。resize 为 true 时缓冲区放不下才重新创建（之后 ranges 必须是全部实例）
+    fn write_model_instances(&mut self, device: &Device, queue: &Queue, ranges: &[std::ops::Range<usize>], resize: bool) {
+        let count = self.model_instance_count;
+        let len = self.model_draws.len() * count;
+        if resize && (self.model_instances.is_none() || len > self.model_instance_capacity) {
+            self.model_instance_capacity = len.max(1);
+            self.model_instances = Some(device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Model Instance Buffer"),
+                size: (self.model_instance_capacity * std::mem::size_of::<InstanceRaw>()) as u64,
+                usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }));
+        }
+        let Some(buffer) = &self.model_instances else {
+            return;
+        };
+        let instances = self.instances.instances();
+        for (i, (_, local)) in self.model_draws.iter().enumerate() {
+            for range in ranges.iter().filter(|range| !range.is_empty()) {
+                let raw: Vec<InstanceRaw> = instances[range.clone()].iter().map(|instance| instance.to_raw_with(*local)).collect();
+                let offset = ((i * count + range.start) * std::mem::size_of::<InstanceRaw>()) as u64;
+                queue.write_buffer(buffer, offset, bytemuck::cast_slice(&raw));
             }
-            _ => {}
         }
-        false
     }
 
-    pub fn update(&mut self) {}
+    //深度设置改变后，已缓存的管线都不再可用
+    pub fn set_depth_config(&mut self, depth_config: DepthConfig) {
+        self.depth_config = depth_config;
+        self.render_pipelines.clear();
+    }
 
-    pub fn render(&mut self) -> Result<(), SurfaceError> {
-        //首先，我们需要获取一个帧（Frame）对象以供渲染
-        let output = self.surface.get_current_texture().unwrap();
+    fn pipeline_builder<'a>(layout: &'a PipelineLayout, shader: &'a ShaderModule, source: &'a str, format: TextureFormat, depth_config: DepthConfig) -> RenderPipelineBuilder<'a> {
+        //使用缓存区顶点数据，并开启深度测试。其余字段的含义见 RenderPipelineBuilder::build
+        RenderPipelineBuilder::new(shader, format)
+            .label("Render Pipeline")
+            .layout(layout)
+            .source(source)
+            .vertex_buffer(Vertex::desc())
+            .vertex_buffer(InstanceRaw::desc())
+            .depth(depth_config)
+    }
 
-        //这一行创建了一个默认设置的纹理视图（TextureView），渲染代码需要利用纹理视图来与纹理交互。
-        let view = output.texture.create_view(&TextureViewDescriptor::default());
+    //开发模式：监视磁盘上的着色器文件（绝对路径，或相对于 SHADER_ROOT），文件或它包含的文件变化后在 reload_shader 中重新预处理和编译
+    pub fn watch_shader<P: AsRef<std::path::Path>>(&mut self, path: P) {
+        self.shader_watcher = Some(ShaderWatcher::new(path));
+    }
 
-        //我们还需要创建一个命令编码器（CommandEncoder）来记录实际的命令发送给 GPU。
-        // 大多数现代图形框架希望命令在被发送到 GPU 之前存储在一个命令缓冲区中。命令编码器创建了一个命令缓冲区，然后我们可以将其发送给 GPU。
-        let mut encoder = self.device.create_command_encoder(&CommandEncoderDescriptor {
-            label: Some("Render Encoder")
-        });
+    //着色器文件变化时重新编译着色器和所有格式的管线。任何一步失败都记录错误并继续使用之前的着色器和管线
+    pub fn reload_shader(&mut self, device: &Device) {
+        let Some(watcher) = &mut self.shader_watcher else {
+            return;
+        };
+        if !watcher.poll() {
+            return;
+        }
+        let path = watcher.path().to_path_buf();
+        let result = Preprocessor::new(SHADER_ROOT).process(&path)
+            .map_err(ShaderError::from)
+            .and_then(|processed| {
+                //包含的文件也要监视。预处理失败时继续监视之前的文件
+                watcher.set_files(processed.files());
+                let shader = shader::compile(device, &processed)?;
+                let mut pipelines = HashMap::new();
+                for &format in self.render_pipelines.keys() {
+                    let builder = Self::pipeline_builder(&self.render_pipeline_layout, &shader, processed.source(), format, self.depth_config);
+                    let pipeline = shader::checked(device, || builder.try_build(device))??;
+                    pipelines.insert(format, pipeline);
+                }
+                Ok((shader, processed.source().to_string(), pipelines))
+            });
+        match result {
+            Ok((shader, source, pipelines)) => {
+                self.shader = shader;
+                self.shader_source = source;
+                self.render_pipelines = pipelines;
+                log::info!("已重新加载着色器 {}", path.display());
+            }
+            Err(e) => log::error!("{}", e),
+        }
+    }
+
+    //把场景绘制到给定的纹理视图上，调用者负责提交 encoder。format 是视图的格式，用来选择（必要时创建）对应的管线。
+    pub fn draw(&mut self, device: &Device, encoder: &mut CommandEncoder, view: &TextureView, format: TextureFormat, depth_view: &TextureView, bindings: SceneBindings) {
+        let depth_config = self.depth_config;
+        self.render_pipelines.entry(format)
+            .or_insert_with(|| Self::pipeline_builder(&self.render_pipeline_layout, &self.shader, &self.shader_source, format, depth_config).build(device));
+        if let Some(skybox) = &mut self.skybox {
+            skybox.prepare(device, format, depth_config);
+        }
+        for particles in &mut self.particles {
+            particles.prepare(device, format, depth_config);
+        }
+        let render_pipeline = &self.render_pipelines[&format];
 
         //现在可以开始执行期盼已久的清屏（用统一的颜色填充指定渲染区域）了。我们需要使用 encoder 来创建渲染通道（RenderPass）。渲染通道编码所有实际绘制的命令。
         {
@@ -554,7 +893,7 @@ impl State {
                 color_attachments: &[Some(RenderPassColorAttachment {
                     //RenderPassColorAttachment 有一个 view 字段，用于通知 wgpu 将颜色保存到什么纹理。
                     //这里我们指定使用 surface.get_current_texture() 创建的 view，这意味着向此附件（Attachment）上绘制的任何颜色都会被绘制到屏幕上。
-                    view: &view,
+                    view,
                     //resolve_target 是接收多重采样解析输出的纹理。除非启用了多重采样, 否则不需要设置它，保留为 None 即可。
                     resolve_target: None,
                     //告诉 wgpu 如何处理屏幕上的颜色（由 view 指定）
@@ -571,49 +910,203 @@ impl State {
                         store: true,
                     },
                 })],
-                depth_stencil_attachment: None,
+                //深度附件：每一帧开始时清除为最远处的深度值
+                depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                    view: depth_view,
+                    depth_ops: Some(Operations {
+                        load: LoadOp::Clear(self.depth_config.clear_value()),
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
             });
 
             //使用管线
             // 把 _render_pass 声明为可变变量并重命名为 render_pass。
             // 在 render_pass 上设置刚刚创建的管线。
             // 告诉 wgpu 用 3 个顶点和 1 个实例（实例的索引就是 @builtin(vertex_index) 的由来）来进行绘制。
-            render_pass.set_pipeline(&self.render_pipeline);
+            render_pass.set_pipeline(render_pipeline);
 
             //设置绑定组
+            render_pass.set_bind_group(1, bindings.camera, &[]);
+            render_pass.set_bind_group(2, bindings.lights, &[]);
+            render_pass.set_bind_group(3, bindings.shadows, &[]);
+            self.draw_geometry(&mut render_pass, true);
+
+            //天空盒在不透明物体之后绘制，只填充深度仍是最远处的像素
+            if let Some(skybox) = &self.skybox {
+                skybox.draw(&mut render_pass, bindings.camera, format, depth_config);
+            }
+
+            //粒子是半透明的，最后绘制
+            for particles in &self.particles {
+                particles.draw(&mut render_pass, bindings.camera, format, depth_config);
+            }
+        }
+    }
+
+    //从每个投射阴影的光源的位置绘制场景，写入阴影贴图，需要在 draw 之前调用
+    pub fn draw_shadows(&self, encoder: &mut CommandEncoder, shadows: &ShadowMaps) {
+        for layer in 0..shadows.count() {
+            let mut render_pass = shadows.begin_layer(encoder, layer);
+            self.draw_geometry(&mut render_pass, false);
+        }
+    }
+
+    //设置顶点、实例、索引缓冲区并绘制五边形或模型。materials 为 false 时不设置材质（@group(0)），用于只写入深度的渲染通道
+    fn draw_geometry<'a>(&'a self, render_pass: &mut RenderPass<'a>, materials: bool) {
+        //实例缓冲区放在第 1 个槽，对应管线中的第二个顶点缓冲区布局
+        render_pass.set_vertex_buffer(1, self.instances.buffer().slice(..));
+
+        //模型的每个网格设置自己的顶点、索引缓冲区和材质，实例缓冲区使用这个网格的那一段
+        if let Some(model) = &self.model {
+            //model_instances 按 model_instance_count 排列，它在 upload_instances 之前可能与 instances 的长度不同
+            if let Some(model_instances) = self.model_instances.as_ref().filter(|_| self.model_instance_count > 0) {
+                let count = self.model_instance_count;
+                for (i, &(mesh, _)) in self.model_draws.iter().enumerate() {
+                    render_pass.set_vertex_buffer(1, model_instances.slice(self.model_instance_range(i)));
+                    let mesh = &model.meshes[mesh];
+                    if materials {
+                        render_pass.draw_mesh_instanced(mesh, &model.materials[mesh.material], 0..count as u32);
+                    } else {
+                        render_pass.draw_mesh_geometry_instanced(mesh, 0..count as u32);
+                    }
+                }
+            }
+            return;
+        }
+        if materials {
             render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]);
+        }
 
-            //设置顶点缓冲区
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        //设置顶点缓冲区
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
 
-            /*
-            set_vertex_buffer 函数接收两个参数，第一个参数是顶点缓冲区要使用的缓冲槽索引。你可以连续设置多个顶点缓冲区。
+        /*
+        set_vertex_buffer 函数接收两个参数，第一个参数是顶点缓冲区要使用的缓冲槽索引。你可以连续设置多个顶点缓冲区。
 
-            第二个参数是要使用的缓冲区的数据片断。你可以在硬件允许的情况下在一个缓冲区中存储尽可能多的对象，所以 slice 允许我们指定使用缓冲区的哪一部分。
-            我们用 .. 来指定整个缓冲区。
+        第二个参数是要使用的缓冲区的数据片断。你可以在硬件允许的情况下在一个缓冲区中存储尽可能多的对象，所以 slice 允许我们指定使用缓冲区的哪一部分。
+        我们用 .. 来指定整个缓冲区。
 
-            在继续之前，我们需要修改 render_pass.draw() 的调用来使用 VERTICES 所指定的顶点数量。
-            在 State 中添加一个num_vertices，令其值等于 VERTICES.len()：
-            */
+        在继续之前，我们需要修改 render_pass.draw() 的调用来使用 VERTICES 所指定的顶点数量。
+        在 State 中添加一个num_vertices，令其值等于 VERTICES.len()：
+        */
 
-            //设置索引缓冲区
-            /*
-            命令名称是 set_index_buffer 而不是 set_index_buffers, 一次绘制（draw_XXX()）只能设置一个索引缓冲区。
-                但是，你可以在一个渲染通道内调用多次绘制，每次都设置不同的索引缓冲区。
-            当使用索引缓冲区时，需使用 draw_indexed 来绘制，draw 命令会忽略索引缓冲区。
-                还需确保你使用的是索引数（num_indices）而非顶点数，否则你的模型要么画错，要么因为没有足够的索引数而导致程序恐慌（panic）。
-            */
-            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        //设置索引缓冲区
+        /*
+        命令名称是 set_index_buffer 而不是 set_index_buffers, 一次绘制（draw_XXX()）只能设置一个索引缓冲区。
+            但是，你可以在一个渲染通道内调用多次绘制，每次都设置不同的索引缓冲区。
+        当使用索引缓冲区时，需使用 draw_indexed 来绘制，draw 命令会忽略索引缓冲区。
+            还需确保你使用的是索引数（num_indices）而非顶点数，否则你的模型要么画错，要么因为没有足够的索引数而导致程序恐慌（panic）。
+        */
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
 
-            // render_pass.draw(0..self.num_vertices, 0..1);
-            render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
-            //在上面的修改生效之前，还需要更新着色器，以便从顶点缓冲区中获取数据。
-        }
+        // render_pass.draw(0..self.num_vertices, 0..1);
+        render_pass.draw_indexed(0..self.num_indices, 0, self.instances.range());
+        //在上面的修改生效之前，还需要更新着色器，以便从顶点缓冲区中获取数据。
+    }
+}
 
-        // submit 命令能接受任何实现了 IntoIter trait 的参数
-        self.queue.submit(std::iter::once(encoder.finish()));
-        output.present();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        Ok(())
+    fn headless_state() -> State {
+        pollster::block_on(State::new_headless(PhysicalSize::new(64, 48)))
+    }
+
+    #[test]
+    fn headless_render_and_capture() {
+        let mut state = headless_state();
+        state.update(Duration::ZERO);
+        state.render().unwrap();
+        let image = state.capture().expect("离屏纹理可以读回");
+        assert_eq!(image.dimensions(), (64, 48));
+
+        //角落是背景，中间是实例网格中心的五边形
+        let corner = *image.get_pixel(0, 0);
+        let center = *image.get_pixel(32, 24);
+        assert_ne!(center, corner);
+        assert!(image.pixels().filter(|&&pixel| pixel != corner).count() > 64, "只画出了清屏颜色");
+    }
+
+    #[test]
+    fn removing_the_last_instance_relayouts_every_mesh() {
+        //两种材质，加载后是两个网格
+        let dir = std::env::temp_dir().join(format!("wgpu_01_model_instances_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("two.mtl"), "newmtl red\nKd 1 0 0\nnewmtl blue\nKd 0 0 1\n").unwrap();
+        std::fs::write(
+            dir.join("two.obj"),
+            "mtllib two.mtl\n\
+             v 0 0 0\nv 1 0 0\nv 0 1 0\n\
+             usemtl red\nf 1 2 3\n\
+             usemtl blue\nf 1 2 3\n",
+        )
+        .unwrap();
+
+        let mut state = headless_state();
+        let loaded = state.load_model(dir.join("two.obj"));
+        std::fs::remove_dir_all(&dir).unwrap();
+        loaded.unwrap();
+        assert_eq!(state.scene.model_draws.len(), 2);
+
+        let size = std::mem::size_of::<InstanceRaw>() as u64;
+        let count = state.scene.instances.len();
+        assert_eq!(state.scene.model_instance_count, count);
+        assert_eq!(state.scene.model_instance_range(1), count as u64 * size..2 * count as u64 * size);
+
+        //移除最后一个实例不会写入任何区间，但每个网格的那一段都要按新的数量重新排列
+        let last = count - 1;
+        state.instances_mut().remove(last);
+        state.scene.upload_instances(&state.device, &state.queue);
+        assert_eq!(state.scene.model_instance_count, last);
+        assert_eq!(state.scene.model_instance_range(0), 0..last as u64 * size);
+        assert_eq!(state.scene.model_instance_range(1), last as u64 * size..2 * last as u64 * size);
+        state.render().unwrap();
+    }
+
+    #[test]
+    fn reload_shader_keeps_the_old_pipelines_on_error() {
+        //入口文件只包含另一个文件，修改被包含的文件也会重新加载
+        let dir = std::env::temp_dir().join(format!("wgpu_01_reload_shader_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let lighting = dir.join("lighting.wgsl");
+        let scene_shader = include_str!("../light/shader.wgsl");
+        std::fs::write(dir.join("scene.wgsl"), format!("#include \"{}\"\n", lighting.display())).unwrap();
+        std::fs::write(&lighting, format!("{}\n// 版本 1\n", scene_shader)).unwrap();
+        let wait = || std::thread::sleep(shader::POLL_INTERVAL + Duration::from_millis(50));
+
+        let mut state = headless_state();
+        let ids = |scene: &Scene| (scene.shader.global_id(), scene.render_pipelines.values().map(RenderPipeline::global_id).collect::<Vec<_>>());
+        let builtin = ids(&state.scene);
+
+        //第一次检查总会重新加载，所有格式的管线都用新的着色器重新创建
+        state.watch_scene_shader(dir.join("scene.wgsl"));
+        state.scene.reload_shader(&state.device);
+        assert!(state.scene.shader_source.contains("// 版本 1"));
+        let first = ids(&state.scene);
+        assert_ne!(first.0, builtin.0);
+        assert_eq!(first.1.len(), builtin.1.len());
+        assert!(first.1.iter().all(|id| !builtin.1.contains(id)));
+
+        //语法错误：记录错误，继续使用之前的着色器和管线
+        wait();
+        std::fs::write(&lighting, format!("{}\nfn broken( {{\n", scene_shader)).unwrap();
+        state.scene.reload_shader(&state.device);
+        assert!(state.scene.shader_source.contains("// 版本 1"));
+        assert_eq!(ids(&state.scene), first);
+        state.render().unwrap();
+
+        //改正之后重新加载
+        wait();
+        std::fs::write(&lighting, format!("{}\n// 版本 2\n", scene_shader)).unwrap();
+        state.scene.reload_shader(&state.device);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(state.scene.shader_source.contains("// 版本 2"));
+        let second = ids(&state.scene);
+        assert_ne!(second.0, first.0);
+        assert!(second.1.iter().all(|id| !first.1.contains(id)));
+        state.render().unwrap();
     }
-}
\ No newline at end of file
+}
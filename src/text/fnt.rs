@@ -0,0 +1,291 @@
+/*
+BMFont 文本格式（.fnt）的解析
+BMFont 把字体中的字形排列到一张或几张纹理（页）上，.fnt 文件描述每个字形在哪一页的什么位置。每行一条语句，第一个单词是语句的类型，之后是 key=value：
+
+info face="Arial" size=32 ...
+common lineHeight=32 base=26 scaleW=256 scaleH=256 pages=1 packed=0
+page id=0 file="arial_0.png"
+char id=65 x=10 y=20 width=18 height=22 xoffset=0 yoffset=4 xadvance=19 page=0 chnl=15
+kerning first=65 second=86 amount=-2
+
+字形的 xoffset、yoffset 是从笔的位置（当前行的顶部）到字形左上角的偏移，xadvance 是画完这个字形后笔向右移动的距离。
+kerning 是某两个字符相邻时额外调整的距离，例如 AV 需要靠得更近。
+
+这里只做解析，不涉及 GPU，加载纹理见 text 模块。只支持 packed=0（字形的颜色不按通道打包）的字体。
+*/
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use super::FontError;
+
+//一个字形在页上的位置和排版参数，单位都是像素
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Glyph {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub xoffset: f32,
+    pub yoffset: f32,
+    pub xadvance: f32,
+    pub page: usize,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct FntData {
+    //行高：相邻两行顶部之间的距离
+    pub line_height: f32,
+    //基线到行顶部的距离
+    pub base: f32,
+    //页的宽和高，用来把像素坐标换算成纹理坐标
+    pub scale_w: f32,
+    pub scale_h: f32,
+    //每一页的图像路径，相对于 .fnt 文件所在的目录，下标是页的 id
+    pub pages: Vec<PathBuf>,
+    pub chars: HashMap<char, Glyph>,
+    pub kernings: HashMap<(char, char), f32>,
+}
+
+impl FntData {
+    //两个相邻字符之间的调整距离
+    pub fn kerning(&self, first: char, second: char) -> f32 {
+        self.kernings.get(&(first, second)).copied().unwrap_or(0.0)
+    }
+}
+
+//一行语句的解析上下文，用来生成带行号的错误
+struct Line<'a> {
+    path: &'a Path,
+    number: usize,
+    //key=value，值两边的引号已经去掉
+    pairs: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> Line<'a> {
+    fn error(&self, message: String) -> FontError {
+        FontError::Parse {
+            path: self.path.to_path_buf(),
+            line: self.number,
+            message,
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<&'a str> {
+        self.pairs.iter().find(|(k, _)| *k == key).map(|(_, v)| *v)
+    }
+
+    fn string(&self, key: &str) -> Result<&'a str, FontError> {
+        self.get(key).ok_or_else(|| self.error(format!("缺少 {}", key)))
+    }
+
+    fn number<T: std::str::FromStr>(&self, key: &str) -> Result<T, FontError> {
+        let value = self.string(key)?;
+        value.parse().map_err(|_| self.error(format!("{} 的值 {} 不是有效的数字", key, value)))
+    }
+
+    //可以省略的数字，省略时使用默认值
+    fn number_or<T: std::str::FromStr>(&self, key: &str, default: T) -> Result<T, FontError> {
+        match self.get(key) {
+            Some(_) => self.number(key),
+            None => Ok(default),
+        }
+    }
+
+    fn char(&self, key: &str) -> Result<char, FontError> {
+        let id: u32 = self.number(key)?;
+        char::from_u32(id).ok_or_else(|| self.error(format!("{} 不是有效的字符编码", id)))
+    }
+}
+
+//把一行拆成类型和 key=value 列表。值可以用双引号括起来，其中可以有空格
+fn split_line(text: &str) -> (&str, Vec<(&str, &str)>) {
+    let text = text.trim();
+    let (tag, mut rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+    let mut pairs = Vec::new();
+    loop {
+        rest = rest.trim_start();
+        let Some((key, after)) = rest.split_once('=') else {
+            break;
+        };
+        let (value, after) = if let Some(quoted) = after.strip_prefix('"') {
+            match quoted.split_once('"') {
+                Some((value, after)) => (value, after),
+                None => (quoted, ""),
+            }
+        } else {
+            after.split_once(char::is_whitespace).unwrap_or((after, ""))
+        };
+        pairs.push((key.trim(), value));
+        rest = after;
+    }
+    (tag, pairs)
+}
+
+//解析 .fnt 文件的内容，path 只用于错误信息
+pub fn parse(path: &Path, source: &str) -> Result<FntData, FontError> {
+    let mut data = FntData::default();
+    let mut has_common = false;
+
+    for (index, text) in source.lines().enumerate() {
+        let (tag, pairs) = split_line(text);
+        let line = Line {
+            path,
+            number: index + 1,
+            pairs,
+        };
+        match tag {
+            "common" => {
+                data.line_height = line.number("lineHeight")?;
+                data.base = line.number("base")?;
+                data.scale_w = line.number("scaleW")?;
+                data.scale_h = line.number("scaleH")?;
+                if line.number_or::<u32>("packed", 0)? != 0 {
+                    return Err(line.error("不支持 packed=1 的字体".to_string()));
+                }
+                has_common = true;
+            }
+            "page" => {
+                let id: usize = line.number("id")?;
+                let file = line.string("file")?;
+                if data.pages.len() <= id {
+                    data.pages.resize(id + 1, PathBuf::new());
+                }
+                data.pages[id] = PathBuf::from(file);
+            }
+            //有些工具用 id=-1 表示缺失字符时显示的字形，这里不使用
+            "char" if line.get("id") == Some("-1") => {}
+            "char" => {
+                let id = line.char("id")?;
+                data.chars.insert(id, Glyph {
+                    x: line.number("x")?,
+                    y: line.number("y")?,
+                    width: line.number("width")?,
+                    height: line.number("height")?,
+                    xoffset: line.number("xoffset")?,
+                    yoffset: line.number("yoffset")?,
+                    xadvance: line.number("xadvance")?,
+                    page: line.number_or("page", 0)?,
+                });
+            }
+            "kerning" => {
+                data.kernings.insert((line.char("first")?, line.char("second")?), line.number("amount")?);
+            }
+            //info、chars、kernings 和空行不影响排版
+            _ => {}
+        }
+    }
+
+    if !has_common {
+        return Err(FontError::Invalid {
+            path: path.to_path_buf(),
+            message: "缺少 common 语句".to_string(),
+        });
+    }
+    if data.scale_w <= 0.0 || data.scale_h <= 0.0 {
+        return Err(FontError::Invalid {
+            path: path.to_path_buf(),
+            message: format!("页的大小 {}x{} 无效", data.scale_w, data.scale_h),
+        });
+    }
+    if let Some(page) = data.pages.iter().position(|page| page.as_os_str().is_empty()) {
+        return Err(FontError::Invalid {
+            path: path.to_path_buf(),
+            message: format!("缺少第 {} 页", page),
+        });
+    }
+    if let Some((c, glyph)) = data.chars.iter().find(|(_, glyph)| glyph.page >= data.pages.len()) {
+        return Err(FontError::Invalid {
+            path: path.to_path_buf(),
+            message: format!("字符 {:?} 使用的第 {} 页不存在", c, glyph.page),
+        });
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FONT: &str = r#"info face="My Font" size=12 bold=0
+common lineHeight=10 base=8 scaleW=64 scaleH=32 pages=1 packed=0
+page id=0 file="my font_0.png"
+chars count=3
+char id=-1 x=0 y=0 width=1 height=1 xoffset=0 yoffset=0 xadvance=1 page=0
+char id=97 x=0 y=0 width=4 height=6 xoffset=1 yoffset=2 xadvance=5 page=0 chnl=15
+char id=32 x=0 y=0 width=0 height=0 xoffset=0 yoffset=0 xadvance=3
+kernings count=1
+kerning first=97 second=32 amount=-1
+"#;
+
+    fn parse_str(source: &str) -> Result<FntData, FontError> {
+        parse(Path::new("test.fnt"), source)
+    }
+
+    #[test]
+    fn parse_font() {
+        let font = parse_str(FONT).unwrap();
+        assert_eq!(font.line_height, 10.0);
+        assert_eq!(font.base, 8.0);
+        assert_eq!([font.scale_w, font.scale_h], [64.0, 32.0]);
+        //引号中的空格是文件名的一部分
+        assert_eq!(font.pages, [PathBuf::from("my font_0.png")]);
+        //id=-1 的字形被忽略
+        assert_eq!(font.chars.len(), 2);
+        assert_eq!(font.chars[&'a'], Glyph {
+            x: 0.0,
+            y: 0.0,
+            width: 4.0,
+            height: 6.0,
+            xoffset: 1.0,
+            yoffset: 2.0,
+            xadvance: 5.0,
+            page: 0,
+        });
+        //省略 page 时是第 0 页
+        assert_eq!(font.chars[&' '].page, 0);
+        assert_eq!(font.kerning('a', ' '), -1.0);
+        assert_eq!(font.kerning(' ', 'a'), 0.0);
+    }
+
+    #[test]
+    fn split_quoted_values() {
+        let (tag, pairs) = split_line(r#"  info face="Open Sans" size=-12 charset="""#);
+        assert_eq!(tag, "info");
+        assert_eq!(pairs, [("face", "Open Sans"), ("size", "-12"), ("charset", "")]);
+    }
+
+    #[test]
+    fn missing_page() {
+        //只有第 1 页，没有第 0 页
+        let source = "common lineHeight=10 base=8 scaleW=64 scaleH=32 pages=2\npage id=1 file=\"b.png\"\n";
+        match parse_str(source) {
+            Err(FontError::Invalid { message, .. }) => assert!(message.contains("第 0 页"), "{}", message),
+            other => panic!("应该缺少第 0 页: {:?}", other),
+        }
+
+        //字符使用了不存在的页
+        let source = "common lineHeight=10 base=8 scaleW=64 scaleH=32 pages=1\npage id=0 file=a.png\n\
+                      char id=65 x=0 y=0 width=1 height=1 xoffset=0 yoffset=0 xadvance=1 page=1\n";
+        match parse_str(source) {
+            Err(FontError::Invalid { message, .. }) => assert!(message.contains("第 1 页"), "{}", message),
+            other => panic!("应该是不存在的页: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn errors_report_the_line() {
+        let source = "common lineHeight=10 base=8 scaleW=64 scaleH=32 pages=1\npage id=0 file=a.png\n\
+                      char id=65 x=0 y=0 width=wide height=1 xoffset=0 yoffset=0 xadvance=1\n";
+        match parse_str(source) {
+            Err(FontError::Parse { line, message, .. }) => {
+                assert_eq!(line, 3);
+                assert!(message.contains("width"), "{}", message);
+            }
+            other => panic!("应该是解析错误: {:?}", other),
+        }
+
+        let source = "common lineHeight=10 base=8 scaleW=64 scaleH=32 packed=1\n";
+        assert!(matches!(parse_str(source), Err(FontError::Parse { line: 1, .. })));
+        assert!(matches!(parse_str("page id=0 file=a.png\n"), Err(FontError::Invalid { .. })));
+    }
+}
@@ -0,0 +1,237 @@
+/*
+文字排版
+把字符串按字体的字形摆放成一个个矩形（字形四边形），坐标是屏幕像素，原点在左上角，y 轴向下。
+
+排版按行进行：遇到 '\n' 换行；设置了 max_width 时，一行超过这个宽度就在最后一个空格处换行（单词本身比 max_width 长时不拆开）。
+每个字符的笔位置加上与前一个字符的 kerning，画完后前进 xadvance。一行排完后得到它的宽度，再按对齐方式整体平移。
+*/
+use super::fnt::{FntData, Glyph};
+
+//每一行相对于 position.x 的对齐方式
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Align {
+    //position.x 是行的左边
+    #[default]
+    Left,
+    //position.x 是行的中点
+    Center,
+    //position.x 是行的右边
+    Right,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TextStyle {
+    //字体中的像素乘上 scale 是屏幕上的像素
+    pub scale: f32,
+    //与页纹理的颜色相乘
+    pub color: [f32; 4],
+    pub align: Align,
+    //一行的最大宽度（屏幕像素），None 表示只在 '\n' 处换行
+    pub max_width: Option<f32>,
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            color: [1.0; 4],
+            align: Align::Left,
+            max_width: None,
+        }
+    }
+}
+
+//一个字形在屏幕上的矩形和在页上的纹理坐标
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GlyphQuad {
+    pub page: usize,
+    pub min: [f32; 2],
+    pub max: [f32; 2],
+    pub uv_min: [f32; 2],
+    pub uv_max: [f32; 2],
+    pub color: [f32; 4],
+}
+
+//字体中没有的字符用 '?' 代替，连 '?' 也没有时跳过
+fn glyph(font: &FntData, c: char) -> Option<&Glyph> {
+    font.chars.get(&c).or_else(|| font.chars.get(&'?'))
+}
+
+//一行中每个字符的笔位置（字体像素，从 0 开始）和这一行的宽度
+fn pen_positions(font: &FntData, line: &[char]) -> (Vec<f32>, f32) {
+    let mut positions = Vec::with_capacity(line.len());
+    let mut pen = 0.0;
+    let mut previous = None;
+    for &c in line {
+        if let Some(previous) = previous {
+            pen += font.kerning(previous, c);
+        }
+        positions.push(pen);
+        pen += glyph(font, c).map_or(0.0, |glyph| glyph.xadvance);
+        previous = Some(c);
+    }
+    (positions, pen)
+}
+
+//按 '\n' 和 max_width（字体像素）把文字拆成行，换行处的空格被丢掉
+fn break_lines(font: &FntData, text: &str, max_width: Option<f32>) -> Vec<Vec<char>> {
+    let mut lines = Vec::new();
+    for paragraph in text.split('\n') {
+        let mut line: Vec<char> = paragraph.chars().filter(|&c| c != '\r').collect();
+        let Some(max_width) = max_width else {
+            lines.push(line);
+            continue;
+        };
+        loop {
+            let (positions, width) = pen_positions(font, &line);
+            if width <= max_width {
+                break;
+            }
+            //第一个超出宽度的字符之前的最后一个空格。超出宽度的是空格本身时就在它那里换行，换行处的空格不占宽度
+            let overflow = line.iter()
+                .zip(&positions)
+                .position(|(&c, &pen)| pen + glyph(font, c).map_or(0.0, |glyph| glyph.xadvance) > max_width)
+                .unwrap_or(line.len());
+            let Some(space) = line[..(overflow + 1).min(line.len())].iter().rposition(|&c| c == ' ').or_else(|| {
+                //第一个单词就超出了宽度，在它后面的空格处换行
+                line[overflow..].iter().position(|&c| c == ' ').map(|i| i + overflow)
+            }) else {
+                break;
+            };
+            let rest = line.split_off(space + 1);
+            line.pop();
+            lines.push(line);
+            line = rest;
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+//排版后整段文字的宽和高（屏幕像素）
+pub fn measure(font: &FntData, text: &str, style: &TextStyle) -> [f32; 2] {
+    let lines = break_lines(font, text, style.max_width.map(|width| width / style.scale));
+    let width = lines.iter()
+        .map(|line| pen_positions(font, line).1)
+        .fold(0.0, f32::max);
+    [width * style.scale, lines.len() as f32 * font.line_height * style.scale]
+}
+
+//排版 text，position 是第一行的顶部（x 的含义取决于对齐方式）
+pub fn layout(font: &FntData, text: &str, position: [f32; 2], style: &TextStyle) -> Vec<GlyphQuad> {
+    let scale = style.scale;
+    let mut quads = Vec::new();
+    let lines = break_lines(font, text, style.max_width.map(|width| width / scale));
+    for (row, line) in lines.iter().enumerate() {
+        let (positions, width) = pen_positions(font, line);
+        let left = match style.align {
+            Align::Left => position[0],
+            Align::Center => position[0] - width * scale * 0.5,
+            Align::Right => position[0] - width * scale,
+        };
+        let top = position[1] + row as f32 * font.line_height * scale;
+        for (&c, &pen) in line.iter().zip(&positions) {
+            let Some(glyph) = glyph(font, c) else {
+                continue;
+            };
+            //空格这类没有像素的字形只前进，不需要画
+            if glyph.width <= 0.0 || glyph.height <= 0.0 {
+                continue;
+            }
+            let min = [left + (pen + glyph.xoffset) * scale, top + glyph.yoffset * scale];
+            quads.push(GlyphQuad {
+                page: glyph.page,
+                min,
+                max: [min[0] + glyph.width * scale, min[1] + glyph.height * scale],
+                uv_min: [glyph.x / font.scale_w, glyph.y / font.scale_h],
+                uv_max: [(glyph.x + glyph.width) / font.scale_w, (glyph.y + glyph.height) / font.scale_h],
+                color: style.color,
+            });
+        }
+    }
+    quads
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    //a、b 宽 4 前进 5，W 宽 8 前进 8，空格前进 3，'?' 宽 2 前进 3；ab 之间的 kerning 是 -1
+    const FONT: &str = "common lineHeight=10 base=8 scaleW=64 scaleH=32 pages=1
+page id=0 file=font.png
+char id=97 x=0 y=0 width=4 height=6 xoffset=1 yoffset=2 xadvance=5
+char id=98 x=8 y=0 width=4 height=6 xoffset=0 yoffset=2 xadvance=5
+char id=87 x=16 y=16 width=8 height=8 xoffset=0 yoffset=0 xadvance=8
+char id=32 x=0 y=0 width=0 height=0 xoffset=0 yoffset=0 xadvance=3
+char id=63 x=32 y=0 width=2 height=8 xoffset=0 yoffset=0 xadvance=3
+kerning first=97 second=98 amount=-1
+";
+
+    fn font() -> FntData {
+        crate::text::fnt::parse(Path::new("test.fnt"), FONT).unwrap()
+    }
+
+    fn lines(font: &FntData, text: &str, max_width: Option<f32>) -> Vec<String> {
+        break_lines(font, text, max_width).into_iter().map(|line| line.into_iter().collect()).collect()
+    }
+
+    #[test]
+    fn break_at_newlines_and_spaces() {
+        let font = font();
+        assert_eq!(lines(&font, "aa bb\r\nb", None), ["aa bb", "b"]);
+        //"aa " 宽 13，再加上 b 就超过了 15，在空格处换行并丢掉空格
+        assert_eq!(lines(&font, "aa bb", Some(15.0)), ["aa", "bb"]);
+        assert_eq!(lines(&font, "aa bb", Some(23.0)), ["aa bb"]);
+        //第二个空格本身超出了宽度，在它那里换行
+        assert_eq!(lines(&font, "a b a b", Some(13.0)), ["a b", "a b"]);
+    }
+
+    #[test]
+    fn first_word_wider_than_max_width() {
+        let font = font();
+        //单词不拆开，在它后面的空格处换行
+        assert_eq!(lines(&font, "WWW a", Some(10.0)), ["WWW", "a"]);
+        assert_eq!(lines(&font, "WWW", Some(10.0)), ["WWW"]);
+    }
+
+    #[test]
+    fn kerning_and_missing_glyphs() {
+        let font = font();
+        let style = TextStyle::default();
+        let quads = layout(&font, "ab z", [10.0, 20.0], &style);
+        //空格不画，没有的 z 用 '?' 代替
+        assert_eq!(quads.len(), 3);
+        //a 的笔位置是 0，加上 xoffset
+        assert_eq!(quads[0].min, [11.0, 22.0]);
+        assert_eq!(quads[0].max, [15.0, 28.0]);
+        assert_eq!(quads[0].uv_min, [0.0, 0.0]);
+        assert_eq!(quads[0].uv_max, [4.0 / 64.0, 6.0 / 32.0]);
+        //b 在 a 之后前进 5，kerning 再拉近 1
+        assert_eq!(quads[1].min, [14.0, 22.0]);
+        assert_eq!(quads[1].uv_min, [8.0 / 64.0, 0.0]);
+        //'?' 在 a(5) + kerning(-1) + b(5) + 空格(3) 之后
+        assert_eq!(quads[2].min, [22.0, 20.0]);
+        assert_eq!(quads[2].uv_min, [0.5, 0.0]);
+    }
+
+    #[test]
+    fn alignment_and_scale() {
+        let font = font();
+        //"ab" 宽 9 个字体像素，放大 2 倍后是 18
+        let style = |align| TextStyle {
+            scale: 2.0,
+            align,
+            ..Default::default()
+        };
+        let left = |align| layout(&font, "ab\nW", [100.0, 0.0], &style(align)).iter().map(|q| q.min[0]).collect::<Vec<_>>();
+        assert_eq!(left(Align::Left), [102.0, 108.0, 100.0]);
+        assert_eq!(left(Align::Center), [93.0, 99.0, 92.0]);
+        assert_eq!(left(Align::Right), [84.0, 90.0, 84.0]);
+
+        //第二行在一个行高之下
+        let quads = layout(&font, "ab\nW", [0.0, 0.0], &style(Align::Left));
+        assert_eq!(quads[2].min[1], 20.0);
+        assert_eq!(measure(&font, "ab\nW", &style(Align::Left)), [18.0, 40.0]);
+    }
+}
@@ -0,0 +1,323 @@
+/*
+位图字体文字
+字体是 BMFont 生成的 .fnt 文件和若干张页纹理，解析见 fnt 子模块，排版见 layout 子模块。
+
+TextRenderer 收集一帧中要画的所有文字，排版成字形四边形后按页排序，写入一个动态的顶点缓冲区（不够大时按 2 的幂扩大），
+每一页只需要一次绘制调用。文字画在屏幕空间中，不受相机影响，适合 FPS 计数器、标签和调试信息。
+
+用法：每一帧先用 queue_text 添加文字，在渲染通道开始之前调用 prepare，然后在渲染通道中调用 draw。prepare 之后已添加的文字会被清空。
+*/
+pub mod fnt;
+pub mod layout;
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use winit::dpi::PhysicalSize;
+use wgpu::{BindGroup, BlendState, Buffer, BufferUsages, Device, PipelineLayout, Queue, RenderPass, RenderPipeline, ShaderModule, ShaderStages, TextureFormat};
+
+use crate::buffer::VertexLayout;
+use crate::pipeline::RenderPipelineBuilder;
+use crate::texture::{Texture, TextureError, TextureOptions};
+
+pub use fnt::{FntData, Glyph};
+pub use layout::{Align, GlyphQuad, TextStyle};
+
+//加载字体时可能出现的错误
+#[derive(Debug)]
+pub enum FontError {
+    //读取文件失败
+    Io { path: PathBuf, source: std::io::Error },
+    //.fnt 文件内容有误，line 从 1 开始
+    Parse { path: PathBuf, line: usize, message: String },
+    //.fnt 文件整体不完整，例如缺少 common 语句或某一页
+    Invalid { path: PathBuf, message: String },
+    //加载页纹理失败
+    Texture(TextureError),
+}
+
+impl fmt::Display for FontError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FontError::Io { path, source } => write!(f, "无法读取字体文件 {}: {}", path.display(), source),
+            FontError::Parse { path, line, message } => write!(f, "{}:{}: {}", path.display(), line, message),
+            FontError::Invalid { path, message } => write!(f, "{}: {}", path.display(), message),
+            FontError::Texture(e) => write!(f, "无法加载字体的页纹理: {}", e),
+        }
+    }
+}
+
+impl Error for FontError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            FontError::Io { source, .. } => Some(source),
+            FontError::Parse { .. } | FontError::Invalid { .. } => None,
+            FontError::Texture(e) => Some(e),
+        }
+    }
+}
+
+impl From<TextureError> for FontError {
+    fn from(e: TextureError) -> Self {
+        FontError::Texture(e)
+    }
+}
+
+//字体：排版数据和每一页的纹理
+pub struct Font {
+    pub data: FntData,
+    pub pages: Vec<Texture>,
+}
+
+impl Font {
+    //加载 .fnt 文件，页纹理的路径相对于 .fnt 文件所在的目录
+    pub fn load<P: AsRef<Path>>(device: &Device, queue: &Queue, path: P) -> Result<Self, FontError> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path).map_err(|source| FontError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let data = fnt::parse(path, &source)?;
+
+        //字形紧挨在一起，mip 会把相邻的字形混进来，文字通常也按原大小绘制，所以不生成 mip
+        let options = TextureOptions {
+            generate_mipmaps: false,
+            ..Default::default()
+        };
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let pages = data.pages.iter()
+            .map(|page| Texture::from_path(device, queue, dir.join(page), &options))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { data, pages })
+    }
+}
+
+//文字的顶点：屏幕像素坐标、页上的纹理坐标和颜色
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, VertexLayout)]
+pub struct TextVertex {
+    position: [f32; 2],
+    tex_coords: [f32; 2],
+    color: [f32; 4],
+}
+
+//每个字形两个三角形，不使用索引
+const VERTICES_PER_GLYPH: usize = 6;
+
+pub struct TextRenderer {
+    font: Font,
+    //每一页的纹理绑定组，对应着色器中的 @group(0)
+    page_bind_groups: Vec<BindGroup>,
+    //屏幕大小，对应着色器中的 @group(1)
+    screen_buffer: Buffer,
+    screen_bind_group: BindGroup,
+
+    shader: ShaderModule,
+    pipeline_layout: PipelineLayout,
+    pipelines: HashMap<TextureFormat, RenderPipeline>,
+
+    //这一帧已添加、还没有上传的字形
+    quads: Vec<GlyphQuad>,
+    vertex_buffer: Buffer,
+    //顶点缓冲区能容纳的顶点数量
+    vertex_capacity: usize,
+    //上一次 prepare 的结果：每一页在顶点缓冲区中的范围
+    draws: Vec<(usize, Range<u32>)>,
+}
+
+impl TextRenderer {
+    pub fn new(device: &Device, font: Font) -> Self {
+        let page_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Text Page Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let page_bind_groups = font.pages.iter()
+            .map(|page| device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Text Page Bind Group"),
+                layout: &page_bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&page.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&page.sampler),
+                    },
+                ],
+            }))
+            .collect();
+
+        let screen_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Text Screen Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let screen_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Text Screen Buffer"),
+            size: std::mem::size_of::<[f32; 4]>() as u64,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let screen_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Text Screen Bind Group"),
+            layout: &screen_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: screen_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("text.wgsl"));
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Text Pipeline Layout"),
+            bind_group_layouts: &[&page_bind_group_layout, &screen_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let vertex_capacity = 256 * VERTICES_PER_GLYPH;
+        let vertex_buffer = Self::create_vertex_buffer(device, vertex_capacity);
+
+        Self {
+            font,
+            page_bind_groups,
+            screen_buffer,
+            screen_bind_group,
+            shader,
+            pipeline_layout,
+            pipelines: HashMap::new(),
+            quads: Vec::new(),
+            vertex_buffer,
+            vertex_capacity,
+            draws: Vec::new(),
+        }
+    }
+
+    fn create_vertex_buffer(device: &Device, capacity: usize) -> Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Text Vertex Buffer"),
+            size: (capacity * std::mem::size_of::<TextVertex>()) as u64,
+            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    pub fn font(&self) -> &Font {
+        &self.font
+    }
+
+    //排版后的宽和高（像素），可以用来在文字后面画背景或计算位置
+    pub fn measure(&self, text: &str, style: &TextStyle) -> [f32; 2] {
+        layout::measure(&self.font.data, text, style)
+    }
+
+    //添加一段文字，position 是第一行顶部的像素坐标（x 的含义取决于 style.align）
+    pub fn queue_text(&mut self, text: &str, position: [f32; 2], style: &TextStyle) {
+        self.quads.extend(layout::layout(&self.font.data, text, position, style));
+    }
+
+    //把已添加的文字上传到顶点缓冲区并准备管线，需要在渲染通道开始之前调用。size 是渲染目标的大小
+    pub fn prepare(&mut self, device: &Device, queue: &Queue, format: TextureFormat, size: PhysicalSize<u32>) {
+        let shader = &self.shader;
+        let pipeline_layout = &self.pipeline_layout;
+        self.pipelines.entry(format).or_insert_with(|| {
+            RenderPipelineBuilder::new(shader, format)
+                .label("Text Pipeline")
+                .layout(pipeline_layout)
+                .source(include_str!("text.wgsl"))
+                .vertex_buffer(TextVertex::desc())
+                .blend(Some(BlendState::ALPHA_BLENDING))
+                .cull_mode(None)
+                .build(device)
+        });
+        queue.write_buffer(&self.screen_buffer, 0, bytemuck::cast_slice(&[size.width as f32, size.height as f32, 0.0, 0.0]));
+
+        //同一页的字形放在一起，每一页只画一次。稳定排序保持同一页中添加的顺序
+        let mut quads = std::mem::take(&mut self.quads);
+        quads.sort_by_key(|quad| quad.page);
+        let mut vertices = Vec::with_capacity(quads.len() * VERTICES_PER_GLYPH);
+        self.draws.clear();
+        for quad in &quads {
+            let start = vertices.len() as u32;
+            let [x0, y0] = quad.min;
+            let [x1, y1] = quad.max;
+            let [u0, v0] = quad.uv_min;
+            let [u1, v1] = quad.uv_max;
+            let color = quad.color;
+            vertices.extend_from_slice(&[
+                TextVertex { position: [x0, y0], tex_coords: [u0, v0], color },
+                TextVertex { position: [x0, y1], tex_coords: [u0, v1], color },
+                TextVertex { position: [x1, y1], tex_coords: [u1, v1], color },
+                TextVertex { position: [x0, y0], tex_coords: [u0, v0], color },
+                TextVertex { position: [x1, y1], tex_coords: [u1, v1], color },
+                TextVertex { position: [x1, y0], tex_coords: [u1, v0], color },
+            ]);
+            let end = vertices.len() as u32;
+            match self.draws.last_mut() {
+                Some((page, range)) if *page == quad.page => range.end = end,
+                _ => self.draws.push((quad.page, start..end)),
+            }
+        }
+        //复用 quads 的内存
+        quads.clear();
+        self.quads = quads;
+
+        if vertices.len() > self.vertex_capacity {
+            self.vertex_capacity = vertices.len().next_power_of_two();
+            self.vertex_buffer = Self::create_vertex_buffer(device, self.vertex_capacity);
+        }
+        if !vertices.is_empty() {
+            queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+        }
+    }
+
+    //绘制上一次 prepare 的文字，每一页一次绘制调用
+    pub fn draw<'a>(&'a self, render_pass: &mut RenderPass<'a>, format: TextureFormat) {
+        if self.draws.is_empty() {
+            return;
+        }
+        let Some(pipeline) = self.pipelines.get(&format) else {
+            log::warn!("文字的管线还没有创建（{:?}），跳过绘制", format);
+            return;
+        };
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(1, &self.screen_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        for (page, range) in &self.draws {
+            render_pass.set_bind_group(0, &self.page_bind_groups[*page], &[]);
+            render_pass.draw(range.clone(), 0..1);
+        }
+    }
+}
@@ -0,0 +1,43 @@
+//屏幕空间的文字：顶点坐标是像素，原点在左上角
+
+struct Screen {
+    //(宽, 高, _, _)
+    size: vec4f
+};
+
+@group(0) @binding(0)
+var t_page: texture_2d<f32>;
+
+@group(0) @binding(1)
+var s_page: sampler;
+
+@group(1) @binding(0)
+var<uniform> screen: Screen;
+
+struct VertexInput {
+    @location(0) position: vec2f,
+    @location(1) tex_coords: vec2f,
+    @location(2) color: vec4f
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4f,
+    @location(0) tex_coords: vec2f,
+    @location(1) color: vec4f
+};
+
+@vertex
+fn vs_main(model: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    //像素坐标转换为标准化设备坐标，y 轴翻转
+    let ndc = model.position / screen.size.xy * vec2f(2.0, -2.0) + vec2f(-1.0, 1.0);
+    out.clip_position = vec4f(ndc, 0.0, 1.0);
+    out.tex_coords = model.tex_coords;
+    out.color = model.color;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4f {
+    return textureSample(t_page, s_page, in.tex_coords) * in.color;
+}
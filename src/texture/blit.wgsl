@@ -0,0 +1,31 @@
+
+//生成 mipmap 用的着色器：把上一级 mip 通过线性过滤采样后画到下一级上。
+//每一级的宽高是上一级的一半，所以线性过滤正好是对 2x2 个纹素取平均。
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4f,
+    @location(0) tex_coords: vec2f
+};
+
+//不需要顶点缓冲区：用 3 个顶点画一个覆盖整个屏幕的大三角形
+@vertex
+fn vs_main(
+    @builtin(vertex_index) in_vertex_index: u32
+) -> VertexOutput {
+    var out: VertexOutput;
+    let uv = vec2f(f32((in_vertex_index << 1u) & 2u), f32(in_vertex_index & 2u));
+    out.clip_position = vec4f(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.tex_coords = uv;
+    return out;
+}
+
+@group(0) @binding(0)
+var t_source: texture_2d<f32>;
+
+@group(0) @binding(1)
+var s_source: sampler;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4f {
+    return textureSample(t_source, s_source, in.tex_coords);
+}
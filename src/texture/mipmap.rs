@@ -0,0 +1,274 @@
+/*
+Mipmap
+当纹理在屏幕上被缩小时，一个像素会覆盖很多个纹素，只采样其中一个就会产生闪烁（摩尔纹）。
+Mipmap 是一串逐级缩小一半的图像，采样器根据缩小的程度选择合适的级别，并用 mipmap_filter 在相邻级别之间混合。
+
+这里提供两种生成方式：
+GPU：每一级用一个渲染通道，把上一级画到下一级的视图上（blit），需要纹理格式可以作为渲染附件。
+     某些后端（例如 OpenGL）在同一个纹理上采样一级、同时渲染另一级时会形成反馈回路，读到的全是 0，
+     所以先把上一级复制到一个临时纹理，再从临时纹理采样。
+CPU：用 image 包逐级缩小图像，再用 write_texture 写入每一级，用于不能作为渲染附件的格式。
+     sRGB 纹理先转换到线性空间再缩小，缩小后再转换回 sRGB，与 GPU 通过 sRGB 视图采样和写入的结果一致。
+     Texture 目前只接受 Rgba8Unorm 和 Rgba8UnormSrgb，它们总是可以作为渲染附件，所以 CPU 生成只在测试中与 GPU 生成对照使用，
+     留给以后支持的不能渲染的格式。
+
+GPU 生成用的着色器、管线和采样器在每个线程中按设备和格式缓存，加载很多纹理时不会为每个纹理重新创建。
+*/
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use image::{imageops::FilterType, Rgba32FImage, RgbaImage};
+use wgpu::{BindGroupLayout, Device, Id, Queue, RenderPipeline, Sampler, ShaderModule, Texture, TextureFormat, TextureUsages};
+
+//完整的 mip 链的级数：一直缩小到 1x1
+pub fn mip_level_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+//GPU 生成 mipmap 需要把每一级作为渲染附件
+pub fn can_render_to(device: &Device, format: TextureFormat) -> bool {
+    format
+        .guaranteed_format_features(device.features())
+        .allowed_usages
+        .contains(TextureUsages::RENDER_ATTACHMENT)
+}
+
+//纹理在 GPU 上生成 mipmap 所需的用途
+pub const GPU_USAGES: TextureUsages = TextureUsages::RENDER_ATTACHMENT.union(TextureUsages::COPY_SRC);
+
+//用渲染通道逐级生成 mipmap。纹理的第 0 级必须已经写入数据，并且带有 GPU_USAGES 中的用途。
+pub fn generate_gpu(device: &Device, queue: &Queue, texture: &Texture) {
+    let mip_count = texture.mip_level_count();
+    if mip_count < 2 {
+        return;
+    }
+
+    let format = texture.format();
+    BLITTERS.with(|blitters| {
+        let mut blitters = blitters.borrow_mut();
+        let blitter = blitters.entry(device.global_id()).or_insert_with(|| Blitter::new(device));
+        blitter.generate(device, queue, texture, format, mip_count);
+    });
+}
+
+thread_local! {
+    //每个设备的 Blitter。设备很少超过一个，所以不处理设备销毁后的清理
+    static BLITTERS: RefCell<HashMap<Id<Device>, Blitter>> = RefCell::new(HashMap::new());
+}
+
+//GPU 生成 mipmap 需要的着色器、采样器和每种格式的管线
+struct Blitter {
+    shader: ShaderModule,
+    sampler: Sampler,
+    pipelines: HashMap<TextureFormat, (RenderPipeline, BindGroupLayout)>,
+}
+
+impl Blitter {
+    fn new(device: &Device) -> Self {
+        Self {
+            shader: device.create_shader_module(wgpu::include_wgsl!("blit.wgsl")),
+            sampler: device.create_sampler(&wgpu::SamplerDescriptor {
+                label: Some("Mipmap Blit Sampler"),
+                address_mode_u: wgpu::AddressMode::ClampToEdge,
+                address_mode_v: wgpu::AddressMode::ClampToEdge,
+                address_mode_w: wgpu::AddressMode::ClampToEdge,
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                mipmap_filter: wgpu::FilterMode::Nearest,
+                ..Default::default()
+            }),
+            pipelines: HashMap::new(),
+        }
+    }
+
+    fn pipeline<'a>(pipelines: &'a mut HashMap<TextureFormat, (RenderPipeline, BindGroupLayout)>, shader: &ShaderModule, device: &Device, format: TextureFormat) -> &'a (RenderPipeline, BindGroupLayout) {
+        pipelines.entry(format).or_insert_with(|| {
+            //layout 为 None 时，wgpu 会根据着色器自动推导出绑定组布局
+            let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("Mipmap Blit Pipeline"),
+                layout: None,
+                vertex: wgpu::VertexState {
+                    module: shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: shader,
+                    entry_point: "fs_main",
+                    targets: &[Some(format.into())],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    ..Default::default()
+                },
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            });
+            let bind_group_layout = pipeline.get_bind_group_layout(0);
+            (pipeline, bind_group_layout)
+        })
+    }
+
+    fn generate(&mut self, device: &Device, queue: &Queue, texture: &Texture, format: TextureFormat, mip_count: u32) {
+        let (pipeline, bind_group_layout) = Self::pipeline(&mut self.pipelines, &self.shader, device, format);
+        let sampler = &self.sampler;
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Mipmap Encoder"),
+        });
+
+        for target_mip in 1..mip_count {
+            //把上一级复制到临时纹理
+            let source_size = texture.size().mip_level_size(target_mip - 1, texture.dimension());
+            let source = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("Mipmap Source Texture"),
+                size: source_size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+                view_formats: &[],
+            });
+            encoder.copy_texture_to_texture(
+                wgpu::ImageCopyTexture {
+                    texture,
+                    mip_level: target_mip - 1,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                source.as_image_copy(),
+                source_size,
+            );
+            let source_view = source.create_view(&wgpu::TextureViewDescriptor::default());
+
+            //只包含目标这一级的视图
+            let target_view = texture.create_view(&wgpu::TextureViewDescriptor {
+                label: Some("Mipmap Level View"),
+                base_mip_level: target_mip,
+                mip_level_count: Some(1),
+                ..Default::default()
+            });
+
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Mipmap Blit Bind Group"),
+                layout: bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&source_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(sampler),
+                    },
+                ],
+            });
+
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Mipmap Blit Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &target_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_bind_group(0, &bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+
+        queue.submit(std::iter::once(encoder.finish()));
+    }
+}
+
+//在 CPU 上逐级缩小图像并写入第 1 级及以后的各级。纹理是 sRGB 格式时在线性空间中缩小
+pub fn generate_cpu(queue: &Queue, texture: &Texture, base: &RgbaImage) {
+    let srgb = texture.format().is_srgb();
+    let mut previous = to_linear(base, srgb);
+    for mip in 1..texture.mip_level_count() {
+        let width = (base.width() >> mip).max(1);
+        let height = (base.height() >> mip).max(1);
+        let linear = image::imageops::resize(&previous, width, height, FilterType::Triangle);
+        let level = from_linear(&linear, srgb);
+
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture,
+                mip_level: mip,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &level,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * width),
+                rows_per_image: Some(height),
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        previous = linear;
+    }
+}
+
+//转换为浮点图像，srgb 为 true 时颜色通道转换到线性空间（alpha 总是线性的）
+fn to_linear(img: &RgbaImage, srgb: bool) -> Rgba32FImage {
+    Rgba32FImage::from_fn(img.width(), img.height(), |x, y| {
+        let mut pixel = image::Rgba(img.get_pixel(x, y).0.map(|c| c as f32 / 255.0));
+        if srgb {
+            for c in &mut pixel.0[..3] {
+                *c = super::srgb_to_linear(*c);
+            }
+        }
+        pixel
+    })
+}
+
+fn from_linear(img: &Rgba32FImage, srgb: bool) -> RgbaImage {
+    RgbaImage::from_fn(img.width(), img.height(), |x, y| {
+        let mut pixel = img.get_pixel(x, y).0;
+        if srgb {
+            for c in &mut pixel[..3] {
+                *c = super::linear_to_srgb(c.clamp(0.0, 1.0));
+            }
+        }
+        image::Rgba(pixel.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headless;
+    use crate::texture::{Texture as ImageTexture, TextureOptions};
+
+    #[test]
+    fn level_count() {
+        assert_eq!(mip_level_count(1, 1), 1);
+        assert_eq!(mip_level_count(0, 0), 1);
+        assert_eq!(mip_level_count(4, 4), 3);
+        assert_eq!(mip_level_count(5, 3), 3);
+        assert_eq!(mip_level_count(256, 1), 9);
+    }
+
+    #[test]
+    fn pipelines_are_cached_per_device_and_format() {
+        let (device, queue) = pollster::block_on(headless::request_device());
+        let img = image::DynamicImage::ImageRgba8(RgbaImage::new(8, 8));
+        for options in [TextureOptions::default(), TextureOptions::default(), TextureOptions::linear()] {
+            ImageTexture::from_image(&device, &queue, &img, None, &options).unwrap();
+        }
+        let formats = BLITTERS.with(|blitters| blitters.borrow().get(&device.global_id()).map(|blitter| blitter.pipelines.len()));
+        assert_eq!(formats, Some(2));
+    }
+}
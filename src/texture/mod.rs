@@ -17,4 +17,359 @@ image 包含的 jpeg 解码器使用 rayon 来加速线程的解码速度。WASM
 
 在 WASM 中解码 jpeg 性能不高。如果你想在 WASM 中加快图像加载速度，可以选择使用浏览器的内置解码器来替换 wasm-bindgen 构建时使用 的 image。
 这涉及到在 Rust 中创建一个 <img> 标记来获取图像，然后创建一个 <canvas> 来获取像素数据，我把这留作读者的练习。
-*/
\ No newline at end of file
+*/
+pub mod mipmap;
+
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use image::{DynamicImage, GenericImageView, ImageError};
+use wgpu::{AddressMode, Device, FilterMode, Queue, Sampler, TextureFormat, TextureUsages, TextureView, TextureViewDescriptor};
+
+//加载纹理时可能出现的错误
+#[derive(Debug)]
+pub enum TextureError {
+    //读取文件失败
+    Io { path: PathBuf, source: std::io::Error },
+    //图像解码失败
+    Image(ImageError),
+    //只支持每像素 4 字节的 RGBA 格式
+    UnsupportedFormat(TextureFormat),
+}
+
+impl fmt::Display for TextureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TextureError::Io { path, source } => write!(f, "无法读取纹理文件 {}: {}", path.display(), source),
+            TextureError::Image(e) => write!(f, "无法解码纹理图像: {}", e),
+            TextureError::UnsupportedFormat(format) => write!(f, "不支持的纹理格式: {:?}", format),
+        }
+    }
+}
+
+impl Error for TextureError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TextureError::Io { source, .. } => Some(source),
+            TextureError::Image(e) => Some(e),
+            TextureError::UnsupportedFormat(_) => None,
+        }
+    }
+}
+
+impl From<ImageError> for TextureError {
+    fn from(e: ImageError) -> Self {
+        TextureError::Image(e)
+    }
+}
+
+/*
+纹理格式与采样器的设置。
+
+大多数图像（颜色贴图）都是使用 sRGB 来存储的，采样时 GPU 会自动把它转换到线性空间。
+但法线贴图、粗糙度等数据纹理存储的就是线性数值，如果也当作 sRGB 来读取，数值就会被错误地转换，这时应该使用 TextureOptions::linear()。
+*/
+#[derive(Copy, Clone, Debug)]
+pub struct TextureOptions {
+    //Rgba8UnormSrgb（颜色）或 Rgba8Unorm（线性数据）
+    pub format: TextureFormat,
+    //纹理坐标超出 0..1 时 u（水平）和 v（垂直）方向的处理方式
+    pub address_mode_u: AddressMode,
+    pub address_mode_v: AddressMode,
+    pub mag_filter: FilterMode,
+    pub min_filter: FilterMode,
+    pub mipmap_filter: FilterMode,
+    //是否生成完整的 mip 链
+    pub generate_mipmaps: bool,
+}
+
+impl Default for TextureOptions {
+    fn default() -> Self {
+        Self {
+            format: TextureFormat::Rgba8UnormSrgb,
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            mipmap_filter: FilterMode::Linear,
+            generate_mipmaps: true,
+        }
+    }
+}
+
+impl TextureOptions {
+    //用于法线贴图等数据纹理的线性格式
+    pub fn linear() -> Self {
+        Self {
+            format: TextureFormat::Rgba8Unorm,
+            ..Default::default()
+        }
+    }
+}
+
+//sRGB 编码的颜色分量（0..1）转换到线性空间
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+//线性空间的颜色分量（0..1）转换到 sRGB 编码
+pub fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 { c * 12.92 } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 }
+}
+
+//纹理、纹理视图和采样器总是一起使用，所以把它们放在一个结构体里
+pub struct Texture {
+    pub texture: wgpu::Texture,
+    pub view: TextureView,
+    pub sampler: Sampler,
+}
+
+impl Texture {
+    //从图像文件的字节（png、jpeg）创建纹理
+    pub fn from_bytes(device: &Device, queue: &Queue, bytes: &[u8], label: &str, options: &TextureOptions) -> Result<Self, TextureError> {
+        let img = image::load_from_memory(bytes)?;
+        Self::from_image(device, queue, &img, Some(label), options)
+    }
+
+    //从图像文件创建纹理，文件名同时作为纹理的标签
+    pub fn from_path<P: AsRef<Path>>(device: &Device, queue: &Queue, path: P, options: &TextureOptions) -> Result<Self, TextureError> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path).map_err(|source| TextureError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let label = path.to_string_lossy();
+        Self::from_bytes(device, queue, &bytes, &label, options)
+    }
+
+    /*
+    只有一个像素的线性纹理，值为 (0.5, 0.5, 1.0)，也就是切线空间中垂直于表面的法线。
+    没有法线贴图的材质绑定它，效果与不使用法线贴图相同。
+    */
+    pub fn flat_normal(device: &Device, queue: &Queue) -> Result<Self, TextureError> {
+        let img = DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(1, 1, image::Rgba([128, 128, 255, 255])));
+        Self::from_image(device, queue, &img, Some("flat_normal"), &TextureOptions::linear())
+    }
+
+    pub fn from_image(device: &Device, queue: &Queue, img: &DynamicImage, label: Option<&str>, options: &TextureOptions) -> Result<Self, TextureError> {
+        Self::create(device, queue, img, label, options, mipmap::can_render_to(device, options.format))
+    }
+
+    //gpu 为 false 时总在 CPU 上生成 mipmap。支持的两种格式总是可以渲染，所以只有测试用它覆盖 CPU 生成（见 mipmap 模块）
+    fn create(device: &Device, queue: &Queue, img: &DynamicImage, label: Option<&str>, options: &TextureOptions, gpu: bool) -> Result<Self, TextureError> {
+        match options.format {
+            TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb => {}
+            format => return Err(TextureError::UnsupportedFormat(format)),
+        }
+
+        /*
+        此处代码把图像转换为 rgba 动态数组。我们还保存了图像的尺寸信息以便在创建实际纹理时使用。
+        */
+        let rgba = img.to_rgba8();
+        let dimensions = img.dimensions();
+
+        //mipmap 的级数，以及是否可以在 GPU 上生成（格式需要能作为渲染附件）
+        let mip_level_count = if options.generate_mipmaps {
+            mipmap::mip_level_count(dimensions.0, dimensions.1)
+        } else {
+            1
+        };
+        let gpu_mipmaps = mip_level_count > 1 && gpu;
+
+        //创建纹理
+        let size = wgpu::Extent3d {
+            width: dimensions.0,
+            height: dimensions.1,
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(
+            &wgpu::TextureDescriptor {
+                // 所有纹理都是以 3D 形式存储的，我们通过设置深度 1 来表示 2D 纹理
+                size,
+                mip_level_count,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: options.format,
+                // TEXTURE_BINDING 表示我们要在着色器中使用这个纹理。
+                // COPY_DST 表示我们能将数据复制到这个纹理上。
+                // COPY_SRC 表示可以把纹理复制出去，例如用 headless::read_texture 读回。
+                // 在 GPU 上生成 mipmap 时每一级都要作为渲染附件，见 mipmap 模块。
+                usage: if gpu_mipmaps {
+                    TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST | mipmap::GPU_USAGES
+                } else {
+                    TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST | TextureUsages::COPY_SRC
+                },
+                label,
+                view_formats: &[],
+            }
+        );
+
+        /*
+        填充数据到纹理中
+        Texture 结构体没有函数可以直接与数据交互。但我们可以使用命令队列上的 write_texture 命令来填充纹理数据。
+        （经典方式是将像素数据先复制到一个缓冲区，然后再从缓冲区复制到纹理中，write_texture 少用了一个缓冲区，因此更有效率。）
+        */
+        queue.write_texture(
+            // 告诉 wgpu 从何处复制像素数据
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            //实际像素数据
+            &rgba,
+            //纹理的内存布局
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * dimensions.0),
+                rows_per_image: Some(dimensions.1),
+            },
+            size,
+        );
+
+        //用第 0 级填充其余各级
+        if gpu_mipmaps {
+            mipmap::generate_gpu(device, queue, &texture);
+        } else if mip_level_count > 1 {
+            mipmap::generate_cpu(queue, &texture, &rgba);
+        }
+
+        /*
+        纹理视图描述纹理及其关联的元数据。采样器控制纹理如何被采样。
+        address_mode_* 指定了纹理坐标超出纹理边界时该如何处理（ClampToEdge、Repeat、MirrorRepeat），
+        mag_filter 与 min_filter 描述了当采样足迹小于或大于一个纹素时该如何处理（Linear 或 Nearest），
+        mipmap_filter 告诉采样器如何在 mipmaps 之间混合。
+        */
+        let view = texture.create_view(&TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: options.address_mode_u,
+            address_mode_v: options.address_mode_v,
+            address_mode_w: options.address_mode_u,
+            mag_filter: options.mag_filter,
+            min_filter: options.min_filter,
+            mipmap_filter: options.mipmap_filter,
+            ..Default::default()
+        });
+
+        Ok(Self {
+            texture,
+            view,
+            sampler,
+        })
+    }
+
+    //深度纹理：作为渲染通道的深度附件，同时可以在着色器中读取（调试视图、阴影）。
+    //采样器是比较采样器，用 textureSampleCompare 采样时返回比较的结果而不是深度值。
+    pub fn create_depth_texture(device: &Device, width: u32, height: u32, format: TextureFormat, label: &str) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        let view = texture.create_view(&TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            mipmap_filter: FilterMode::Nearest,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+
+        Self {
+            texture,
+            view,
+            sampler,
+        }
+    }
+
+    pub fn size(&self) -> wgpu::Extent3d {
+        self.texture.size()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headless;
+    use winit::dpi::PhysicalSize;
+
+    //4x4 的图像，四个 2x2 的块颜色不同，每个通道的平均值都是 127.5
+    fn quadrants() -> DynamicImage {
+        let colors = [[0, 0, 255, 255], [255, 0, 0, 255], [0, 255, 0, 255], [255, 255, 255, 255]];
+        DynamicImage::ImageRgba8(image::RgbaImage::from_fn(4, 4, |x, y| {
+            image::Rgba(colors[(y / 2 * 2 + x / 2) as usize])
+        }))
+    }
+
+    fn read_level(device: &Device, queue: &Queue, texture: &Texture, mip: u32) -> image::RgbaImage {
+        let size = texture.size().mip_level_size(mip, wgpu::TextureDimension::D2);
+        let source = wgpu::ImageCopyTexture {
+            texture: &texture.texture,
+            mip_level: mip,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        };
+        headless::read_texture(device, queue, source, PhysicalSize::new(size.width, size.height), texture.texture.format())
+            .expect("Rgba8Unorm 可以读回")
+    }
+
+    #[test]
+    fn cpu_and_gpu_mipmaps_average_down_to_one_texel() {
+        let (device, queue) = pollster::block_on(headless::request_device());
+        //线性格式直接平均；sRGB 格式在线性空间中平均，0 和 1 的平均值 0.5 编码为 sRGB 是 188
+        for (options, expected) in [(TextureOptions::linear(), 127), (TextureOptions::default(), 188)] {
+            for gpu in [false, true] {
+                let texture = Texture::create(&device, &queue, &quadrants(), Some("mipmap test"), &options, gpu).unwrap();
+                assert_eq!(texture.texture.mip_level_count(), 3);
+
+                let last = read_level(&device, &queue, &texture, 2);
+                assert_eq!(last.dimensions(), (1, 1));
+                let texel = last.get_pixel(0, 0).0;
+                for channel in &texel[..3] {
+                    assert!((*channel as i32 - expected).abs() <= 2, "{:?}, gpu = {}: {:?}", options.format, gpu, texel);
+                }
+                assert_eq!(texel[3], 255);
+            }
+        }
+    }
+
+    #[test]
+    fn mipmaps_disabled() {
+        let (device, queue) = pollster::block_on(headless::request_device());
+        let options = TextureOptions {
+            generate_mipmaps: false,
+            ..TextureOptions::linear()
+        };
+        let texture = Texture::from_image(&device, &queue, &quadrants(), None, &options).unwrap();
+        assert_eq!(texture.texture.mip_level_count(), 1);
+        assert_eq!(read_level(&device, &queue, &texture, 0), quadrants().to_rgba8());
+    }
+
+    #[test]
+    fn unsupported_format() {
+        let (device, queue) = pollster::block_on(headless::request_device());
+        let options = TextureOptions {
+            format: TextureFormat::Rgba16Float,
+            ..Default::default()
+        };
+        assert!(matches!(
+            Texture::from_image(&device, &queue, &quadrants(), None, &options),
+            Err(TextureError::UnsupportedFormat(TextureFormat::Rgba16Float))
+        ));
+    }
+}
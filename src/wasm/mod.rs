@@ -47,21 +47,16 @@ pub async fn run() {
     event_loop.run(move |event, _, control_flow| {
         match event {
             Event::WindowEvent {
-                ref event,
-                window_id
-            } if window_id == window.id() => {
-                match event {
-                    WindowEvent::CloseRequested | WindowEvent::KeyboardInput {
-                        input: KeyboardInput {
-                            state: ElementState::Pressed,
-                            virtual_keycode: Some(VirtualKeyCode::Escape),
-                            ..
-                        },
+                event: WindowEvent::CloseRequested | WindowEvent::KeyboardInput {
+                    input: KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::Escape),
                         ..
-                    } => *control_flow = ControlFlow::Exit,
-                    _ => {}
-                }
-            }
+                    },
+                    ..
+                },
+                window_id
+            } if window_id == window.id() => *control_flow = ControlFlow::Exit,
             _ => {}
         }
     });
@@ -0,0 +1,6 @@
+//#[derive(VertexLayout)] 对错误用法给出的编译错误。修改错误信息后用 TRYBUILD=overwrite cargo test --test derive 更新 .stderr 文件
+#[test]
+fn derive_errors() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,44 @@
+use wgpu_01::buffer::VertexLayout;
+
+#[derive(VertexLayout)]
+struct NormalizedFloat {
+    #[vertex(normalized)]
+    position: [f32; 3],
+}
+
+#[derive(VertexLayout)]
+struct NotNormalizedFloat {
+    #[vertex(normalized = false)]
+    position: [f32; 3],
+}
+
+#[derive(VertexLayout)]
+struct UnknownFieldAttribute {
+    #[vertex(offset = 4)]
+    position: [f32; 3],
+}
+
+#[derive(VertexLayout)]
+#[vertex(stride = 16)]
+struct UnknownStructAttribute {
+    position: [f32; 3],
+}
+
+#[derive(VertexLayout)]
+struct DuplicateLocation {
+    #[vertex(location = 1)]
+    position: [f32; 3],
+    #[vertex(location = 0)]
+    normal: [f32; 3],
+    color: [f32; 4],
+}
+
+#[derive(VertexLayout)]
+struct Tuple([f32; 3]);
+
+#[derive(VertexLayout)]
+enum Shape {
+    Point,
+}
+
+fn main() {}
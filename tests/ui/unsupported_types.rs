@@ -0,0 +1,30 @@
+use wgpu_01::buffer::VertexLayout;
+
+#[derive(VertexLayout)]
+struct Text {
+    name: String,
+}
+
+#[derive(VertexLayout)]
+struct FiveFloats {
+    values: [f32; 5],
+}
+
+#[derive(VertexLayout)]
+struct ThreeBytes {
+    color: [u8; 3],
+}
+
+#[derive(VertexLayout)]
+struct Flag {
+    visible: bool,
+}
+
+#[derive(VertexLayout)]
+struct ConstLength {
+    values: [f32; LEN],
+}
+
+const LEN: usize = 3;
+
+fn main() {}
@@ -0,0 +1,192 @@
+/*
+#[derive(VertexLayout)]
+为顶点结构体实现 wgpu_01::buffer::VertexLayout：根据每个字段的类型生成顶点属性，偏移量用 offset_of! 计算，
+这样属性的格式、偏移量和 location 不需要再手写，也不会和字段不一致。
+
+字段类型与顶点格式的对应关系：
+    f32 / [f32; 2..=4]            Float32 / Float32x2..4（f64、u32、i32 同理）
+    [u8; 2|4] / [i8; 2|4]         Unorm8x2|4 / Snorm8x2|4，加上 #[vertex(normalized = false)] 时是 Uint8 / Sint8
+    [u16; 2|4] / [i16; 2|4]       Unorm16 / Snorm16，同上
+    [[f32; N]; M]                 M 个连续的 location，每个是 Float32xN（例如 4x4 矩阵）
+
+结构体上的属性：
+    #[vertex(instance)]           每个实例读取一次，默认每个顶点读取一次
+    #[vertex(location = 5)]       第一个字段的 location，默认为 0
+字段上的属性：
+    #[vertex(location = 9)]       指定这个字段的 location，之后的字段从它后面继续编号
+    #[vertex(normalized)]         8 位和 16 位整数是否归一化到 0..1（或 -1..1），默认归一化。其他类型不能使用
+    #[vertex(skip)]               不作为顶点属性（例如着色器不需要的字段或者填充）
+*/
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Data, DeriveInput, Expr, ExprLit, Fields, Lit, LitInt, Type};
+
+#[proc_macro_derive(VertexLayout, attributes(vertex))]
+pub fn derive_vertex_layout(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input).unwrap_or_else(syn::Error::into_compile_error).into()
+}
+
+//结构体上的属性
+#[derive(Default)]
+struct StructOptions {
+    instance: bool,
+    location: u32,
+}
+
+//字段上的属性
+#[derive(Default)]
+struct FieldOptions {
+    location: Option<u32>,
+    normalized: Option<bool>,
+    skip: bool,
+}
+
+fn parse_struct_options(input: &DeriveInput) -> syn::Result<StructOptions> {
+    let mut options = StructOptions::default();
+    for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("vertex")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("instance") {
+                options.instance = true;
+            } else if meta.path.is_ident("location") {
+                options.location = meta.value()?.parse::<LitInt>()?.base10_parse()?;
+            } else {
+                return Err(meta.error("结构体上只支持 #[vertex(instance)] 和 #[vertex(location = N)]"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(options)
+}
+
+fn parse_field_options(field: &syn::Field) -> syn::Result<FieldOptions> {
+    let mut options = FieldOptions::default();
+    for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("vertex")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("skip") {
+                options.skip = true;
+            } else if meta.path.is_ident("location") {
+                options.location = Some(meta.value()?.parse::<LitInt>()?.base10_parse()?);
+            } else if meta.path.is_ident("normalized") {
+                //#[vertex(normalized)] 或 #[vertex(normalized = false)]
+                options.normalized = Some(if meta.input.peek(syn::Token![=]) {
+                    meta.value()?.parse::<syn::LitBool>()?.value
+                } else {
+                    true
+                });
+            } else {
+                return Err(meta.error("字段上只支持 #[vertex(location = N)]、#[vertex(normalized)] 和 #[vertex(skip)]"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(options)
+}
+
+//数组的长度必须是整数字面量
+fn array_len(len: &Expr) -> syn::Result<usize> {
+    match len {
+        Expr::Lit(ExprLit { lit: Lit::Int(lit), .. }) => lit.base10_parse(),
+        _ => Err(syn::Error::new_spanned(len, "数组长度必须是整数字面量")),
+    }
+}
+
+//标量或一维数组对应的顶点格式，例如 [f32; 3] 是 Float32x3
+fn vertex_format(ty: &Type, normalized: Option<bool>) -> syn::Result<String> {
+    let (scalar, count) = match ty {
+        Type::Array(array) => (&*array.elem, array_len(&array.len)?),
+        _ => (ty, 1),
+    };
+    let Type::Path(path) = scalar else {
+        return Err(syn::Error::new_spanned(ty, "不支持的顶点属性类型"));
+    };
+    let name = path.path.get_ident().map(|ident| ident.to_string()).unwrap_or_default();
+    let unsupported = || syn::Error::new_spanned(ty, format!("不支持的顶点属性类型，{} 没有对应的顶点格式", quote!(#ty)));
+
+    let (prefix, counts): (String, &[usize]) = match name.as_str() {
+        //其他类型没有归一化的区别，normalized = false 也是写错了
+        _ if normalized.is_some() && !matches!(name.as_str(), "u8" | "i8" | "u16" | "i16") => {
+            return Err(syn::Error::new_spanned(ty, "#[vertex(normalized)] 只能用于 8 位和 16 位整数"));
+        }
+        "f32" => ("Float32".into(), &[1, 2, 3, 4]),
+        "f64" => ("Float64".into(), &[1, 2, 3, 4]),
+        "u32" | "i32" => (if name == "u32" { "Uint32" } else { "Sint32" }.into(), &[1, 2, 3, 4]),
+        "u8" | "i8" | "u16" | "i16" => {
+            let bits = &name[1..];
+            let kind = match (name.starts_with('u'), normalized.unwrap_or(true)) {
+                (true, true) => "Unorm",
+                (false, true) => "Snorm",
+                (true, false) => "Uint",
+                (false, false) => "Sint",
+            };
+            (format!("{}{}", kind, bits), &[2, 4])
+        }
+        _ => return Err(unsupported()),
+    };
+    if !counts.contains(&count) {
+        return Err(unsupported());
+    }
+    Ok(if count == 1 { prefix } else { format!("{}x{}", prefix, count) })
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let options = parse_struct_options(&input)?;
+    let Data::Struct(data) = &input.data else {
+        return Err(syn::Error::new_spanned(&input, "VertexLayout 只能用于结构体"));
+    };
+    let Fields::Named(fields) = &data.fields else {
+        return Err(syn::Error::new_spanned(&input, "VertexLayout 只能用于有字段名的结构体"));
+    };
+
+    let mut attributes = Vec::new();
+    let mut location = options.location;
+    //已经使用的 location，重复时报错
+    let mut used: Vec<u32> = Vec::new();
+    for field in &fields.named {
+        let field_options = parse_field_options(field)?;
+        if field_options.skip {
+            continue;
+        }
+        let ident = field.ident.as_ref().expect("有名字的字段");
+        if let Some(explicit) = field_options.location {
+            location = explicit;
+        }
+
+        //二维数组（矩阵）的每一行占用一个 location
+        let rows = match &field.ty {
+            Type::Array(array) if matches!(&*array.elem, Type::Array(_)) => {
+                let row = &*array.elem;
+                let format = vertex_format(row, field_options.normalized)?;
+                (0..array_len(&array.len)?).map(|i| (format.clone(), quote!(#i * ::std::mem::size_of::<#row>()))).collect()
+            }
+            ty => vec![(vertex_format(ty, field_options.normalized)?, quote!(0))],
+        };
+        for (format, row_offset) in rows {
+            if used.contains(&location) {
+                return Err(syn::Error::new_spanned(field, format!("location {} 已经被其他字段使用", location)));
+            }
+            used.push(location);
+            let format = format_ident!("{}", format);
+            let shader_location = LitInt::new(&location.to_string(), Span::call_site());
+            attributes.push(quote! {
+                ::wgpu::VertexAttribute {
+                    offset: (::std::mem::offset_of!(Self, #ident) + #row_offset) as ::wgpu::BufferAddress,
+                    shader_location: #shader_location,
+                    format: ::wgpu::VertexFormat::#format,
+                }
+            });
+            location += 1;
+        }
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let step_mode = if options.instance { quote!(Instance) } else { quote!(Vertex) };
+    Ok(quote! {
+        impl #impl_generics ::wgpu_01::buffer::VertexLayout for #name #ty_generics #where_clause {
+            const ATTRIBUTES: &'static [::wgpu::VertexAttribute] = &[#(#attributes),*];
+            const STEP_MODE: ::wgpu::VertexStepMode = ::wgpu::VertexStepMode::#step_mode;
+        }
+    })
+}